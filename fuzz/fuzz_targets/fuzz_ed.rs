@@ -4,12 +4,16 @@ extern crate libfuzzer_sys;
 use diffutilslib::ed_diff;
 use diffutilslib::ed_diff::DiffError;
 use diffutilslib::params::Params;
+use diffutilslib::{compare, CompareOptions};
 use std::fs::{self, File};
 use std::io::Write;
 use std::process::Command;
 
 fn diff_w(expected: &[u8], actual: &[u8], filename: &str) -> Result<Vec<u8>, DiffError> {
-    let mut output = ed_diff::diff(expected, actual, &Params::default())?;
+    let mut output = Vec::new();
+    let params = Params::default();
+    let diff = compare(expected, actual, &CompareOptions::from(&params));
+    ed_diff::diff(&mut output, &diff, &params)?;
     writeln!(&mut output, "w {filename}").unwrap();
     Ok(output)
 }
@@ -3,6 +3,7 @@
 extern crate libfuzzer_sys;
 use diffutilslib::normal_diff;
 use diffutilslib::params::Params;
+use diffutilslib::{compare, CompareOptions};
 
 use std::fs::{self, File};
 use std::io::Write;
@@ -22,7 +23,14 @@ fuzz_target!(|x: (Vec<u8>, Vec<u8>)| {
     } else {
         return
     }*/
-    let diff = normal_diff::diff(&from, &to, &Params::default());
+    let params = Params::default();
+    let mut diff = Vec::new();
+    normal_diff::diff(
+        &mut diff,
+        &compare(&from, &to, &CompareOptions::from(&params)),
+        &params,
+    )
+    .unwrap();
     File::create("target/fuzz.file.original")
         .unwrap()
         .write_all(&from)
@@ -3,6 +3,7 @@
 extern crate libfuzzer_sys;
 use diffutilslib::params::Params;
 use diffutilslib::unified_diff;
+use diffutilslib::{compare, CompareOptions};
 use std::fs::{self, File};
 use std::io::Write;
 use std::process::Command;
@@ -21,16 +22,19 @@ fuzz_target!(|x: (Vec<u8>, Vec<u8>, u8)| {
     } else {
         return
     }*/
-    let diff = unified_diff::diff(
-        &from,
-        &to,
-        &Params {
-            from: "a/fuzz.file".into(),
-            to: "target/fuzz.file".into(),
-            context_count: context as usize,
-            ..Default::default()
-        }
-    );
+    let params = Params {
+        from: "a/fuzz.file".into(),
+        to: "target/fuzz.file".into(),
+        context_count: context as usize,
+        ..Default::default()
+    };
+    let mut diff = Vec::new();
+    unified_diff::diff(
+        &mut diff,
+        &compare(&from, &to, &CompareOptions::from(&params)),
+        &params,
+    )
+    .unwrap();
     File::create("target/fuzz.file.original")
         .unwrap()
         .write_all(&from)
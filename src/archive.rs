@@ -0,0 +1,141 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The extensions of the archives that tar extracts, compressed or not.
+const TAR_EXTENSIONS: [&str; 9] = [
+    ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tzst",
+];
+
+/// Whether a file is an archive that --archives compares as a directory,
+/// judging by its name. Only regular files are archives.
+#[must_use]
+pub fn is_archive(path: &OsStr) -> bool {
+    archive_kind(path).is_some() && Path::new(path).is_file()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ArchiveKind {
+    Tar,
+    Zip,
+}
+
+fn archive_kind(path: &OsStr) -> Option<ArchiveKind> {
+    let name = path.to_string_lossy().to_lowercase();
+    if TAR_EXTENSIONS
+        .iter()
+        .any(|extension| name.ends_with(extension))
+    {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// The members of an archive, extracted in a temporary directory that is
+/// removed when it is dropped.
+#[derive(Debug)]
+pub struct ExtractedArchive {
+    dir: PathBuf,
+}
+
+impl ExtractedArchive {
+    /// Extracts an archive with tar or unzip. The errors are described in
+    /// the words of `executable`.
+    pub fn new(executable: &OsStr, path: &OsStr) -> Result<Self, String> {
+        let executable = executable.to_string_lossy();
+        let fail = |e: &dyn std::fmt::Display| {
+            format!(
+                "{executable}: cannot extract {}: {e}",
+                path.to_string_lossy()
+            )
+        };
+        let archive = Self {
+            dir: temporary_dir().map_err(|e| fail(&e))?,
+        };
+        let mut command = match archive_kind(path) {
+            Some(ArchiveKind::Zip) => {
+                let mut command = Command::new("unzip");
+                command.arg("-qq").arg(path).arg("-d").arg(&archive.dir);
+                command
+            }
+            _ => {
+                let mut command = Command::new("tar");
+                command.arg("-xf").arg(path).arg("-C").arg(&archive.dir);
+                command
+            }
+        };
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| fail(&e))?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr);
+            return Err(match message.lines().next() {
+                Some(line) => fail(&line),
+                None => fail(&output.status),
+            });
+        }
+        Ok(archive)
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for ExtractedArchive {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Creates a new directory in the temporary directory of the system.
+fn temporary_dir() -> io::Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("diffutils-{}-{n}", process::id()));
+        match fs::create_dir(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|()| dir),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        for name in ["a.tar", "a.tar.gz", "A.TGZ", "dir/a.tar.zst", "a.tbz2"] {
+            assert_eq!(archive_kind(OsStr::new(name)), Some(ArchiveKind::Tar));
+        }
+        assert_eq!(archive_kind(OsStr::new("a.zip")), Some(ArchiveKind::Zip));
+        for name in ["a", "a.gz", "tar", "a.tar.orig"] {
+            assert_eq!(archive_kind(OsStr::new(name)), None);
+        }
+    }
+
+    #[test]
+    fn temporary_dirs() {
+        let (a, b) = (temporary_dir().unwrap(), temporary_dir().unwrap());
+        assert_ne!(a, b);
+        drop(ExtractedArchive { dir: a.clone() });
+        drop(ExtractedArchive { dir: b.clone() });
+        assert!(!a.exists() && !b.exists());
+    }
+}
@@ -0,0 +1,566 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+// Diffing of files too large to be compared line by line at once.
+//
+// Finding the longest common subsequence of two lists of lines takes memory
+// proportional to the product of their lengths, once their common prefix and
+// suffix are set aside. Past a limit, the lists are split on anchors: blocks
+// of lines that appear once in each list, found the way rsync does, with the
+// hashes of the blocks of one list looked up among the rolling hashes of
+// every block of the other. The windows between the anchors are then diffed
+// independently, splitting them again if needed, and the results are
+// stitched together. The result may not be minimal, like with GNU diff's
+// heuristics for large files.
+//
+// When no anchors can be found in a window that is too large, or with -d,
+// which asks for the smallest set of changes, its lines are compared with
+// Myers' algorithm in linear space instead: it takes memory proportional to
+// the sum of their lengths, and time proportional to that sum times the
+// number of differences.
+//
+// The files themselves aren't read into memory either when they're large:
+// they're mapped, so that the system reads their pages as they're compared,
+// and can drop them again when memory runs short. What stays in memory is
+// proportional to their number of lines, not to their size. Standard input,
+// pipes and other files that can't be mapped are read whole, as are the
+// contents that options like --strip-trailing-cr or --textconv transform.
+
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+// The largest number of cells of the table used to compare lines at once,
+// i.e. 64 MiB.
+const MAX_TABLE_SIZE: usize = 1 << 24;
+
+// The size from which files are mapped instead of read, i.e. 64 MiB.
+const MAP_THRESHOLD: u64 = 1 << 26;
+
+// The number of lines of the blocks used as anchors.
+const BLOCK_LINES: usize = 4;
+
+// The multiplier of the polynomial rolling hash of blocks.
+const HASH_BASE: u64 = 0x100_0000_01b3;
+
+/// The content of a file, either read into memory or mapped.
+#[derive(Debug)]
+pub enum Content {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Content {
+    /// Reads a file, or maps it when it's a large regular file.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.is_file() && metadata.len() >= MAP_THRESHOLD {
+            // SAFETY: a file that's truncated while it's compared makes the
+            // pages past its new end unreadable, like for any program that
+            // maps files. Its content changing otherwise is only as bad as
+            // reading it while it's written.
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Self::Mapped(map));
+            }
+        }
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        Ok(Self::Read(content))
+    }
+
+    /// The content as a vector that can be changed, which a mapped file is
+    /// copied to first.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Self::Mapped(map) = self {
+            *self = Self::Read(map.to_vec());
+        }
+        match self {
+            Self::Read(content) => content,
+            Self::Mapped(_) => unreachable!("the mapped content was copied"),
+        }
+    }
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self::Read(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Content {
+    fn from(content: Vec<u8>) -> Self {
+        Self::Read(content)
+    }
+}
+
+impl PartialEq for Content {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Deref for Content {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Read(content) => content,
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
+/// Diffs two lists of lines, like `diff::slice`, but without running out of
+/// memory on large lists. With `minimal`, the smallest set of changes is
+/// found whatever the size of the lists.
+#[must_use]
+pub fn diff<'a, T: Eq + Hash>(
+    expected: &'a [T],
+    actual: &'a [T],
+    minimal: bool,
+) -> Vec<diff::Result<&'a T>> {
+    let mut results = Vec::with_capacity(expected.len().max(actual.len()));
+    diff_window(expected, actual, MAX_TABLE_SIZE, minimal, &mut results);
+    results
+}
+
+fn diff_window<'a, T: Eq + Hash>(
+    expected: &'a [T],
+    actual: &'a [T],
+    max_table_size: usize,
+    minimal: bool,
+    results: &mut Vec<diff::Result<&'a T>>,
+) {
+    let prefix = expected
+        .iter()
+        .zip(actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+    let both = |(e, a)| diff::Result::Both(e, a);
+    results.extend(expected[..prefix].iter().zip(actual).map(both));
+    let expected_middle = &expected[prefix..expected.len() - suffix];
+    let actual_middle = &actual[prefix..actual.len() - suffix];
+
+    if expected_middle
+        .len()
+        .saturating_mul(actual_middle.len())
+        .saturating_add(expected_middle.len() + actual_middle.len())
+        <= max_table_size
+    {
+        results.extend(diff::slice(expected_middle, actual_middle));
+    } else {
+        let anchors = if minimal {
+            Vec::new()
+        } else {
+            find_anchors(expected_middle, actual_middle)
+        };
+        if anchors.is_empty() {
+            diff_linear(expected_middle, actual_middle, results);
+        } else {
+            let (mut expected_start, mut actual_start) = (0, 0);
+            for (e, a) in anchors {
+                diff_window(
+                    &expected_middle[expected_start..e],
+                    &actual_middle[actual_start..a],
+                    max_table_size,
+                    minimal,
+                    results,
+                );
+                let block = e..e + BLOCK_LINES;
+                results.extend(
+                    expected_middle[block]
+                        .iter()
+                        .zip(&actual_middle[a..])
+                        .map(both),
+                );
+                expected_start = e + BLOCK_LINES;
+                actual_start = a + BLOCK_LINES;
+            }
+            diff_window(
+                &expected_middle[expected_start..],
+                &actual_middle[actual_start..],
+                max_table_size,
+                minimal,
+                results,
+            );
+        }
+    }
+
+    let expected_suffix = &expected[expected.len() - suffix..];
+    let actual_suffix = &actual[actual.len() - suffix..];
+    results.extend(expected_suffix.iter().zip(actual_suffix).map(both));
+}
+
+// Diffs two lists with Myers' algorithm in linear space: the lists are split
+// on the middle snake of the shortest edit script between them, and the parts
+// before and after it are diffed the same way.
+fn diff_linear<'a, T: Eq>(
+    expected: &'a [T],
+    actual: &'a [T],
+    results: &mut Vec<diff::Result<&'a T>>,
+) {
+    let prefix = expected
+        .iter()
+        .zip(actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+    let both = |(e, a)| diff::Result::Both(e, a);
+    results.extend(expected[..prefix].iter().zip(actual).map(both));
+    let expected_middle = &expected[prefix..expected.len() - suffix];
+    let actual_middle = &actual[prefix..actual.len() - suffix];
+
+    if expected_middle.is_empty() || actual_middle.is_empty() {
+        results.extend(expected_middle.iter().map(diff::Result::Left));
+        results.extend(actual_middle.iter().map(diff::Result::Right));
+    } else {
+        // The lists start and end with differences, so there are at least two
+        // of them, and both parts around the snake have fewer.
+        let (start, end) = middle_snake(expected_middle, actual_middle);
+        diff_linear(
+            &expected_middle[..start.0],
+            &actual_middle[..start.1],
+            results,
+        );
+        results.extend(
+            expected_middle[start.0..end.0]
+                .iter()
+                .zip(&actual_middle[start.1..end.1])
+                .map(both),
+        );
+        diff_linear(&expected_middle[end.0..], &actual_middle[end.1..], results);
+    }
+
+    let expected_suffix = &expected[expected.len() - suffix..];
+    let actual_suffix = &actual[actual.len() - suffix..];
+    results.extend(expected_suffix.iter().zip(actual_suffix).map(both));
+}
+
+// Finds the middle snake of a shortest edit script between two lists: the
+// run of common elements where the paths searched from their starts and from
+// their ends meet. Returns the positions in both lists where it starts and
+// ends.
+fn middle_snake<T: Eq>(expected: &[T], actual: &[T]) -> ((usize, usize), (usize, usize)) {
+    let (n, m) = (expected.len() as isize, actual.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    // The furthest position reached in the expected list on each diagonal
+    // k = x - y, from the starts and from the ends of the lists.
+    let offset = max + 1;
+    let mut forward = vec![0; 2 * offset as usize + 1];
+    let mut backward = vec![0; 2 * offset as usize + 1];
+    let index = |k: isize| (k + offset) as usize;
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || k != d && forward[index(k - 1)] < forward[index(k + 1)] {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let start = (x, x - k);
+            while x < n && x - k < m && expected[x as usize] == actual[(x - k) as usize] {
+                x += 1;
+            }
+            forward[index(k)] = x;
+            // The diagonal k is the diagonal delta - k from the ends.
+            if delta % 2 != 0 && (delta - k).abs() < d && x + backward[index(delta - k)] >= n {
+                return snake(start, (x, x - k));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || k != d && backward[index(k - 1)] < backward[index(k + 1)] {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let end = (x, x - k);
+            while x < n
+                && x - k < m
+                && expected[(n - x - 1) as usize] == actual[(m - x + k - 1) as usize]
+            {
+                x += 1;
+            }
+            backward[index(k)] = x;
+            if delta % 2 == 0 && (delta - k).abs() <= d && x + forward[index(delta - k)] >= n {
+                return snake((n - x, m - x + k), (n - end.0, m - end.1));
+            }
+        }
+    }
+    unreachable!("the paths meet by the time they cover both lists")
+}
+
+fn snake(start: (isize, isize), end: (isize, isize)) -> ((usize, usize), (usize, usize)) {
+    (
+        (start.0 as usize, start.1 as usize),
+        (end.0 as usize, end.1 as usize),
+    )
+}
+
+// Hashes a block of line hashes, such that the hash of the next block can be
+// computed from the hash of the previous one.
+fn block_hash(line_hashes: &[u64]) -> u64 {
+    line_hashes.iter().fold(0, |hash, &line| {
+        hash.wrapping_mul(HASH_BASE).wrapping_add(line)
+    })
+}
+
+// Finds the positions of blocks of lines that appear once in both lists, in
+// increasing order in both, without overlapping.
+fn find_anchors<T: Eq + Hash>(expected: &[T], actual: &[T]) -> Vec<(usize, usize)> {
+    let line_hash = |line: &T| {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        hasher.finish()
+    };
+    let expected_hashes: Vec<u64> = expected.iter().map(line_hash).collect();
+    let actual_hashes: Vec<u64> = actual.iter().map(line_hash).collect();
+    if expected_hashes.len() < BLOCK_LINES || actual_hashes.len() < BLOCK_LINES {
+        return Vec::new();
+    }
+
+    // The blocks that start at multiples of BLOCK_LINES in the expected lines,
+    // by hash, unless the hash appears more than once.
+    let mut blocks: HashMap<u64, Option<usize>> = HashMap::new();
+    for (i, block) in expected_hashes.chunks_exact(BLOCK_LINES).enumerate() {
+        blocks
+            .entry(block_hash(block))
+            .and_modify(|position| *position = None)
+            .or_insert(Some(i * BLOCK_LINES));
+    }
+
+    // The positions of those blocks among the blocks that start at every
+    // line of the actual lines, unless they appear more than once.
+    let mut matches: HashMap<usize, Option<usize>> = HashMap::new();
+    let top_weight = HASH_BASE.wrapping_pow(BLOCK_LINES as u32 - 1);
+    let mut hash = block_hash(&actual_hashes[..BLOCK_LINES]);
+    for a in 0..=actual_hashes.len() - BLOCK_LINES {
+        if a > 0 {
+            hash = hash
+                .wrapping_sub(actual_hashes[a - 1].wrapping_mul(top_weight))
+                .wrapping_mul(HASH_BASE)
+                .wrapping_add(actual_hashes[a + BLOCK_LINES - 1]);
+        }
+        if let Some(&Some(e)) = blocks.get(&hash) {
+            match matches.entry(e) {
+                Entry::Occupied(mut entry) => *entry.get_mut() = None,
+                Entry::Vacant(entry) => {
+                    entry.insert(Some(a));
+                }
+            }
+        }
+    }
+    let mut candidates: Vec<(usize, usize)> = matches
+        .into_iter()
+        .filter_map(|(e, a)| Some((e, a?)))
+        .filter(|&(e, a)| expected[e..e + BLOCK_LINES] == actual[a..a + BLOCK_LINES])
+        .collect();
+    candidates.sort_unstable();
+
+    // Keep the longest sequence of blocks that are in the same order in both
+    // lists, like patience diff does.
+    let mut anchors = longest_increasing_subsequence(&candidates);
+    let mut actual_end = 0;
+    anchors.retain(|&(_, a)| {
+        let keep = a >= actual_end;
+        if keep {
+            actual_end = a + BLOCK_LINES;
+        }
+        keep
+    });
+    anchors
+}
+
+// Finds the longest subsequence of pairs whose second elements increase, given
+// pairs sorted by their first elements.
+fn longest_increasing_subsequence(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // The index of the last pair of the best subsequence of each length.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; pairs.len()];
+    for (i, &(_, a)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].1 < a);
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+    let mut subsequence = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        subsequence.push(pairs[i]);
+        next = previous[i];
+    }
+    subsequence.reverse();
+    subsequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Checks that the results describe the lines of both lists, in order.
+    fn check(expected: &[u32], actual: &[u32], results: &[diff::Result<&u32>]) {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for result in results {
+            match *result {
+                diff::Result::Left(e) => left.push(*e),
+                diff::Result::Right(a) => right.push(*a),
+                diff::Result::Both(e, a) => {
+                    assert_eq!(e, a);
+                    left.push(*e);
+                    right.push(*a);
+                }
+            }
+        }
+        assert_eq!(left, expected);
+        assert_eq!(right, actual);
+    }
+
+    fn changed_lines(results: &[diff::Result<&u32>]) -> usize {
+        results
+            .iter()
+            .filter(|result| !matches!(result, diff::Result::Both(_, _)))
+            .count()
+    }
+
+    #[test]
+    fn small_lists_are_diffed_at_once() {
+        let expected = [1, 2, 3, 4, 5];
+        let actual = [1, 3, 4, 6, 5];
+        assert_eq!(
+            diff(&expected, &actual, false),
+            diff::slice(&expected, &actual)
+        );
+    }
+
+    #[test]
+    fn large_lists_are_split_on_anchors() {
+        let expected: Vec<u32> = (0..1000).collect();
+        let mut actual = expected.clone();
+        actual[10] = 5000;
+        actual.remove(500);
+        actual.insert(900, 6000);
+        let mut results = Vec::new();
+        diff_window(&expected, &actual, 1000, false, &mut results);
+        check(&expected, &actual, &results);
+        assert_eq!(changed_lines(&results), 4);
+        assert_eq!(results, diff::slice(&expected, &actual));
+    }
+
+    #[test]
+    fn repeated_blocks_are_not_anchors() {
+        let expected: Vec<u32> = (0..400).map(|i| i % 8).collect();
+        let actual: Vec<u32> = (0..400).map(|i| (i + 1) % 8).collect();
+        assert_eq!(find_anchors(&expected, &actual), []);
+        // Without anchors, the lines are still compared, in linear space.
+        let mut results = Vec::new();
+        diff_window(&expected, &actual, 1000, false, &mut results);
+        check(&expected, &actual, &results);
+        assert_eq!(changed_lines(&results), 2);
+    }
+
+    #[test]
+    fn linear_space_diff_is_minimal() {
+        // Sequences with differences everywhere, and a lot of them in a row.
+        let lists: Vec<Vec<u32>> = vec![
+            vec![],
+            vec![1],
+            vec![1, 2, 3, 4, 5, 6],
+            vec![6, 5, 4, 3, 2, 1],
+            vec![1, 3, 5, 2, 4, 6, 1, 3],
+            (0..50).map(|i| i * 7 % 11).collect(),
+            (0..60).map(|i| i * 5 % 13).collect(),
+            (0..40).map(|i| i % 3).collect(),
+        ];
+        for expected in &lists {
+            for actual in &lists {
+                let mut results = Vec::new();
+                diff_linear(expected, actual, &mut results);
+                check(expected, actual, &results);
+                assert_eq!(
+                    changed_lines(&results),
+                    changed_lines(&diff::slice(expected, actual)),
+                    "{expected:?} {actual:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn anchors_in_order() {
+        // The blocks of 40.. and 80.. swapped places: only one of them is
+        // kept as an anchor.
+        let expected: Vec<u32> = (0..120).collect();
+        let actual: Vec<u32> = (0..40).chain(80..120).chain(40..80).collect();
+        let anchors = find_anchors(&expected, &actual);
+        assert!(anchors.windows(2).all(|pair| {
+            pair[0].0 + BLOCK_LINES <= pair[1].0 && pair[0].1 + BLOCK_LINES <= pair[1].1
+        }));
+        assert_eq!(anchors.len(), 20);
+        let mut results = Vec::new();
+        diff_window(&expected, &actual, 100, false, &mut results);
+        check(&expected, &actual, &results);
+        assert_eq!(changed_lines(&results), 80);
+        // The smallest set of changes moves the lines of only one block.
+        let mut results = Vec::new();
+        diff_window(&expected, &actual, 100, true, &mut results);
+        check(&expected, &actual, &results);
+        assert_eq!(changed_lines(&results), 80);
+    }
+
+    #[test]
+    fn large_files_are_mapped() -> io::Result<()> {
+        let small = tempfile::NamedTempFile::new()?;
+        std::fs::write(small.path(), "a\nb\n")?;
+        let content = Content::from_file(small.path())?;
+        assert!(matches!(content, Content::Read(_)));
+        assert_eq!(&*content, b"a\nb\n");
+
+        let large = tempfile::NamedTempFile::new()?;
+        std::fs::write(large.path(), "a\nb\n")?;
+        large.as_file().set_len(MAP_THRESHOLD)?;
+        let mut content = Content::from_file(large.path())?;
+        assert!(matches!(content, Content::Mapped(_)));
+        assert_eq!(content.len() as u64, MAP_THRESHOLD);
+        assert_eq!(&content[..4], b"a\nb\n");
+        // Changing it copies it.
+        content.to_mut().push(b'\n');
+        assert!(matches!(content, Content::Read(_)));
+        assert_eq!(content.len() as u64, MAP_THRESHOLD + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn longest_subsequence() {
+        assert_eq!(
+            longest_increasing_subsequence(&[(0, 3), (4, 1), (8, 2), (12, 0), (16, 5)]),
+            [(4, 1), (8, 2), (16, 5)]
+        );
+        assert_eq!(longest_increasing_subsequence(&[]), []);
+    }
+}
@@ -3,10 +3,10 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use crate::utils::format_failure_to_read_input_file;
+use crate::utils::{format_failure_to_read_input_file, is_same_file, parse_byte_quantity};
 use std::env::{self, ArgsOs};
 use std::ffi::OsString;
-use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::iter::Peekable;
 use std::process::ExitCode;
 use std::{cmp, fs, io};
@@ -20,6 +20,15 @@ use std::os::unix::fs::MetadataExt;
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::MetadataExt;
 
+/// The base that the values of differing bytes are printed in, for --format.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ValueFormat {
+    #[default]
+    Octal,
+    Hexadecimal,
+    Decimal,
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Params {
     executable: OsString,
@@ -31,6 +40,8 @@ pub struct Params {
     max_bytes: Option<usize>,
     verbose: bool,
     quiet: bool,
+    hex_context: Option<usize>,
+    value_format: ValueFormat,
 }
 
 #[inline]
@@ -38,6 +49,37 @@ fn usage_string(executable: &str) -> String {
     format!("Usage: {} <from> <to>", executable)
 }
 
+// The options of --help, in the words of GNU cmp.
+const OPTIONS_HELP: &str = "\
+Compare two files byte by byte.
+
+The optional SKIP1 and SKIP2 specify the number of bytes to skip
+at the beginning of each file (zero by default).
+
+Mandatory arguments to long options are mandatory for short options too.
+  -b, --print-bytes          print differing bytes
+  -i, --ignore-initial=SKIP         skip first SKIP bytes of both inputs
+  -i, --ignore-initial=SKIP1:SKIP2  skip first SKIP1 bytes of FILE1 and
+                                      first SKIP2 bytes of FILE2
+  -l, --verbose              output byte numbers and differing byte values
+      --format=FORMAT        print byte values in FORMAT: 'octal' (the
+                               default), 'hex' or 'decimal'
+  -n, --bytes=LIMIT          compare at most LIMIT bytes
+  -s, --quiet, --silent      suppress all normal output
+      --hex-context=N        print N bytes of both files before and from
+                               the first difference, in hexadecimal
+      --help                 display this help and exit
+  -v, --version              output version information and exit
+
+SKIP values may be followed by the following multiplicative suffixes:
+kB 1000, K 1024, MB 1,000,000, M 1,048,576,
+GB 1,000,000,000, G 1,073,741,824, and so on for T, P, E, Z, Y.
+SKIP and LIMIT values can be in hexadecimal notation, as in 0x10.
+
+If a FILE is '-' or missing, read standard input.
+Exit status is 0 if inputs are the same, 1 if different, 2 if trouble.
+";
+
 #[cfg(not(target_os = "windows"))]
 fn is_stdout_dev_null() -> bool {
     let Ok(dev_null) = fs::metadata("/dev/null") else {
@@ -67,55 +109,12 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
     let executable_str = executable.to_string_lossy().to_string();
 
     let parse_skip = |param: &str, skip_desc: &str| -> Result<usize, String> {
-        let suffix_start = param
-            .find(|b: char| !b.is_ascii_digit())
-            .unwrap_or(param.len());
-        let mut num = match param[..suffix_start].parse::<usize>() {
-            Ok(num) => num,
-            Err(e) if *e.kind() == std::num::IntErrorKind::PosOverflow => usize::MAX,
-            Err(_) => {
-                return Err(format!(
-                    "{}: invalid --ignore-initial value '{}'",
-                    executable_str, skip_desc
-                ))
-            }
-        };
-
-        if suffix_start != param.len() {
-            // Note that GNU cmp advertises supporting up to Y, but fails if you try
-            // to actually use anything beyond E.
-            let multiplier: usize = match &param[suffix_start..] {
-                "kB" => 1_000,
-                "K" => 1_024,
-                "MB" => 1_000_000,
-                "M" => 1_048_576,
-                "GB" => 1_000_000_000,
-                "G" => 1_073_741_824,
-                "TB" => 1_000_000_000_000,
-                "T" => 1_099_511_627_776,
-                "PB" => 1_000_000_000_000_000,
-                "P" => 1_125_899_906_842_624,
-                "EB" => 1_000_000_000_000_000_000,
-                "E" => 1_152_921_504_606_846_976,
-                "ZB" => usize::MAX, // 1_000_000_000_000_000_000_000,
-                "Z" => usize::MAX,  // 1_180_591_620_717_411_303_424,
-                "YB" => usize::MAX, // 1_000_000_000_000_000_000_000_000,
-                "Y" => usize::MAX,  // 1_208_925_819_614_629_174_706_176,
-                _ => {
-                    return Err(format!(
-                        "{}: invalid --ignore-initial value '{}'",
-                        executable_str, skip_desc
-                    ));
-                }
-            };
-
-            num = match num.overflowing_mul(multiplier) {
-                (n, false) => n,
-                _ => usize::MAX,
-            }
-        }
-
-        Ok(num)
+        parse_byte_quantity(param).ok_or_else(|| {
+            format!(
+                "{}: invalid --ignore-initial value '{}'",
+                executable_str, skip_desc
+            )
+        })
     };
 
     let mut params = Params {
@@ -165,16 +164,9 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
                 let (_, arg) = param_str.split_once('=').unwrap();
                 arg.to_string()
             };
-            let max_bytes = match max_bytes.parse::<usize>() {
-                Ok(num) => num,
-                Err(e) if *e.kind() == std::num::IntErrorKind::PosOverflow => usize::MAX,
-                Err(_) => {
-                    return Err(format!(
-                        "{}: invalid --bytes value '{}'",
-                        executable_str, max_bytes
-                    ))
-                }
-            };
+            let max_bytes = parse_byte_quantity(&max_bytes).ok_or_else(|| {
+                format!("{}: invalid --bytes value '{}'", executable_str, max_bytes)
+            })?;
             params.max_bytes = Some(max_bytes);
             continue;
         }
@@ -205,8 +197,40 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
             params.quiet = true;
             continue;
         }
+        if param_str.starts_with("--format=") {
+            let (_, arg) = param_str.split_once('=').unwrap();
+            params.value_format = match arg {
+                "octal" => ValueFormat::Octal,
+                "hex" => ValueFormat::Hexadecimal,
+                "decimal" => ValueFormat::Decimal,
+                _ => {
+                    return Err(format!(
+                        "{}: invalid --format value '{}'",
+                        executable_str, arg
+                    ))
+                }
+            };
+            continue;
+        }
+        if param_str.starts_with("--hex-context=") {
+            let (_, arg) = param_str.split_once('=').unwrap();
+            // Without any context, there would be no bytes to dump.
+            let context = parse_byte_quantity(arg)
+                .filter(|&context| context > 0)
+                .ok_or_else(|| {
+                    format!("{}: invalid --hex-context value '{}'", executable_str, arg)
+                })?;
+            params.hex_context = Some(context);
+            continue;
+        }
         if param == "--help" {
-            println!("{}", usage_string(&executable_str));
+            print!(
+                "Usage: {executable_str} [OPTION]... FILE1 [FILE2 [SKIP1 [SKIP2]]]\n{OPTIONS_HELP}"
+            );
+            std::process::exit(0);
+        }
+        if param == "-v" || param == "--version" {
+            println!("cmp (uutils diffutils) {}", env!("CARGO_PKG_VERSION"));
             std::process::exit(0);
         }
         if param_str.starts_with('-') {
@@ -240,6 +264,12 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
             executable_str
         ));
     }
+    if params.verbose && params.hex_context.is_some() {
+        return Err(format!(
+            "{}: options -l and --hex-context are incompatible",
+            executable_str
+        ));
+    }
 
     params.from = if let Some(from) = from {
         from
@@ -277,34 +307,41 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
     Ok(params)
 }
 
+// Inputs are read in chunks this large, so that big files are compared with
+// few reads.
+const BUFFER_SIZE: usize = 256 * 1024;
+
 fn prepare_reader(
     path: &OsString,
     skip: &Option<usize>,
     params: &Params,
 ) -> Result<Box<dyn BufRead>, String> {
+    let fail = |e: &io::Error| format_failure_to_read_input_file(&params.executable, path, e);
+    let mut skip = skip.unwrap_or(0) as u64;
     let mut reader: Box<dyn BufRead> = if path == "-" {
-        Box::new(BufReader::new(io::stdin()))
+        Box::new(BufReader::with_capacity(BUFFER_SIZE, io::stdin()))
     } else {
-        match fs::File::open(path) {
-            Ok(file) => Box::new(BufReader::new(file)),
-            Err(e) => {
-                return Err(format_failure_to_read_input_file(
-                    &params.executable,
-                    path,
-                    &e,
-                ));
+        let mut file = fs::File::open(path).map_err(|e| fail(&e))?;
+        // Seek past the skipped bytes of regular files, instead of reading them.
+        if skip > 0 {
+            if let Ok(meta) = file.metadata() {
+                if meta.is_file() {
+                    file.seek(SeekFrom::Start(cmp::min(skip, meta.len())))
+                        .map_err(|e| fail(&e))?;
+                    skip = 0;
+                }
             }
         }
+        Box::new(BufReader::with_capacity(BUFFER_SIZE, file))
     };
 
-    if let Some(skip) = skip {
-        if let Err(e) = io::copy(&mut reader.by_ref().take(*skip as u64), &mut io::sink()) {
-            return Err(format_failure_to_read_input_file(
-                &params.executable,
-                path,
-                &e,
-            ));
-        }
+    if skip > 0 {
+        io::copy(&mut reader.by_ref().take(skip), &mut io::sink()).map_err(|e| fail(&e))?;
+    }
+
+    // Don't read past the bytes we compare.
+    if let Some(max_bytes) = params.max_bytes {
+        reader = Box::new(reader.take(max_bytes as u64));
     }
 
     Ok(reader)
@@ -316,29 +353,54 @@ pub enum Cmp {
     Different,
 }
 
-pub fn cmp(params: &Params) -> Result<Cmp, String> {
-    let mut from = prepare_reader(&params.from, &params.skip_a, params)?;
-    let mut to = prepare_reader(&params.to, &params.skip_b, params)?;
+// The number of bytes of a file that are compared, past the skipped ones and
+// up to --bytes.
+fn compared_size(size: u64, skip: &Option<usize>, params: &Params) -> usize {
+    let size = (size as usize).saturating_sub(skip.unwrap_or(0));
+    cmp::min(size, params.max_bytes.unwrap_or(usize::MAX))
+}
 
-    let mut offset_width = params.max_bytes.unwrap_or(usize::MAX);
+// The size of a regular file. Pipes, FIFOs and devices have no size, and
+// standard input is not a file named "-".
+fn regular_file_size(path: &OsString) -> Option<u64> {
+    if path == "-" {
+        return None;
+    }
+    let meta = fs::metadata(path).ok().filter(|meta| meta.is_file())?;
 
-    if let (Ok(a_meta), Ok(b_meta)) = (fs::metadata(&params.from), fs::metadata(&params.to)) {
-        #[cfg(not(target_os = "windows"))]
-        let (a_size, b_size) = (a_meta.size(), b_meta.size());
+    #[cfg(not(target_os = "windows"))]
+    let size = meta.size();
+
+    #[cfg(target_os = "windows")]
+    let size = meta.file_size();
+
+    Some(size)
+}
+
+pub fn cmp(params: &Params) -> Result<Cmp, String> {
+    // Like GNU cmp, offsets are at most as wide as the largest file offset.
+    let mut offset_width = cmp::min(params.max_bytes.unwrap_or(usize::MAX), i64::MAX as usize);
 
-        #[cfg(target_os = "windows")]
-        let (a_size, b_size) = (a_meta.file_size(), b_meta.file_size());
+    let a_size =
+        regular_file_size(&params.from).map(|size| compared_size(size, &params.skip_a, params));
+    let b_size =
+        regular_file_size(&params.to).map(|size| compared_size(size, &params.skip_b, params));
 
-        // If the files have different sizes, we already know they are not identical. If we have not
-        // been asked to show even the first difference, we can quit early.
+    // If the files have different sizes, we already know they are not identical. If we have not
+    // been asked to show even the first difference, we can quit early, without reading them.
+    if let (Some(a_size), Some(b_size)) = (a_size, b_size) {
         if params.quiet && a_size != b_size {
             return Ok(Cmp::Different);
         }
+    }
 
-        let smaller = cmp::min(a_size, b_size) as usize;
-        offset_width = cmp::min(smaller, offset_width);
+    for size in [a_size, b_size].into_iter().flatten() {
+        offset_width = cmp::min(size, offset_width);
     }
 
+    let mut from = prepare_reader(&params.from, &params.skip_a, params)?;
+    let mut to = prepare_reader(&params.to, &params.skip_b, params)?;
+
     let offset_width = 1 + offset_width.checked_ilog10().unwrap_or(1) as usize;
 
     // Capacity calc: at_byte width + 2 x 3-byte octal numbers + 2 x 4-byte value + 4 spaces
@@ -349,6 +411,8 @@ pub fn cmp(params: &Params) -> Result<Cmp, String> {
     let mut start_of_line = true;
     let mut stdout = BufWriter::new(io::stdout().lock());
     let mut compare = Cmp::Equal;
+    // The last bytes before the first difference, for --hex-context.
+    let mut history = Vec::new();
     loop {
         // Fill up our buffers.
         let from_buf = match from.fill_buf() {
@@ -390,54 +454,67 @@ pub fn cmp(params: &Params) -> Result<Cmp, String> {
         }
 
         // Fast path - for long files in which almost all bytes are the same we
-        // can do a direct comparison to let the compiler optimize.
+        // can do a direct comparison to let the compiler optimize, and only
+        // look at single bytes from the first difference on.
         let consumed = std::cmp::min(from_buf.len(), to_buf.len());
-        if from_buf[..consumed] == to_buf[..consumed] {
-            let last = from_buf[..consumed].last().unwrap();
-
-            at_byte += consumed;
-            at_line += from_buf[..consumed].iter().filter(|&c| *c == b'\n').count();
-
-            start_of_line = *last == b'\n';
-
-            if let Some(max_bytes) = params.max_bytes {
-                if at_byte > max_bytes {
+        let mut at = 0;
+        while at < consumed {
+            let same = if at == 0 && from_buf[..consumed] == to_buf[..consumed] {
+                consumed
+            } else {
+                common_prefix(&from_buf[at..consumed], &to_buf[at..consumed])
+            };
+            if same > 0 {
+                let run = &from_buf[at..at + same];
+                at_byte += same;
+                at_line += run.iter().filter(|&c| *c == b'\n').count();
+                start_of_line = run[same - 1] == b'\n';
+                if let Some(context) = params.hex_context {
+                    history.extend_from_slice(&run[same.saturating_sub(context)..]);
+                    history.drain(..history.len().saturating_sub(context));
+                }
+                at += same;
+                if at == consumed {
                     break;
                 }
             }
 
-            from.consume(consumed);
-            to.consume(consumed);
-
-            continue;
-        }
-
-        // Iterate over the buffers, the zip iterator will stop us as soon as the
-        // first one runs out.
-        for (&from_byte, &to_byte) in from_buf.iter().zip(to_buf.iter()) {
-            if from_byte != to_byte {
-                compare = Cmp::Different;
-
-                if params.verbose {
-                    format_verbose_difference(
-                        from_byte,
-                        to_byte,
-                        at_byte,
-                        offset_width,
-                        &mut output,
-                        params,
-                    )?;
-                    stdout.write_all(output.as_slice()).map_err(|e| {
-                        format!(
-                            "{}: error printing output: {e}",
-                            params.executable.to_string_lossy()
-                        )
-                    })?;
-                    output.clear();
-                } else {
-                    report_difference(from_byte, to_byte, at_byte, at_line, params);
-                    return Ok(Cmp::Different);
+            let (from_byte, to_byte) = (from_buf[at], to_buf[at]);
+            compare = Cmp::Different;
+
+            if params.verbose {
+                format_verbose_difference(
+                    from_byte,
+                    to_byte,
+                    at_byte,
+                    offset_width,
+                    &mut output,
+                    params,
+                )?;
+                stdout.write_all(output.as_slice()).map_err(|e| {
+                    format!(
+                        "{}: error printing output: {e}",
+                        params.executable.to_string_lossy()
+                    )
+                })?;
+                output.clear();
+            } else {
+                report_difference(from_byte, to_byte, at_byte, at_line, params);
+                if let (Some(context), false) = (params.hex_context, params.quiet) {
+                    let from_after = from_buf[at..].to_vec();
+                    let to_after = to_buf[at..].to_vec();
+                    from.consume(at + from_after.len());
+                    to.consume(at + to_after.len());
+                    let fail = |path, e: &io::Error| {
+                        format_failure_to_read_input_file(&params.executable, path, e)
+                    };
+                    let from_after = read_context(&mut from, from_after, context)
+                        .map_err(|e| fail(&params.from, &e))?;
+                    let to_after = read_context(&mut to, to_after, context)
+                        .map_err(|e| fail(&params.to, &e))?;
+                    report_hex_context(&history, &from_after, &to_after, at_byte, params);
                 }
+                return Ok(Cmp::Different);
             }
 
             start_of_line = from_byte == b'\n';
@@ -446,12 +523,7 @@ pub fn cmp(params: &Params) -> Result<Cmp, String> {
             }
 
             at_byte += 1;
-
-            if let Some(max_bytes) = params.max_bytes {
-                if at_byte > max_bytes {
-                    break;
-                }
-            }
+            at += 1;
         }
 
         // Notify our readers about the bytes we went over.
@@ -462,6 +534,25 @@ pub fn cmp(params: &Params) -> Result<Cmp, String> {
     Ok(compare)
 }
 
+// The length of the common prefix of two buffers. Whole chunks are compared
+// first, which the compiler turns into wide comparisons, and single bytes
+// only in the chunk that differs.
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    const CHUNK: usize = 32;
+    let chunks = a
+        .chunks_exact(CHUNK)
+        .zip(b.chunks_exact(CHUNK))
+        .take_while(|(a, b)| a == b)
+        .count();
+    let start = chunks * CHUNK;
+    start
+        + a[start..]
+            .iter()
+            .zip(&b[start..])
+            .take_while(|(a, b)| a == b)
+            .count()
+}
+
 // Exit codes are documented at
 // https://www.gnu.org/software/diffutils/manual/html_node/Invoking-cmp.html
 //     An exit status of 0 means no differences were found,
@@ -476,9 +567,13 @@ pub fn main(opts: Peekable<ArgsOs>) -> ExitCode {
         }
     };
 
-    if params.from == "-" && params.to == "-"
-        || same_file::is_same_file(&params.from, &params.to).unwrap_or(false)
-    {
+    // A file is the same as itself, unless different bytes of it are skipped.
+    let same_input = if params.from == "-" || params.to == "-" {
+        params.from == params.to
+    } else {
+        is_same_file(&params.from, &params.to)
+    };
+    if same_input && params.skip_a.unwrap_or(0) == params.skip_b.unwrap_or(0) {
         return ExitCode::SUCCESS;
     }
 
@@ -486,6 +581,7 @@ pub fn main(opts: Peekable<ArgsOs>) -> ExitCode {
         Ok(Cmp::Equal) => ExitCode::SUCCESS,
         Ok(Cmp::Different) => ExitCode::from(1),
         Err(e) => {
+            // Like GNU cmp, -s silences the errors too.
             if !params.quiet {
                 eprintln!("{e}");
             }
@@ -500,22 +596,32 @@ fn is_ascii_printable(byte: u8) -> bool {
     c.is_ascii() && !c.is_ascii_control()
 }
 
+// Octal and decimal values are padded with spaces to 3 columns, like GNU cmp
+// does, and hexadecimal ones with zeros to 2 columns.
 #[inline]
-fn format_octal(byte: u8, buf: &mut [u8; 3]) -> &str {
+fn format_value(byte: u8, format: ValueFormat, buf: &mut [u8; 3]) -> &str {
+    let (radix, start) = match format {
+        ValueFormat::Octal => (8, 0),
+        ValueFormat::Decimal => (10, 0),
+        ValueFormat::Hexadecimal => (16, 1),
+    };
     *buf = [b' ', b' ', b'0'];
+    if start == 1 {
+        buf[1] = b'0';
+    }
 
     let mut num = byte;
     let mut idx = 2; // Start at the last position in the buffer
 
-    // Generate octal digits
+    // Generate the digits
     while num > 0 {
-        buf[idx] = b'0' + num % 8;
-        num /= 8;
+        buf[idx] = b"0123456789abcdef"[(num % radix) as usize];
+        num /= radix;
         idx = idx.saturating_sub(1);
     }
 
     // SAFETY: the operations we do above always land within ascii range.
-    unsafe { std::str::from_utf8_unchecked(&buf[..]) }
+    unsafe { std::str::from_utf8_unchecked(&buf[start..]) }
 }
 
 #[inline]
@@ -562,8 +668,9 @@ fn format_verbose_difference(
     assert!(!params.quiet);
 
     let mut at_byte_buf = itoa::Buffer::new();
-    let mut from_oct = [0u8; 3]; // for octal conversions
-    let mut to_oct = [0u8; 3];
+    let mut from_value = [0u8; 3]; // for value conversions
+    let mut to_value = [0u8; 3];
+    let format = params.value_format;
 
     if params.print_bytes {
         // "{:>width$} {:>3o} {:4} {:>3o} {}",
@@ -578,7 +685,7 @@ fn format_verbose_difference(
 
         output.push(b' ');
 
-        output.extend_from_slice(format_octal(from_byte, &mut from_oct).as_bytes());
+        output.extend_from_slice(format_value(from_byte, format, &mut from_value).as_bytes());
 
         output.push(b' ');
 
@@ -593,7 +700,7 @@ fn format_verbose_difference(
 
         output.push(b' ');
 
-        output.extend_from_slice(format_octal(to_byte, &mut to_oct).as_bytes());
+        output.extend_from_slice(format_value(to_byte, format, &mut to_value).as_bytes());
 
         output.push(b' ');
 
@@ -613,11 +720,11 @@ fn format_verbose_difference(
 
         output.push(b' ');
 
-        output.extend_from_slice(format_octal(from_byte, &mut from_oct).as_bytes());
+        output.extend_from_slice(format_value(from_byte, format, &mut from_value).as_bytes());
 
         output.push(b' ');
 
-        output.extend_from_slice(format_octal(to_byte, &mut to_oct).as_bytes());
+        output.extend_from_slice(format_value(to_byte, format, &mut to_value).as_bytes());
 
         output.push(b'\n');
     }
@@ -697,17 +804,78 @@ fn report_difference(from_byte: u8, to_byte: u8, at_byte: usize, at_line: usize,
     );
     if params.print_bytes {
         let char_width = if to_byte >= 0x7F { 2 } else { 1 };
+        let (mut from_buf, mut to_buf) = ([0u8; 3], [0u8; 3]);
         print!(
-            " is {:>3o} {:char_width$} {:>3o} {:char_width$}",
-            from_byte,
+            " is {} {:char_width$} {} {:char_width$}",
+            format_value(from_byte, params.value_format, &mut from_buf),
             format_byte(from_byte),
-            to_byte,
+            format_value(to_byte, params.value_format, &mut to_buf),
             format_byte(to_byte)
         );
     }
     println!();
 }
 
+// Completes the first `context` bytes from a difference on, of which the
+// reader has already given `bytes`.
+fn read_context(
+    reader: &mut dyn BufRead,
+    mut bytes: Vec<u8>,
+    context: usize,
+) -> io::Result<Vec<u8>> {
+    bytes.truncate(context);
+    let missing = context - bytes.len();
+    reader.take(missing as u64).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Writes the bytes around a difference like `hexdump -C`, with the offsets of
+// each file.
+fn format_hex_context(output: &mut String, mut offset: usize, bytes: &[u8]) {
+    for line in bytes.chunks(16) {
+        output.push_str(&format!("{offset:08x} "));
+        for (i, byte) in line.iter().enumerate() {
+            if i == 8 {
+                output.push(' ');
+            }
+            output.push_str(&format!(" {byte:02x}"));
+        }
+        let missing = 16 - line.len();
+        output.push_str(&" ".repeat(missing * 3 + usize::from(line.len() <= 8)));
+        output.push_str("  |");
+        for &byte in line {
+            output.push(if is_ascii_printable(byte) {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        output.push_str("|\n");
+        offset += line.len();
+    }
+}
+
+// Prints the bytes of both files around the first difference, for
+// --hex-context. The bytes before the difference are the same in both files.
+fn report_hex_context(
+    before: &[u8],
+    from_after: &[u8],
+    to_after: &[u8],
+    at_byte: usize,
+    params: &Params,
+) {
+    let mut output = String::new();
+    for (path, skip, after) in [
+        (&params.from, params.skip_a, from_after),
+        (&params.to, params.skip_b, to_after),
+    ] {
+        let offset = skip.unwrap_or(0).saturating_add(at_byte - 1 - before.len());
+        output.push_str(&format!("{}:\n", path.to_string_lossy()));
+        format_hex_context(&mut output, offset, &[before, after].concat());
+    }
+    print!("{output}");
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -969,11 +1137,27 @@ mod tests {
             )
         );
 
+        assert_eq!(
+            Ok(Params {
+                executable: os("cmp"),
+                from: os("foo"),
+                to: os("bar"),
+                max_bytes: Some(0x1000 * 1024),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("cmp"), os("-n"), os("0x1000K"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+
         // Failure case
         assert_eq!(
-            Err("cmp: invalid --bytes value '1K'".to_string()),
+            Err("cmp: invalid --bytes value '1Q'".to_string()),
             parse_params(
-                [os("cmp"), os("--bytes=1K"), os("foo"), os("bar")]
+                [os("cmp"), os("--bytes=1Q"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
@@ -1176,4 +1360,114 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn common_prefixes() {
+        let a: Vec<u8> = (0..100).collect();
+        assert_eq!(common_prefix(&a, &a), 100);
+        assert_eq!(common_prefix(&a, &a[..70]), 70);
+        assert_eq!(common_prefix(&a, &[]), 0);
+        for at in [0, 1, 31, 32, 33, 64, 99] {
+            let mut b = a.clone();
+            b[at] = 200;
+            assert_eq!(common_prefix(&a, &b), at);
+        }
+    }
+
+    #[test]
+    fn hex_context() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("cmp"),
+                from: os("foo"),
+                to: os("bar"),
+                hex_context: Some(16),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("cmp"), os("--hex-context=0x10"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        for value in ["x", "0"] {
+            assert_eq!(
+                Err(format!("cmp: invalid --hex-context value '{value}'")),
+                parse_params(
+                    [
+                        os("cmp"),
+                        OsString::from(format!("--hex-context={value}")),
+                        os("foo"),
+                        os("bar")
+                    ]
+                    .iter()
+                    .cloned()
+                    .peekable()
+                )
+            );
+        }
+        assert_eq!(
+            Err("cmp: options -l and --hex-context are incompatible".to_string()),
+            parse_params(
+                [
+                    os("cmp"),
+                    os("-l"),
+                    os("--hex-context=4"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+
+        let mut output = String::new();
+        format_hex_context(&mut output, 0x1e, b"0123456789abcdef\x00\n\x7f");
+        assert_eq!(
+            output,
+            "0000001e  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             0000002e  00 0a 7f                                          |...|\n"
+        );
+    }
+
+    #[test]
+    fn value_formats() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("cmp"),
+                from: os("foo"),
+                to: os("bar"),
+                value_format: ValueFormat::Hexadecimal,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("cmp"), os("--format=hex"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Err("cmp: invalid --format value 'binary'".to_string()),
+            parse_params(
+                [os("cmp"), os("--format=binary"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+
+        let mut buf = [0u8; 3];
+        for (byte, octal, hex, decimal) in [
+            (0, "  0", "00", "  0"),
+            (10, " 12", "0a", " 10"),
+            (255, "377", "ff", "255"),
+        ] {
+            assert_eq!(format_value(byte, ValueFormat::Octal, &mut buf), octal);
+            assert_eq!(format_value(byte, ValueFormat::Hexadecimal, &mut buf), hex);
+            assert_eq!(format_value(byte, ValueFormat::Decimal, &mut buf), decimal);
+        }
+    }
 }
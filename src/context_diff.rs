@@ -3,12 +3,13 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use std::collections::VecDeque;
-use std::io::Write;
+use std::io::{self, Write};
+use std::ops::Range;
 
+use crate::hunks::Diff;
+use crate::palette::ColorClass;
 use crate::params::Params;
-use crate::utils::do_write_line;
-use crate::utils::get_modification_time;
+use crate::utils::{file_header, write_colored_line, write_marked_line, CONTEXT_TIME_FORMAT};
 
 #[derive(Debug, PartialEq)]
 pub enum DiffLine {
@@ -44,248 +45,130 @@ impl Mismatch {
     }
 }
 
-// Produces a diff between the expected output and actual output.
-fn make_diff(
-    expected: &[u8],
-    actual: &[u8],
-    context_size: usize,
-    stop_early: bool,
-) -> Vec<Mismatch> {
-    let mut line_number_expected = 1;
-    let mut line_number_actual = 1;
-    let mut context_queue: VecDeque<&[u8]> = VecDeque::with_capacity(context_size);
-    let mut lines_since_mismatch = context_size + 1;
-    let mut results = Vec::new();
-    let mut mismatch = Mismatch::new(0, 0);
-
-    let mut expected_lines: Vec<&[u8]> = expected.split(|&c| c == b'\n').collect();
-    let mut actual_lines: Vec<&[u8]> = actual.split(|&c| c == b'\n').collect();
-
-    debug_assert_eq!(b"".split(|&c| c == b'\n').count(), 1);
-    // ^ means that underflow here is impossible
-    let expected_lines_count = expected_lines.len() - 1;
-    let actual_lines_count = actual_lines.len() - 1;
-
-    if expected_lines.last() == Some(&&b""[..]) {
-        expected_lines.pop();
+// Splits off the trailing newline of a line, telling whether it was missing.
+fn strip_newline(line: &[u8]) -> (Vec<u8>, bool) {
+    match line.strip_suffix(b"\n") {
+        Some(line) => (line.to_vec(), false),
+        None => (line.to_vec(), true),
     }
+}
 
-    if actual_lines.last() == Some(&&b""[..]) {
-        actual_lines.pop();
+// Pushes lines to one side of a mismatch, noting if the last one is missing
+// its newline.
+fn push_lines(
+    side: &mut Vec<DiffLine>,
+    missing_nl: &mut bool,
+    lines: &[&[u8]],
+    make_line: fn(Vec<u8>) -> DiffLine,
+) {
+    for line in lines {
+        let (line, missing) = strip_newline(line);
+        side.push(make_line(line));
+        *missing_nl |= missing;
     }
+}
 
-    // Rust only allows allocations to grow to isize::MAX, and this is bigger than that.
-    let mut expected_lines_change_idx: usize = !0;
-
-    for result in diff::slice(&expected_lines, &actual_lines) {
-        match result {
-            diff::Result::Left(str) => {
-                if lines_since_mismatch > context_size && lines_since_mismatch > 0 {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(
-                        line_number_expected - context_queue.len(),
-                        line_number_actual - context_queue.len(),
-                    );
-                }
-
-                while let Some(line) = context_queue.pop_front() {
-                    mismatch.expected.push(DiffLine::Context(line.to_vec()));
-                    mismatch.actual.push(DiffLine::Context(line.to_vec()));
-                }
-
-                expected_lines_change_idx = mismatch.expected.len();
-                mismatch.expected.push(DiffLine::Add(str.to_vec()));
-                if line_number_expected > expected_lines_count {
-                    mismatch.expected_missing_nl = true;
-                }
-                line_number_expected += 1;
-                lines_since_mismatch = 0;
-            }
-            diff::Result::Right(str) => {
-                if lines_since_mismatch > context_size && lines_since_mismatch > 0 {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(
-                        line_number_expected - context_queue.len(),
-                        line_number_actual - context_queue.len(),
-                    );
-                    expected_lines_change_idx = !0;
-                }
-
-                while let Some(line) = context_queue.pop_front() {
-                    mismatch.expected.push(DiffLine::Context(line.to_vec()));
-                    mismatch.actual.push(DiffLine::Context(line.to_vec()));
-                }
-
-                if let Some(DiffLine::Add(content)) =
-                    mismatch.expected.get_mut(expected_lines_change_idx)
-                {
-                    let content = std::mem::take(content);
-                    mismatch.expected[expected_lines_change_idx] = DiffLine::Change(content);
-                    expected_lines_change_idx = expected_lines_change_idx.wrapping_sub(1); // if 0, becomes !0
-                    mismatch.actual.push(DiffLine::Change(str.to_vec()));
-                } else {
-                    mismatch.actual.push(DiffLine::Add(str.to_vec()));
-                }
-                if line_number_actual > actual_lines_count {
-                    mismatch.actual_missing_nl = true;
-                }
-                line_number_actual += 1;
-                lines_since_mismatch = 0;
-            }
-            diff::Result::Both(str, _) => {
-                expected_lines_change_idx = !0;
-                // if one of them is missing a newline and the other isn't, then they don't actually match
-                if (line_number_actual > actual_lines_count)
-                    && (line_number_expected > expected_lines_count)
-                {
-                    if context_queue.len() < context_size {
-                        while let Some(line) = context_queue.pop_front() {
-                            mismatch.expected.push(DiffLine::Context(line.to_vec()));
-                            mismatch.actual.push(DiffLine::Context(line.to_vec()));
-                        }
-                        if lines_since_mismatch < context_size {
-                            mismatch.expected.push(DiffLine::Context(str.to_vec()));
-                            mismatch.actual.push(DiffLine::Context(str.to_vec()));
-                            mismatch.expected_missing_nl = true;
-                            mismatch.actual_missing_nl = true;
-                        }
-                    }
-                    lines_since_mismatch = 0;
-                } else if line_number_actual > actual_lines_count {
-                    if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                        results.push(mismatch);
-                        mismatch = Mismatch::new(
-                            line_number_expected - context_queue.len(),
-                            line_number_actual - context_queue.len(),
-                        );
-                    }
-                    while let Some(line) = context_queue.pop_front() {
-                        mismatch.expected.push(DiffLine::Context(line.to_vec()));
-                        mismatch.actual.push(DiffLine::Context(line.to_vec()));
-                    }
-                    mismatch.expected.push(DiffLine::Change(str.to_vec()));
-                    mismatch.actual.push(DiffLine::Change(str.to_vec()));
-                    mismatch.actual_missing_nl = true;
-                    lines_since_mismatch = 0;
-                } else if line_number_expected > expected_lines_count {
-                    if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                        results.push(mismatch);
-                        mismatch = Mismatch::new(
-                            line_number_expected - context_queue.len(),
-                            line_number_actual - context_queue.len(),
-                        );
-                    }
-                    while let Some(line) = context_queue.pop_front() {
-                        mismatch.expected.push(DiffLine::Context(line.to_vec()));
-                        mismatch.actual.push(DiffLine::Context(line.to_vec()));
-                    }
-                    mismatch.expected.push(DiffLine::Change(str.to_vec()));
-                    mismatch.expected_missing_nl = true;
-                    mismatch.actual.push(DiffLine::Change(str.to_vec()));
-                    lines_since_mismatch = 0;
+// Collects the lines of the hunks of a diff, with their context.
+fn make_diff(diff: &Diff) -> Vec<Mismatch> {
+    let (expected_lines, actual_lines) = (&diff.expected_lines, &diff.actual_lines);
+    let mut results = Vec::new();
+    for hunk in &diff.hunks {
+        let mut mismatch = Mismatch::new(hunk.expected.start + 1, hunk.actual.start + 1);
+        let push_context = |mismatch: &mut Mismatch, expected: Range<usize>, actual| {
+            push_lines(
+                &mut mismatch.expected,
+                &mut mismatch.expected_missing_nl,
+                &expected_lines[expected],
+                DiffLine::Context,
+            );
+            push_lines(
+                &mut mismatch.actual,
+                &mut mismatch.actual_missing_nl,
+                &actual_lines[actual],
+                DiffLine::Context,
+            );
+        };
+        let mut line_expected = hunk.expected.start;
+        let mut line_actual = hunk.actual.start;
+        for change in &hunk.changes {
+            push_context(
+                &mut mismatch,
+                line_expected..change.expected.start,
+                line_actual..change.actual.start,
+            );
+            // A group with lines on both sides is a change ('!'); otherwise
+            // it's a plain deletion ('-') or insertion ('+').
+            let make_line: fn(Vec<u8>) -> DiffLine =
+                if change.expected.is_empty() || change.actual.is_empty() {
+                    DiffLine::Add
                 } else {
-                    debug_assert!(context_queue.len() <= context_size);
-                    if context_queue.len() >= context_size {
-                        let _ = context_queue.pop_front();
-                    }
-                    if lines_since_mismatch < context_size {
-                        mismatch.expected.push(DiffLine::Context(str.to_vec()));
-                        mismatch.actual.push(DiffLine::Context(str.to_vec()));
-                    } else if context_size > 0 {
-                        context_queue.push_back(str);
-                    }
-                    lines_since_mismatch += 1;
-                }
-                line_number_expected += 1;
-                line_number_actual += 1;
-            }
-        }
-        if stop_early && !results.is_empty() {
-            // Optimization: stop analyzing the files as soon as there are any differences
-            return results;
-        }
-    }
-
-    results.push(mismatch);
-    results.remove(0);
-
-    if results.is_empty() && expected_lines_count != actual_lines_count {
-        let mut mismatch = Mismatch::new(expected_lines.len(), actual_lines.len());
-        // empty diff and only expected lines has a missing line at end
-        if expected_lines_count != expected_lines.len() {
-            mismatch.expected.push(DiffLine::Change(
-                expected_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.expected_missing_nl = true;
-            mismatch.actual.push(DiffLine::Change(
-                actual_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            results.push(mismatch);
-        } else if actual_lines_count != actual_lines.len() {
-            mismatch.expected.push(DiffLine::Change(
-                expected_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.actual.push(DiffLine::Change(
-                actual_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.actual_missing_nl = true;
-            results.push(mismatch);
+                    DiffLine::Change
+                };
+            push_lines(
+                &mut mismatch.expected,
+                &mut mismatch.expected_missing_nl,
+                &expected_lines[change.expected.clone()],
+                make_line,
+            );
+            push_lines(
+                &mut mismatch.actual,
+                &mut mismatch.actual_missing_nl,
+                &actual_lines[change.actual.clone()],
+                make_line,
+            );
+            line_expected = change.expected.end;
+            line_actual = change.actual.end;
         }
-    }
+        push_context(
+            &mut mismatch,
+            line_expected..hunk.expected.end,
+            line_actual..hunk.actual.end,
+        );
 
-    // hunks with pure context lines get truncated to empty
-    for mismatch in &mut results {
-        if !mismatch
+        // hunks with pure context lines get truncated to empty
+        mismatch.expected_all_context = mismatch
             .expected
             .iter()
-            .any(|x| !matches!(&x, DiffLine::Context(_)))
-        {
-            mismatch.expected_all_context = true;
-        }
-        if !mismatch
+            .all(|x| matches!(&x, DiffLine::Context(_)));
+        mismatch.actual_all_context = mismatch
             .actual
             .iter()
-            .any(|x| !matches!(&x, DiffLine::Context(_)))
-        {
-            mismatch.actual_all_context = true;
-        }
+            .all(|x| matches!(&x, DiffLine::Context(_)));
+        results.push(mismatch);
     }
 
     results
 }
 
-#[must_use]
-pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
-    let from_modified_time = get_modification_time(&params.from.to_string_lossy());
-    let to_modified_time = get_modification_time(&params.to.to_string_lossy());
-    let mut output = format!(
-        "*** {0}\t{1}\n--- {2}\t{3}\n",
-        params.from.to_string_lossy(),
-        from_modified_time,
-        params.to.to_string_lossy(),
-        to_modified_time
-    )
-    .into_bytes();
-    let diff_results = make_diff(expected, actual, params.context_count, params.brief);
-    if diff_results.is_empty() {
-        return Vec::new();
+/// Writes the differences in the context format: the headers of both files,
+/// then each hunk as its expected lines followed by its actual lines, each
+/// with the context around the changes.
+pub fn diff(output: &mut impl Write, diff: &Diff, params: &Params) -> io::Result<()> {
+    if diff.hunks.is_empty() {
+        return Ok(());
     }
+    write_colored_line(
+        output,
+        ColorClass::Header,
+        format_args!("*** {}", file_header(params, true, CONTEXT_TIME_FORMAT)),
+        params,
+    )?;
+    write_colored_line(
+        output,
+        ColorClass::Header,
+        format_args!("--- {}", file_header(params, false, CONTEXT_TIME_FORMAT)),
+        params,
+    )?;
     if params.brief {
-        return output;
+        return Ok(());
     }
-    for result in diff_results {
+    // With -T, a tab separates the markers from the lines.
+    let [context_marker, change_marker, removed_marker, added_marker] = if params.initial_tab {
+        [" \t", "!\t", "-\t", "+\t"]
+    } else {
+        ["  ", "! ", "- ", "+ "]
+    };
+    for result in make_diff(diff) {
         let mut line_number_expected = result.line_number_expected;
         let mut line_number_actual = result.line_number_actual;
         let mut expected_count = result.expected.len();
@@ -310,77 +193,75 @@ pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
         } else {
             format!("{line_number_actual},")
         };
-        writeln!(
+        writeln!(output, "***************")?;
+        write_colored_line(
             output,
-            "***************\n*** {exp_start}{end_line_number_expected} ****"
-        )
-        .expect("write to Vec is infallible");
+            ColorClass::LineNumbers,
+            format_args!("*** {exp_start}{end_line_number_expected} ****"),
+            params,
+        )?;
+        // Unlike in the unified format, context lines are colored too.
         if !result.expected_all_context {
+            let class = Some(ColorClass::Deleted);
             for line in result.expected {
                 match line {
                     DiffLine::Context(e) => {
-                        write!(output, "  ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, context_marker, &e, class, params)?;
                     }
                     DiffLine::Change(e) => {
-                        write!(output, "! ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, change_marker, &e, class, params)?;
                     }
                     DiffLine::Add(e) => {
-                        write!(output, "- ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, removed_marker, &e, class, params)?;
                     }
                 }
             }
             if result.expected_missing_nl {
-                writeln!(output, r"\ No newline at end of file")
-                    .expect("write to Vec is infallible");
+                writeln!(output, r"\ No newline at end of file")?;
             }
         }
-        writeln!(output, "--- {act_start}{end_line_number_actual} ----")
-            .expect("write to Vec is infallible");
+        write_colored_line(
+            output,
+            ColorClass::LineNumbers,
+            format_args!("--- {act_start}{end_line_number_actual} ----"),
+            params,
+        )?;
         if !result.actual_all_context {
+            let class = Some(ColorClass::Added);
             for line in result.actual {
                 match line {
                     DiffLine::Context(e) => {
-                        write!(output, "  ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, context_marker, &e, class, params)?;
                     }
                     DiffLine::Change(e) => {
-                        write!(output, "! ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, change_marker, &e, class, params)?;
                     }
                     DiffLine::Add(e) => {
-                        write!(output, "+ ").expect("write to Vec is infallible");
-                        do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                            .expect("write to Vec is infallible");
-                        writeln!(output).unwrap();
+                        write_marked_line(output, added_marker, &e, class, params)?;
                     }
                 }
             }
             if result.actual_missing_nl {
-                writeln!(output, r"\ No newline at end of file")
-                    .expect("write to Vec is infallible");
+                writeln!(output, r"\ No newline at end of file")?;
             }
         }
     }
-    output
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hunks::{self, CompareOptions};
     use pretty_assertions::assert_eq;
+
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+        let mut output = Vec::new();
+        let diff = hunks::compare(expected, actual, &CompareOptions::from(params));
+        super::diff(&mut output, &diff, params).unwrap();
+        output
+    }
+
     #[test]
     fn test_permutations() {
         // test all possible six-line files.
@@ -720,6 +601,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_change_markers() {
+        use crate::assert_diff_eq;
+
+        // A group with lines on both sides is marked as changed as a whole,
+        // even when it has more lines on one side than on the other.
+        let diff = diff(
+            b"a\nb\nc\nd\ne\nf\ng\nh\n",
+            b"x\ny\nb\nc\nd\ne\nf\ng\n",
+            &Params {
+                from: "foo".into(),
+                to: "bar".into(),
+                context_count: 1,
+                ..Default::default()
+            },
+        );
+        let expected = [
+            "*** foo\tTIMESTAMP",
+            "--- bar\tTIMESTAMP",
+            "***************",
+            "*** 1,2 ****",
+            "! a",
+            "  b",
+            "--- 1,3 ----",
+            "! x",
+            "! y",
+            "  b",
+            "***************",
+            "*** 7,8 ****",
+            "  g",
+            "- h",
+            "--- 8 ----",
+            "",
+        ]
+        .join("\n");
+        assert_diff_eq!(diff, expected);
+    }
+
     #[test]
     fn test_stop_early() {
         use crate::assert_diff_eq;
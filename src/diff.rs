@@ -3,15 +3,31 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use crate::params::{parse_params, Format};
-use crate::utils::report_failure_to_read_input_file;
-use crate::{context_diff, ed_diff, normal_diff, unified_diff};
-use std::env::ArgsOs;
-use std::ffi::OsString;
+use crate::archive::{is_archive, ExtractedArchive};
+use crate::chunked::Content;
+use crate::gitignore::{IgnoreRules, IGNORE_FILE};
+use crate::hunks::{self, CompareOptions, Diff, Hunk};
+use crate::params::{parse_params, Format, Params, StatFormat};
+use crate::side_diff::SideBySideOptions;
+use crate::stat::{write_stat, FileStat};
+use crate::utils::{
+    decompressor, expand_response_files, file_header, format_failure_to_read_input_file, is_binary,
+    is_same_file, report_failure_to_read_input_file, shell_quote, strip_ansi_escapes,
+    strip_trailing_cr, wildcard_to_regex, write_suppressed_hunks,
+};
+use crate::{context_diff, ed_diff, ifdef_diff, normal_diff, rcs_diff, side_diff, unified_diff};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env::{self, ArgsOs};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, IsTerminal, Read, Write};
 use std::iter::Peekable;
-use std::process::{exit, ExitCode};
+use std::path::Path;
+use std::process::{exit, Child, Command, ExitCode, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use regex::bytes::Regex;
 
 // Exit codes are documented at
 // https://www.gnu.org/software/diffutils/manual/html_node/Invoking-diff.html.
@@ -19,80 +35,1497 @@ use std::process::{exit, ExitCode};
 //     1 means some differences were found,
 //     and 2 means trouble.
 pub fn main(opts: Peekable<ArgsOs>) -> ExitCode {
-    let params = parse_params(opts).unwrap_or_else(|error| {
+    let args = expand_response_files(opts.collect()).unwrap_or_else(|error| {
         eprintln!("{error}");
         exit(2);
     });
-    // if from and to are the same file, no need to perform any comparison
-    let maybe_report_identical_files = || {
-        if params.report_identical_files {
-            println!(
-                "Files {} and {} are identical",
-                params.from.to_string_lossy(),
-                params.to.to_string_lossy(),
+    let params = parse_params(args.iter().cloned().peekable()).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        exit(2);
+    });
+    let comparisons = if params.batch {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|e| {
+                format_failure_to_read_input_file(
+                    &params.executable,
+                    &OsString::from("standard input"),
+                    &e,
+                )
+            })
+            .and_then(|_| params.batch_comparisons(&input))
+            .unwrap_or_else(|error| {
+                eprintln!("{error}");
+                exit(2);
+            })
+    } else {
+        params.comparisons()
+    };
+    let mut output = Output {
+        max_hunks: params.max_hunks,
+        max_lines: params.max_lines,
+        stat: params.stat,
+        pager: params.paginate.then(start_pager).flatten(),
+        keep_text: params.verify_against.is_some(),
+        ..Default::default()
+    };
+    let switches = switch_string(&args, &params);
+    let exit_code = comparisons
+        .iter()
+        .map(|params| {
+            output.write_out();
+            run(params, &switches, &mut output)
+        })
+        .max()
+        .unwrap_or(0);
+    output.finish();
+    if let Some(error) = &output.error {
+        report_failure_to_read_input_file(
+            &params.executable,
+            &OsString::from("standard output"),
+            error,
+        );
+        return ExitCode::from(2);
+    }
+    match &params.verify_against {
+        Some(reference) if !verify_against(reference, &args, &params, exit_code, &output.text) => 2,
+        _ => exit_code,
+    }
+    .into()
+}
+
+// The options given on the command line, as they are repeated in the
+// "diff ..." line that precedes each comparison of files inside directories,
+// or with --batch.
+fn switch_string(args: &[OsString], params: &Params) -> String {
+    let mut operands: Vec<&OsString> = [&params.from, &params.to]
+        .into_iter()
+        .chain(&params.operands)
+        .collect();
+    let mut switches = String::new();
+    for arg in without_own_options(args) {
+        if let Some(position) = operands.iter().position(|operand| *operand == arg) {
+            operands.remove(position);
+            continue;
+        }
+        if arg == "--" || arg == "--batch" {
+            continue;
+        }
+        switches.push(' ');
+        switches.push_str(&shell_quote(&arg.to_string_lossy()));
+    }
+    switches
+}
+
+// The arguments, without the executable and the options that GNU diff doesn't
+// have and that don't change the output (--verify-against, --jobs and
+// --paginate).
+fn without_own_options(args: &[OsString]) -> Vec<&OsString> {
+    let mut result = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg_bytes = arg.as_encoded_bytes();
+        if arg == "--jobs" {
+            args.next();
+        } else if !arg_bytes.starts_with(b"--verify-against=")
+            && !arg_bytes.starts_with(b"--jobs=")
+            && arg != "--paginate"
+        {
+            result.push(arg);
+        }
+    }
+    result
+}
+
+// Whether a file that doesn't exist on the given side should be compared as
+// if it were empty.
+fn absent_as_empty(params: &Params, from_side: bool) -> bool {
+    params.new_file || from_side && params.unidirectional_new_file
+}
+
+fn exists(path: &OsString) -> bool {
+    path == "-" || fs::metadata(path).is_ok()
+}
+
+// Whether the file exists but is neither a regular file nor a directory,
+// e.g. a FIFO, a socket or a device, which aren't compared in directories.
+fn is_special(path: &OsString) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+}
+
+// Describes the type of a file, in the words of GNU diff.
+fn file_type_name(path: &OsString) -> &'static str {
+    let Ok(metadata) = fs::metadata(path) else {
+        return "weird file";
+    };
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        return "directory";
+    }
+    if file_type.is_file() {
+        return if metadata.len() == 0 {
+            "regular empty file"
+        } else {
+            "regular file"
+        };
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return "fifo";
+        }
+        if file_type.is_socket() {
+            return "socket";
+        }
+        if file_type.is_char_device() {
+            return "character special file";
+        }
+        if file_type.is_block_device() {
+            return "block special file";
+        }
+    }
+    "weird file"
+}
+
+// Everything that is written to stdout. It is written as the comparisons go,
+// so that it comes before the errors that are reported on stderr after it,
+// and it is only kept whole for --verify-against.
+#[derive(Default)]
+struct Output {
+    text: Vec<u8>,
+    written: usize,
+    error: Option<io::Error>,
+    // With --max-hunks, the hunks that are output, and those left out.
+    max_hunks: Option<usize>,
+    hunks: usize,
+    suppressed_hunks: usize,
+    // With --max-lines, the lines that are output, and those left out, and
+    // whether the last line written is incomplete, and kept.
+    max_lines: Option<usize>,
+    lines: usize,
+    suppressed_lines: usize,
+    partial_line: bool,
+    line_kept: bool,
+    // With --paginate, the pager that the output is written to.
+    pager: Option<Child>,
+    // With --verify-against, the text is kept whole once written, to be
+    // compared with the output of the other program.
+    keep_text: bool,
+    // With --stat, --numstat or --shortstat, how much each file changed,
+    // summed up at the end.
+    stat: Option<StatFormat>,
+    stats: Vec<FileStat>,
+}
+
+impl Output {
+    // Appends the result of a comparison, and returns its exit code.
+    fn add(&mut self, comparison: Comparison) -> u8 {
+        self.write_all(&comparison.text).unwrap();
+        self.partial_line = false;
+        self.stats.extend(comparison.stat);
+        for error in comparison.errors {
+            self.report(&error);
+        }
+        comparison.exit_code
+    }
+
+    // Compares files, and returns the exit code. The differences are
+    // written as they are found.
+    fn compare(&mut self, params: &Params, header: Option<&str>) -> u8 {
+        let mut comparison = Comparison::default();
+        comparison.exit_code = compare_files(
+            params,
+            header,
+            self,
+            &mut comparison.errors,
+            &mut comparison.stat,
+        );
+        self.add(comparison)
+    }
+
+    // Appends text to the output, leaving out the lines past --max-lines.
+    fn push(&mut self, text: &[u8]) {
+        let Some(max) = self.max_lines else {
+            self.text.extend_from_slice(text);
+            return;
+        };
+        for piece in text.split_inclusive(|&c| c == b'\n') {
+            // A line written in pieces is kept or left out whole.
+            if !self.partial_line {
+                self.line_kept = self.lines < max;
+                if self.line_kept {
+                    self.lines += 1;
+                } else {
+                    self.suppressed_lines += 1;
+                }
+            }
+            if self.line_kept {
+                self.text.extend_from_slice(piece);
+            }
+            self.partial_line = !piece.ends_with(b"\n");
+        }
+    }
+
+    // Reports an error on stderr, after the output so far.
+    fn report(&mut self, error: &str) {
+        self.write_out();
+        eprintln!("{error}");
+    }
+
+    // Writes the summary of the changes, tells how many hunks --max-hunks and
+    // lines --max-lines left out, writes the rest, and waits for the pager to
+    // be quit.
+    fn finish(&mut self) {
+        if let Some(format) = self.stat {
+            write_stat(&mut self.text, &self.stats, format).unwrap();
+        }
+        write_suppressed_hunks(&mut self.text, self.suppressed_hunks).unwrap();
+        match self.suppressed_lines {
+            0 => {}
+            1 => writeln!(self.text, "... 1 more line suppressed").unwrap(),
+            n => writeln!(self.text, "... {n} more lines suppressed").unwrap(),
+        }
+        self.write_out();
+        if let Some(mut pager) = self.pager.take() {
+            // Closing its input lets the pager know that the output is over.
+            drop(pager.stdin.take());
+            let _ = pager.wait();
+            // Quitting the pager before the end of the output isn't trouble.
+            if self
+                .error
+                .as_ref()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+            {
+                self.error = None;
+            }
+        }
+    }
+
+    // Writes what hasn't been written yet. After an error, nothing more is
+    // written.
+    fn write_out(&mut self) {
+        if self.error.is_none() && self.written < self.text.len() {
+            let text = &self.text[self.written..];
+            let result = match self.pager.as_mut().and_then(|pager| pager.stdin.as_mut()) {
+                Some(pager) => pager.write_all(text).and_then(|()| pager.flush()),
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(text).and_then(|()| stdout.flush())
+                }
+            };
+            if let Err(e) = result {
+                self.error = Some(e);
+            }
+        }
+        if self.keep_text {
+            self.written = self.text.len();
+        } else {
+            self.text.clear();
+            self.written = 0;
+        }
+    }
+}
+
+// What compare_files writes goes out as it comes, a block at a time.
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push(buf);
+        if self.text.len() - self.written >= OUTPUT_BLOCK_SIZE {
+            self.write_out();
+        }
+        // Errors are kept, to be reported at the end.
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_out();
+        Ok(())
+    }
+}
+
+// Where compare_files writes the differences: with --max-hunks, it is
+// asked how many of the hunks of a comparison to keep before they're written.
+trait HunkLimit: Write {
+    fn keep_hunks(&mut self, count: usize) -> usize {
+        count
+    }
+}
+
+impl HunkLimit for Vec<u8> {}
+
+// With --max-hunks, the hunks are counted across the comparisons, and those
+// past the limit are left out.
+impl HunkLimit for Output {
+    fn keep_hunks(&mut self, count: usize) -> usize {
+        let Some(max) = self.max_hunks else {
+            return count;
+        };
+        let kept = count.min(max - self.hunks);
+        self.hunks += kept;
+        self.suppressed_hunks += count - kept;
+        kept
+    }
+}
+
+// Leaves out the hunks of a comparison that the output doesn't keep. Those of
+// the normal, ed and RCS formats are single changes, and an ed script starts
+// with the last one.
+fn limit_hunks(diff: &mut Diff, format: Format, output: &mut impl HunkLimit) {
+    let single_changes = !matches!(format, Format::Unified | Format::Context);
+    let count = if single_changes {
+        diff.changes().count()
+    } else {
+        diff.hunks.len()
+    };
+    let kept = output.keep_hunks(count);
+    if kept == count {
+        return;
+    }
+    if single_changes {
+        let changes: Vec<_> = diff.changes().cloned().collect();
+        let kept = if format == Format::Ed {
+            &changes[count - kept..]
+        } else {
+            &changes[..kept]
+        };
+        diff.hunks = kept
+            .iter()
+            .map(|change| Hunk {
+                expected: change.expected.clone(),
+                actual: change.actual.clone(),
+                changes: vec![change.clone()],
+            })
+            .collect();
+    } else {
+        diff.hunks.truncate(kept);
+    }
+}
+
+// How much of the output of a comparison is collected before being written.
+const OUTPUT_BLOCK_SIZE: usize = 1 << 16;
+
+// The result of comparing two files: what is written to stdout, then the
+// errors that are reported on stderr, and the exit code.
+#[derive(Default)]
+struct Comparison {
+    text: Vec<u8>,
+    errors: Vec<String>,
+    exit_code: u8,
+    // With --stat, --numstat or --shortstat, how much the files changed.
+    stat: Option<FileStat>,
+}
+
+impl Comparison {
+    fn new(params: &Params, header: Option<&str>) -> Self {
+        let mut comparison = Self::default();
+        comparison.exit_code = compare_files(
+            params,
+            header,
+            &mut comparison.text,
+            &mut comparison.errors,
+            &mut comparison.stat,
+        );
+        comparison
+    }
+
+    // The result of a comparison that is known without reading the files.
+    fn of_text(text: String, exit_code: u8) -> Self {
+        Self {
+            text: text.into_bytes(),
+            exit_code,
+            ..Default::default()
+        }
+    }
+}
+
+// Runs the comparison described by params, appending everything that should
+// be written to stdout to output, and returns the exit code.
+fn run(params: &Params, switches: &str, output: &mut Output) -> u8 {
+    // Standard input can only be read once.
+    if params.from == "-" && params.to == "-" {
+        eprintln!(
+            "{}: cannot compare standard input to itself",
+            params.executable.to_string_lossy()
+        );
+        return 2;
+    }
+    // With --archives, archives are compared as the directories they are
+    // extracted to, under their own names.
+    if params.archives && (is_archive(&params.from) || is_archive(&params.to)) {
+        let mut params = Params {
+            archives: false,
+            ..params.clone()
+        };
+        let mut extracted = Vec::new();
+        for (path, name) in [
+            (&mut params.from, &mut params.from_name),
+            (&mut params.to, &mut params.to_name),
+        ] {
+            *name = Some(path.to_string_lossy().to_string());
+            if !is_archive(path) {
+                continue;
+            }
+            match ExtractedArchive::new(&params.executable, path) {
+                Ok(archive) => {
+                    *path = archive.dir().into();
+                    extracted.push(archive);
+                }
+                Err(error) => {
+                    output.report(&error);
+                    return 2;
+                }
+            }
+        }
+        return run(&params, switches, output);
+    }
+    let from_is_dir = Path::new(&params.from).is_dir();
+    let to_is_dir = Path::new(&params.to).is_dir();
+    if from_is_dir && params.to == "-" || to_is_dir && params.from == "-" {
+        eprintln!(
+            "{}: cannot compare '-' to a directory",
+            params.executable.to_string_lossy()
+        );
+        return 2;
+    }
+    let from_is_new = !exists(&params.from) && absent_as_empty(params, true);
+    let to_is_new = !exists(&params.to) && absent_as_empty(params, false);
+    if (from_is_dir || from_is_new && to_is_dir) && (to_is_dir || to_is_new && from_is_dir) {
+        if params.format == Format::Ifdef {
+            eprintln!(
+                "{}: -D option not supported with directories",
+                params.executable.to_string_lossy()
             );
+            return 2;
         }
+        let walk = DirWalk::new(params, switches);
+        compare_directories(params, &walk, &IgnoreRules::default(), output)
+    } else {
+        // With --batch or --git, each pair of files is introduced like the
+        // files of directories.
+        let header = (params.batch || params.git).then(|| header(params, switches));
+        output.compare(params, header.as_deref())
+    }
+}
+
+// The "diff ..." line that precedes the differences between two files that
+// aren't given on the command line, or with --git, between any two files.
+fn header(params: &Params, switches: &str) -> String {
+    if params.git {
+        return format!(
+            "diff --git {} {}",
+            params.reported_name(true),
+            params.reported_name(false)
+        );
+    }
+    format!(
+        "diff{switches} {} {}",
+        params
+            .from_label
+            .clone()
+            .unwrap_or_else(|| params.reported_name(true)),
+        params
+            .to_label
+            .clone()
+            .unwrap_or_else(|| params.reported_name(false))
+    )
+}
+
+// What stays the same while walking directories.
+struct DirWalk<'a> {
+    switches: &'a str,
+    excluded: Option<Regex>,
+}
+
+impl<'a> DirWalk<'a> {
+    fn new(params: &Params, switches: &'a str) -> Self {
+        let excluded = (!params.exclude.is_empty()).then(|| {
+            let patterns: Vec<String> = params
+                .exclude
+                .iter()
+                .map(|pattern| wildcard_to_regex(pattern))
+                .collect();
+            let flags = if params.ignore_file_name_case {
+                "si"
+            } else {
+                "s"
+            };
+            Regex::new(&format!("^(?{flags}:{})$", patterns.join("|"))).unwrap()
+        });
+        Self { switches, excluded }
+    }
+
+    fn is_excluded(&self, name: &OsStr) -> bool {
+        self.excluded
+            .as_ref()
+            .is_some_and(|excluded| excluded.is_match(name.as_encoded_bytes()))
+    }
+}
+
+// Lists the names of the entries of a directory, in the order they are
+// reported in. A directory that doesn't exist has no entries.
+fn read_dir_names(path: &OsString) -> io::Result<BTreeSet<OsString>> {
+    match fs::read_dir(path) {
+        Ok(entries) => entries.map(|entry| Ok(entry?.file_name())).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// The key that entries are sorted and matched by. With --ignore-file-name-case,
+// names that only differ in case have the same key.
+fn name_key(name: &OsStr, ignore_case: bool) -> Vec<u8> {
+    if !ignore_case {
+        return name.as_encoded_bytes().to_vec();
+    }
+    match name.to_str() {
+        Some(name) => name.to_lowercase().into_bytes(),
+        None => name.as_encoded_bytes().to_ascii_lowercase(),
+    }
+}
+
+// Pairs up the entries of two directories by name, in the order they are
+// reported in. When names only match ignoring case, an exact match is
+// preferred, and the names that are left over are only in one directory.
+fn pair_names(
+    from_names: BTreeSet<OsString>,
+    to_names: BTreeSet<OsString>,
+    ignore_case: bool,
+) -> Vec<(Option<OsString>, Option<OsString>)> {
+    let mut groups: BTreeMap<Vec<u8>, (Vec<OsString>, Vec<OsString>)> = BTreeMap::new();
+    for name in from_names {
+        groups
+            .entry(name_key(&name, ignore_case))
+            .or_default()
+            .0
+            .push(name);
+    }
+    for name in to_names {
+        groups
+            .entry(name_key(&name, ignore_case))
+            .or_default()
+            .1
+            .push(name);
+    }
+
+    let mut pairs = Vec::new();
+    for (mut from, mut to) in groups.into_values() {
+        from.retain(|name| match to.iter().position(|other| other == name) {
+            Some(i) => {
+                pairs.push((Some(name.clone()), Some(to.remove(i))));
+                false
+            }
+            None => true,
+        });
+        let mut from = from.into_iter();
+        let mut to = to.into_iter();
+        loop {
+            match (from.next(), to.next()) {
+                (None, None) => break,
+                pair => pairs.push(pair),
+            }
+        }
+    }
+    pairs
+}
+
+// The path of an entry of a directory. Like GNU diff, the trailing slashes
+// of the directory are collapsed into one.
+fn join_path(dir: &OsStr, name: &OsStr) -> OsString {
+    let dir = dir.as_encoded_bytes();
+    let trimmed = dir.len() - dir.iter().rev().take_while(|&&c| c == b'/').count();
+    let mut path = dir[..trimmed].to_vec();
+    path.push(b'/');
+    path.extend_from_slice(name.as_encoded_bytes());
+    // SAFETY: the path is made of valid OS strings joined by an ASCII slash.
+    unsafe { OsString::from_encoded_bytes_unchecked(path) }
+}
+
+// An entry of a directory, as it is compared.
+enum Entry {
+    Compared(Comparison),
+    Directories(Params, IgnoreRules),
+    Files(Params, String),
+}
+
+// Compares two directories, skipping the entries that the rules of the
+// .gitignore files of their parents ignore.
+fn compare_directories(
+    params: &Params,
+    walk: &DirWalk,
+    ignores: &IgnoreRules,
+    output: &mut Output,
+) -> u8 {
+    // A directory has nothing to tell about itself, not even its common
+    // subdirectories.
+    if is_same_file(&params.from, &params.to) {
+        return 0;
+    }
+    let mut io_error = false;
+    let mut read_names = |path: &OsString| {
+        read_dir_names(path).unwrap_or_else(|e| {
+            output.report(&format_failure_to_read_input_file(
+                &params.executable,
+                path,
+                &e,
+            ));
+            io_error = true;
+            BTreeSet::new()
+        })
     };
-    if params.from == "-" && params.to == "-"
-        || same_file::is_same_file(&params.from, &params.to).unwrap_or(false)
+    let from_names = read_names(&params.from);
+    let to_names = read_names(&params.to);
+    if io_error {
+        return 2;
+    }
+    let mut ignores = ignores.clone();
+    if params.exclude_vcs_ignores {
+        for dir in [&params.from, &params.to] {
+            if let Ok(contents) = fs::read(join_path(dir, OsStr::new(IGNORE_FILE))) {
+                ignores.add(&contents);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    let ignore_case = params.ignore_file_name_case;
+    for (from_name, to_name) in pair_names(from_names, to_names, ignore_case) {
+        let name = from_name.as_ref().or(to_name.as_ref()).unwrap();
+        if walk.is_excluded(name)
+            || params
+                .starting_file
+                .as_ref()
+                .is_some_and(|file| name_key(name, ignore_case) < name_key(file, ignore_case))
+        {
+            continue;
+        }
+        let in_from = from_name.is_some();
+        let in_to = to_name.is_some();
+        let (from_entry_name, to_entry_name) = (params.relative || params.from_name.is_some())
+            .then(|| {
+                params.entry_names(
+                    from_name.as_ref().unwrap_or(name),
+                    to_name.as_ref().unwrap_or(name),
+                )
+            })
+            .unzip();
+        let entry_params = Params {
+            from: join_path(&params.from, from_name.as_ref().unwrap_or(name)),
+            to: join_path(&params.to, to_name.as_ref().unwrap_or(name)),
+            from_name: from_entry_name,
+            to_name: to_entry_name,
+            entry_path: Some(match &params.entry_path {
+                Some(dir) => format!("{dir}/{}", name.to_string_lossy()),
+                None => name.to_string_lossy().to_string(),
+            }),
+            // Like GNU diff, only the top-level directories start at the
+            // starting file.
+            starting_file: None,
+            ..params.clone()
+        };
+        let present = if in_from {
+            &entry_params.from
+        } else {
+            &entry_params.to
+        };
+        if ignores.ignores(&name.to_string_lossy(), Path::new(present).is_dir()) {
+            continue;
+        }
+        let entry = if !in_from && !absent_as_empty(params, true)
+            || !in_to && !absent_as_empty(params, false)
+        {
+            Entry::Compared(Comparison::of_text(
+                format!(
+                    "Only in {}: {}\n",
+                    params.shown_path(in_from),
+                    name.to_string_lossy()
+                ),
+                1,
+            ))
+        } else if let Some(Err(e)) = (in_from != in_to).then(|| fs::metadata(present)) {
+            // The entry that is missing is only compared as empty to one that
+            // can be read, which isn't the case of a dangling symbolic link.
+            Entry::Compared(Comparison {
+                errors: vec![format_failure_to_read_input_file(
+                    &params.executable,
+                    present,
+                    &e,
+                )],
+                exit_code: 2,
+                ..Default::default()
+            })
+        } else if in_from
+            && in_to
+            && (is_special(&entry_params.from)
+                || is_special(&entry_params.to)
+                || Path::new(&entry_params.from).is_dir() != Path::new(&entry_params.to).is_dir())
+        {
+            // Reading FIFOs or devices could block or never end, and a file
+            // can't be compared to a directory.
+            Entry::Compared(Comparison::of_text(
+                format!(
+                    "File {} is a {} while file {} is a {}\n",
+                    entry_params.shown_path(true),
+                    file_type_name(&entry_params.from),
+                    entry_params.shown_path(false),
+                    file_type_name(&entry_params.to)
+                ),
+                1,
+            ))
+        } else if (!in_from || Path::new(&entry_params.from).is_dir())
+            && (!in_to || Path::new(&entry_params.to).is_dir())
+        {
+            if params.recursive && params.max_depth != Some(0) {
+                Entry::Directories(
+                    Params {
+                        max_depth: params.max_depth.map(|depth| depth - 1),
+                        ..entry_params
+                    },
+                    ignores.subdirectory(&name.to_string_lossy()),
+                )
+            } else {
+                Entry::Compared(Comparison::of_text(
+                    format!(
+                        "Common subdirectories: {} and {}\n",
+                        entry_params.shown_path(true),
+                        entry_params.shown_path(false)
+                    ),
+                    0,
+                ))
+            }
+        } else {
+            let header = header(&entry_params, walk.switches);
+            // Only an entry that is missing from a listing is a new file:
+            // one that is listed but can't be read, like a dangling symbolic
+            // link, is trouble.
+            let entry_params = if in_from && in_to {
+                Params {
+                    new_file: false,
+                    unidirectional_new_file: false,
+                    ..entry_params
+                }
+            } else {
+                entry_params
+            };
+            Entry::Files(entry_params, header)
+        };
+        entries.push(entry);
+    }
+
+    // With --jobs, the files are compared beforehand, and their results are
+    // output in order. With --max-hunks, the hunks are counted as they are
+    // written, so the files are compared one after the other.
+    let mut compared = if params.jobs > 1 && params.max_hunks.is_none() {
+        compare_in_parallel(&entries, params.jobs)
+    } else {
+        Vec::new()
+    }
+    .into_iter();
+    let mut exit_code = 0;
+    for entry in entries {
+        let entry_exit_code = match entry {
+            Entry::Compared(comparison) => output.add(comparison),
+            Entry::Directories(entry_params, ignores) => {
+                compare_directories(&entry_params, walk, &ignores, output)
+            }
+            Entry::Files(entry_params, header) => match compared.next() {
+                Some(comparison) => output.add(comparison),
+                None => output.compare(&entry_params, Some(&header)),
+            },
+        };
+        exit_code = exit_code.max(entry_exit_code);
+    }
+    exit_code
+}
+
+// Compares the files of entries with up to `jobs` threads, and returns the
+// results in the order of the entries.
+fn compare_in_parallel(entries: &[Entry], jobs: usize) -> Vec<Comparison> {
+    let files: Vec<(&Params, &str)> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Files(params, header) => Some((params, header.as_str())),
+            _ => None,
+        })
+        .collect();
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Comparison)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(files.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&(params, header)) = files.get(i) else {
+                            return results;
+                        };
+                        results.push((i, Comparison::new(params, Some(header))));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|&(i, _)| i);
+    results
+        .into_iter()
+        .map(|(_, comparison)| comparison)
+        .collect()
+}
+
+// Compares two files, or a file and standard input.
+// The header, if any, is written before the differences between text files.
+// With --max-hunks, the output tells how many hunks of differences to write.
+// The errors are collected, to be reported after the output. With --stat,
+// --numstat or --shortstat, how much the files changed is returned in stat
+// instead of the differences.
+fn compare_files(
+    params: &Params,
+    header: Option<&str>,
+    output: &mut impl HunkLimit,
+    errors: &mut Vec<String>,
+    stat: &mut Option<FileStat>,
+) -> u8 {
+    // if from and to are the same file, or are assumed to be the same with
+    // --assume-unchanged-by-mtime, no need to perform any comparison (except
+    // for side-by-side and #ifdef output, which list all the lines)
+    if !matches!(params.format, Format::SideBySide | Format::Ifdef)
+        && (is_same_file(&params.from, &params.to)
+            || params.assume_unchanged_by_mtime && same_size_and_mtime(params))
     {
-        maybe_report_identical_files();
-        return ExitCode::SUCCESS;
+        maybe_report_identical_files(params, output);
+        return 0;
+    }
+
+    // With --brief, unless some differences are ignored, there is no need to
+    // read the files any further than their first difference.
+    if params.brief
+        && !params.ignores_line_differences()
+        && !params.strip_trailing_cr
+        && !params.strip_ansi
+        && !params.decompress
+        && params.stat.is_none()
+        && params.textconv.is_none()
+        && params.preprocess.is_none()
+        && params.from != "-"
+        && params.to != "-"
+    {
+        // Errors are reported when reading the files as usual.
+        if let Ok(same) = same_contents(&params.from, &params.to) {
+            return report_brief(params, same, output);
+        }
     }
 
     // read files
-    fn read_file_contents(filepath: &OsString) -> io::Result<Vec<u8>> {
+    fn read_file_contents(filepath: &OsString) -> io::Result<Content> {
         if filepath == "-" {
             let mut content = Vec::new();
-            io::stdin().read_to_end(&mut content).and(Ok(content))
+            io::stdin()
+                .read_to_end(&mut content)
+                .and(Ok(content.into()))
         } else {
-            fs::read(filepath)
+            Content::from_file(filepath)
         }
     }
+    // A missing file is only compared as empty if the other one exists.
+    fn is_new(error: &io::Error, other: &OsString, absent_as_empty: bool) -> bool {
+        absent_as_empty && error.kind() == io::ErrorKind::NotFound && exists(other)
+    }
     let mut io_error = false;
-    let from_content = match read_file_contents(&params.from) {
+    let mut from_content = match read_file_contents(&params.from) {
         Ok(from_content) => from_content,
+        Err(e) if is_new(&e, &params.to, absent_as_empty(params, true)) => Content::default(),
         Err(e) => {
-            report_failure_to_read_input_file(&params.executable, &params.from, &e);
+            errors.push(format_failure_to_read_input_file(
+                &params.executable,
+                &params.from,
+                &e,
+            ));
             io_error = true;
-            vec![]
+            Content::default()
         }
     };
-    let to_content = match read_file_contents(&params.to) {
+    let mut to_content = match read_file_contents(&params.to) {
         Ok(to_content) => to_content,
+        Err(e) if is_new(&e, &params.from, absent_as_empty(params, false)) => Content::default(),
         Err(e) => {
-            report_failure_to_read_input_file(&params.executable, &params.to, &e);
+            errors.push(format_failure_to_read_input_file(
+                &params.executable,
+                &params.to,
+                &e,
+            ));
             io_error = true;
-            vec![]
+            Content::default()
         }
     };
     if io_error {
-        return ExitCode::from(2);
+        return 2;
+    }
+    // With --decompress, compressed contents are compared once decompressed,
+    // still under the names of the compressed files.
+    if params.decompress {
+        for (path, content) in [
+            (&params.from, &mut from_content),
+            (&params.to, &mut to_content),
+        ] {
+            let Some(command) = decompressor(content) else {
+                continue;
+            };
+            match run_filter(params, command, path, false, content) {
+                Ok(output) => *content = output.into(),
+                Err(error) => {
+                    errors.push(error);
+                    io_error = true;
+                }
+            }
+        }
+        if io_error {
+            return 2;
+        }
+    }
+    // With --textconv, what is compared is the text that the files convert
+    // to. Absent files are still compared as empty.
+    if let Some(command) = &params.textconv {
+        for (path, content) in [
+            (&params.from, &mut from_content),
+            (&params.to, &mut to_content),
+        ] {
+            if !exists(path) {
+                continue;
+            }
+            // The command reads the file, unless it is standard input.
+            let input = if path == "-" {
+                std::mem::take(content)
+            } else {
+                Content::default()
+            };
+            match run_filter(params, command, path, path != "-", &input) {
+                Ok(text) => *content = text.into(),
+                Err(error) => {
+                    errors.push(error);
+                    io_error = true;
+                }
+            }
+        }
+        if io_error {
+            return 2;
+        }
+    }
+    // With --preprocess, what is compared is what the command outputs for
+    // the contents.
+    if let Some(command) = &params.preprocess {
+        for (path, content) in [
+            (&params.from, &mut from_content),
+            (&params.to, &mut to_content),
+        ] {
+            if !exists(path) {
+                continue;
+            }
+            match run_filter(params, command, path, false, content) {
+                Ok(output) => *content = output.into(),
+                Err(error) => {
+                    errors.push(error);
+                    io_error = true;
+                }
+            }
+        }
+        if io_error {
+            return 2;
+        }
+    }
+
+    // Binary files are compared as they are. What --textconv outputs is text.
+    let binary = !params.text
+        && params.textconv.is_none()
+        && (is_binary(&from_content) || is_binary(&to_content));
+    if params.strip_trailing_cr && !binary {
+        from_content = strip_trailing_cr(&from_content).into();
+        to_content = strip_trailing_cr(&to_content).into();
+    }
+    if params.strip_ansi && !binary {
+        from_content = strip_ansi_escapes(&from_content).into_owned().into();
+        to_content = strip_ansi_escapes(&to_content).into_owned().into();
+    }
+    // An ed script can't express that a file doesn't end with a newline: like
+    // GNU diff, compare the last line as if it was complete and warn about it.
+    let mut missing_newline = Vec::new();
+    if params.format == Format::Ed && !params.brief && !binary {
+        for (path, content) in [
+            (&params.from, &mut from_content),
+            (&params.to, &mut to_content),
+        ] {
+            if content.last().is_some_and(|&c| c != b'\n') {
+                content.to_mut().push(b'\n');
+                missing_newline.push(path);
+            }
+        }
+    }
+
+    // Whether the differences between the files, if any, are all insignificant.
+    let equivalent = || {
+        from_content == to_content
+            || !binary
+                && params.ignores_line_differences()
+                && hunks::compare(&from_content, &to_content, &CompareOptions::from(params))
+                    .changes()
+                    .next()
+                    .is_none()
+    };
+
+    if params.stat.is_some() {
+        // The entries of directories are named by their paths inside them,
+        // so that every line names them the same way. A file that doesn't
+        // exist is named after the other one.
+        let name = params
+            .entry_path
+            .clone()
+            .unwrap_or_else(|| params.shown_path(exists(&params.from)));
+        let file_stat = if from_content == to_content {
+            None
+        } else if binary {
+            Some(FileStat::binary(name, &from_content, &to_content))
+        } else {
+            Some(FileStat::new(name, &from_content, &to_content, params))
+        };
+        *stat = file_stat.filter(FileStat::changed);
+        if stat.is_none() {
+            maybe_report_identical_files(params, output);
+            return 0;
+        }
+        return 1;
+    }
+
+    if params.brief {
+        // No need to compute the differences to know whether there are any:
+        // unless some differences are ignored, comparing the contents stops
+        // at the first differing byte.
+        return report_brief(params, equivalent(), output);
+    }
+
+    if binary {
+        if from_content == to_content {
+            maybe_report_identical_files(params, output);
+            return 0;
+        }
+        if params.git {
+            if let Some(header) = header {
+                writeln!(output, "{header}").unwrap();
+            }
+            write_git_modes(params, output);
+            // The time format doesn't matter: there are no times with --git.
+            writeln!(
+                output,
+                "Binary files {} and {} differ",
+                file_header(params, true, ""),
+                file_header(params, false, "")
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                output,
+                "Binary files {} and {} differ",
+                params.shown_path(true),
+                params.shown_path(false)
+            )
+            .unwrap();
+        }
+        return 1;
+    }
+
+    // Side by side and with -D or formats, the output is written as it's
+    // formatted, after the header when the files differ. It isn't empty when
+    // there are no differences, and it can be empty when there are some.
+    if matches!(params.format, Format::SideBySide | Format::Ifdef) {
+        let equivalent = equivalent();
+        if !equivalent {
+            write_header(header, params, output);
+        }
+        if params.format == Format::SideBySide {
+            side_diff::diff(
+                output,
+                &from_content,
+                &to_content,
+                &SideBySideOptions::from(params),
+            )
+        } else {
+            ifdef_diff::diff(output, &from_content, &to_content, params)
+        }
+        .unwrap();
+        if equivalent {
+            maybe_report_identical_files(params, output);
+            return 0;
+        }
+        return 1;
     }
 
     // run diff
-    let result: Vec<u8> = match params.format {
-        Format::Normal => normal_diff::diff(&from_content, &to_content, &params),
-        Format::Unified => unified_diff::diff(&from_content, &to_content, &params),
-        Format::Context => context_diff::diff(&from_content, &to_content, &params),
-        Format::Ed => ed_diff::diff(&from_content, &to_content, &params).unwrap_or_else(|error| {
-            eprintln!("{error}");
-            exit(2);
-        }),
-    };
-    if params.brief && !result.is_empty() {
-        println!(
-            "Files {} and {} differ",
-            params.from.to_string_lossy(),
-            params.to.to_string_lossy()
-        );
+    let mut diff = hunks::compare(&from_content, &to_content, &CompareOptions::from(params));
+    let exit_code = if diff.hunks.is_empty() {
+        if missing_newline.is_empty() {
+            maybe_report_identical_files(params, output);
+        }
+        0
     } else {
-        io::stdout().write_all(&result).unwrap();
+        limit_hunks(&mut diff, params.format, output);
+        // Files whose hunks are all left out aren't output at all. Nothing
+        // else was written for the others.
+        if !diff.hunks.is_empty() {
+            write_header(header, params, output);
+            match params.format {
+                Format::Normal => normal_diff::diff(output, &diff, params).unwrap(),
+                Format::Unified => unified_diff::diff(output, &diff, params).unwrap(),
+                Format::Context => context_diff::diff(output, &diff, params).unwrap(),
+                Format::Rcs => rcs_diff::diff(output, &diff, params).unwrap(),
+                Format::SideBySide | Format::Ifdef => unreachable!("already written"),
+                Format::Ed => ed_diff::diff(output, &diff, params)
+                    .expect("the last lines of both files are complete"),
+            }
+        }
+        1
+    };
+    if missing_newline.is_empty() {
+        return exit_code;
     }
-    if result.is_empty() {
-        maybe_report_identical_files();
-        ExitCode::SUCCESS
-    } else {
-        ExitCode::from(1)
+    for path in missing_newline {
+        errors.push(format!(
+            "{}: {}: No newline at end of file\n",
+            params.executable.to_string_lossy(),
+            path.to_string_lossy()
+        ));
+    }
+    2
+}
+
+// Writes what precedes the differences between two files: the "diff ..."
+// line when there's one, and with --git, the modes of the files.
+fn write_header(header: Option<&str>, params: &Params, output: &mut impl Write) {
+    if let Some(header) = header {
+        writeln!(output, "{header}").unwrap();
+    }
+    if params.git {
+        write_git_modes(params, output);
+    }
+}
+
+// With --report-identical-files, reports that the files are the same.
+fn maybe_report_identical_files(params: &Params, output: &mut impl Write) {
+    if params.report_identical_files {
+        writeln!(
+            output,
+            "Files {} and {} are identical",
+            params.shown_path(true),
+            params.shown_path(false),
+        )
+        .unwrap();
+    }
+}
+
+// Runs a command through the shell for a file, with its path as argument if
+// requested, and the input on its standard input, and returns what it
+// outputs. Its error messages go to stderr.
+fn run_filter(
+    params: &Params,
+    command: &str,
+    path: &OsString,
+    path_as_argument: bool,
+    input: &[u8],
+) -> Result<Vec<u8>, String> {
+    let executable = params.executable.to_string_lossy();
+    #[cfg(unix)]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(format!("{command} \"$@\""))
+            .arg(command);
+        shell
+    };
+    #[cfg(not(unix))]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    };
+    let mut child = shell
+        .args(path_as_argument.then_some(path))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{executable}: cannot run '{command}': {e}"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let output = thread::scope(|scope| {
+        scope.spawn(move || {
+            // The command doesn't have to read all of its input.
+            let _ = stdin.write_all(input);
+        });
+        child.wait_with_output()
+    })
+    .map_err(|e| format!("{executable}: cannot run '{command}': {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{executable}: '{command}' failed on {} with {}",
+            path.to_string_lossy(),
+            output.status
+        ));
+    }
+    Ok(output.stdout)
+}
+
+// With --paginate, starts the pager that the output is written to when it
+// goes to a terminal: $PAGER, or less. Like git, less is told to exit when
+// the output fits on the screen, and to keep the colors.
+fn start_pager() -> Option<Child> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    if pager.is_empty() || pager == "cat" {
+        return None;
+    }
+    #[cfg(unix)]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(&pager);
+        shell
+    };
+    #[cfg(not(unix))]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(&pager);
+        shell
+    };
+    if env::var_os("LESS").is_none() {
+        shell.env("LESS", "FRX");
+    }
+    shell.stdin(Stdio::piped()).spawn().ok()
+}
+
+// The mode of a file as git reports it, which only tells whether it is
+// executable, or None if the file doesn't exist.
+fn git_mode(path: &OsString) -> Option<&'static str> {
+    if path == "-" {
+        return Some("100644");
+    }
+    let metadata = fs::metadata(path).ok()?;
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o100 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = {
+        let _ = metadata;
+        false
+    };
+    Some(if executable { "100755" } else { "100644" })
+}
+
+// Writes the lines of git's extended headers that tell that a file is created
+// or deleted, or that its mode changed.
+fn write_git_modes(params: &Params, output: &mut impl Write) {
+    match (git_mode(&params.from), git_mode(&params.to)) {
+        (None, Some(mode)) => writeln!(output, "new file mode {mode}"),
+        (Some(mode), None) => writeln!(output, "deleted file mode {mode}"),
+        (Some(old), Some(new)) if old != new => {
+            writeln!(output, "old mode {old}\nnew mode {new}")
+        }
+        _ => Ok(()),
+    }
+    .unwrap();
+}
+
+// Reports whether the files differ the way --brief does, and returns the exit
+// code.
+fn report_brief(params: &Params, same: bool, output: &mut impl Write) -> u8 {
+    if same {
+        maybe_report_identical_files(params, output);
+        return 0;
+    }
+    writeln!(
+        output,
+        "Files {} and {} differ",
+        params.shown_path(true),
+        params.shown_path(false)
+    )
+    .unwrap();
+    1
+}
+
+// Whether both files are regular files of the same size, last modified at
+// the same time.
+fn same_size_and_mtime(params: &Params) -> bool {
+    if params.from == "-" || params.to == "-" {
+        return false;
+    }
+    let (Ok(from), Ok(to)) = (fs::metadata(&params.from), fs::metadata(&params.to)) else {
+        return false;
+    };
+    from.is_file()
+        && to.is_file()
+        && from.len() == to.len()
+        && matches!((from.modified(), to.modified()), (Ok(from), Ok(to)) if from == to)
+}
+
+// Whether two files have the same contents, reading them only up to their
+// first difference.
+fn same_contents(from: &OsString, to: &OsString) -> io::Result<bool> {
+    let mut from = fs::File::open(from)?;
+    let mut to = fs::File::open(to)?;
+    let from_metadata = from.metadata()?;
+    let to_metadata = to.metadata()?;
+    if from_metadata.is_file() && to_metadata.is_file() && from_metadata.len() != to_metadata.len()
+    {
+        return Ok(false);
+    }
+    let mut from_buffer = vec![0; 64 * 1024];
+    let mut to_buffer = vec![0; 64 * 1024];
+    loop {
+        let from_len = read_chunk(&mut from, &mut from_buffer)?;
+        let to_len = read_chunk(&mut to, &mut to_buffer)?;
+        if from_buffer[..from_len] != to_buffer[..to_len] {
+            return Ok(false);
+        }
+        if from_len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// Fills the buffer, unless the end of the file comes first, and returns how
+// much was read.
+fn read_chunk(file: &mut fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match file.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+// Replaces the parts of the output that legitimately vary between two runs
+// (file header timestamps) with a placeholder, so that outputs can be compared.
+fn normalize_output(output: &[u8]) -> Vec<u8> {
+    let re = Regex::new(r"(?m)^((?:\*\*\*|---|\+\+\+) .*)\t.*$").unwrap();
+    re.replace_all(output, &b"$1\tTIMESTAMP"[..]).into_owned()
+}
+
+// Runs the reference implementation with the same arguments (minus the
+// options it doesn't have) and reports any divergence in exit code or output
+// on stderr. Returns false if a divergence was found.
+// Note that standard input is not replayed to the reference program, so
+// comparisons involving '-' can't be verified this way.
+fn verify_against(
+    reference: &OsString,
+    args: &[OsString],
+    params: &Params,
+    exit_code: u8,
+    output: &[u8],
+) -> bool {
+    let executable = params.executable.to_string_lossy();
+    let reference_args = without_own_options(args);
+    let reference_output = match Command::new(reference).args(reference_args).output() {
+        Ok(reference_output) => reference_output,
+        Err(e) => {
+            eprintln!(
+                "{executable}: cannot run reference program {}: {e}",
+                reference.to_string_lossy()
+            );
+            return false;
+        }
+    };
+    let mut agree = true;
+    let reference_code = reference_output.status.code();
+    if reference_code != Some(i32::from(exit_code)) {
+        eprintln!(
+            "{executable}: exit status {exit_code} differs from {}'s ({})",
+            reference.to_string_lossy(),
+            reference_code.map_or("killed by signal".to_string(), |code| code.to_string())
+        );
+        agree = false;
+    }
+    if normalize_output(output) != normalize_output(&reference_output.stdout) {
+        eprintln!(
+            "{executable}: output differs from {}'s",
+            reference.to_string_lossy()
+        );
+        agree = false;
+    }
+    agree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn compare_contents() -> io::Result<()> {
+        let mut contents = vec![b'a'; 200 * 1024];
+        let mut file = NamedTempFile::new()?;
+        file.write_all(&contents)?;
+        let mut same = NamedTempFile::new()?;
+        same.write_all(&contents)?;
+        // A difference after the first chunk
+        contents[150 * 1024] = b'b';
+        let mut other = NamedTempFile::new()?;
+        other.write_all(&contents)?;
+        let mut shorter = NamedTempFile::new()?;
+        shorter.write_all(&contents[..100])?;
+
+        let path = |file: &NamedTempFile| file.path().as_os_str().to_os_string();
+        assert!(same_contents(&path(&file), &path(&same))?);
+        assert!(!same_contents(&path(&file), &path(&other))?);
+        assert!(!same_contents(&path(&shorter), &path(&file))?);
+        assert!(same_contents(&path(&file), &OsString::from("missing")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_timestamps() {
+        let output = b"--- a\t2024-03-24 23:43:05.189597645 +0530\n\
++++ b\t2024-03-24 23:35:08 +0530\n\
+@@ -1 +1 @@\n\
+-foo\tbar\n\
++foo\n";
+        assert_eq!(
+            normalize_output(output),
+            b"--- a\tTIMESTAMP\n+++ b\tTIMESTAMP\n@@ -1 +1 @@\n-foo\tbar\n+foo\n"
+        );
+    }
+
+    #[test]
+    fn switches() {
+        let args: Vec<OsString> = ["diff", "-r", "-u", "--", "a", "b"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let params = parse_params(args.iter().cloned().peekable()).unwrap();
+        assert_eq!(switch_string(&args, &params), " -r -u");
+
+        let args: Vec<OsString> = ["diff", "--jobs", "4", "-r", "--jobs=2", "a", "b"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let params = parse_params(args.iter().cloned().peekable()).unwrap();
+        assert_eq!(switch_string(&args, &params), " -r");
+
+        let args: Vec<OsString> = ["diff", "-x", "*.o", "--exclude=it's", "a", "b"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let params = parse_params(args.iter().cloned().peekable()).unwrap();
+        assert_eq!(
+            switch_string(&args, &params),
+            r" -x '*.o' '--exclude=it'\''s'"
+        );
     }
 }
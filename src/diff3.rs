@@ -0,0 +1,934 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use crate::hunks::{self, CompareOptions};
+use crate::utils::{format_failure_to_read_input_file, is_binary, strip_trailing_cr};
+use std::env::ArgsOs;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::process::ExitCode;
+
+// The files, in the order of the operands.
+pub const MINE: usize = 0;
+pub const OLDER: usize = 1;
+pub const YOURS: usize = 2;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Params {
+    executable: OsString,
+    /// MYFILE, OLDFILE and YOURFILE.
+    files: [OsString; 3],
+    text: bool,
+    strip_trailing_cr: bool,
+    /// Output an ed script that incorporates the changes into MYFILE,
+    /// instead of the default format.
+    ed_script: bool,
+    /// Only incorporate the changes that overlap, with -x.
+    overlap_only: bool,
+    /// Only incorporate the changes that don't overlap, with -3.
+    easy_only: bool,
+    /// Bracket the conflicts, and the changes where only OLDFILE differs,
+    /// with -A.
+    show_all: bool,
+    /// End ed scripts with the commands that save the file and quit, with
+    /// -i.
+    final_write: bool,
+    /// The labels of -L, which replace the names of the files in the
+    /// brackets, in order.
+    labels: Vec<OsString>,
+    /// Start the lines of the files with a tab instead of two spaces.
+    initial_tab: bool,
+}
+
+// The options of --help, in the words of GNU diff3.
+const OPTIONS_HELP: &str = "\
+Compare three files line by line.
+
+Mandatory arguments to long options are mandatory for short options too.
+  -A, --show-all              output all changes, bracketing conflicts
+
+  -e, --ed                    output ed script incorporating changes
+                                from OLDFILE to YOURFILE into MYFILE
+  -3, --easy-only             like -e, but incorporate only nonoverlapping changes
+  -x, --overlap-only          like -e, but incorporate only overlapping changes
+  -i                          append 'w' and 'q' commands to ed scripts
+
+  -a, --text                  treat all files as text
+      --strip-trailing-cr     strip trailing carriage return on input
+  -T, --initial-tab           make tabs line up by prepending a tab
+  -L, --label=LABEL           use LABEL instead of file name
+                                (can be repeated up to three times)
+
+      --help                  display this help and exit
+  -v, --version               output version information and exit
+
+The default output format is a somewhat human-readable representation of
+the changes.
+
+If a FILE is '-', read standard input.
+Exit status is 0 if successful, 1 if conflicts, 2 if trouble.
+";
+
+pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Result<Params, String> {
+    let Some(executable) = opts.next() else {
+        return Err("Usage: <exe> <myfile> <oldfile> <yourfile>".to_string());
+    };
+    let executable_str = executable.to_string_lossy().to_string();
+    let mut params = Params {
+        executable,
+        ..Default::default()
+    };
+    let mut operands = Vec::new();
+    // The number of options that select the output format, which can only
+    // be given once.
+    let mut formats = 0;
+    while let Some(param) = opts.next() {
+        if param == "--" {
+            break;
+        }
+        if param == "-" {
+            operands.push(param);
+            continue;
+        }
+        if param == "-a" || param == "--text" {
+            params.text = true;
+            continue;
+        }
+        if param == "--strip-trailing-cr" {
+            params.strip_trailing_cr = true;
+            continue;
+        }
+        if param == "-i" {
+            params.final_write = true;
+            continue;
+        }
+        if param == "-T" || param == "--initial-tab" {
+            params.initial_tab = true;
+            continue;
+        }
+        if param == "-L" || param == "--label" {
+            let Some(label) = opts.next() else {
+                return Err(format!(
+                    "{executable_str}: option requires an argument -- '{}'",
+                    param.to_string_lossy()
+                ));
+            };
+            params.labels.push(label);
+            continue;
+        }
+        if let Some(label) = param
+            .as_encoded_bytes()
+            .strip_prefix(b"--label=")
+            .or(param.as_encoded_bytes().strip_prefix(b"-L"))
+        {
+            // SAFETY: the prefix that was removed is ASCII, so the rest is
+            // still made of whole characters.
+            let label = unsafe { OsStr::from_encoded_bytes_unchecked(label) };
+            params.labels.push(label.to_os_string());
+            continue;
+        }
+        if param == "-A" || param == "--show-all" {
+            params.show_all = true;
+            formats += 1;
+            continue;
+        }
+        if param == "-e" || param == "--ed" {
+            formats += 1;
+            continue;
+        }
+        if param == "-x" || param == "--overlap-only" {
+            params.overlap_only = true;
+            formats += 1;
+            continue;
+        }
+        if param == "-3" || param == "--easy-only" {
+            params.easy_only = true;
+            formats += 1;
+            continue;
+        }
+        if param == "--help" {
+            print!("Usage: {executable_str} [OPTION]... MYFILE OLDFILE YOURFILE\n{OPTIONS_HELP}");
+            std::process::exit(0);
+        }
+        if param == "-v" || param == "--version" {
+            println!("diff3 (uutils diffutils) {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        if param.to_string_lossy().starts_with('-') {
+            return Err(format!("Unknown option: {:?}", param));
+        }
+        operands.push(param);
+    }
+    operands.extend(opts);
+
+    if params.labels.len() > 3 {
+        return Err(format!("{executable_str}: too many file label options"));
+    }
+    // Like GNU diff3, the labels are only for the brackets of -A.
+    if formats > 1 || (!params.labels.is_empty() && !params.show_all) {
+        return Err(format!("{executable_str}: incompatible options"));
+    }
+    params.ed_script = formats > 0;
+
+    params.files = match <[OsString; 3]>::try_from(operands) {
+        Ok(files) => files,
+        Err(operands) if operands.len() > 3 => {
+            return Err(format!(
+                "{executable_str}: extra operand '{}'",
+                operands[3].to_string_lossy()
+            ))
+        }
+        Err(operands) => {
+            return Err(match operands.last() {
+                Some(operand) => format!(
+                    "{executable_str}: missing operand after '{}'",
+                    operand.to_string_lossy()
+                ),
+                None => format!("{executable_str}: missing operand"),
+            })
+        }
+    };
+    Ok(params)
+}
+
+/// A change to one or more of the three files, with the lines it covers in
+/// each of them, as ranges of (0-based) line indices.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub ranges: [Range<usize>; 3],
+    /// The file whose lines differ from the lines of the other two, which
+    /// are the same, or None when the lines of all three files differ.
+    pub differs: Option<usize>,
+}
+
+// The files that are compared to the common one.
+fn others(common: usize) -> [usize; 2] {
+    match common {
+        MINE => [OLDER, YOURS],
+        OLDER => [MINE, YOURS],
+        _ => [MINE, OLDER],
+    }
+}
+
+/// Collects the changes between three files, in order, like GNU diff3 does:
+/// the other two files are compared to the `common` one, and their changes
+/// that overlap or touch in it are combined.
+#[must_use]
+pub fn blocks(files: &[Vec<&[u8]>; 3], common: usize) -> Vec<Block> {
+    let options = CompareOptions::default();
+    // The changes from each of the other files to the common one.
+    let changes = others(common).map(|file| {
+        hunks::changes(&files[file], &files[common], &options)
+            .into_iter()
+            .map(|change| hunks::Change {
+                expected: change.actual,
+                actual: change.expected,
+                ..change
+            })
+            .collect::<Vec<_>>()
+    });
+    blocks_of_changes(files, common, &changes)
+}
+
+/// Like `blocks`, with the changes from the common file to each of the
+/// other two already known, in the order of the files: their `expected`
+/// lines are those of the common file.
+#[must_use]
+pub fn blocks_of_changes(
+    files: &[Vec<&[u8]>; 3],
+    common: usize,
+    changes: &[Vec<hunks::Change>; 2],
+) -> Vec<Block> {
+    let others = others(common);
+    let mut next = [0, 0];
+    // How many more lines each other file has than the common one, before
+    // the next change.
+    let mut offsets = [0isize, 0isize];
+    let mut blocks = Vec::new();
+    loop {
+        let first = match (changes[0].get(next[0]), changes[1].get(next[1])) {
+            (None, None) => break,
+            (Some(_), None) => 0,
+            (None, Some(_)) => 1,
+            (Some(a), Some(b)) => usize::from(b.expected.start < a.expected.start),
+        };
+        let mut lines = changes[first][next[first]].expected.clone();
+
+        // Take in all the changes of either side that overlap or touch the
+        // lines of the common file covered so far.
+        let mut taken = [next[0]..next[0], next[1]..next[1]];
+        taken[first].end += 1;
+        loop {
+            let side = (0..2).find(|&side| {
+                changes[side]
+                    .get(taken[side].end)
+                    .is_some_and(|change| change.expected.start <= lines.end)
+            });
+            let Some(side) = side else {
+                break;
+            };
+            let change = &changes[side][taken[side].end];
+            lines.start = lines.start.min(change.expected.start);
+            lines.end = lines.end.max(change.expected.end);
+            taken[side].end += 1;
+        }
+
+        let mut ranges = [0..0, 0..0, 0..0];
+        ranges[common] = lines.clone();
+        for (side, file) in others.into_iter().enumerate() {
+            let taken = &changes[side][taken[side].clone()];
+            let (start_offset, end_offset) = match (taken.first(), taken.last()) {
+                (Some(first), Some(last)) => {
+                    let offset =
+                        |line: usize, common_line: usize| line as isize - common_line as isize;
+                    (
+                        offset(first.actual.start, first.expected.start),
+                        offset(last.actual.end, last.expected.end),
+                    )
+                }
+                _ => (offsets[side], offsets[side]),
+            };
+            ranges[file] = (lines.start as isize + start_offset) as usize
+                ..(lines.end as isize + end_offset) as usize;
+            offsets[side] = end_offset;
+        }
+
+        let [a, b] = others;
+        let differs = match (taken[0].is_empty(), taken[1].is_empty()) {
+            (false, true) => Some(a),
+            (true, false) => Some(b),
+            _ if files[a][ranges[a].clone()] == files[b][ranges[b].clone()] => Some(common),
+            _ => None,
+        };
+        blocks.push(Block { ranges, differs });
+        next = [taken[0].end, taken[1].end];
+    }
+    blocks
+}
+
+// Writes the lines of a file with their prefix, followed by GNU's marker if
+// the last one is missing its newline.
+fn write_lines(output: &mut Vec<u8>, lines: &[&[u8]], params: &Params) {
+    for line in lines {
+        output.extend_from_slice(if params.initial_tab { b"\t" } else { b"  " });
+        output.extend_from_slice(line);
+    }
+    if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        output.extend_from_slice(b"\n\\ No newline at end of file\n");
+    }
+}
+
+/// Writes the changes between three files in the default format of GNU
+/// diff3: each block starts with "====", followed by the number of the file
+/// that differs from the other two, if any, then the lines of each file.
+/// The lines that two files have in common are only written once.
+pub fn write_normal(
+    output: &mut Vec<u8>,
+    files: &[Vec<&[u8]>; 3],
+    blocks: &[Block],
+    params: &Params,
+) {
+    for block in blocks {
+        output.extend_from_slice(b"====");
+        if let Some(file) = block.differs {
+            write!(output, "{}", file + 1).unwrap();
+        }
+        output.push(b'\n');
+
+        // When MYFILE and YOURFILE are the same, YOURFILE comes before
+        // OLDFILE, so that the lines that are written once come last.
+        let order = if block.differs == Some(OLDER) {
+            [MINE, YOURS, OLDER]
+        } else {
+            [MINE, OLDER, YOURS]
+        };
+        // The lines of the file that is the same as a file that comes later.
+        let unwritten = match block.differs {
+            Some(MINE) => Some(OLDER),
+            Some(_) => Some(MINE),
+            None => None,
+        };
+        for file in order {
+            let range = &block.ranges[file];
+            write!(output, "{}:", file + 1).unwrap();
+            if range.is_empty() {
+                writeln!(output, "{}a", range.start).unwrap();
+            } else if range.len() == 1 {
+                writeln!(output, "{}c", range.end).unwrap();
+            } else {
+                writeln!(output, "{},{}c", range.start + 1, range.end).unwrap();
+            }
+            if unwritten != Some(file) {
+                write_lines(output, &files[file][range.clone()], params);
+            }
+        }
+    }
+}
+
+// Writes lines as the text of an ed command, with a newline added to an
+// incomplete last line. Like GNU diff3, the lines that start with a dot get
+// another one, and the return value tells whether any did.
+fn write_dotted_lines(output: &mut Vec<u8>, lines: &[&[u8]]) -> bool {
+    let mut leading_dot = false;
+    for line in lines {
+        if line.starts_with(b".") {
+            leading_dot = true;
+            output.push(b'.');
+        }
+        output.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            output.push(b'\n');
+        }
+    }
+    leading_dot
+}
+
+// Ends the text of an ed command, then removes the extra dots from the
+// `count` lines from `first_line` on, once they are in place.
+fn end_ed_text(output: &mut Vec<u8>, leading_dot: bool, first_line: usize, count: usize) {
+    output.extend_from_slice(b".\n");
+    if leading_dot {
+        if count == 1 {
+            writeln!(output, "{first_line}s/^\\.//").unwrap();
+        } else {
+            let last_line = first_line + count - 1;
+            writeln!(output, "{first_line},{last_line}s/^\\.//").unwrap();
+        }
+    }
+}
+
+// Writes a line of the brackets of a conflict, with the label of a file, or
+// its name. They are written as they are, in whatever encoding.
+fn write_bracket(output: &mut Vec<u8>, marker: &str, file: usize, params: &Params) {
+    let label = params.labels.get(file).unwrap_or(&params.files[file]);
+    output.extend_from_slice(marker.as_bytes());
+    output.push(b' ');
+    output.extend_from_slice(label.as_encoded_bytes());
+    output.push(b'\n');
+}
+
+/// Writes an ed script that incorporates into MYFILE the changes from
+/// OLDFILE to YOURFILE, for blocks computed with OLDFILE as the common file.
+/// The changes where only YOURFILE differs are easy to incorporate, while
+/// those where MYFILE changed too overlap and take the lines of YOURFILE;
+/// -3 and -x select one kind or the other. With -A, the overlapping changes
+/// and those where only OLDFILE differs are bracketed as conflicts instead.
+/// The commands go from the end of the file to its start, so that their
+/// line numbers stay valid. With -i, the script ends with the commands that
+/// save the file and quit, so that it can be given to `ed` as it is.
+/// Returns whether any conflict was bracketed.
+#[must_use]
+pub fn write_ed_script(
+    output: &mut Vec<u8>,
+    files: &[Vec<&[u8]>; 3],
+    blocks: &[Block],
+    params: &Params,
+) -> bool {
+    let mut conflicts = false;
+    for block in blocks.iter().rev() {
+        let conflict = match block.differs {
+            Some(OLDER) if params.show_all => true,
+            Some(YOURS) if !params.overlap_only => false,
+            None if !params.easy_only => params.show_all,
+            _ => continue,
+        };
+        let mine = &block.ranges[MINE];
+        let older = &files[OLDER][block.ranges[OLDER].clone()];
+        let yours = &files[YOURS][block.ranges[YOURS].clone()];
+        if conflict {
+            conflicts = true;
+            // The end of the conflict goes after the lines of MYFILE...
+            writeln!(output, "{}a", mine.end).unwrap();
+            let mut leading_dot = false;
+            if block.differs.is_none() {
+                write_bracket(output, "|||||||", OLDER, params);
+                leading_dot = write_dotted_lines(output, older);
+                output.extend_from_slice(b"=======\n");
+                leading_dot |= write_dotted_lines(output, yours);
+            }
+            write_bracket(output, ">>>>>>>", YOURS, params);
+            end_ed_text(
+                output,
+                leading_dot,
+                mine.end + 2,
+                older.len() + yours.len() + 1,
+            );
+
+            // ...and its start before them.
+            writeln!(output, "{}a", mine.start).unwrap();
+            let start_file = if block.differs.is_none() { MINE } else { OLDER };
+            write_bracket(output, "<<<<<<<", start_file, params);
+            let mut leading_dot = false;
+            if block.differs == Some(OLDER) {
+                leading_dot = write_dotted_lines(output, older);
+                output.extend_from_slice(b"=======\n");
+            }
+            end_ed_text(output, leading_dot, mine.start + 2, older.len());
+        } else if yours.is_empty() {
+            if mine.len() == 1 {
+                writeln!(output, "{}d", mine.end).unwrap();
+            } else {
+                writeln!(output, "{},{}d", mine.start + 1, mine.end).unwrap();
+            }
+        } else {
+            match mine.len() {
+                0 => writeln!(output, "{}a", mine.end).unwrap(),
+                1 => writeln!(output, "{}c", mine.end).unwrap(),
+                _ => writeln!(output, "{},{}c", mine.start + 1, mine.end).unwrap(),
+            }
+            let leading_dot = write_dotted_lines(output, yours);
+            end_ed_text(output, leading_dot, mine.start + 1, yours.len());
+        }
+    }
+    if params.final_write {
+        output.extend_from_slice(b"w\nq\n");
+    }
+    conflicts
+}
+
+// How many times the changes between two files cover the last line of
+// either of them, when it's missing its newline.
+fn incomplete_lines_changed(a: &[&[u8]], b: &[&[u8]]) -> usize {
+    let incomplete = |lines: &[&[u8]], range: &Range<usize>| {
+        range.end == lines.len() && !range.is_empty() && !lines[range.end - 1].ends_with(b"\n")
+    };
+    hunks::changes(a, b, &CompareOptions::default())
+        .iter()
+        .map(|change| {
+            usize::from(incomplete(a, &change.expected))
+                + usize::from(incomplete(b, &change.actual))
+        })
+        .sum()
+}
+
+// Reads a file, or standard input for "-", which is only read once.
+fn read_file(
+    path: &OsString,
+    stdin: &mut Option<Vec<u8>>,
+    params: &Params,
+) -> Result<Vec<u8>, String> {
+    let fail = |e: &io::Error| format_failure_to_read_input_file(&params.executable, path, e);
+    if path == "-" {
+        if stdin.is_none() {
+            let mut content = Vec::new();
+            io::stdin()
+                .read_to_end(&mut content)
+                .map_err(|e| fail(&e))?;
+            *stdin = Some(content);
+        }
+        Ok(stdin.clone().unwrap_or_default())
+    } else {
+        fs::read(path).map_err(|e| fail(&e))
+    }
+}
+
+// Exit codes are documented at
+// https://www.gnu.org/software/diffutils/manual/html_node/Invoking-diff3.html
+//     An exit status of 0 means diff3 was successful,
+//     1 means some conflicts were found,
+//     and 2 means trouble.
+pub fn main(opts: Peekable<ArgsOs>) -> ExitCode {
+    let params = match parse_params(opts) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut stdin = None;
+    let mut contents = Vec::new();
+    for path in &params.files {
+        match read_file(path, &mut stdin, &params) {
+            Ok(content) if params.strip_trailing_cr => contents.push(strip_trailing_cr(&content)),
+            Ok(content) => contents.push(content),
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    // Like GNU diff3, the default format compares the files to YOURFILE,
+    // and ed scripts to OLDFILE.
+    let common = if params.ed_script { OLDER } else { YOURS };
+
+    // Without -a, the comparison fails when a file that differs from the
+    // common one is binary, in the order GNU diff3 compares them.
+    if !params.text {
+        for other in others(common).into_iter().rev() {
+            let (a, b) = (&contents[other], &contents[common]);
+            if a != b && (is_binary(a) || is_binary(b)) {
+                eprintln!(
+                    "{}: diff failed: Binary files {} and {} differ",
+                    params.executable.to_string_lossy(),
+                    params.files[other].to_string_lossy(),
+                    params.files[common].to_string_lossy()
+                );
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let files = [0, 1, 2].map(|file| contents[file].split_inclusive(|&c| c == b'\n').collect());
+
+    let blocks = blocks(&files, common);
+    let mut output = Vec::new();
+    let mut conflicts = false;
+    if params.ed_script {
+        // The lines of an ed script always end with a newline, so GNU diff3
+        // warns about each incomplete line in the changes it compared.
+        for other in others(common).into_iter().rev() {
+            for _ in 0..incomplete_lines_changed(&files[other], &files[common]) {
+                eprintln!(
+                    "{}: No newline at end of file",
+                    params.executable.to_string_lossy()
+                );
+            }
+        }
+        conflicts = write_ed_script(&mut output, &files, &blocks, &params);
+    } else {
+        write_normal(&mut output, &files, &blocks, &params);
+    }
+    if let Err(e) = io::stdout().write_all(&output) {
+        eprintln!("{}: {e}", params.executable.to_string_lossy());
+        return ExitCode::from(2);
+    }
+    // Only the conflicts that were bracketed count, like in GNU diff3.
+    if conflicts {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn os(s: &str) -> OsString {
+        OsString::from(s)
+    }
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        text.as_bytes().split_inclusive(|&c| c == b'\n').collect()
+    }
+
+    fn normal(mine: &str, older: &str, yours: &str) -> String {
+        let files = [lines(mine), lines(older), lines(yours)];
+        let mut output = Vec::new();
+        write_normal(
+            &mut output,
+            &files,
+            &blocks(&files, YOURS),
+            &Params::default(),
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    fn ed_script(mine: &str, older: &str, yours: &str, args: &[&str]) -> String {
+        let mut params_args = vec![os("diff3")];
+        params_args.extend(args.iter().map(|arg| os(arg)));
+        params_args.extend([os("mine"), os("older"), os("yours")]);
+        let params = parse_params(params_args.into_iter().peekable()).unwrap();
+        let files = [lines(mine), lines(older), lines(yours)];
+        let mut output = Vec::new();
+        let _ = write_ed_script(&mut output, &files, &blocks(&files, OLDER), &params);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff3"),
+                files: [os("a"), os("-"), os("c")],
+                text: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff3"), os("-a"), os("a"), os("-"), os("c")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff3"),
+                files: [os("a"), os("-b"), os("c")],
+                strip_trailing_cr: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff3"),
+                    os("--strip-trailing-cr"),
+                    os("a"),
+                    os("--"),
+                    os("-b"),
+                    os("c")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert_eq!(
+            Err("diff3: missing operand after 'b'".to_string()),
+            parse_params([os("diff3"), os("a"), os("b")].iter().cloned().peekable())
+        );
+        assert_eq!(
+            Err("diff3: incompatible options".to_string()),
+            parse_params(
+                [os("diff3"), os("-e"), os("-3"), os("a"), os("b"), os("c")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Err("diff3: missing operand".to_string()),
+            parse_params([os("diff3")].iter().cloned().peekable())
+        );
+        assert_eq!(
+            Err("diff3: extra operand 'd'".to_string()),
+            parse_params(
+                [os("diff3"), os("a"), os("b"), os("c"), os("d")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+    }
+
+    #[test]
+    fn combined_changes() {
+        let files = [
+            lines("a\nB\nc\nd\nE\n"),
+            lines("a\nb\nc\nd\ne\n"),
+            lines("a\nb\nC\nd\nE\n"),
+        ];
+        assert_eq!(
+            blocks(&files, OLDER),
+            vec![
+                // Changes that touch each other are combined.
+                Block {
+                    ranges: [1..3, 1..3, 1..3],
+                    differs: None,
+                },
+                Block {
+                    ranges: [4..5, 4..5, 4..5],
+                    differs: Some(OLDER),
+                },
+            ]
+        );
+
+        let files = [lines("x\na\nb\n"), lines("a\nb\n"), lines("a\nb\ny\nz\n")];
+        assert_eq!(
+            blocks(&files, OLDER),
+            vec![
+                Block {
+                    ranges: [0..1, 0..0, 0..0],
+                    differs: Some(MINE),
+                },
+                Block {
+                    ranges: [3..3, 2..2, 2..4],
+                    differs: Some(YOURS),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn normal_format() {
+        assert_eq!(normal("a\nb\n", "a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            normal("a\nB\nc\n", "a\nb\nc\n", "a\nb\nc\n"),
+            "====1\n1:2c\n  B\n2:2c\n3:2c\n  b\n"
+        );
+        assert_eq!(
+            normal("a\nb\nc\n", "a\nB\nc\n", "a\nb\nc\n"),
+            "====2\n1:2c\n3:2c\n  b\n2:2c\n  B\n"
+        );
+        assert_eq!(
+            normal("a\nc\n", "a\nb\nc\n", "a\nb\nc\nd\ne\n"),
+            "====1\n1:1a\n2:2c\n3:2c\n  b\n====3\n1:2a\n2:3a\n3:4,5c\n  d\n  e\n"
+        );
+        assert_eq!(
+            normal("a\nx", "a\nb\n", "a\ny\n"),
+            "====\n1:2c\n  x\n\\ No newline at end of file\n2:2c\n  b\n3:2c\n  y\n"
+        );
+
+        let files = [lines("a\nB\n"), lines("a\nb\n"), lines("a\nb\n")];
+        let params = Params {
+            initial_tab: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_normal(&mut output, &files, &blocks(&files, YOURS), &params);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "====1\n1:2c\n\tB\n2:2c\n3:2c\n\tb\n"
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let parse = |args: &[&str]| {
+            let mut params_args = vec![os("diff3")];
+            params_args.extend(args.iter().map(|arg| os(arg)));
+            parse_params(params_args.into_iter().peekable())
+        };
+        assert_eq!(
+            parse(&["-A", "-L", "x", "--label=y z", "-Lw", "a", "b", "c"]).map(|p| p.labels),
+            Ok(vec![os("x"), os("y z"), os("w")])
+        );
+        assert_eq!(
+            parse(&["-A", "--label", "x", "a", "b", "c"]).map(|p| p.labels),
+            Ok(vec![os("x")])
+        );
+        assert_eq!(
+            parse(&["-A", "-L1", "-L2", "-L3", "-L4", "a", "b", "c"]),
+            Err("diff3: too many file label options".to_string())
+        );
+        assert_eq!(
+            parse(&["-e", "-L1", "a", "b", "c"]),
+            Err("diff3: incompatible options".to_string())
+        );
+        assert_eq!(
+            parse(&["-A", "a", "b", "c", "-L"]),
+            Err("diff3: option requires an argument -- '-L'".to_string())
+        );
+    }
+
+    #[test]
+    fn ed_scripts() {
+        // YOURFILE changed the second line, and both changed the fourth.
+        let (mine, older, yours) = ("a\nb\nc\nD\n", "a\nb\nc\nd\n", "a\nB\nc\nd2\n");
+        assert_eq!(
+            ed_script(mine, older, yours, &["-e"]),
+            "4c\nd2\n.\n2c\nB\n.\n"
+        );
+        assert_eq!(ed_script(mine, older, yours, &["-3"]), "2c\nB\n.\n");
+        assert_eq!(
+            ed_script(mine, older, yours, &["-i", "-3"]),
+            "2c\nB\n.\nw\nq\n"
+        );
+        assert_eq!(ed_script(mine, older, yours, &["-x"]), "4c\nd2\n.\n");
+        // The changes of MYFILE alone are already there.
+        assert_eq!(ed_script("a\nx\n", "a\n", "a\n", &["-e"]), "");
+        assert_eq!(ed_script("a\nx\n", "a\n", "a\n", &["-e", "-i"]), "w\nq\n");
+        assert_eq!(ed_script("x\na\nb\n", "a\nb\n", "a\n", &["-e"]), "3d\n");
+        assert_eq!(
+            ed_script("a\nb\nc\n", "a\nb\nc\n", "a\n", &["-e"]),
+            "2,3d\n"
+        );
+        // Lines that start with a dot are escaped, and incomplete ones get
+        // their newline.
+        assert_eq!(
+            ed_script("a\n", "a\n", "a\n.\n.b\nc", &["-e"]),
+            "1a\n..\n..b\nc\n.\n2,4s/^\\.//\n"
+        );
+    }
+
+    #[test]
+    fn show_all() {
+        let (mine, older, yours) = ("a\nb\nc\nD\n", "a\nb\nc\nd\n", "a\nB\nc\nd2\n");
+        assert_eq!(
+            ed_script(mine, older, yours, &["-A"]),
+            "4a\n||||||| older\nd\n=======\nd2\n>>>>>>> yours\n.\n\
+             3a\n<<<<<<< mine\n.\n2c\nB\n.\n"
+        );
+        // When only OLDFILE differs, its lines come first.
+        assert_eq!(
+            ed_script("a\nb\n", "a\nB\n", "a\nb\n", &["-A"]),
+            "2a\n>>>>>>> yours\n.\n1a\n<<<<<<< older\nB\n=======\n.\n"
+        );
+        assert_eq!(
+            ed_script("a\nb\n", "a\n.B\n", "a\n.c\n", &["-A"]),
+            "2a\n||||||| older\n..B\n=======\n..c\n>>>>>>> yours\n.\n4,6s/^\\.//\n\
+             1a\n<<<<<<< mine\n.\n"
+        );
+        assert_eq!(
+            ed_script("a\nb\n", "a\nB\n", "a\nb\n", &["-A", "-L", "x", "-L", "y"]),
+            "2a\n>>>>>>> yours\n.\n1a\n<<<<<<< y\nB\n=======\n.\n"
+        );
+    }
+
+    #[test]
+    fn bracketed_conflicts() {
+        let conflicts = |mine: &str, older: &str, yours: &str, option: &str| {
+            let files = [lines(mine), lines(older), lines(yours)];
+            let params = parse_params(
+                [os("diff3"), os(option), os("a"), os("b"), os("c")]
+                    .into_iter()
+                    .peekable(),
+            )
+            .unwrap();
+            write_ed_script(&mut Vec::new(), &files, &blocks(&files, OLDER), &params)
+        };
+        // Overlapping changes are only conflicts when they are bracketed.
+        assert!(!conflicts("a\nx\n", "a\nb\n", "a\ny\n", "-e"));
+        assert!(!conflicts("a\nx\n", "a\nb\n", "a\ny\n", "-x"));
+        assert!(conflicts("a\nx\n", "a\nb\n", "a\ny\n", "-A"));
+        assert!(conflicts("a\nb\n", "a\nB\n", "a\nb\n", "-A"));
+        assert!(!conflicts("a\nb\n", "a\nb\n", "a\nB\n", "-A"));
+    }
+
+    #[test]
+    fn non_utf8() {
+        // Latin-1 lines, which aren't valid UTF-8.
+        let files: [Vec<&[u8]>; 3] = [
+            vec![b"caf\xe9\n", b"na\xefve\n"],
+            vec![b"caf\xe9\n", b"naive\n"],
+            vec![b"cafe\n", b"naive\n"],
+        ];
+        let mut output = Vec::new();
+        write_normal(
+            &mut output,
+            &files,
+            &blocks(&files, YOURS),
+            &Params::default(),
+        );
+        assert_eq!(
+            output,
+            b"====\n1:1,2c\n  caf\xe9\n  na\xefve\n2:1,2c\n  caf\xe9\n  naive\n\
+              3:1,2c\n  cafe\n  naive\n"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let label = |bytes: &[u8]| OsStr::from_bytes(bytes).to_os_string();
+            let params = parse_params(
+                [
+                    os("diff3"),
+                    os("-A"),
+                    label(b"-Lm\xe9"),
+                    os("--label"),
+                    label(b"\xff"),
+                    os("a"),
+                    os("b"),
+                    os("c"),
+                ]
+                .into_iter()
+                .peekable(),
+            )
+            .unwrap();
+            assert_eq!(params.labels, vec![label(b"m\xe9"), label(b"\xff")]);
+            let mut output = Vec::new();
+            let conflicts = write_ed_script(&mut output, &files, &blocks(&files, OLDER), &params);
+            assert!(conflicts);
+            assert_eq!(
+                output,
+                b"2a\n||||||| \xff\ncaf\xe9\nnaive\n=======\ncafe\nnaive\n>>>>>>> c\n.\n\
+                  0a\n<<<<<<< m\xe9\n.\n"
+            );
+        }
+    }
+}
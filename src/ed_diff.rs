@@ -3,165 +3,115 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use std::io::Write;
+use std::io::{self, Write};
 
+use crate::hunks::Diff;
 use crate::params::Params;
 use crate::utils::do_write_line;
 
-#[derive(Debug, PartialEq)]
-struct Mismatch {
-    pub line_number_expected: usize,
-    pub line_number_actual: usize,
-    pub expected: Vec<Vec<u8>>,
-    pub actual: Vec<Vec<u8>>,
-}
-
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum DiffError {
     MissingNL,
+    Io(io::Error),
 }
 
 impl std::fmt::Display for DiffError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        std::fmt::Display::fmt("No newline at end of file", f)
+        match self {
+            DiffError::MissingNL => std::fmt::Display::fmt("No newline at end of file", f),
+            DiffError::Io(error) => std::fmt::Display::fmt(error, f),
+        }
     }
 }
 
-impl From<DiffError> for String {
-    fn from(_: DiffError) -> String {
-        "No newline at end of file".into()
+impl From<io::Error> for DiffError {
+    fn from(error: io::Error) -> DiffError {
+        DiffError::Io(error)
     }
 }
 
-impl Mismatch {
-    fn new(line_number_expected: usize, line_number_actual: usize) -> Mismatch {
-        Mismatch {
-            line_number_expected,
-            line_number_actual,
-            expected: Vec::new(),
-            actual: Vec::new(),
-        }
+impl From<DiffError> for String {
+    fn from(error: DiffError) -> String {
+        error.to_string()
     }
 }
 
-// Produces a diff between the expected output and actual output.
-fn make_diff(expected: &[u8], actual: &[u8], stop_early: bool) -> Result<Vec<Mismatch>, DiffError> {
-    let mut line_number_expected = 1;
-    let mut line_number_actual = 1;
-    let mut results = Vec::new();
-    let mut mismatch = Mismatch::new(line_number_expected, line_number_actual);
-
-    let mut expected_lines: Vec<&[u8]> = expected.split(|&c| c == b'\n').collect();
-    let mut actual_lines: Vec<&[u8]> = actual.split(|&c| c == b'\n').collect();
-
-    debug_assert_eq!(b"".split(|&c| c == b'\n').count(), 1);
-    // ^ means that underflow here is impossible
-    let _expected_lines_count = expected_lines.len() - 1;
-    let _actual_lines_count = actual_lines.len() - 1;
-
-    if expected_lines.last() == Some(&&b""[..]) {
-        expected_lines.pop();
-    } else {
-        return Err(DiffError::MissingNL);
-    }
-
-    if actual_lines.last() == Some(&&b""[..]) {
-        actual_lines.pop();
-    } else {
-        return Err(DiffError::MissingNL);
-    }
-
-    for result in diff::slice(&expected_lines, &actual_lines) {
-        match result {
-            diff::Result::Left(str) => {
-                if !mismatch.actual.is_empty() {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(line_number_expected, line_number_actual);
-                }
-                mismatch.expected.push(str.to_vec());
-                line_number_expected += 1;
-            }
-            diff::Result::Right(str) => {
-                mismatch.actual.push(str.to_vec());
-                line_number_actual += 1;
-            }
-            diff::Result::Both(_str, _) => {
-                line_number_expected += 1;
-                line_number_actual += 1;
-                if !mismatch.actual.is_empty() || !mismatch.expected.is_empty() {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(line_number_expected, line_number_actual);
-                } else {
-                    mismatch.line_number_expected = line_number_expected;
-                    mismatch.line_number_actual = line_number_actual;
-                }
-            }
-        }
-        if stop_early && !results.is_empty() {
-            // Optimization: stop analyzing the files as soon as there are any differences
-            return Ok(results);
+/// Writes the differences as an ed script that turns the expected file into
+/// the actual one. Both files must end with a newline.
+pub fn diff(output: &mut impl Write, diff: &Diff, params: &Params) -> Result<(), DiffError> {
+    // An ed script can't tell that a file doesn't end with a newline.
+    for lines in [&diff.expected_lines, &diff.actual_lines] {
+        if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+            return Err(DiffError::MissingNL);
         }
     }
-
-    if !mismatch.actual.is_empty() || !mismatch.expected.is_empty() {
-        results.push(mismatch);
-    }
-
-    Ok(results)
-}
-
-pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Result<Vec<u8>, DiffError> {
-    let mut output = Vec::new();
-    let diff_results = make_diff(expected, actual, params.brief)?;
-    if params.brief && !diff_results.is_empty() {
-        write!(&mut output, "\0").unwrap();
-        return Ok(output);
+    let changes: Vec<_> = diff.changes().collect();
+    if params.brief && !changes.is_empty() {
+        write!(output, "\0")?;
+        return Ok(());
     }
-    let mut lines_offset = 0;
-    for result in diff_results {
-        let line_number_expected: isize = result.line_number_expected as isize + lines_offset;
-        let _line_number_actual: isize = result.line_number_actual as isize + lines_offset;
-        let expected_count: isize = result.expected.len() as isize;
-        let actual_count: isize = result.actual.len() as isize;
-        match (expected_count, actual_count) {
-            (0, 0) => unreachable!(),
-            (0, _) => writeln!(&mut output, "{}a", line_number_expected - 1).unwrap(),
-            (_, 0) => writeln!(
-                &mut output,
-                "{},{}d",
-                line_number_expected,
-                expected_count + line_number_expected - 1
-            )
-            .unwrap(),
-            (1, _) => writeln!(&mut output, "{line_number_expected}c").unwrap(),
-            _ => writeln!(
-                &mut output,
-                "{},{}c",
+    // The commands are emitted from the end of the file to its start, so that
+    // applying one doesn't shift the line numbers used by the following ones.
+    for change in changes.into_iter().rev() {
+        let line_number_expected = change.expected.start + 1;
+        let expected_count = change.expected.len();
+        let actual_count = change.actual.len();
+        let range = if expected_count <= 1 {
+            format!("{line_number_expected}")
+        } else {
+            format!(
+                "{},{}",
                 line_number_expected,
                 expected_count + line_number_expected - 1
             )
-            .unwrap(),
+        };
+        match (expected_count, actual_count) {
+            (0, 0) => unreachable!(),
+            (0, _) => writeln!(output, "{}a", line_number_expected - 1)?,
+            (_, 0) => writeln!(output, "{range}d")?,
+            _ => writeln!(output, "{range}c")?,
         }
-        lines_offset += actual_count - expected_count;
         if actual_count != 0 {
-            for actual in &result.actual {
+            let mut insert_mode = true;
+            for actual in diff.added(change) {
+                let actual = actual.strip_suffix(b"\n").unwrap_or(actual);
+                if !insert_mode {
+                    writeln!(output, "a")?;
+                    insert_mode = true;
+                }
                 if actual == b"." {
-                    writeln!(&mut output, "..\n.\ns/.//\na").unwrap();
+                    // A line with a single dot would end the insert mode, so
+                    // write it with an extra dot, then leave the insert mode
+                    // and remove the extra dot.
+                    writeln!(output, "..\n.\ns/.//")?;
+                    insert_mode = false;
                 } else {
-                    do_write_line(&mut output, actual, params.expand_tabs, params.tabsize).unwrap();
-                    writeln!(&mut output).unwrap();
+                    do_write_line(output, actual, params.expand_tabs, params.tabsize)?;
+                    writeln!(output)?;
                 }
             }
-            writeln!(&mut output, ".").unwrap();
+            if insert_mode {
+                writeln!(output, ".")?;
+            }
         }
     }
-    Ok(output)
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hunks::{self, CompareOptions};
     use pretty_assertions::assert_eq;
+    use std::io::Write;
+
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Result<Vec<u8>, DiffError> {
+        let mut output = Vec::new();
+        let diff = hunks::compare(expected, actual, &CompareOptions::from(params));
+        super::diff(&mut output, &diff, params)?;
+        Ok(output)
+    }
+
     pub fn diff_w(expected: &[u8], actual: &[u8], filename: &str) -> Result<Vec<u8>, DiffError> {
         let mut output = diff(expected, actual, &Params::default())?;
         writeln!(&mut output, "w {filename}").unwrap();
@@ -177,6 +127,27 @@ mod tests {
         assert_eq!(diff, expected.as_bytes());
     }
 
+    #[test]
+    fn test_reverse_order() {
+        let from = b"a\nb\nc\nd\ne\n";
+        let to = b"a\nx\nc\nd\n";
+        let diff = diff(from, to, &Params::default()).unwrap();
+        let expected = ["5d", "2c", "x", ".", ""].join("\n");
+        assert_eq!(diff, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_single_dot() {
+        let from = b"a\nb\n";
+        let to = b"a\n.\n.\nb\nc\n";
+        let diff = diff(from, to, &Params::default()).unwrap();
+        let expected = [
+            "2a", "c", ".", "1a", "..", ".", "s/.//", "a", "..", ".", "s/.//", "",
+        ]
+        .join("\n");
+        assert_eq!(diff, expected.as_bytes());
+    }
+
     #[test]
     fn test_permutations() {
         let target = "target/ed-diff/";
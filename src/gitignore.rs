@@ -0,0 +1,158 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use regex::Regex;
+
+use crate::utils::path_wildcard_to_regex;
+
+/// The name of the files that rules are read from.
+pub const IGNORE_FILE: &str = ".gitignore";
+
+// A line of a .gitignore file.
+#[derive(Clone, Debug)]
+struct Rule {
+    // Matches the whole path of an entry, relative to the top directory.
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of the .gitignore files that apply to the entries of a
+/// directory, read from that directory and from its parents, up to the
+/// directories being compared.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    // The path of the directory, relative to the top directory, with a
+    // trailing slash unless it is the top directory.
+    dir: String,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// The rules that apply to the entries of the subdirectory `name`,
+    /// before its own .gitignore file is read.
+    #[must_use]
+    pub fn subdirectory(&self, name: &str) -> Self {
+        Self {
+            dir: format!("{}{name}/", self.dir),
+            rules: self.rules.clone(),
+        }
+    }
+
+    /// Adds the rules of a .gitignore file of the directory. Like with git,
+    /// the later rules take precedence over the earlier ones.
+    pub fn add(&mut self, contents: &[u8]) {
+        for line in String::from_utf8_lossy(contents).lines() {
+            let mut pattern = line;
+            // Trailing spaces are ignored, unless they are escaped.
+            while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+                pattern = &pattern[..pattern.len() - 1];
+            }
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            let negated = pattern.starts_with('!');
+            if negated {
+                pattern = &pattern[1..];
+            }
+            let dir_only = pattern.ends_with('/');
+            if dir_only {
+                pattern = &pattern[..pattern.len() - 1];
+            }
+            // A pattern with a slash is relative to the directory, and one
+            // without matches names at any depth.
+            let any_depth = if pattern.contains('/') {
+                pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+                ""
+            } else {
+                "(?:.*/)?"
+            };
+            if pattern.is_empty() {
+                continue;
+            }
+            let regex = format!(
+                "^{}{any_depth}(?:{})$",
+                regex::escape(&self.dir),
+                path_wildcard_to_regex(pattern)
+            );
+            if let Ok(regex) = Regex::new(&regex) {
+                self.rules.push(Rule {
+                    regex,
+                    negated,
+                    dir_only,
+                });
+            }
+        }
+    }
+
+    /// Whether the entry `name` of the directory is ignored.
+    #[must_use]
+    pub fn ignores(&self, name: &str, is_dir: bool) -> bool {
+        let path = format!("{}{name}", self.dir);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.regex.is_match(&path))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(contents: &str) -> IgnoreRules {
+        let mut rules = IgnoreRules::default();
+        rules.add(contents.as_bytes());
+        rules
+    }
+
+    #[test]
+    fn names() {
+        let rules = rules("# build output\n*.o\ntarget/\n\n\\#notes\n");
+        assert!(rules.ignores("foo.o", false));
+        assert!(!rules.ignores("foo.c", false));
+        assert!(rules.ignores("target", true));
+        assert!(!rules.ignores("target", false));
+        assert!(rules.ignores("#notes", false));
+        assert!(!rules.ignores("# build output", false));
+
+        // Names without slashes match at any depth.
+        let rules = rules.subdirectory("src").subdirectory("target");
+        assert!(rules.ignores("foo.o", false));
+        assert!(rules.ignores("target", true));
+    }
+
+    #[test]
+    fn paths() {
+        let mut rules = rules("/build\ndocs/*.html\n");
+        assert!(rules.ignores("build", true));
+        assert!(!rules.subdirectory("src").ignores("build", true));
+        assert!(rules.subdirectory("docs").ignores("index.html", false));
+        assert!(!rules
+            .subdirectory("docs")
+            .subdirectory("api")
+            .ignores("index.html", false));
+
+        // The rules of a subdirectory are relative to it.
+        let mut sub = rules.subdirectory("sub");
+        sub.add(b"/only-here\n");
+        assert!(sub.ignores("only-here", false));
+        assert!(!sub.subdirectory("deeper").ignores("only-here", false));
+        rules.add(b"trailing  \nescaped\\ \n");
+        assert!(rules.ignores("trailing", false));
+        assert!(rules.ignores("escaped ", false));
+    }
+
+    #[test]
+    fn negation() {
+        let mut rules = rules("*.log\n!keep.log\n");
+        assert!(rules.ignores("debug.log", false));
+        assert!(!rules.ignores("keep.log", false));
+        // Later rules take precedence.
+        rules.add(b"keep.*\n");
+        assert!(rules.ignores("keep.log", false));
+    }
+}
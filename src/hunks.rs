@@ -0,0 +1,435 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::cmp;
+use std::ops::Range;
+
+use crate::params::{Normalization, Params, Tolerance};
+use crate::utils::{diff_lines, IgnoredLines, LinePattern};
+
+/// How to compare two files: which differences between their lines don't
+/// count, and how many lines of context go around the changes of hunks.
+/// The command-line options of diff make them with `CompareOptions::from`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompareOptions {
+    /// The number of lines of context around the changes of hunks.
+    pub context: usize,
+    /// Whether to look for the smallest set of changes, like -d, however
+    /// large the files.
+    pub minimal: bool,
+    /// Whether white space is ignored, like with -w.
+    pub ignore_all_space: bool,
+    /// Whether tabs compare equal to the spaces they expand to, every
+    /// `tabsize` columns, like with -E.
+    pub ignore_tab_expansion: bool,
+    pub tabsize: usize,
+    /// Whether ANSI escape sequences are ignored, like with --ignore-ansi.
+    pub ignore_ansi: bool,
+    /// The Unicode normalization form lines are compared in, like with
+    /// --normalize.
+    pub normalize: Option<Normalization>,
+    /// The tolerance numbers are compared with, like with --numeric-fuzz.
+    pub numeric_fuzz: Option<Tolerance>,
+    /// The patterns of the lines whose changes are ignored, like with -I.
+    pub ignore_matching_lines: Vec<LinePattern>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            minimal: false,
+            ignore_all_space: false,
+            ignore_tab_expansion: false,
+            tabsize: 8,
+            ignore_ansi: false,
+            normalize: None,
+            numeric_fuzz: None,
+            ignore_matching_lines: Vec::new(),
+        }
+    }
+}
+
+impl From<&Params> for CompareOptions {
+    fn from(params: &Params) -> Self {
+        Self {
+            context: params.context_count,
+            minimal: params.minimal,
+            ignore_all_space: params.ignore_all_space,
+            ignore_tab_expansion: params.ignore_tab_expansion,
+            tabsize: params.tabsize,
+            ignore_ansi: params.ignore_ansi,
+            normalize: params.normalize,
+            numeric_fuzz: params.numeric_fuzz,
+            ignore_matching_lines: params.ignore_matching_lines.clone(),
+        }
+    }
+}
+
+/// A maximal run of differing lines, expressed as ranges of (0-based) line
+/// indices in the expected and actual files.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    pub expected: Range<usize>,
+    pub actual: Range<usize>,
+    /// Whether all the lines of the change are ignored with -I.
+    pub ignored: bool,
+}
+
+/// What a change does to the expected file to get the actual one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Adds lines, after the expected lines before the change.
+    Add,
+    /// Deletes lines.
+    Delete,
+    /// Replaces lines with others.
+    Change,
+}
+
+impl Change {
+    /// What the change does, from which files have lines in it.
+    #[must_use]
+    pub fn operation(&self) -> Operation {
+        match (self.expected.is_empty(), self.actual.is_empty()) {
+            (true, _) => Operation::Add,
+            (_, true) => Operation::Delete,
+            _ => Operation::Change,
+        }
+    }
+}
+
+/// Changes that are output together, along with the context around them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hunk {
+    /// The lines covered by the hunk, context included.
+    pub expected: Range<usize>,
+    pub actual: Range<usize>,
+    pub changes: Vec<Change>,
+}
+
+/// The differences between two files: their lines, which keep their trailing
+/// newline, and the hunks of changes between them, which refer to the lines
+/// by their indices.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diff<'a> {
+    pub expected_lines: Vec<&'a [u8]>,
+    pub actual_lines: Vec<&'a [u8]>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl<'a> Diff<'a> {
+    /// The lines of the expected file that a change removes.
+    #[must_use]
+    pub fn removed(&self, change: &Change) -> &[&'a [u8]] {
+        &self.expected_lines[change.expected.clone()]
+    }
+
+    /// The lines of the actual file that a change adds.
+    #[must_use]
+    pub fn added(&self, change: &Change) -> &[&'a [u8]] {
+        &self.actual_lines[change.actual.clone()]
+    }
+
+    /// The changes of all the hunks that aren't ignored with -I, in order.
+    pub fn changes(&self) -> impl Iterator<Item = &Change> {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.changes)
+            .filter(|change| !change.ignored)
+    }
+}
+
+/// Compares two files line by line, with the options that make some
+/// differences insignificant, and groups the changes into hunks with
+/// `options.context` lines of context. This is what all the formats of diff
+/// output, and what other programs can use to compare files too.
+#[must_use]
+pub fn compare<'a>(expected: &'a [u8], actual: &'a [u8], options: &CompareOptions) -> Diff<'a> {
+    let expected_lines: Vec<&[u8]> = expected.split_inclusive(|&c| c == b'\n').collect();
+    let actual_lines: Vec<&[u8]> = actual.split_inclusive(|&c| c == b'\n').collect();
+    let changes = changes(&expected_lines, &actual_lines, options);
+    let hunks = hunks(changes, options.context, expected_lines.len());
+    Diff {
+        expected_lines,
+        actual_lines,
+        hunks,
+    }
+}
+
+/// Collects the changes between two files, in order. Lines keep their
+/// trailing newline, so that a last line that is missing its newline doesn't
+/// compare equal to the same line with a newline.
+#[must_use]
+pub fn changes(
+    expected_lines: &[&[u8]],
+    actual_lines: &[&[u8]],
+    options: &CompareOptions,
+) -> Vec<Change> {
+    let ignored_lines = IgnoredLines::new(options);
+    let finish = |expected: Range<usize>, actual: Range<usize>| {
+        let ignored = ignored_lines.ignores(
+            expected_lines[expected.clone()]
+                .iter()
+                .chain(&actual_lines[actual.clone()])
+                .copied(),
+        );
+        Change {
+            expected,
+            actual,
+            ignored,
+        }
+    };
+
+    let mut changes = Vec::new();
+    let mut change: Option<(Range<usize>, Range<usize>)> = None;
+    let mut line_expected = 0;
+    let mut line_actual = 0;
+    for result in diff_lines(expected_lines, actual_lines, options) {
+        match result {
+            diff::Result::Left(_) => {
+                line_expected += 1;
+                change
+                    .get_or_insert((
+                        line_expected - 1..line_expected - 1,
+                        line_actual..line_actual,
+                    ))
+                    .0
+                    .end = line_expected;
+            }
+            diff::Result::Right(_) => {
+                line_actual += 1;
+                change
+                    .get_or_insert((
+                        line_expected..line_expected,
+                        line_actual - 1..line_actual - 1,
+                    ))
+                    .1
+                    .end = line_actual;
+            }
+            diff::Result::Both(_, _) => {
+                if let Some((expected, actual)) = change.take() {
+                    changes.push(finish(expected, actual));
+                }
+                line_expected += 1;
+                line_actual += 1;
+            }
+        }
+    }
+    changes.extend(change.map(|(expected, actual)| finish(expected, actual)));
+    changes
+}
+
+/// Groups changes into hunks with `context_size` lines of context, merging
+/// the changes whose context would overlap or touch (i.e. that are at most
+/// 2 * `context_size` lines apart), so that hunks never overlap.
+/// Changes ignored with -I are only merged when they are within the context
+/// of the previous change, and hunks made only of such changes are dropped.
+/// `expected_len` is the number of lines of the expected file.
+#[must_use]
+pub fn hunks(changes: Vec<Change>, context_size: usize, expected_len: usize) -> Vec<Hunk> {
+    let threshold = |change: &Change| {
+        if change.ignored {
+            context_size
+        } else {
+            2 * context_size + 1
+        }
+    };
+
+    let mut hunks = Vec::new();
+    let mut changes = changes.into_iter().peekable();
+    while let Some(first) = changes.next() {
+        let mut group = vec![first];
+        while let Some(next) = changes.next_if(|next| {
+            next.expected.start - group.last().unwrap().expected.end < threshold(next)
+        }) {
+            group.push(next);
+        }
+        if group.iter().all(|change| change.ignored) {
+            continue;
+        }
+
+        let (first, last) = (&group[0], &group[group.len() - 1]);
+        // The lines around the changes are the same in both files.
+        let context_before = cmp::min(first.expected.start, context_size);
+        let context_after = cmp::min(expected_len - last.expected.end, context_size);
+        hunks.push(Hunk {
+            expected: first.expected.start - context_before..last.expected.end + context_after,
+            actual: first.actual.start - context_before..last.actual.end + context_after,
+            changes: group,
+        });
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn change(expected: Range<usize>, actual: Range<usize>) -> Change {
+        Change {
+            expected,
+            actual,
+            ignored: false,
+        }
+    }
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        text.as_bytes().split_inclusive(|&c| c == b'\n').collect()
+    }
+
+    #[test]
+    fn collect_changes() {
+        let expected = lines("a\nb\nc\nd\ne\n");
+        let actual = lines("a\nB\nc\nd\nE\nf");
+        let options = CompareOptions::default();
+        assert_eq!(
+            changes(&expected, &actual, &options),
+            [change(1..2, 1..2), change(4..5, 4..6)]
+        );
+        assert_eq!(changes(&expected, &expected, &options), []);
+    }
+
+    #[test]
+    fn ignored_changes() {
+        let expected = lines("#a\nb\nc\nd\ne\n");
+        let actual = lines("b\nC\nd\nE\n");
+        let options = CompareOptions {
+            ignore_matching_lines: vec![LinePattern::new("^#").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(
+            changes(&expected, &actual, &options),
+            [
+                Change {
+                    expected: 0..1,
+                    actual: 0..0,
+                    ignored: true,
+                },
+                change(2..3, 1..2),
+                change(4..5, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_context() {
+        // Changes 6 lines apart have touching context with 3 lines of context
+        let changes = vec![
+            change(2..3, 2..3),
+            change(9..10, 9..11),
+            change(17..17, 18..19),
+        ];
+        assert_eq!(
+            hunks(changes.clone(), 3, 20),
+            [
+                Hunk {
+                    expected: 0..13,
+                    actual: 0..14,
+                    changes: changes[..2].to_vec(),
+                },
+                Hunk {
+                    expected: 14..20,
+                    actual: 15..22,
+                    changes: changes[2..].to_vec(),
+                },
+            ]
+        );
+        assert_eq!(
+            hunks(changes.clone(), 4, 20),
+            [Hunk {
+                expected: 0..20,
+                actual: 0..22,
+                changes,
+            }]
+        );
+    }
+
+    #[test]
+    fn no_context() {
+        let changes = vec![change(0..1, 0..1), change(2..2, 2..3), change(3..4, 4..4)];
+        assert_eq!(
+            hunks(changes.clone(), 0, 4),
+            [
+                Hunk {
+                    expected: 0..1,
+                    actual: 0..1,
+                    changes: changes[..1].to_vec(),
+                },
+                Hunk {
+                    expected: 2..2,
+                    actual: 2..3,
+                    changes: changes[1..2].to_vec(),
+                },
+                Hunk {
+                    expected: 3..4,
+                    actual: 4..4,
+                    changes: changes[2..].to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_files() {
+        let options = CompareOptions::from(&Params {
+            context_count: 1,
+            ..Default::default()
+        });
+        let diff = compare(b"a\nb\nc\nd\ne\nf\ng\n", b"a\nB\nc\nd\ne\ng\nh", &options);
+        assert_eq!(
+            diff.hunks,
+            [
+                Hunk {
+                    expected: 0..3,
+                    actual: 0..3,
+                    changes: vec![change(1..2, 1..2)],
+                },
+                Hunk {
+                    expected: 4..7,
+                    actual: 4..7,
+                    changes: vec![change(5..6, 5..5), change(7..7, 6..7)],
+                },
+            ]
+        );
+        let changes: Vec<_> = diff.changes().collect();
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| change.operation())
+                .collect::<Vec<_>>(),
+            [Operation::Change, Operation::Delete, Operation::Add]
+        );
+        assert_eq!(diff.removed(changes[0]), [b"b\n"]);
+        assert_eq!(diff.added(changes[0]), [b"B\n"]);
+        assert_eq!(diff.added(changes[2]), [b"h"]);
+        assert!(compare(b"a\n", b"a\n", &options).hunks.is_empty());
+    }
+
+    #[test]
+    fn ignored_hunks() {
+        let ignored = |expected, actual| Change {
+            ignored: true,
+            ..change(expected, actual)
+        };
+        // An ignored change is merged only within the context of the
+        // previous change, and a hunk of ignored changes is dropped.
+        let changes = vec![
+            change(0..1, 0..1),
+            ignored(2..3, 2..3),
+            ignored(8..9, 8..9),
+            ignored(12..13, 12..13),
+        ];
+        assert_eq!(
+            hunks(changes.clone(), 2, 20),
+            [Hunk {
+                expected: 0..5,
+                actual: 0..5,
+                changes: changes[..2].to_vec(),
+            }]
+        );
+    }
+}
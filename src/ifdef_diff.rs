@@ -0,0 +1,590 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::hunks::{self, CompareOptions};
+use crate::params::Params;
+use crate::utils::do_write_line;
+
+// The line format used when none is given: the line, always followed by a
+// newline, even the last line of a file that doesn't end with one.
+const DEFAULT_LINE_FORMAT: &str = "%l\n";
+
+// A printf-style specification of how to format a number:
+// %[-][WIDTH][.[PREC]]{doxX}, where 0 and ' flags are also accepted.
+#[derive(Debug, Eq, PartialEq)]
+struct NumberSpec {
+    left_justify: bool,
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+impl NumberSpec {
+    // Parses a specification from just after its '%', returning it along
+    // with its length.
+    fn parse(spec: &[u8]) -> Option<(Self, usize)> {
+        let mut i = 0;
+        let mut left_justify = false;
+        let mut zero_pad = false;
+        while let Some(&flag) = spec.get(i) {
+            match flag {
+                b'-' => left_justify = true,
+                b'0' => zero_pad = true,
+                // Grouping digits by thousands has no effect in the C locale.
+                b'\'' => {}
+                _ => break,
+            }
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while spec.get(*i).is_some_and(u8::is_ascii_digit) {
+                *i += 1;
+            }
+            std::str::from_utf8(&spec[start..*i])
+                .unwrap()
+                .parse::<usize>()
+                .ok()
+        };
+        let width = digits(&mut i).unwrap_or(0);
+        let precision = if spec.get(i) == Some(&b'.') {
+            i += 1;
+            Some(digits(&mut i).unwrap_or(0))
+        } else {
+            None
+        };
+        let conversion = *spec.get(i).filter(|c| b"doxX".contains(c))?;
+        Some((
+            Self {
+                left_justify,
+                zero_pad,
+                width,
+                precision,
+                conversion,
+            },
+            i + 1,
+        ))
+    }
+
+    fn format(&self, number: usize) -> String {
+        let digits = match self.conversion {
+            b'o' => format!("{number:o}"),
+            b'x' => format!("{number:x}"),
+            b'X' => format!("{number:X}"),
+            _ => number.to_string(),
+        };
+        let width = self.width;
+        match self.precision {
+            // Like printf, a zero precision formats zero as nothing.
+            Some(0) if number == 0 => format!("{:width$}", ""),
+            Some(precision) if self.left_justify => {
+                format!("{:<width$}", format!("{digits:0>precision$}"))
+            }
+            Some(precision) => format!("{:>width$}", format!("{digits:0>precision$}")),
+            None if self.left_justify => format!("{digits:<width$}"),
+            None if self.zero_pad => format!("{digits:0>width$}"),
+            None => format!("{digits:>width$}"),
+        }
+    }
+}
+
+// Parses the quoted character of a %c directive, 'C' or '\OOO', from just
+// after the c, returning it along with its length.
+fn parse_char(quoted: &[u8]) -> Option<(u8, usize)> {
+    match quoted {
+        [b'\'', b'\\', rest @ ..] => {
+            let digits = rest
+                .iter()
+                .take(3)
+                .take_while(|c| (b'0'..=b'7').contains(c))
+                .count();
+            let code = rest[..digits]
+                .iter()
+                .fold(0u32, |code, digit| code * 8 + u32::from(digit - b'0'));
+            match rest.get(digits) {
+                Some(b'\'') if digits > 0 && code <= 0xff => Some((code as u8, digits + 3)),
+                _ => None,
+            }
+        }
+        [b'\'', c, b'\'', ..] if *c != b'\'' => Some((*c, 3)),
+        _ => None,
+    }
+}
+
+// Writes a line in a line format, where %l stands for the line without its
+// newline, %L for the line, %[spec]n for its number, %c'C' for the character C
+// and %% for %. Anything else is written as is.
+fn write_formatted_line(
+    output: &mut dyn Write,
+    format: &[u8],
+    line: &[u8],
+    line_number: usize,
+    params: &Params,
+) -> io::Result<()> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let mut i = 0;
+    while i < format.len() {
+        let c = format[i];
+        i += 1;
+        if c != b'%' {
+            output.write_all(&[c])?;
+            continue;
+        }
+        match format.get(i) {
+            Some(b'l') => {
+                do_write_line(output, content, params.expand_tabs, params.tabsize)?;
+                i += 1;
+            }
+            Some(b'L') => {
+                do_write_line(output, content, params.expand_tabs, params.tabsize)?;
+                output.write_all(&line[content.len()..])?;
+                i += 1;
+            }
+            Some(b'%') => {
+                output.write_all(b"%")?;
+                i += 1;
+            }
+            Some(b'c') => match parse_char(&format[i + 1..]) {
+                Some((c, len)) => {
+                    output.write_all(&[c])?;
+                    i += 1 + len;
+                }
+                None => output.write_all(b"%")?,
+            },
+            _ => match NumberSpec::parse(&format[i..]) {
+                Some((spec, len)) if format.get(i + len) == Some(&b'n') => {
+                    output.write_all(spec.format(line_number).as_bytes())?;
+                    i += len + 1;
+                }
+                _ => output.write_all(b"%")?,
+            },
+        }
+    }
+    Ok(())
+}
+
+// Writes a range of (0-based) lines in a line format.
+fn write_lines(
+    output: &mut dyn Write,
+    lines: &[&[u8]],
+    range: Range<usize>,
+    format: Option<&String>,
+    params: &Params,
+) -> io::Result<()> {
+    let format = format.map_or(DEFAULT_LINE_FORMAT, String::as_str);
+    for line_number in range {
+        write_formatted_line(
+            output,
+            format.as_bytes(),
+            lines[line_number],
+            line_number + 1,
+            params,
+        )?;
+    }
+    Ok(())
+}
+
+// A group of lines, expressed as ranges of (0-based) line indices in the old
+// and new files.
+struct Group<'a> {
+    expected_lines: &'a [&'a [u8]],
+    actual_lines: &'a [&'a [u8]],
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+impl Group<'_> {
+    // The value of a letter of a group format: F for the first line number,
+    // L for the last, N for the number of lines, E for F-1 and M for L+1.
+    // Upper case letters are for the new lines, lower case for the old ones.
+    fn letter_value(&self, letter: u8) -> Option<usize> {
+        let range = if letter.is_ascii_uppercase() {
+            &self.new
+        } else {
+            &self.old
+        };
+        match letter.to_ascii_lowercase() {
+            b'e' => Some(range.start),
+            b'f' => Some(range.start + 1),
+            b'l' => Some(range.end),
+            b'm' => Some(range.end + 1),
+            b'n' => Some(range.len()),
+            _ => None,
+        }
+    }
+}
+
+// Parses the A=B? part of a %(A=B?T:E) directive, where A and B are decimal
+// numbers or letters, returning whether they are equal along with its length.
+fn parse_condition(format: &[u8], group: &Group) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let mut values = [0; 2];
+    for (value, separator) in values.iter_mut().zip([b'=', b'?']) {
+        let digits = format[i..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits > 0 {
+            *value = std::str::from_utf8(&format[i..i + digits])
+                .unwrap()
+                .parse()
+                .ok()?;
+            i += digits;
+        } else {
+            *value = group.letter_value(*format.get(i)?)?;
+            i += 1;
+        }
+        if format.get(i) != Some(&separator) {
+            return None;
+        }
+        i += 1;
+    }
+    Some((values[0] == values[1], i))
+}
+
+// Writes a group of lines in a group format, where %< stands for its old
+// lines, %> for its new lines, %= for its (common) old lines, %[spec]LETTER
+// for one of its line numbers and %(A=B?T:E) for T if A equals B and E
+// otherwise. The format ends at the delimiter, if any, and the length that
+// was used is returned.
+fn write_group(
+    output: &mut dyn Write,
+    format: &[u8],
+    delimiter: Option<u8>,
+    group: &Group,
+    params: &Params,
+) -> io::Result<usize> {
+    let mut i = 0;
+    while i < format.len() && Some(format[i]) != delimiter {
+        let c = format[i];
+        i += 1;
+        if c != b'%' {
+            output.write_all(&[c])?;
+            continue;
+        }
+        match format.get(i) {
+            Some(b'<') => {
+                let format = params.old_line_format.as_ref();
+                write_lines(
+                    output,
+                    group.expected_lines,
+                    group.old.clone(),
+                    format,
+                    params,
+                )?;
+                i += 1;
+            }
+            Some(b'>') => {
+                let format = params.new_line_format.as_ref();
+                write_lines(
+                    output,
+                    group.actual_lines,
+                    group.new.clone(),
+                    format,
+                    params,
+                )?;
+                i += 1;
+            }
+            Some(b'=') => {
+                let format = params.unchanged_line_format.as_ref();
+                write_lines(
+                    output,
+                    group.expected_lines,
+                    group.old.clone(),
+                    format,
+                    params,
+                )?;
+                i += 1;
+            }
+            Some(b'%') => {
+                output.write_all(b"%")?;
+                i += 1;
+            }
+            Some(b'c') => match parse_char(&format[i + 1..]) {
+                Some((c, len)) => {
+                    output.write_all(&[c])?;
+                    i += 1 + len;
+                }
+                None => output.write_all(b"%")?,
+            },
+            Some(b'(') => match parse_condition(&format[i + 1..], group) {
+                Some((equal, len)) => {
+                    i += 1 + len;
+                    // The branch that isn't taken is written nowhere.
+                    let mut skipped = io::sink();
+                    let (then_output, else_output): (&mut dyn Write, &mut dyn Write) = if equal {
+                        (&mut *output, &mut skipped)
+                    } else {
+                        (&mut skipped, &mut *output)
+                    };
+                    i += write_group(then_output, &format[i..], Some(b':'), group, params)?;
+                    if i < format.len() {
+                        i += 1;
+                        i += write_group(else_output, &format[i..], Some(b')'), group, params)?;
+                        if i < format.len() {
+                            i += 1;
+                        }
+                    }
+                }
+                None => output.write_all(b"%")?,
+            },
+            _ => match NumberSpec::parse(&format[i..]).and_then(|(spec, len)| {
+                let value = group.letter_value(*format.get(i + len)?)?;
+                Some((spec.format(value), len))
+            }) {
+                Some((number, len)) => {
+                    output.write_all(number.as_bytes())?;
+                    i += len + 1;
+                }
+                None => output.write_all(b"%")?,
+            },
+        }
+    }
+    Ok(i)
+}
+
+/// Produces the merged contents of both files, where each group of lines is
+/// output in the format given for its kind (--old-group-format,
+/// --new-group-format, --changed-group-format and --unchanged-group-format),
+/// and each line in the format given for its kind (--old-line-format,
+/// --new-line-format and --unchanged-line-format). -D NAME sets the group
+/// formats to wrap the changes in `#ifndef NAME` and `#ifdef NAME` blocks.
+pub fn diff(
+    output: &mut impl Write,
+    expected: &[u8],
+    actual: &[u8],
+    params: &Params,
+) -> io::Result<()> {
+    let expected_lines: Vec<&[u8]> = expected.split_inclusive(|&c| c == b'\n').collect();
+    let actual_lines: Vec<&[u8]> = actual.split_inclusive(|&c| c == b'\n').collect();
+    let old_format = (params.old_group_format.as_ref())
+        .or(params.changed_group_format.as_ref())
+        .map_or("%<", String::as_str);
+    let new_format = (params.new_group_format.as_ref())
+        .or(params.changed_group_format.as_ref())
+        .map_or("%>", String::as_str);
+    let unchanged_format = params.unchanged_group_format.as_deref().unwrap_or("%=");
+    let changed_format = params
+        .changed_group_format
+        .clone()
+        .unwrap_or_else(|| format!("{old_format}{new_format}"));
+
+    let mut write = |format: &str, old: Range<usize>, new: Range<usize>| {
+        let group = Group {
+            expected_lines: &expected_lines,
+            actual_lines: &actual_lines,
+            old,
+            new,
+        };
+        write_group(output, format.as_bytes(), None, &group, params).map(|_| ())
+    };
+    let mut line_expected = 0;
+    let mut line_actual = 0;
+    // Changes ignored with -I are part of the common lines around them.
+    for change in hunks::changes(
+        &expected_lines,
+        &actual_lines,
+        &CompareOptions::from(params),
+    )
+    .into_iter()
+    .filter(|change| !change.ignored)
+    {
+        if line_expected < change.expected.start {
+            write(
+                unchanged_format,
+                line_expected..change.expected.start,
+                line_actual..change.actual.start,
+            )?;
+        }
+        let format = if change.actual.is_empty() {
+            old_format
+        } else if change.expected.is_empty() {
+            new_format
+        } else {
+            &changed_format
+        };
+        line_expected = change.expected.end;
+        line_actual = change.actual.end;
+        write(format, change.expected, change.actual)?;
+    }
+    if line_expected < expected_lines.len() {
+        write(
+            unchanged_format,
+            line_expected..expected_lines.len(),
+            line_actual..actual_lines.len(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::LinePattern;
+    use pretty_assertions::assert_eq;
+
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+        let mut output = Vec::new();
+        super::diff(&mut output, expected, actual, params).unwrap();
+        output
+    }
+
+    fn ifdef(name: &str) -> Params {
+        let args = ["diff", "-D", name, "foo", "bar"];
+        crate::params::parse_params(args.iter().map(|x| x.into()).peekable()).unwrap()
+    }
+
+    #[test]
+    fn merged() {
+        let from = b"a\nb\nc\nd\ne\n";
+        let to = b"a\nB\nc\ne\nf";
+        assert_eq!(
+            String::from_utf8(diff(from, to, &ifdef("FOO"))).unwrap(),
+            "a\n\
+             #ifndef FOO\nb\n#else /* FOO */\nB\n#endif /* FOO */\n\
+             c\n\
+             #ifndef FOO\nd\n#endif /* ! FOO */\n\
+             e\n\
+             #ifdef FOO\nf\n#endif /* FOO */\n"
+        );
+    }
+
+    #[test]
+    fn identical() {
+        assert_eq!(diff(b"a\nb", b"a\nb", &ifdef("FOO")), b"a\nb\n");
+        assert_eq!(diff(b"", b"", &ifdef("FOO")), b"");
+    }
+
+    #[test]
+    fn ignored_changes() {
+        let params = Params {
+            ignore_matching_lines: vec![LinePattern::new("^#").unwrap()],
+            ..ifdef("FOO")
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"#a\nb\n", b"#A\nb\nc\n", &params)).unwrap(),
+            "#a\nb\n#ifdef FOO\nc\n#endif /* FOO */\n"
+        );
+    }
+
+    #[test]
+    fn line_formats() {
+        let params = Params {
+            old_line_format: Some("-%3dn %l\n".to_string()),
+            new_line_format: Some("+%-3dn|%L".to_string()),
+            unchanged_line_format: Some("=%.2xn %X%c'\\101'%c'x'%%%l\n".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\nb\nc\nd\ne\n", b"a\nB\nc\ne\nf", &params)).unwrap(),
+            "=01 %XAx%a\n-  2 b\n+2  |B\n=03 %XAx%c\n-  4 d\n=05 %XAx%e\n+5  |f"
+        );
+
+        // Line formats also apply within #ifdef blocks
+        let params = Params {
+            old_line_format: Some("o%l\n".to_string()),
+            ..ifdef("N")
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\nb\n", b"c\n", &params)).unwrap(),
+            "#ifndef N\noa\nob\n#else /* N */\nc\n#endif /* N */\n"
+        );
+    }
+
+    #[test]
+    fn group_formats() {
+        let params = Params {
+            old_group_format: Some("OLD %df %dl %dn %de %dm %dF %dL %dN %dE %dM\n".to_string()),
+            new_group_format: Some("NEW %df %dl %dn %de %dm %dF %dL %dN %dE %dM\n".to_string()),
+            changed_group_format: Some(
+                "CHG %(n=N?one:other) %(N=1?ONE:O) %(2=2?y:z)\n".to_string(),
+            ),
+            unchanged_group_format: Some("U %df %dl %dF %dL [%=]\n".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\nb\nc\nd\ne\n", b"x\na\nB\nc\ne\n", &params)).unwrap(),
+            "NEW 1 0 0 0 1 1 1 1 0 2\n\
+             U 1 1 2 2 [a\n]\n\
+             CHG one ONE y\n\
+             U 3 3 4 4 [c\n]\n\
+             OLD 4 4 1 3 5 5 4 0 4 5\n\
+             U 5 5 5 5 [e\n]\n"
+        );
+
+        // The old and new formats default to the changed one, which defaults
+        // to both of them.
+        let params = Params {
+            changed_group_format: Some("c".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\nb\nc\nd\n", b"a\nB\nc\n", &params)).unwrap(),
+            "a\ncc\nc"
+        );
+        let params = Params {
+            old_group_format: Some("o".to_string()),
+            new_group_format: Some("n".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\nb\nc\nd\n", b"a\nB\nc\n", &params)).unwrap(),
+            "a\nonc\no"
+        );
+    }
+
+    #[test]
+    fn bad_group_directives() {
+        let params = Params {
+            unchanged_group_format: Some("%F %dQ %( %(x=1?a:b) %(1=1?a:b %(1=2?c:d)".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\n", b"a\n", &params)).unwrap(),
+            "%F %dQ %( %(x=1?a:b) a"
+        );
+    }
+
+    #[test]
+    fn number_specs() {
+        for (spec, number, expected) in [
+            ("dn", 12, "12"),
+            ("5dn", 12, "   12"),
+            ("-5dn", 12, "12   "),
+            ("05dn", 12, "00012"),
+            ("0-5dn", 12, "12   "),
+            ("5.3dn", 12, "  012"),
+            ("'dn", 1234, "1234"),
+            ("on", 8, "10"),
+            ("xn", 255, "ff"),
+            ("Xn", 255, "FF"),
+            (".0dn", 0, ""),
+            ("3.dn", 0, "   "),
+        ] {
+            let (parsed, len) = NumberSpec::parse(spec.as_bytes()).unwrap();
+            assert_eq!(len, spec.len() - 1, "{spec}");
+            assert_eq!(parsed.format(number), expected, "{spec}");
+        }
+        for spec in ["n", "5n", "-.n", "en"] {
+            assert_eq!(NumberSpec::parse(spec.as_bytes()), None, "{spec}");
+        }
+    }
+
+    #[test]
+    fn literal_directives() {
+        let params = Params {
+            unchanged_line_format: Some("%n %q %c'ab' %c'\\' %3 %".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            String::from_utf8(diff(b"a\n", b"a\n", &params)).unwrap(),
+            "%n %q %c'ab' %c'\\' %3 %"
+        );
+    }
+}
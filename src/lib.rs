@@ -1,14 +1,29 @@
+pub mod archive;
+pub mod chunked;
 pub mod cmp;
 pub mod context_diff;
+pub mod diff3;
 pub mod ed_diff;
+pub mod gitignore;
+pub mod hunks;
+pub mod ifdef_diff;
 pub mod macros;
 pub mod normal_diff;
+pub mod palette;
 pub mod params;
+pub mod patch;
+pub mod rcs_diff;
+pub mod side_diff;
+pub mod stat;
 pub mod unified_diff;
 pub mod utils;
 
 // Re-export the public functions/types you need
 pub use context_diff::diff as context_diff;
 pub use ed_diff::diff as ed_diff;
+pub use hunks::{compare, Change, CompareOptions, Diff, Hunk, Operation};
+pub use ifdef_diff::diff as ifdef_diff;
 pub use normal_diff::diff as normal_diff;
+pub use rcs_diff::diff as rcs_diff;
+pub use side_diff::{diff as side_diff, SideBySideOptions};
 pub use unified_diff::diff as unified_diff;
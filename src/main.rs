@@ -11,13 +11,24 @@ use std::{
     process::ExitCode,
 };
 
+mod archive;
+mod chunked;
 mod cmp;
 mod context_diff;
 mod diff;
+mod diff3;
 mod ed_diff;
+mod gitignore;
+mod hunks;
+mod ifdef_diff;
 mod macros;
 mod normal_diff;
+mod palette;
 mod params;
+mod patch;
+mod rcs_diff;
+mod side_diff;
+mod stat;
 mod unified_diff;
 mod utils;
 
@@ -42,7 +53,7 @@ fn usage(name: &str) {
     println!("{name} {VERSION} (multi-call binary)\n");
     println!("Usage: {name} [function [arguments...]]\n");
     println!("Currently defined functions:\n");
-    println!("    cmp, diff\n");
+    println!("    cmp, diff, diff3, patch\n");
 }
 
 fn second_arg_error(name: &OsStr) -> ! {
@@ -71,6 +82,8 @@ fn main() -> ExitCode {
     match util_name.to_str() {
         Some("diff") => diff::main(args),
         Some("cmp") => cmp::main(args),
+        Some("diff3") => diff3::main(args),
+        Some("patch") => patch::main(args),
         Some(name) => {
             eprintln!("{}: utility not supported", name);
             ExitCode::from(2)
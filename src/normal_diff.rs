@@ -3,217 +3,96 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use std::io::Write;
+use std::io::{self, Write};
+use std::ops::Range;
 
+use crate::hunks::{Diff, Operation};
+use crate::palette::ColorClass;
 use crate::params::Params;
-use crate::utils::do_write_line;
+use crate::utils::{write_colored_line, write_marked_line};
 
-#[derive(Debug, PartialEq)]
-struct Mismatch {
-    pub line_number_expected: usize,
-    pub line_number_actual: usize,
-    pub expected: Vec<Vec<u8>>,
-    pub actual: Vec<Vec<u8>>,
-    pub expected_missing_nl: bool,
-    pub actual_missing_nl: bool,
-}
-
-impl Mismatch {
-    fn new(line_number_expected: usize, line_number_actual: usize) -> Mismatch {
-        Mismatch {
-            line_number_expected,
-            line_number_actual,
-            expected: Vec::new(),
-            actual: Vec::new(),
-            expected_missing_nl: false,
-            actual_missing_nl: false,
-        }
-    }
-}
-
-// Produces a diff between the expected output and actual output.
-fn make_diff(expected: &[u8], actual: &[u8], stop_early: bool) -> Vec<Mismatch> {
-    let mut line_number_expected = 1;
-    let mut line_number_actual = 1;
-    let mut results = Vec::new();
-    let mut mismatch = Mismatch::new(line_number_expected, line_number_actual);
-
-    let mut expected_lines: Vec<&[u8]> = expected.split(|&c| c == b'\n').collect();
-    let mut actual_lines: Vec<&[u8]> = actual.split(|&c| c == b'\n').collect();
-
-    debug_assert_eq!(b"".split(|&c| c == b'\n').count(), 1);
-    // ^ means that underflow here is impossible
-    let expected_lines_count = expected_lines.len() - 1;
-    let actual_lines_count = actual_lines.len() - 1;
-
-    if expected_lines.last() == Some(&&b""[..]) {
-        expected_lines.pop();
-    }
-
-    if actual_lines.last() == Some(&&b""[..]) {
-        actual_lines.pop();
-    }
-
-    for result in diff::slice(&expected_lines, &actual_lines) {
-        match result {
-            diff::Result::Left(str) => {
-                if !mismatch.actual.is_empty() && !mismatch.actual_missing_nl {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(line_number_expected, line_number_actual);
-                }
-                mismatch.expected.push(str.to_vec());
-                mismatch.expected_missing_nl = line_number_expected > expected_lines_count;
-                line_number_expected += 1;
-            }
-            diff::Result::Right(str) => {
-                mismatch.actual.push(str.to_vec());
-                mismatch.actual_missing_nl = line_number_actual > actual_lines_count;
-                line_number_actual += 1;
-            }
-            diff::Result::Both(str, _) => {
-                match (
-                    line_number_expected > expected_lines_count,
-                    line_number_actual > actual_lines_count,
-                ) {
-                    (true, false) => {
-                        line_number_expected += 1;
-                        line_number_actual += 1;
-                        mismatch.expected.push(str.to_vec());
-                        mismatch.expected_missing_nl = true;
-                        mismatch.actual.push(str.to_vec());
-                    }
-                    (false, true) => {
-                        line_number_expected += 1;
-                        line_number_actual += 1;
-                        mismatch.actual.push(str.to_vec());
-                        mismatch.actual_missing_nl = true;
-                        mismatch.expected.push(str.to_vec());
-                    }
-                    (true, true) | (false, false) => {
-                        line_number_expected += 1;
-                        line_number_actual += 1;
-                        if !mismatch.actual.is_empty() || !mismatch.expected.is_empty() {
-                            results.push(mismatch);
-                            mismatch = Mismatch::new(line_number_expected, line_number_actual);
-                        } else {
-                            mismatch.line_number_expected = line_number_expected;
-                            mismatch.line_number_actual = line_number_actual;
-                        }
-                    }
-                }
-            }
-        }
-        if stop_early && !results.is_empty() {
-            // Optimization: stop analyzing the files as soon as there are any differences
-            return results;
-        }
-    }
-
-    if !mismatch.actual.is_empty() || !mismatch.expected.is_empty() {
-        results.push(mismatch);
-    }
-
-    results
-}
-
-#[must_use]
-pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+/// Writes the differences in the normal format, as a command like `3,5c3`
+/// for each change followed by the lines it removes and adds.
+pub fn diff<W: Write>(output: &mut W, diff: &Diff, params: &Params) -> io::Result<()> {
     // See https://www.gnu.org/software/diffutils/manual/html_node/Detailed-Normal.html
     // for details on the syntax of the normal format.
-    let mut output = Vec::new();
-    let diff_results = make_diff(expected, actual, params.brief);
-    if params.brief && !diff_results.is_empty() {
-        write!(&mut output, "\0").unwrap();
-        return output;
+    if params.brief && !diff.hunks.is_empty() {
+        return write!(output, "\0");
     }
-    for result in diff_results {
-        let line_number_expected = result.line_number_expected;
-        let line_number_actual = result.line_number_actual;
-        let expected_count = result.expected.len();
-        let actual_count = result.actual.len();
-        match (expected_count, actual_count) {
-            (0, 0) => unreachable!(),
-            (0, _) => writeln!(
-                // 'a' stands for "Add lines"
-                &mut output,
-                "{}a{},{}",
-                line_number_expected - 1,
-                line_number_actual,
-                line_number_actual + actual_count - 1
-            )
-            .unwrap(),
-            (_, 0) => writeln!(
-                // 'd' stands for "Delete lines"
-                &mut output,
-                "{},{}d{}",
-                line_number_expected,
-                expected_count + line_number_expected - 1,
-                line_number_actual - 1
-            )
-            .unwrap(),
-            (1, 1) => writeln!(
-                // 'c' stands for "Change lines"
-                // exactly one line replaced by one line
-                &mut output,
-                "{line_number_expected}c{line_number_actual}"
-            )
-            .unwrap(),
-            (1, _) => writeln!(
-                // one line replaced by multiple lines
-                &mut output,
-                "{}c{},{}",
-                line_number_expected,
-                line_number_actual,
-                actual_count + line_number_actual - 1
-            )
-            .unwrap(),
-            (_, 1) => writeln!(
-                // multiple lines replaced by one line
-                &mut output,
-                "{},{}c{}",
-                line_number_expected,
-                expected_count + line_number_expected - 1,
-                line_number_actual
-            )
-            .unwrap(),
-            _ => writeln!(
-                // general case: multiple lines replaced by multiple lines
-                &mut output,
-                "{},{}c{},{}",
-                line_number_expected,
-                expected_count + line_number_expected - 1,
-                line_number_actual,
-                actual_count + line_number_actual - 1
-            )
-            .unwrap(),
-        }
-        for expected in &result.expected {
-            write!(&mut output, "< ").unwrap();
-            do_write_line(&mut output, expected, params.expand_tabs, params.tabsize).unwrap();
-            writeln!(&mut output).unwrap();
-        }
-        if result.expected_missing_nl {
-            writeln!(&mut output, r"\ No newline at end of file").unwrap();
-        }
-        if expected_count != 0 && actual_count != 0 {
-            writeln!(&mut output, "---").unwrap();
-        }
-        for actual in &result.actual {
-            write!(&mut output, "> ").unwrap();
-            do_write_line(&mut output, actual, params.expand_tabs, params.tabsize).unwrap();
-            writeln!(&mut output).unwrap();
+    // With -T, a tab separates the markers from the lines, so that the
+    // lines keep their alignment.
+    let (old_marker, new_marker) = if params.initial_tab {
+        ("<\t", ">\t")
+    } else {
+        ("< ", "> ")
+    };
+    // Writes the lines of one side of a change, which may be missing their
+    // newline at the end of the file.
+    let write_lines = |output: &mut W, lines: &[&[u8]], marker, class| {
+        for line in lines {
+            let text = line.strip_suffix(b"\n").unwrap_or(line);
+            write_marked_line(output, marker, text, Some(class), params)?;
+            if text.len() == line.len() {
+                writeln!(output, r"\ No newline at end of file")?;
+            }
         }
-        if result.actual_missing_nl {
-            writeln!(&mut output, r"\ No newline at end of file").unwrap();
+        io::Result::Ok(())
+    };
+    for change in diff.changes() {
+        // A range of lines is written as "start,end", or just "start" when it
+        // spans a single line. For an insertion or deletion, the side that has
+        // no lines refers to the line right before the change.
+        let range = |lines: &Range<usize>| match lines.len() {
+            0 => format!("{}", lines.start),
+            1 => format!("{}", lines.start + 1),
+            _ => format!("{},{}", lines.start + 1, lines.end),
+        };
+        let command = match change.operation() {
+            // 'a' stands for "Add lines"
+            Operation::Add => 'a',
+            // 'd' stands for "Delete lines"
+            Operation::Delete => 'd',
+            // 'c' stands for "Change lines"
+            Operation::Change => 'c',
+        };
+        write_colored_line(
+            output,
+            ColorClass::LineNumbers,
+            format_args!(
+                "{}{}{}",
+                range(&change.expected),
+                command,
+                range(&change.actual)
+            ),
+            params,
+        )?;
+        write_lines(
+            output,
+            diff.removed(change),
+            old_marker,
+            ColorClass::Deleted,
+        )?;
+        if command == 'c' {
+            writeln!(output, "---")?;
         }
+        write_lines(output, diff.added(change), new_marker, ColorClass::Added)?;
     }
-    output
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hunks::{self, CompareOptions};
     use pretty_assertions::assert_eq;
+    use std::io::Write;
+
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+        let mut output = Vec::new();
+        let diff = hunks::compare(expected, actual, &CompareOptions::from(params));
+        super::diff(&mut output, &diff, params).unwrap();
+        output
+    }
 
     #[test]
     fn test_basic() {
@@ -226,6 +105,32 @@ mod tests {
         assert_eq!(diff, expected);
     }
 
+    #[test]
+    fn test_ranges() {
+        assert_eq!(
+            diff(b"a\nb\nc\n", b"a\nc\n", &Params::default()),
+            b"2d1\n< b\n"
+        );
+        assert_eq!(
+            diff(b"a\nc\n", b"a\nb\nc\n", &Params::default()),
+            b"1a2\n> b\n"
+        );
+        assert_eq!(
+            diff(b"a\nb\nc\nd\n", b"a\nd\n", &Params::default()),
+            b"2,3d1\n< b\n< c\n"
+        );
+        assert_eq!(
+            diff(b"a\nd\n", b"a\nb\nc\nd\n", &Params::default()),
+            b"1a2,3\n> b\n> c\n"
+        );
+        assert_eq!(
+            diff(b"a\nb\nd\n", b"a\nx\ny\nd\n", &Params::default()),
+            b"2c2,3\n< b\n---\n> x\n> y\n"
+        );
+        assert_eq!(diff(b"a\n", b"", &Params::default()), b"1d0\n< a\n");
+        assert_eq!(diff(b"", b"a\n", &Params::default()), b"0a1\n> a\n");
+    }
+
     #[test]
     fn test_permutations() {
         let target = "target/normal-diff/";
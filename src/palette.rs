@@ -0,0 +1,190 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+/// The parts of the output that --color highlights.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorClass {
+    /// File headers
+    Header,
+    /// Hunk headers, that hold line numbers
+    LineNumbers,
+    /// Lines from the first file only
+    Deleted,
+    /// Lines from the second file only
+    Added,
+}
+
+/// The escape sequences used by --color, as set by --palette.
+/// Each class of output is colored with `left`, its SGR parameters and `right`,
+/// and the color is reset with `left`, `reset` and `right`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Palette {
+    pub reset: String,
+    pub header: String,
+    pub added: String,
+    pub deleted: String,
+    pub line_numbers: String,
+    pub left: String,
+    pub right: String,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            reset: "0".to_string(),
+            header: "1".to_string(),
+            added: "32".to_string(),
+            deleted: "31".to_string(),
+            line_numbers: "36".to_string(),
+            left: "\x1b[".to_string(),
+            right: "m".to_string(),
+        }
+    }
+}
+
+impl Palette {
+    /// Parses a palette specification such as `ad=32:de=9;31:ln=36`, in the
+    /// format of GNU diff's --palette. Capabilities that aren't specified
+    /// keep their default value.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut palette = Self::default();
+        let mut chars = spec.chars().peekable();
+        while chars.peek().is_some() {
+            let name = unescape_until(&mut chars, '=')?;
+            let value = unescape_until(&mut chars, ':')?;
+            let capability = match name.as_str() {
+                "rs" => &mut palette.reset,
+                "hd" => &mut palette.header,
+                "ad" => &mut palette.added,
+                "de" => &mut palette.deleted,
+                "ln" => &mut palette.line_numbers,
+                "lc" => &mut palette.left,
+                "rc" => &mut palette.right,
+                // The end code isn't used by diff.
+                "ec" => continue,
+                _ => return Err(format!("unrecognized prefix: {name}")),
+            };
+            *capability = value;
+        }
+        Ok(palette)
+    }
+
+    /// The sequence that starts coloring the given class of output.
+    #[must_use]
+    pub fn start(&self, class: ColorClass) -> String {
+        let sgr = match class {
+            ColorClass::Header => &self.header,
+            ColorClass::LineNumbers => &self.line_numbers,
+            ColorClass::Deleted => &self.deleted,
+            ColorClass::Added => &self.added,
+        };
+        format!("{}{}{}", self.left, sgr, self.right)
+    }
+
+    /// The sequence that stops coloring.
+    #[must_use]
+    pub fn end(&self) -> String {
+        format!("{}{}{}", self.left, self.reset, self.right)
+    }
+}
+
+// Reads characters up to the terminator (or the end), interpreting backslash
+// escapes and caret notation the same way as dircolors.
+fn unescape_until(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    terminator: char,
+) -> Result<String, String> {
+    let error = || "unparsable value for --palette".to_string();
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == terminator => return Ok(value),
+            // A name must be followed by a value.
+            ':' => return Err(error()),
+            '\\' => {
+                let c = chars.next().ok_or_else(error)?;
+                value.push(match c {
+                    'a' => '\x07',
+                    'b' => '\x08',
+                    'e' => '\x1b',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'v' => '\x0b',
+                    '?' => '\x7f',
+                    '_' => ' ',
+                    '0'..='7' => {
+                        let mut code = c.to_digit(8).unwrap();
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|c| c.to_digit(8)) {
+                                Some(digit) => {
+                                    code = code * 8 + digit;
+                                    chars.next();
+                                }
+                                None => break,
+                            }
+                        }
+                        char::from_u32(code).ok_or_else(error)?
+                    }
+                    c => c,
+                });
+            }
+            '^' => {
+                let c = chars.next().ok_or_else(error)?;
+                value.push(match c {
+                    '?' => '\x7f',
+                    '@'..='~' => char::from(c as u8 & 0x1f),
+                    _ => return Err(error()),
+                });
+            }
+            c => value.push(c),
+        }
+    }
+    if terminator == '=' {
+        Err(error())
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn default() {
+        let palette = Palette::default();
+        assert_eq!(palette.start(ColorClass::Header), "\x1b[1m");
+        assert_eq!(palette.start(ColorClass::Added), "\x1b[32m");
+        assert_eq!(palette.end(), "\x1b[0m");
+        assert_eq!(Palette::parse(""), Ok(Palette::default()));
+    }
+
+    #[test]
+    fn parse() {
+        let palette = Palette::parse("ad=1;32:de=9;31:ln=35").unwrap();
+        assert_eq!(palette.start(ColorClass::Added), "\x1b[1;32m");
+        assert_eq!(palette.start(ColorClass::Deleted), "\x1b[9;31m");
+        assert_eq!(palette.start(ColorClass::LineNumbers), "\x1b[35m");
+        assert_eq!(palette.start(ColorClass::Header), "\x1b[1m");
+
+        let palette = Palette::parse("rs=7:lc=<:rc=>:hd=").unwrap();
+        assert_eq!(palette.start(ColorClass::Header), "<>");
+        assert_eq!(palette.end(), "<7>");
+
+        let palette = Palette::parse(r"lc=\e[:rc=^[m:ad=\061\_\:").unwrap();
+        assert_eq!(palette.start(ColorClass::Added), "\x1b[1 :\x1bm");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Palette::parse("zz=1").is_err());
+        assert!(Palette::parse("ad").is_err());
+        assert!(Palette::parse("ad:de=1").is_err());
+        assert!(Palette::parse("ad=\\").is_err());
+    }
+}
@@ -1,9 +1,16 @@
-use std::ffi::OsString;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
 use std::iter::Peekable;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use regex::Regex;
 
+use crate::archive::is_archive;
+use crate::palette::Palette;
+use crate::utils::{format_failure_to_read_input_file, path_from_bytes, LinePattern};
+
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub enum Format {
     #[default]
@@ -11,6 +18,47 @@ pub enum Format {
     Unified,
     Context,
     Ed,
+    Rcs,
+    SideBySide,
+    Ifdef,
+}
+
+/// The summary of the changes that is output instead of the differences,
+/// with --stat, --numstat or --shortstat.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StatFormat {
+    Stat,
+    Numstat,
+    Shortstat,
+}
+
+/// The Unicode normalization form that lines are compared in, for
+/// --normalize.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+}
+
+/// The tolerance of --numeric-fuzz, a finite number that isn't negative,
+/// kept as the bits of its value so that it can be compared exactly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tolerance(u64);
+
+impl Tolerance {
+    /// Parses a tolerance, or gives None if it isn't a finite number that
+    /// isn't negative.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.parse::<f64>() {
+            // Without the sign of -0, which would compare differently.
+            Ok(value) if value.is_finite() && value >= 0.0 => Some(Self(value.abs().to_bits())),
+            _ => None,
+        }
+    }
+
+    pub fn value(self) -> f64 {
+        f64::from_bits(self.0)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -22,8 +70,71 @@ pub struct Params {
     pub context_count: usize,
     pub report_identical_files: bool,
     pub brief: bool,
+    pub text: bool,
+    pub ignore_tab_expansion: bool,
+    pub ignore_all_space: bool,
+    pub ignore_ansi: bool,
+    pub normalize: Option<Normalization>,
+    pub ignore_matching_lines: Vec<LinePattern>,
+    pub numeric_fuzz: Option<Tolerance>,
+    pub minimal: bool,
+    pub recursive: bool,
+    pub max_depth: Option<usize>,
+    pub archives: bool,
+    pub new_file: bool,
+    pub unidirectional_new_file: bool,
+    pub exclude: Vec<String>,
+    pub exclude_vcs_ignores: bool,
+    pub starting_file: Option<OsString>,
+    pub jobs: usize,
+    pub assume_unchanged_by_mtime: bool,
+    pub ignore_file_name_case: bool,
+    pub from_label: Option<String>,
+    pub to_label: Option<String>,
+    pub src_prefix: Option<String>,
+    pub dst_prefix: Option<String>,
+    pub relative: bool,
+    pub git: bool,
+    pub stat: Option<StatFormat>,
+    /// The names of the files in headers, when they are entries of
+    /// directories compared with --relative, or of archives compared with
+    /// --archives.
+    pub from_name: Option<String>,
+    pub to_name: Option<String>,
+    /// The path of the compared entries inside the compared directories,
+    /// which names them with --stat.
+    pub entry_path: Option<String>,
+    pub from_file: Option<OsString>,
+    pub to_file: Option<OsString>,
+    pub batch: bool,
+    pub operands: Vec<OsString>,
     pub expand_tabs: bool,
+    pub initial_tab: bool,
+    pub suppress_blank_empty: bool,
+    pub strip_trailing_cr: bool,
+    pub strip_ansi: bool,
+    pub decompress: bool,
+    pub textconv: Option<String>,
+    pub preprocess: Option<String>,
+    pub color: bool,
+    pub palette: Palette,
+    pub paginate: bool,
     pub tabsize: usize,
+    pub width: usize,
+    pub left_column: bool,
+    pub suppress_common_lines: bool,
+    pub mark_truncated: bool,
+    pub wrap_lines: bool,
+    pub max_hunks: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub verify_against: Option<OsString>,
+    pub old_group_format: Option<String>,
+    pub new_group_format: Option<String>,
+    pub changed_group_format: Option<String>,
+    pub unchanged_group_format: Option<String>,
+    pub old_line_format: Option<String>,
+    pub new_line_format: Option<String>,
+    pub unchanged_line_format: Option<String>,
 }
 
 impl Default for Params {
@@ -36,12 +147,201 @@ impl Default for Params {
             context_count: 3,
             report_identical_files: false,
             brief: false,
+            text: false,
+            ignore_tab_expansion: false,
+            ignore_all_space: false,
+            ignore_ansi: false,
+            normalize: None,
+            ignore_matching_lines: Vec::new(),
+            numeric_fuzz: None,
+            minimal: false,
+            recursive: false,
+            max_depth: None,
+            archives: false,
+            new_file: false,
+            unidirectional_new_file: false,
+            exclude: Vec::new(),
+            exclude_vcs_ignores: false,
+            starting_file: None,
+            jobs: 1,
+            assume_unchanged_by_mtime: false,
+            ignore_file_name_case: false,
+            from_label: None,
+            to_label: None,
+            src_prefix: None,
+            dst_prefix: None,
+            relative: false,
+            git: false,
+            stat: None,
+            from_name: None,
+            to_name: None,
+            entry_path: None,
+            from_file: None,
+            to_file: None,
+            batch: false,
+            operands: Vec::new(),
             expand_tabs: false,
+            initial_tab: false,
+            suppress_blank_empty: false,
+            strip_trailing_cr: false,
+            strip_ansi: false,
+            decompress: false,
+            textconv: None,
+            preprocess: None,
+            color: false,
+            palette: Palette::default(),
+            paginate: false,
             tabsize: 8,
+            width: 130,
+            left_column: false,
+            suppress_common_lines: false,
+            mark_truncated: false,
+            wrap_lines: false,
+            max_hunks: None,
+            max_lines: None,
+            verify_against: None,
+            old_group_format: None,
+            new_group_format: None,
+            changed_group_format: None,
+            unchanged_group_format: None,
+            old_line_format: None,
+            new_line_format: None,
+            unchanged_line_format: None,
         }
     }
 }
 
+// The options of --help, in the words of GNU diff.
+const OPTIONS_HELP: &str = "\
+Compare FILES line by line.
+
+Mandatory arguments to long options are mandatory for short options too.
+      --normal                  output a normal diff (the default)
+  -q, --brief                   report only when files differ
+  -s, --report-identical-files  report when two files are the same
+  -c, -C NUM, --context[=NUM]   output NUM (default 3) lines of copied context
+  -u, -U NUM, --unified[=NUM]   output NUM (default 3) lines of unified context
+  -e, --ed                      output an ed script
+  -n, --rcs                     output an RCS format diff
+  -y, --side-by-side            output in two columns
+  -W, --width=NUM               output at most NUM (default 130) print columns
+      --left-column             output only the left column of common lines
+      --suppress-common-lines   do not output common lines
+      --mark-truncated          end the lines cut to fit a column with '…'
+      --wrap-lines              wrap the lines that don't fit a column
+      --max-hunks=NUM           output at most NUM hunks in all
+      --max-lines=NUM           output at most NUM lines in all
+
+      --label LABEL             use LABEL instead of file name and timestamp
+                                  (can be repeated)
+      --src-prefix=PREFIX       prepend PREFIX to the name of FILE1 in headers
+      --dst-prefix=PREFIX       prepend PREFIX to the name of FILE2 in headers
+      --relative                name files relative to the compared directories
+                                  in headers
+      --git                     output a unified diff with the headers of git
+      --stat                    output how many lines of each file changed,
+                                  and the totals, instead of the differences
+      --numstat                 output the numbers of added and removed lines
+                                  of each file, separated by tabs
+      --shortstat               output only the totals of --stat
+
+  -t, --expand-tabs             expand tabs to spaces in output
+  -T, --initial-tab             make tabs line up by prepending a tab
+      --tabsize=NUM             tab stops every NUM (default 8) print columns
+      --suppress-blank-empty    suppress space or tab before empty output lines
+
+  -r, --recursive                 recursively compare any subdirectories found
+      --max-depth=NUM             descend at most NUM levels of subdirectories
+      --archives                  compare tar and zip archives as the
+                                    directories of their members
+  -N, --new-file                  treat absent files as empty
+      --unidirectional-new-file   treat absent first files as empty
+      --ignore-file-name-case     ignore case when comparing file names
+      --no-ignore-file-name-case  consider case when comparing file names
+  -x, --exclude=PAT               exclude files that match PAT
+  -X, --exclude-from=FILE         exclude files that match any pattern in FILE
+      --exclude-vcs-ignores       exclude files that .gitignore files ignore
+  -S, --starting-file=FILE        start with FILE when comparing directories
+      --jobs=NUM                  compare up to NUM files of a directory at once
+      --assume-unchanged-by-mtime
+                                  assume that files of the same size and
+                                    modification time are the same
+      --from-file=FILE1           compare FILE1 to all operands;
+                                    FILE1 can be a directory
+      --to-file=FILE2             compare all operands to FILE2;
+                                    FILE2 can be a directory
+      --batch                     compare the pairs of FILES read from standard
+                                    input, as FILE1 TAB FILE2 lines, or
+                                    separated by NUL characters
+
+  -E, --ignore-tab-expansion      ignore changes due to tab expansion
+  -w, --ignore-all-space          ignore all white space
+  -I, --ignore-matching-lines=RE  ignore changes where all lines match RE
+      --ignore-ansi               ignore terminal escape sequences (colors)
+      --normalize=FORM            compare lines in Unicode normalization FORM,
+                                    'nfc' or 'nfd'
+      --numeric-fuzz=EPS          consider numbers equal when they differ by at
+                                    most EPS, relative to numbers above 1
+
+  -a, --text                      treat all files as text
+      --strip-trailing-cr         strip trailing carriage return on input
+      --strip-ansi                strip terminal escape sequences on input
+  -z, --decompress                decompress inputs compressed with gzip, bzip2,
+                                    xz or zstd
+      --textconv=CMD              compare the text that CMD outputs for each
+                                    file given as its argument
+      --preprocess=CMD            compare what CMD outputs for the contents of
+                                    each file given on its standard input
+
+  -D, --ifdef=NAME                output merged file with '#ifdef NAME' diffs
+      --GTYPE-group-format=GFMT   format GTYPE input groups with GFMT
+      --line-format=LFMT          format all input lines with LFMT
+      --LTYPE-line-format=LFMT    format LTYPE input lines with LFMT
+    These format options provide fine-grained control over the output
+      of diff, generalizing -D/--ifdef.
+    LTYPE is 'old', 'new', or 'unchanged'.  GTYPE is LTYPE or 'changed'.
+    GFMT (only) may contain:
+      %<  lines from FILE1
+      %>  lines from FILE2
+      %=  lines common to FILE1 and FILE2
+      %[-][WIDTH][.[PREC]]{doxX}LETTER  printf-style spec for LETTER
+        LETTERs are as follows for new group, lower case for old group:
+          F  first line number
+          L  last line number
+          N  number of lines = L-F+1
+          E  F-1
+          M  L+1
+      %(A=B?T:E)  if A equals B then T else E
+    LFMT (only) may contain:
+      %L  contents of line
+      %l  contents of line, excluding any trailing newline
+      %[-][WIDTH][.[PREC]]{doxX}n  printf-style spec for input line number
+    Both GFMT and LFMT may contain:
+      %%  %
+      %c'C'  the single character C
+      %c'\\OOO'  the character with octal code OOO
+      C    the character C (other characters represent themselves)
+
+  -d, --minimal            try hard to find a smaller set of changes
+      --horizon-lines=NUM  accepted for compatibility; the common prefix and
+                             suffix are never discarded, so NUM is ignored
+      --color[=WHEN]       color output; WHEN is 'never', 'always', or 'auto';
+                             plain --color means --color='auto'
+      --palette=PALETTE    the colors to use when --color is active; PALETTE is
+                             a colon-separated list of terminfo capabilities
+      --paginate           pipe the output into $PAGER (default less) when
+                             it goes to a terminal
+
+      --help               display this help and exit
+  -v, --version            output version information and exit
+
+FILES are 'FILE1 FILE2' or 'DIR1 DIR2' or 'DIR FILE' or 'FILE DIR'.
+If --from-file or --to-file is given, there are no restrictions on FILE(s).
+If a FILE is '-', read standard input.
+Arguments '@LIST' are replaced with the lines of the file LIST.
+Exit status is 0 if inputs are the same, 1 if different, 2 if trouble.
+";
+
 pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Result<Params, String> {
     // parse CLI
 
@@ -52,29 +352,30 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
         executable,
         ..Default::default()
     };
-    let mut from = None;
-    let mut to = None;
+    let mut operands = Vec::new();
     let mut format = None;
     let mut context = None;
-    let tabsize_re = Regex::new(r"^--tabsize=(?<num>\d+)$").unwrap();
+    let width_re = Regex::new(r"^(?:-W|--width=)(?<num>.*)$").unwrap();
     while let Some(param) = opts.next() {
         let next_param = opts.peek();
         if param == "--" {
             break;
         }
         if param == "-" {
-            if from.is_none() {
-                from = Some(param);
-            } else if to.is_none() {
-                to = Some(param);
-            } else {
-                return Err(format!(
-                    "Usage: {} <from> <to>",
-                    params.executable.to_string_lossy()
-                ));
-            }
+            operands.push(param);
             continue;
         }
+        if param == "--help" {
+            print!(
+                "Usage: {} [OPTION]... FILES\n{OPTIONS_HELP}",
+                params.executable.to_string_lossy()
+            );
+            std::process::exit(0);
+        }
+        if param == "-v" || param == "--version" {
+            println!("diff (uutils diffutils) {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
         if param == "-s" || param == "--report-identical-files" {
             params.report_identical_files = true;
             continue;
@@ -83,10 +384,323 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
             params.brief = true;
             continue;
         }
+        if param == "-r" || param == "--recursive" {
+            params.recursive = true;
+            continue;
+        }
+        if param == "-N" || param == "--new-file" {
+            params.new_file = true;
+            continue;
+        }
+        if param == "--unidirectional-new-file" {
+            params.unidirectional_new_file = true;
+            continue;
+        }
+        if param == "-x" {
+            match opts.next() {
+                Some(pattern) => params.exclude.push(pattern.to_string_lossy().to_string()),
+                None => return Err("option requires an argument -- 'x'".to_string()),
+            }
+            continue;
+        }
+        if let Some(pattern) = param.to_str().and_then(|param| {
+            param
+                .strip_prefix("--exclude=")
+                .or(param.strip_prefix("-x"))
+        }) {
+            params.exclude.push(pattern.to_string());
+            continue;
+        }
+        if param == "-X" || param.to_string_lossy().starts_with("--exclude-from=") {
+            let file = if param == "-X" {
+                match opts.next() {
+                    Some(file) => file,
+                    None => return Err("option requires an argument -- 'X'".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                OsString::from(param.split_once('=').unwrap().1)
+            };
+            let contents = if file == "-" {
+                let mut contents = Vec::new();
+                io::stdin().read_to_end(&mut contents).and(Ok(contents))
+            } else {
+                fs::read(&file)
+            }
+            .map_err(|e| format_failure_to_read_input_file(&params.executable, &file, &e))?;
+            params.exclude.extend(
+                String::from_utf8_lossy(&contents)
+                    .lines()
+                    .filter(|pattern| !pattern.is_empty())
+                    .map(str::to_string),
+            );
+            continue;
+        }
+        if param == "--assume-unchanged-by-mtime" {
+            params.assume_unchanged_by_mtime = true;
+            continue;
+        }
+        if param == "--exclude-vcs-ignores" {
+            params.exclude_vcs_ignores = true;
+            continue;
+        }
+        if param == "-S" {
+            match opts.next() {
+                Some(file) => params.starting_file = Some(file),
+                None => return Err("option requires an argument -- 'S'".to_string()),
+            }
+            continue;
+        }
+        if let Some(file) = param.to_str().and_then(|param| {
+            param
+                .strip_prefix("--starting-file=")
+                .or(param.strip_prefix("-S"))
+        }) {
+            params.starting_file = Some(OsString::from(file));
+            continue;
+        }
+        if param == "--ignore-file-name-case" {
+            params.ignore_file_name_case = true;
+            continue;
+        }
+        if param == "--no-ignore-file-name-case" {
+            params.ignore_file_name_case = false;
+            continue;
+        }
+        if param == "-L" || param == "--label" {
+            let Some(label) = opts.next() else {
+                return Err(format!(
+                    "option requires an argument -- '{}'",
+                    param.to_string_lossy()
+                ));
+            };
+            add_label(&mut params, label.to_string_lossy().to_string())?;
+            continue;
+        }
+        if let Some(label) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--label=").or(param.strip_prefix("-L")))
+        {
+            add_label(&mut params, label.to_string())?;
+            continue;
+        }
+        if param == "--src-prefix" || param == "--dst-prefix" {
+            let Some(prefix) = opts.next() else {
+                return Err(format!(
+                    "option '{}' requires an argument",
+                    param.to_string_lossy()
+                ));
+            };
+            let prefix = Some(prefix.to_string_lossy().to_string());
+            if param == "--src-prefix" {
+                params.src_prefix = prefix;
+            } else {
+                params.dst_prefix = prefix;
+            }
+            continue;
+        }
+        if let Some(prefix) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--src-prefix="))
+        {
+            params.src_prefix = Some(prefix.to_string());
+            continue;
+        }
+        if let Some(prefix) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--dst-prefix="))
+        {
+            params.dst_prefix = Some(prefix.to_string());
+            continue;
+        }
+        if param == "--archives" {
+            params.archives = true;
+            continue;
+        }
+        if param == "--relative" {
+            params.relative = true;
+            continue;
+        }
+        if let Some(option) = ["--from-file", "--to-file"].into_iter().find(|option| {
+            param == *option
+                || param
+                    .as_encoded_bytes()
+                    .strip_prefix(option.as_bytes())
+                    .is_some_and(|rest| rest.starts_with(b"="))
+        }) {
+            // The file is given as --from-file=FILE or --from-file FILE.
+            let file = if param == option {
+                match opts.next() {
+                    Some(file) => file,
+                    None => return Err(format!("option '{option}' requires an argument")),
+                }
+            } else {
+                path_from_bytes(&param.as_encoded_bytes()[option.len() + 1..])
+            };
+            if option == "--from-file" {
+                params.from_file = Some(file);
+            } else {
+                params.to_file = Some(file);
+            }
+            continue;
+        }
+        if param == "--batch" {
+            params.batch = true;
+            continue;
+        }
+        if param == "-a" || param == "--text" {
+            params.text = true;
+            continue;
+        }
+        if param == "-E" || param == "--ignore-tab-expansion" {
+            params.ignore_tab_expansion = true;
+            continue;
+        }
+        if param == "-w" || param == "--ignore-all-space" {
+            params.ignore_all_space = true;
+            continue;
+        }
+        if param == "-I"
+            || param
+                .to_string_lossy()
+                .starts_with("--ignore-matching-lines=")
+        {
+            let pattern = if param == "-I" {
+                match opts.next() {
+                    Some(pattern) => pattern.to_string_lossy().to_string(),
+                    None => return Err("option requires an argument -- 'I'".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            let pattern =
+                LinePattern::new(&pattern).map_err(|error| format!("{pattern}: {error}"))?;
+            params.ignore_matching_lines.push(pattern);
+            continue;
+        }
         if param == "-t" || param == "--expand-tabs" {
             params.expand_tabs = true;
             continue;
         }
+        if param == "-T" || param == "--initial-tab" {
+            params.initial_tab = true;
+            continue;
+        }
+        if param == "--color" || param.to_string_lossy().starts_with("--color=") {
+            let param = param.to_string_lossy();
+            params.color = match param.split_once('=').map_or("auto", |(_, when)| when) {
+                "never" => false,
+                "always" => true,
+                "auto" => {
+                    io::stdout().is_terminal()
+                        && env::var_os("TERM").is_some_and(|term| term != "dumb")
+                        && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+                }
+                when => return Err(format!("invalid argument «{when}» for '--color'")),
+            };
+            continue;
+        }
+        if let Some(spec) = param.to_string_lossy().strip_prefix("--palette=") {
+            params.palette = Palette::parse(spec)?;
+            continue;
+        }
+        if param == "--paginate" {
+            params.paginate = true;
+            continue;
+        }
+        if param == "--strip-trailing-cr" {
+            params.strip_trailing_cr = true;
+            continue;
+        }
+        if param == "--strip-ansi" {
+            params.strip_ansi = true;
+            continue;
+        }
+        if param == "-z" || param == "--decompress" {
+            params.decompress = true;
+            continue;
+        }
+        if param == "--ignore-ansi" {
+            params.ignore_ansi = true;
+            continue;
+        }
+        if param == "--normalize" || param.to_string_lossy().starts_with("--normalize=") {
+            let form = if param == "--normalize" {
+                match opts.next() {
+                    Some(form) => form.to_string_lossy().to_string(),
+                    None => return Err("option '--normalize' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            params.normalize = match form.as_str() {
+                "nfc" => Some(Normalization::Nfc),
+                "nfd" => Some(Normalization::Nfd),
+                _ => return Err(format!("invalid argument «{form}» for '--normalize'")),
+            };
+            continue;
+        }
+        if param == "-d" || param == "--minimal" {
+            // Large files are otherwise split on the blocks of lines they
+            // have in common, which may not give the smallest set of changes.
+            params.minimal = true;
+            continue;
+        }
+        if param == "--binary" {
+            // Files are always read and written as bytes, without any newline
+            // translation, so this is the default on every platform.
+            continue;
+        }
+        if param == "--suppress-blank-empty" {
+            params.suppress_blank_empty = true;
+            continue;
+        }
+        if param == "--textconv" {
+            match opts.next() {
+                Some(command) => params.textconv = Some(command.to_string_lossy().to_string()),
+                None => return Err("option '--textconv' requires an argument".to_string()),
+            }
+            continue;
+        }
+        if let Some(command) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--textconv="))
+        {
+            params.textconv = Some(command.to_string());
+            continue;
+        }
+        if param == "--preprocess" {
+            match opts.next() {
+                Some(command) => params.preprocess = Some(command.to_string_lossy().to_string()),
+                None => return Err("option '--preprocess' requires an argument".to_string()),
+            }
+            continue;
+        }
+        if let Some(command) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--preprocess="))
+        {
+            params.preprocess = Some(command.to_string());
+            continue;
+        }
+        if param == "--stat" || param == "--numstat" || param == "--shortstat" {
+            params.stat = Some(match param.to_str() {
+                Some("--stat") => StatFormat::Stat,
+                Some("--numstat") => StatFormat::Numstat,
+                _ => StatFormat::Shortstat,
+            });
+            continue;
+        }
+        if param == "--git" {
+            if format.is_some() && format != Some(Format::Unified) {
+                return Err("Conflicting output style options".to_string());
+            }
+            format = Some(Format::Unified);
+            params.git = true;
+            continue;
+        }
         if param == "--normal" {
             if format.is_some() && format != Some(Format::Normal) {
                 return Err("Conflicting output style options".to_string());
@@ -101,20 +715,253 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
             format = Some(Format::Ed);
             continue;
         }
-        if tabsize_re.is_match(param.to_string_lossy().as_ref()) {
-            // Because param matches the regular expression,
-            // it is safe to assume it is valid UTF-8.
-            let param = param.into_string().unwrap();
-            let tabsize_str = tabsize_re
-                .captures(param.as_str())
-                .unwrap()
-                .name("num")
-                .unwrap()
-                .as_str();
+        if param == "-n" || param == "--rcs" {
+            if format.is_some() && format != Some(Format::Rcs) {
+                return Err("Conflicting output style options".to_string());
+            }
+            format = Some(Format::Rcs);
+            continue;
+        }
+        if param == "-y" || param == "--side-by-side" {
+            if format.is_some() && format != Some(Format::SideBySide) {
+                return Err("Conflicting output style options".to_string());
+            }
+            format = Some(Format::SideBySide);
+            continue;
+        }
+        if param == "-D"
+            || param.to_string_lossy().starts_with("-D")
+            || param.to_string_lossy().starts_with("--ifdef=")
+        {
+            let name = if param == "-D" {
+                match opts.next() {
+                    Some(name) => name.to_string_lossy().to_string(),
+                    None => return Err("option requires an argument -- 'D'".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param
+                    .strip_prefix("--ifdef=")
+                    .or(param.strip_prefix("-D"))
+                    .unwrap()
+                    .to_string()
+            };
+            if format.is_some() && format != Some(Format::Ifdef) {
+                return Err("Conflicting output style options".to_string());
+            }
+            // -D sets the group formats, so that the lines that only belong
+            // to one of the files are wrapped in #ifndef NAME (the first one)
+            // and #ifdef NAME (the second one) blocks.
+            for (group_format, value) in [
+                (&mut params.unchanged_group_format, "%=".to_string()),
+                (
+                    &mut params.old_group_format,
+                    format!("#ifndef {name}\n%<#endif /* ! {name} */\n"),
+                ),
+                (
+                    &mut params.new_group_format,
+                    format!("#ifdef {name}\n%>#endif /* {name} */\n"),
+                ),
+                (
+                    &mut params.changed_group_format,
+                    format!("#ifndef {name}\n%<#else /* {name} */\n%>#endif /* {name} */\n"),
+                ),
+            ] {
+                set_once(group_format, "-D", &value)?;
+            }
+            format = Some(Format::Ifdef);
+            continue;
+        }
+        if let Some((option, group_format)) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--")?.split_once('='))
+            .filter(|(option, _)| option.ends_with("group-format"))
+        {
+            let value = match option {
+                "old-group-format" => &mut params.old_group_format,
+                "new-group-format" => &mut params.new_group_format,
+                "changed-group-format" => &mut params.changed_group_format,
+                "unchanged-group-format" => &mut params.unchanged_group_format,
+                _ => return Err(format!("Unknown option: {param:?}")),
+            };
+            set_once(value, &format!("--{option}"), group_format)?;
+            if format.is_some() && format != Some(Format::Ifdef) {
+                return Err("Conflicting output style options".to_string());
+            }
+            format = Some(Format::Ifdef);
+            continue;
+        }
+        if let Some((option, line_format)) = param
+            .to_str()
+            .and_then(|param| param.strip_prefix("--")?.split_once('='))
+            .filter(|(option, _)| option.ends_with("line-format"))
+        {
+            let line_formats = match option {
+                "line-format" => vec![
+                    &mut params.old_line_format,
+                    &mut params.new_line_format,
+                    &mut params.unchanged_line_format,
+                ],
+                "old-line-format" => vec![&mut params.old_line_format],
+                "new-line-format" => vec![&mut params.new_line_format],
+                "unchanged-line-format" => vec![&mut params.unchanged_line_format],
+                _ => return Err(format!("Unknown option: {param:?}")),
+            };
+            for value in line_formats {
+                set_once(value, &format!("--{option}"), line_format)?;
+            }
+            if format.is_some() && format != Some(Format::Ifdef) {
+                return Err("Conflicting output style options".to_string());
+            }
+            format = Some(Format::Ifdef);
+            continue;
+        }
+        if param == "--left-column" {
+            params.left_column = true;
+            continue;
+        }
+        if param == "--suppress-common-lines" {
+            params.suppress_common_lines = true;
+            continue;
+        }
+        if param == "--mark-truncated" {
+            params.mark_truncated = true;
+            continue;
+        }
+        if param == "--wrap-lines" {
+            params.wrap_lines = true;
+            continue;
+        }
+        if param == "-W" || width_re.is_match(param.to_string_lossy().as_ref()) {
+            let width_str = if param == "-W" {
+                match opts.next() {
+                    Some(width) => width.to_string_lossy().to_string(),
+                    None => return Err("option requires an argument -- 'W'".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                width_re.captures(&param).unwrap()["num"].to_string()
+            };
+            params.width = match width_str.parse::<usize>() {
+                Ok(num) if num > 0 => num,
+                _ => return Err(format!("invalid width «{width_str}»")),
+            };
+            continue;
+        }
+        if param == "--tabsize" || param.to_string_lossy().starts_with("--tabsize=") {
+            let tabsize_str = if param == "--tabsize" {
+                match opts.next() {
+                    Some(tabsize) => tabsize.to_string_lossy().to_string(),
+                    None => return Err("option '--tabsize' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            // Tab stops are at least one column apart.
             params.tabsize = match tabsize_str.parse::<usize>() {
-                Ok(num) => num,
-                Err(_) => return Err(format!("invalid tabsize «{tabsize_str}»")),
+                Ok(num) if num > 0 => num,
+                _ => return Err(format!("invalid tabsize «{tabsize_str}»")),
+            };
+            continue;
+        }
+        if param == "--numeric-fuzz" || param.to_string_lossy().starts_with("--numeric-fuzz=") {
+            let fuzz_str = if param == "--numeric-fuzz" {
+                match opts.next() {
+                    Some(fuzz) => fuzz.to_string_lossy().to_string(),
+                    None => return Err("option '--numeric-fuzz' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            match Tolerance::parse(&fuzz_str) {
+                Some(fuzz) => params.numeric_fuzz = Some(fuzz),
+                None => return Err(format!("invalid numeric fuzz «{fuzz_str}»")),
+            }
+            continue;
+        }
+        if param == "--max-hunks"
+            || param == "--max-lines"
+            || param.to_string_lossy().starts_with("--max-hunks=")
+            || param.to_string_lossy().starts_with("--max-lines=")
+        {
+            let param = param.to_string_lossy().to_string();
+            let (option, max_str) = match param.split_once('=') {
+                Some((option, max)) => (option.to_string(), max.to_string()),
+                None => match opts.next() {
+                    Some(max) => (param, max.to_string_lossy().to_string()),
+                    None => return Err(format!("option '{param}' requires an argument")),
+                },
+            };
+            let max = match max_str.parse::<usize>() {
+                Ok(max) => Some(max),
+                _ => return Err(format!("invalid argument «{max_str}» for '{option}'")),
+            };
+            if option == "--max-hunks" {
+                params.max_hunks = max;
+            } else {
+                params.max_lines = max;
+            }
+            continue;
+        }
+        if param == "--max-depth" || param.to_string_lossy().starts_with("--max-depth=") {
+            let depth_str = if param == "--max-depth" {
+                match opts.next() {
+                    Some(depth) => depth.to_string_lossy().to_string(),
+                    None => return Err("option '--max-depth' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            params.max_depth = match depth_str.parse::<usize>() {
+                Ok(num) => Some(num),
+                _ => return Err(format!("invalid maximum depth «{depth_str}»")),
+            };
+            continue;
+        }
+        if param == "--jobs" || param.to_string_lossy().starts_with("--jobs=") {
+            let jobs_str = if param == "--jobs" {
+                match opts.next() {
+                    Some(jobs) => jobs.to_string_lossy().to_string(),
+                    None => return Err("option '--jobs' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
+            };
+            params.jobs = match jobs_str.parse::<usize>() {
+                Ok(num) if num > 0 => num,
+                _ => return Err(format!("invalid number of jobs «{jobs_str}»")),
+            };
+            continue;
+        }
+        if param == "--horizon-lines" || param.to_string_lossy().starts_with("--horizon-lines=") {
+            let horizon_str = if param == "--horizon-lines" {
+                match opts.next() {
+                    Some(horizon) => horizon.to_string_lossy().to_string(),
+                    None => return Err("option '--horizon-lines' requires an argument".to_string()),
+                }
+            } else {
+                let param = param.to_string_lossy();
+                param.split_once('=').unwrap().1.to_string()
             };
+            // GNU diff keeps this many lines of the common prefix and suffix
+            // when it sets them aside. Here they never change the result:
+            // every line takes part in finding the changes, so the value is
+            // checked and then ignored. Large values are fine.
+            if horizon_str.is_empty() || !horizon_str.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("invalid horizon length «{horizon_str}»"));
+            }
+            continue;
+        }
+        if let Some(prog) = param.as_encoded_bytes().strip_prefix(b"--verify-against=") {
+            // Hidden option: not meant to be documented in --help.
+            if prog.is_empty() {
+                return Err("option '--verify-against' requires a program name".to_string());
+            }
+            params.verify_against = Some(path_from_bytes(prog));
             continue;
         }
         match match_context_diff_params(&param, next_param, format) {
@@ -158,49 +1005,35 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
         if param.to_string_lossy().starts_with('-') {
             return Err(format!("Unknown option: {:?}", param));
         }
-        if from.is_none() {
-            from = Some(param);
-        } else if to.is_none() {
-            to = Some(param);
-        } else {
-            return Err(format!(
-                "Usage: {} <from> <to>",
-                params.executable.to_string_lossy()
-            ));
-        }
+        operands.push(param);
     }
-    params.from = if let Some(from) = from {
-        from
-    } else if let Some(param) = opts.next() {
-        param
-    } else {
-        return Err(format!(
-            "Usage: {} <from> <to>",
-            params.executable.to_string_lossy()
-        ));
-    };
-    params.to = if let Some(to) = to {
-        to
-    } else if let Some(param) = opts.next() {
-        param
-    } else {
-        return Err(format!(
-            "Usage: {} <from> <to>",
-            params.executable.to_string_lossy()
-        ));
-    };
+    operands.extend(opts);
 
-    // diff DIRECTORY FILE => diff DIRECTORY/FILE FILE
-    // diff FILE DIRECTORY => diff FILE DIRECTORY/FILE
-    let mut from_path: PathBuf = PathBuf::from(&params.from);
-    let mut to_path: PathBuf = PathBuf::from(&params.to);
+    if params.batch {
+        if params.from_file.is_some() || params.to_file.is_some() {
+            return Err("--batch and --from-file or --to-file both specified".to_string());
+        }
+        if let Some(operand) = operands.first() {
+            return Err(format!("extra operand '{}'", operand.to_string_lossy()));
+        }
+    } else if params.from_file.is_some() || params.to_file.is_some() {
+        if params.from_file.is_some() && params.to_file.is_some() {
+            return Err("--from-file and --to-file both specified".to_string());
+        }
+        params.operands = operands;
+    } else {
+        let [from, to]: [OsString; 2] = operands
+            .try_into()
+            .map_err(|_| format!("Usage: {} <from> <to>", params.executable.to_string_lossy()))?;
+        (params.from, params.to) = (from, to);
+        promote_to_directory_entry(&mut params);
+    }
 
-    if from_path.is_dir() && to_path.is_file() {
-        from_path.push(to_path.file_name().unwrap());
-        params.from = from_path.into_os_string();
-    } else if from_path.is_file() && to_path.is_dir() {
-        to_path.push(from_path.file_name().unwrap());
-        params.to = to_path.into_os_string();
+    // Like git, name the files after the compared directories, under a/ and b/.
+    if params.git {
+        params.relative = true;
+        params.src_prefix.get_or_insert_with(|| "a/".to_string());
+        params.dst_prefix.get_or_insert_with(|| "b/".to_string());
     }
 
     params.format = format.unwrap_or(Format::default());
@@ -210,21 +1043,224 @@ pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Resu
     Ok(params)
 }
 
-struct DiffStyleMatch {
-    is_match: bool,
-    context_count: Option<usize>,
-    next_param_consumed: bool,
+// Like GNU diff, an option that sets a format can be repeated, but only with
+// the same value.
+fn set_once(value: &mut Option<String>, option: &str, new_value: &str) -> Result<(), String> {
+    if value.as_ref().is_some_and(|value| value != new_value) {
+        return Err(format!("conflicting {option} option value '{new_value}'"));
+    }
+    *value = Some(new_value.to_string());
+    Ok(())
 }
 
-fn match_context_diff_params(
-    param: &OsString,
-    next_param: Option<&OsString>,
-    format: Option<Format>,
-) -> Result<DiffStyleMatch, String> {
-    const CONTEXT_RE: &str = r"^(-[cC](?<num1>\d*)|--context(=(?<num2>\d*))?|-(?<num3>\d+)c)$";
-    let regex = Regex::new(CONTEXT_RE).unwrap();
-    let is_match = regex.is_match(param.to_string_lossy().as_ref());
-    let mut context_count = None;
+// The first label replaces the name of the first file in headers, the second
+// one that of the second file.
+fn add_label(params: &mut Params, label: String) -> Result<(), String> {
+    if params.from_label.is_none() {
+        params.from_label = Some(label);
+    } else if params.to_label.is_none() {
+        params.to_label = Some(label);
+    } else {
+        return Err("too many file label options".to_string());
+    }
+    Ok(())
+}
+
+// diff DIRECTORY FILE => diff DIRECTORY/FILE FILE
+// diff FILE DIRECTORY => diff FILE DIRECTORY/FILE
+// The file doesn't have to be a regular file (e.g. /dev/null), or even exist,
+// so that it's the entry of the directory that is reported missing, but
+// standard input has no name to look up in the directory. A missing file that
+// -N makes an empty directory isn't promoted. With --archives, archives are
+// compared as directories.
+fn promote_to_directory_entry(params: &mut Params) {
+    let mut from_path: PathBuf = PathBuf::from(&params.from);
+    let mut to_path: PathBuf = PathBuf::from(&params.to);
+    let is_file = |path: &PathBuf, from_side: bool| {
+        let absent_as_empty = params.new_file || from_side && params.unidirectional_new_file;
+        path != Path::new("-")
+            && (path.exists() || !absent_as_empty)
+            && !path.is_dir()
+            && !(params.archives && is_archive(path.as_os_str()))
+    };
+
+    if from_path.is_dir() && is_file(&to_path, false) {
+        if let Some(name) = to_path.file_name() {
+            from_path.push(name);
+            params.from = from_path.into_os_string();
+        }
+    } else if is_file(&from_path, true) && to_path.is_dir() {
+        if let Some(name) = from_path.file_name() {
+            to_path.push(name);
+            params.to = to_path.into_os_string();
+        }
+    }
+}
+
+impl Params {
+    /// Whether lines that aren't byte for byte identical can compare equal.
+    #[must_use]
+    pub fn ignores_line_differences(&self) -> bool {
+        self.ignore_tab_expansion
+            || self.ignore_all_space
+            || self.ignore_ansi
+            || self.normalize.is_some()
+            || !self.ignore_matching_lines.is_empty()
+            || self.numeric_fuzz.is_some()
+    }
+
+    /// The name of the first or second file in headers: its path, or with
+    /// --relative, its name, after the prefix given with --src-prefix or
+    /// --dst-prefix.
+    #[must_use]
+    pub fn reported_name(&self, from_side: bool) -> String {
+        let (path, name, prefix) = if from_side {
+            (&self.from, &self.from_name, &self.src_prefix)
+        } else {
+            (&self.to, &self.to_name, &self.dst_prefix)
+        };
+        if let Some(name) = name {
+            return name.clone();
+        }
+        let path = match Path::new(path).file_name() {
+            Some(file_name) if self.relative => file_name,
+            _ => path,
+        };
+        format!(
+            "{}{}",
+            prefix.as_deref().unwrap_or_default(),
+            path.to_string_lossy()
+        )
+    }
+
+    /// The path of the first or second file in messages: its path, or its
+    /// path inside the archive it was extracted from, with --archives.
+    #[must_use]
+    pub fn shown_path(&self, from_side: bool) -> String {
+        let (path, name) = if from_side {
+            (&self.from, &self.from_name)
+        } else {
+            (&self.to, &self.to_name)
+        };
+        match name {
+            Some(name) if !self.relative => name.clone(),
+            _ => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// The names in headers of the entries of the directories, with
+    /// --relative: their paths inside the compared directories, after the
+    /// prefixes.
+    #[must_use]
+    pub fn entry_names(&self, from_name: &OsStr, to_name: &OsStr) -> (String, String) {
+        let join = |dir: &Option<String>, prefix: &Option<String>, name: &OsStr| {
+            let dir = dir.as_deref().or(prefix.as_deref()).unwrap_or_default();
+            let separator = if dir.is_empty() || dir.ends_with('/') {
+                ""
+            } else {
+                "/"
+            };
+            format!("{dir}{separator}{}", name.to_string_lossy())
+        };
+        (
+            join(&self.from_name, &self.src_prefix, from_name),
+            join(&self.to_name, &self.dst_prefix, to_name),
+        )
+    }
+
+    /// The comparisons to run: `from` against `to`, or with --from-file or
+    /// --to-file, the given file against each of the operands in turn.
+    #[must_use]
+    pub fn comparisons(&self) -> Vec<Params> {
+        let Some(file) = self.from_file.as_ref().or(self.to_file.as_ref()) else {
+            return vec![self.clone()];
+        };
+        self.operands
+            .iter()
+            .map(|operand| {
+                let (from, to) = if self.from_file.is_some() {
+                    (file.clone(), operand.clone())
+                } else {
+                    (operand.clone(), file.clone())
+                };
+                let mut params = Params {
+                    from,
+                    to,
+                    ..self.clone()
+                };
+                promote_to_directory_entry(&mut params);
+                params
+            })
+            .collect()
+    }
+
+    /// The comparisons to run with --batch: the pairs of paths of `input`,
+    /// made of lines with the paths separated by a tab, or, if it contains
+    /// NUL characters, of paths that are all terminated by one.
+    pub fn batch_comparisons(&self, input: &[u8]) -> Result<Vec<Params>, String> {
+        let executable = self.executable.to_string_lossy();
+        let mut pairs = Vec::new();
+        if input.contains(&0) {
+            let mut paths = input
+                .strip_suffix(b"\0")
+                .unwrap_or(input)
+                .split(|&c| c == 0);
+            while let Some(from) = paths.next() {
+                let Some(to) = paths.next() else {
+                    return Err(format!("{executable}: standard input: odd number of paths"));
+                };
+                pairs.push((from, to));
+            }
+        } else {
+            for (i, line) in input.split(|&c| c == b'\n').enumerate() {
+                if line.is_empty() {
+                    continue;
+                }
+                let Some(tab) = line.iter().position(|&c| c == b'\t') else {
+                    return Err(format!(
+                        "{executable}: standard input:{}: missing tab between paths",
+                        i + 1
+                    ));
+                };
+                pairs.push((&line[..tab], &line[tab + 1..]));
+            }
+        }
+
+        pairs
+            .into_iter()
+            .map(|(from, to)| {
+                if from == b"-" || to == b"-" {
+                    return Err(format!(
+                        "{executable}: cannot compare standard input with --batch"
+                    ));
+                }
+                let mut params = Params {
+                    from: path_from_bytes(from),
+                    to: path_from_bytes(to),
+                    ..self.clone()
+                };
+                promote_to_directory_entry(&mut params);
+                Ok(params)
+            })
+            .collect()
+    }
+}
+
+struct DiffStyleMatch {
+    is_match: bool,
+    context_count: Option<usize>,
+    next_param_consumed: bool,
+}
+
+fn match_context_diff_params(
+    param: &OsString,
+    next_param: Option<&OsString>,
+    format: Option<Format>,
+) -> Result<DiffStyleMatch, String> {
+    const CONTEXT_RE: &str = r"^(-[cC](?<num1>\d*)|--context(=(?<num2>\d*))?|-(?<num3>\d+)c)$";
+    let regex = Regex::new(CONTEXT_RE).unwrap();
+    let is_match = regex.is_match(param.to_string_lossy().as_ref());
+    let mut context_count = None;
     let mut next_param_consumed = false;
     if is_match {
         if format.is_some() && format != Some(Format::Context) {
@@ -240,8 +1276,8 @@ fn match_context_diff_params(
                 context_count = Some(numvalue.as_str().parse::<usize>().unwrap());
             }
         }
-        if param == "-C" && next_param.is_some() {
-            match next_param.unwrap().to_string_lossy().parse::<usize>() {
+        if let (true, Some(next_param)) = (param == "-C", next_param) {
+            match next_param.to_string_lossy().parse::<usize>() {
                 Ok(context_size) => {
                     context_count = Some(context_size);
                     next_param_consumed = true;
@@ -249,7 +1285,7 @@ fn match_context_diff_params(
                 Err(_) => {
                     return Err(format!(
                         "invalid context length '{}'",
-                        next_param.unwrap().to_string_lossy()
+                        next_param.to_string_lossy()
                     ))
                 }
             }
@@ -286,8 +1322,8 @@ fn match_unified_diff_params(
                 context_count = Some(numvalue.as_str().parse::<usize>().unwrap());
             }
         }
-        if param == "-U" && next_param.is_some() {
-            match next_param.unwrap().to_string_lossy().parse::<usize>() {
+        if let (true, Some(next_param)) = (param == "-U", next_param) {
+            match next_param.to_string_lossy().parse::<usize>() {
                 Ok(context_size) => {
                     context_count = Some(context_size);
                     next_param_consumed = true;
@@ -295,7 +1331,7 @@ fn match_unified_diff_params(
                 Err(_) => {
                     return Err(format!(
                         "invalid context length '{}'",
-                        next_param.unwrap().to_string_lossy()
+                        next_param.to_string_lossy()
                     ))
                 }
             }
@@ -311,11 +1347,1092 @@ fn match_unified_diff_params(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
     fn os(s: &str) -> OsString {
         OsString::from(s)
     }
     #[test]
-    fn basics() {
+    fn basics() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("--normal"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn basics_ed() {
+        for arg in ["-e", "--ed"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Ed,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(arg), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn basics_rcs() {
+        for arg in ["-n", "--rcs"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Rcs,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(arg), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn basics_ifdef() {
+        for args in [vec!["-D", "FOO"], vec!["-DFOO"], vec!["--ifdef=FOO"]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Ifdef,
+                    old_group_format: Some("#ifndef FOO\n%<#endif /* ! FOO */\n".to_string()),
+                    new_group_format: Some("#ifdef FOO\n%>#endif /* FOO */\n".to_string()),
+                    changed_group_format: Some(
+                        "#ifndef FOO\n%<#else /* FOO */\n%>#endif /* FOO */\n".to_string()
+                    ),
+                    unchanged_group_format: Some("%=".to_string()),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for params in [
+            vec!["diff", "-D"],
+            vec!["diff", "-D", "FOO", "-D", "BAR", "foo", "bar"],
+            vec!["diff", "-D", "FOO", "-u", "foo", "bar"],
+            vec!["diff", "-e", "--ifdef=FOO", "foo", "bar"],
+            vec!["diff", "-D", "FOO", "--old-group-format=%<", "foo", "bar"],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn group_formats() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Ifdef,
+                old_group_format: Some("-%<".to_string()),
+                new_group_format: Some("+%>".to_string()),
+                changed_group_format: Some("%(n=N?same:different)\n".to_string()),
+                unchanged_group_format: Some("%=".to_string()),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--old-group-format=-%<"),
+                    os("--new-group-format=+%>"),
+                    os("--changed-group-format=%(n=N?same:different)\n"),
+                    os("--unchanged-group-format=%="),
+                    os("--unchanged-group-format=%="),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        for params in [
+            vec!["diff", "--group-format=%<", "foo", "bar"],
+            vec!["diff", "--old-group-format=%<", "-n", "foo", "bar"],
+            vec![
+                "diff",
+                "--old-group-format=<",
+                "--old-group-format=>",
+                "foo",
+                "bar",
+            ],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn line_formats() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Ifdef,
+                old_line_format: Some("-%l\n".to_string()),
+                new_line_format: Some("+%L".to_string()),
+                unchanged_line_format: Some("%dn".to_string()),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--old-line-format=-%l\n"),
+                    os("--new-line-format=+%L"),
+                    os("--unchanged-line-format=%dn"),
+                    os("--new-line-format=+%L"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        for params in [
+            vec!["diff", "--line-format=%L", "-u", "foo", "bar"],
+            vec!["diff", "-y", "--old-line-format=%L", "foo", "bar"],
+            vec!["diff", "--changed-line-format=%L", "foo", "bar"],
+            vec![
+                "diff",
+                "--old-line-format=%L",
+                "--line-format=%l",
+                "foo",
+                "bar",
+            ],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn basics_side_by_side() {
+        for arg in ["-y", "--side-by-side"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::SideBySide,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(arg), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::SideBySide,
+                left_column: true,
+                suppress_common_lines: true,
+                mark_truncated: true,
+                wrap_lines: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-y"),
+                    os("--left-column"),
+                    os("--suppress-common-lines"),
+                    os("--mark-truncated"),
+                    os("--wrap-lines"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+    }
+    #[test]
+    fn width() {
+        for args in [vec!["-W", "40"], vec!["-W40"], vec!["--width=40"]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    width: 40,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for args in [
+            vec!["-W", "0"],
+            vec!["-Wx"],
+            vec!["--width="],
+            vec!["--width=-1"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn context_valid() {
+        for args in [vec!["-c"], vec!["--context"], vec!["--context="]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Context,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for args in [
+            vec!["-c42"],
+            vec!["-C42"],
+            vec!["-C", "42"],
+            vec!["--context=42"],
+            vec!["-42c"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Context,
+                    context_count: 42,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+    }
+    #[test]
+    fn context_invalid() {
+        for args in [
+            vec!["-c", "42"],
+            vec!["-c=42"],
+            vec!["-c="],
+            vec!["-C"],
+            vec!["-C=42"],
+            vec!["-C="],
+            vec!["--context42"],
+            vec!["--context", "42"],
+            vec!["-42C"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn unified_valid() {
+        for args in [vec!["-u"], vec!["--unified"], vec!["--unified="]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Unified,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for args in [
+            vec!["-u42"],
+            vec!["-U42"],
+            vec!["-U", "42"],
+            vec!["--unified=42"],
+            vec!["-42u"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Unified,
+                    context_count: 42,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+    }
+    #[test]
+    fn unified_zero_context() {
+        for args in [
+            vec!["-U0"],
+            vec!["-U", "0"],
+            vec!["--unified=0"],
+            vec!["-0u"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    format: Format::Unified,
+                    context_count: 0,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+    }
+    #[test]
+    fn unified_invalid() {
+        for args in [
+            vec!["-u", "42"],
+            vec!["-u=42"],
+            vec!["-u="],
+            vec!["-U"],
+            vec!["-U=42"],
+            vec!["-U="],
+            vec!["--unified42"],
+            vec!["--unified", "42"],
+            vec!["-42U"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn context_count() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Unified,
+                context_count: 54,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-u54"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Unified,
+                context_count: 54,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-U54"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Unified,
+                context_count: 54,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-U"), os("54"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Context,
+                context_count: 54,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-c54"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn report_identical_files() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                report_identical_files: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-s"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                report_identical_files: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--report-identical-files"),
+                    os("foo"),
+                    os("bar"),
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+    }
+    #[test]
+    fn recursive() {
+        for option in ["-r", "--recursive"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    recursive: true,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn new_file() {
+        for option in ["-N", "--new-file"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    new_file: true,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn unidirectional_new_file() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                unidirectional_new_file: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--unidirectional-new-file"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+    }
+    #[test]
+    fn exclude() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                exclude: vec!["*.o".to_string(), "target".to_string(), "x".to_string()],
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-x"),
+                    os("*.o"),
+                    os("--exclude=target"),
+                    os("-xx"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert!(parse_params([os("diff"), os("-x")].iter().cloned().peekable()).is_err());
+    }
+    #[test]
+    fn exclude_from() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"*.o\n\ntarget\n").unwrap();
+        let exclude_from = format!("--exclude-from={}", file.path().to_string_lossy());
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                exclude: vec!["x".to_string(), "*.o".to_string(), "target".to_string()],
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-x"),
+                    os("x"),
+                    os(&exclude_from),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                exclude: vec!["*.o".to_string(), "target".to_string()],
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-X"),
+                    file.path().as_os_str().to_os_string(),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert!(parse_params(
+            [
+                os("diff"),
+                os("-X"),
+                os("target/nonexistent"),
+                os("foo"),
+                os("bar")
+            ]
+            .iter()
+            .cloned()
+            .peekable()
+        )
+        .is_err());
+    }
+    #[test]
+    fn exclude_vcs_ignores() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                recursive: true,
+                exclude_vcs_ignores: true,
+                ..Default::default()
+            }),
+            parse_params(
+                ["diff", "-r", "--exclude-vcs-ignores", "foo", "bar"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn assume_unchanged_by_mtime() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                assume_unchanged_by_mtime: true,
+                ..Default::default()
+            }),
+            parse_params(
+                ["diff", "--assume-unchanged-by-mtime", "foo", "bar"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn starting_file() {
+        for args in [
+            vec!["-S", "foo.c"],
+            vec!["-Sfoo.c"],
+            vec!["--starting-file=foo.c"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    starting_file: Some(os("foo.c")),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+    }
+    #[test]
+    fn ignore_file_name_case() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ignore_file_name_case: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--ignore-file-name-case"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--ignore-file-name-case"),
+                    os("--no-ignore-file-name-case"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+    }
+    #[test]
+    fn from_file() {
+        let params = parse_params(
+            [os("diff"), os("--from-file=foo"), os("bar"), os("baz")]
+                .iter()
+                .cloned()
+                .peekable(),
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            Params {
+                executable: os("diff"),
+                from_file: Some(os("foo")),
+                operands: vec![os("bar"), os("baz")],
+                ..Default::default()
+            }
+        );
+        let comparisons: Vec<(OsString, OsString)> = params
+            .comparisons()
+            .into_iter()
+            .map(|params| (params.from, params.to))
+            .collect();
+        assert_eq!(
+            comparisons,
+            [(os("foo"), os("bar")), (os("foo"), os("baz"))]
+        );
+    }
+    #[test]
+    fn to_file() {
+        let params = parse_params(
+            [os("diff"), os("--to-file=foo"), os("bar"), os("-")]
+                .iter()
+                .cloned()
+                .peekable(),
+        )
+        .unwrap();
+        let comparisons: Vec<(OsString, OsString)> = params
+            .comparisons()
+            .into_iter()
+            .map(|params| (params.from, params.to))
+            .collect();
+        assert_eq!(comparisons, [(os("bar"), os("foo")), (os("-"), os("foo"))]);
+
+        // The file can be a separate argument.
+        let params = parse_params(
+            [os("diff"), os("--to-file"), os("foo"), os("bar")]
+                .iter()
+                .cloned()
+                .peekable(),
+        )
+        .unwrap();
+        assert_eq!(params.to_file, Some(os("foo")));
+        assert_eq!(params.operands, [os("bar")]);
+        assert!(parse_params([os("diff"), os("--from-file")].iter().cloned().peekable()).is_err());
+
+        // No operands is fine, but not both options
+        let params =
+            parse_params([os("diff"), os("--to-file=foo")].iter().cloned().peekable()).unwrap();
+        assert!(params.comparisons().is_empty());
+        assert!(parse_params(
+            [os("diff"), os("--from-file=foo"), os("--to-file=bar")]
+                .iter()
+                .cloned()
+                .peekable()
+        )
+        .is_err());
+    }
+    #[test]
+    fn prefixes() {
+        for args in [
+            vec!["--src-prefix=a/", "--dst-prefix=b/"],
+            vec!["--src-prefix", "a/", "--dst-prefix", "b/"],
+        ] {
+            let mut params = vec!["diff", "--relative"];
+            params.extend(args);
+            params.extend(["/tmp/x/foo", "bar"]);
+            let params = parse_params(params.iter().map(|x| os(x)).peekable()).unwrap();
+            assert_eq!(
+                params,
+                Params {
+                    executable: os("diff"),
+                    from: os("/tmp/x/foo"),
+                    to: os("bar"),
+                    src_prefix: Some("a/".to_string()),
+                    dst_prefix: Some("b/".to_string()),
+                    relative: true,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(params.reported_name(true), "a/foo");
+            assert_eq!(params.reported_name(false), "b/bar");
+            assert_eq!(
+                params.entry_names(OsStr::new("x"), OsStr::new("y")),
+                ("a/x".to_string(), "b/y".to_string())
+            );
+        }
+        assert!(parse_params(
+            ["diff", "foo", "bar", "--src-prefix"]
+                .iter()
+                .map(|x| os(x))
+                .peekable()
+        )
+        .is_err());
+
+        // Without --relative, the prefixes come before the paths.
+        let params = Params {
+            from: os("x/foo"),
+            to: os("y/foo"),
+            src_prefix: Some("a/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(params.reported_name(true), "a/x/foo");
+        assert_eq!(params.reported_name(false), "y/foo");
+
+        // The entries of directories are named after them.
+        let params = Params {
+            from_name: Some("src".to_string()),
+            to_name: Some(String::new()),
+            relative: true,
+            ..params
+        };
+        assert_eq!(
+            params.entry_names(OsStr::new("x"), OsStr::new("y")),
+            ("src/x".to_string(), "y".to_string())
+        );
+    }
+    #[test]
+    fn git() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                format: Format::Unified,
+                context_count: 1,
+                src_prefix: Some("a/".to_string()),
+                dst_prefix: Some("new/".to_string()),
+                relative: true,
+                git: true,
+                ..Default::default()
+            }),
+            parse_params(
+                ["diff", "--git", "-U1", "--dst-prefix=new/", "foo", "bar"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+        for args in [vec!["--git", "-c"], vec!["-e", "--git"]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn textconv() {
+        for args in [
+            vec!["--textconv=pdftotext -q"],
+            vec!["--textconv", "pdftotext -q"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    textconv: Some("pdftotext -q".to_string()),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        let params = ["diff", "foo", "bar", "--textconv"];
+        assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+    }
+    #[test]
+    fn preprocess() {
+        for args in [
+            vec!["--preprocess=jq -S ."],
+            vec!["--preprocess", "jq -S ."],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    preprocess: Some("jq -S .".to_string()),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        let params = ["diff", "foo", "bar", "--preprocess"];
+        assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+    }
+    #[test]
+    fn batch() {
+        let params = parse_params([os("diff"), os("--batch")].iter().cloned().peekable()).unwrap();
+        assert_eq!(
+            params,
+            Params {
+                executable: os("diff"),
+                batch: true,
+                ..Default::default()
+            }
+        );
+        let pairs = |input: &[u8]| {
+            params.batch_comparisons(input).map(|comparisons| {
+                comparisons
+                    .into_iter()
+                    .map(|params| (params.from, params.to))
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            pairs(b"a\tb\n\nc d\te\tf"),
+            Ok(vec![(os("a"), os("b")), (os("c d"), os("e\tf"))])
+        );
+        assert_eq!(pairs(b"a\tb\0c\nd\0"), Ok(vec![(os("a\tb"), os("c\nd"))]));
+        assert_eq!(pairs(b""), Ok(vec![]));
+        assert_eq!(
+            pairs(b"a\tb\nc\n"),
+            Err("diff: standard input:2: missing tab between paths".to_string())
+        );
+        assert!(pairs(b"a\0b\0c\0").is_err());
+        assert!(pairs(b"-\tb\n").is_err());
+
+        for args in [
+            vec!["diff", "--batch", "a"],
+            vec!["diff", "--batch", "--from-file=a"],
+        ] {
+            assert!(parse_params(args.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn labels() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                from_label: Some("x".to_string()),
+                to_label: Some("y z".to_string()),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-L"),
+                    os("x"),
+                    os("--label=y z"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                from_label: Some("x".to_string()),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("-Lx"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert!(parse_params(
+            [
+                os("diff"),
+                os("-Lx"),
+                os("-Ly"),
+                os("--label"),
+                os("z"),
+                os("foo"),
+                os("bar")
+            ]
+            .iter()
+            .cloned()
+            .peekable()
+        )
+        .is_err());
+    }
+    #[test]
+    fn brief() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
@@ -335,10 +2452,26 @@ mod tests {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
+                brief: true,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("--normal"), os("foo"), os("bar")]
+                [os("diff"), os("-q"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                brief: true,
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("--brief"), os("foo"), os("bar"),]
                     .iter()
                     .cloned()
                     .peekable()
@@ -346,18 +2479,32 @@ mod tests {
         );
     }
     #[test]
-    fn basics_ed() {
-        for arg in ["-e", "--ed"] {
+    fn expand_tabs() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("diff"), os("foo"), os("bar")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        for option in ["-t", "--expand-tabs"] {
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    format: Format::Ed,
+                    expand_tabs: true,
                     ..Default::default()
                 }),
                 parse_params(
-                    [os("diff"), os(arg), os("foo"), os("bar")]
+                    [os("diff"), os(option), os("foo"), os("bar")]
                         .iter()
                         .cloned()
                         .peekable()
@@ -366,235 +2513,302 @@ mod tests {
         }
     }
     #[test]
-    fn context_valid() {
-        for args in [vec!["-c"], vec!["--context"], vec!["--context="]] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
+    fn text() {
+        for option in ["-a", "--text"] {
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    format: Format::Context,
+                    text: true,
                     ..Default::default()
                 }),
-                parse_params(params.iter().map(|x| os(x)).peekable())
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
             );
         }
-        for args in [
-            vec!["-c42"],
-            vec!["-C42"],
-            vec!["-C", "42"],
-            vec!["--context=42"],
-            vec!["-42c"],
-        ] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
+    }
+    #[test]
+    fn ignore_tab_expansion() {
+        for option in ["-E", "--ignore-tab-expansion"] {
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    format: Format::Context,
-                    context_count: 42,
+                    ignore_tab_expansion: true,
                     ..Default::default()
                 }),
-                parse_params(params.iter().map(|x| os(x)).peekable())
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
             );
         }
     }
     #[test]
-    fn context_invalid() {
-        for args in [
-            vec!["-c", "42"],
-            vec!["-c=42"],
-            vec!["-c="],
-            vec!["-C"],
-            vec!["-C=42"],
-            vec!["-C="],
-            vec!["--context42"],
-            vec!["--context", "42"],
-            vec!["-42C"],
+    fn ignore_all_space() {
+        for option in ["-w", "--ignore-all-space"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    ignore_all_space: true,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn ignore_matching_lines() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                ignore_matching_lines: vec![
+                    LinePattern::new("^#").unwrap(),
+                    LinePattern::new("[0-9]\\+").unwrap()
+                ],
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("-I"),
+                    os("^#"),
+                    os("--ignore-matching-lines=[0-9]\\+"),
+                    os("foo"),
+                    os("bar")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        for params in [
+            vec!["diff", "-I", "a\\(", "foo", "bar"],
+            vec!["diff", "foo", "bar", "-I"],
         ] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
             assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
         }
     }
     #[test]
-    fn unified_valid() {
-        for args in [vec!["-u"], vec!["--unified"], vec!["--unified="]] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
+    fn minimal() {
+        for option in ["-d", "--minimal"] {
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    format: Format::Unified,
+                    minimal: true,
                     ..Default::default()
                 }),
-                parse_params(params.iter().map(|x| os(x)).peekable())
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
             );
         }
-        for args in [
-            vec!["-u42"],
-            vec!["-U42"],
-            vec!["-U", "42"],
-            vec!["--unified=42"],
-            vec!["-42u"],
+    }
+    #[test]
+    fn horizon_lines() {
+        for params in [
+            vec!["diff", "--horizon-lines=5", "foo", "bar"],
+            vec!["diff", "--horizon-lines", "0", "foo", "bar"],
+            vec!["diff", "--horizon-lines=99999999999999999999", "foo", "bar"],
         ] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    format: Format::Unified,
-                    context_count: 42,
                     ..Default::default()
                 }),
                 parse_params(params.iter().map(|x| os(x)).peekable())
             );
         }
-    }
-    #[test]
-    fn unified_invalid() {
-        for args in [
-            vec!["-u", "42"],
-            vec!["-u=42"],
-            vec!["-u="],
-            vec!["-U"],
-            vec!["-U=42"],
-            vec!["-U="],
-            vec!["--unified42"],
-            vec!["--unified", "42"],
-            vec!["-42U"],
+        for params in [
+            vec!["diff", "--horizon-lines=x", "foo", "bar"],
+            vec!["diff", "--horizon-lines=-1", "foo", "bar"],
+            vec!["diff", "--horizon-lines=", "foo", "bar"],
+            vec!["diff", "foo", "bar", "--horizon-lines"],
         ] {
-            let mut params = vec!["diff"];
-            params.extend(args);
-            params.extend(["foo", "bar"]);
             assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
         }
     }
     #[test]
-    fn context_count() {
-        assert_eq!(
-            Ok(Params {
-                executable: os("diff"),
-                from: os("foo"),
-                to: os("bar"),
-                format: Format::Unified,
-                context_count: 54,
-                ..Default::default()
-            }),
-            parse_params(
-                [os("diff"), os("-u54"), os("foo"), os("bar")]
-                    .iter()
-                    .cloned()
-                    .peekable()
-            )
-        );
+    fn binary() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                format: Format::Unified,
-                context_count: 54,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("-U54"), os("foo"), os("bar")]
+                [os("diff"), os("--binary"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
             )
         );
+    }
+    #[test]
+    fn strip_trailing_cr() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                format: Format::Unified,
-                context_count: 54,
+                strip_trailing_cr: true,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("-U"), os("54"), os("foo"), os("bar")]
+                [os("diff"), os("--strip-trailing-cr"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
             )
         );
+    }
+    #[test]
+    fn ansi() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                format: Format::Context,
-                context_count: 54,
+                ignore_ansi: true,
+                strip_ansi: true,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("-c54"), os("foo"), os("bar")]
+                ["diff", "--ignore-ansi", "--strip-ansi", "foo", "bar"]
                     .iter()
-                    .cloned()
+                    .map(|x| os(x))
                     .peekable()
             )
         );
     }
     #[test]
-    fn report_identical_files() {
+    fn decompress() {
+        for option in ["-z", "--decompress"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo.gz"),
+                    to: os("bar.gz"),
+                    decompress: true,
+                    ..Default::default()
+                }),
+                parse_params(
+                    ["diff", option, "foo.gz", "bar.gz"]
+                        .iter()
+                        .map(|x| os(x))
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn normalize() {
+        for (form, normalize) in [("nfc", Normalization::Nfc), ("nfd", Normalization::Nfd)] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    normalize: Some(normalize),
+                    ..Default::default()
+                }),
+                parse_params(
+                    [
+                        os("diff"),
+                        os(&format!("--normalize={form}")),
+                        os("foo"),
+                        os("bar")
+                    ]
+                    .into_iter()
+                    .peekable()
+                )
+            );
+        }
         assert_eq!(
-            Ok(Params {
-                executable: os("diff"),
-                from: os("foo"),
-                to: os("bar"),
-                ..Default::default()
-            }),
+            Err("invalid argument «nfkc» for '--normalize'".to_string()),
             parse_params(
-                [os("diff"), os("foo"), os("bar")]
+                ["diff", "--normalize=nfkc", "foo", "bar"]
                     .iter()
-                    .cloned()
+                    .map(|x| os(x))
                     .peekable()
             )
         );
+    }
+    #[test]
+    fn initial_tab() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                report_identical_files: true,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("-s"), os("foo"), os("bar")]
+                [os("diff"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
             )
         );
+        for option in ["-T", "--initial-tab"] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    initial_tab: true,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+    }
+    #[test]
+    fn suppress_blank_empty() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                report_identical_files: true,
+                suppress_blank_empty: true,
                 ..Default::default()
             }),
             parse_params(
                 [
                     os("diff"),
-                    os("--report-identical-files"),
+                    os("--suppress-blank-empty"),
                     os("foo"),
-                    os("bar"),
+                    os("bar")
                 ]
                 .iter()
                 .cloned()
@@ -603,87 +2817,112 @@ mod tests {
         );
     }
     #[test]
-    fn brief() {
-        assert_eq!(
-            Ok(Params {
-                executable: os("diff"),
-                from: os("foo"),
-                to: os("bar"),
-                ..Default::default()
-            }),
-            parse_params(
-                [os("diff"), os("foo"), os("bar")]
-                    .iter()
-                    .cloned()
-                    .peekable()
-            )
-        );
-        assert_eq!(
-            Ok(Params {
-                executable: os("diff"),
-                from: os("foo"),
-                to: os("bar"),
-                brief: true,
-                ..Default::default()
-            }),
-            parse_params(
-                [os("diff"), os("-q"), os("foo"), os("bar")]
-                    .iter()
-                    .cloned()
-                    .peekable()
-            )
-        );
-        assert_eq!(
-            Ok(Params {
-                executable: os("diff"),
-                from: os("foo"),
-                to: os("bar"),
-                brief: true,
-                ..Default::default()
-            }),
-            parse_params(
-                [os("diff"), os("--brief"), os("foo"), os("bar"),]
+    fn color() {
+        for (option, color) in [("--color=always", true), ("--color=never", false)] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    color,
+                    ..Default::default()
+                }),
+                parse_params(
+                    [os("diff"), os(option), os("foo"), os("bar")]
+                        .iter()
+                        .cloned()
+                        .peekable()
+                )
+            );
+        }
+        for option in ["--color=sometimes", "--color="] {
+            assert!(parse_params(
+                [os("diff"), os(option), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
             )
-        );
+            .is_err());
+        }
     }
     #[test]
-    fn expand_tabs() {
+    fn palette() {
         assert_eq!(
             Ok(Params {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
+                color: true,
+                palette: Palette {
+                    added: "1;32".to_string(),
+                    ..Default::default()
+                },
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("foo"), os("bar")]
-                    .iter()
-                    .cloned()
-                    .peekable()
+                [
+                    os("diff"),
+                    os("--color=always"),
+                    os("--palette=ad=1;32"),
+                    os("foo"),
+                    os("bar"),
+                ]
+                .iter()
+                .cloned()
+                .peekable()
             )
         );
-        for option in ["-t", "--expand-tabs"] {
+        assert!(parse_params(
+            [os("diff"), os("--palette=zz=1"), os("foo"), os("bar")]
+                .iter()
+                .cloned()
+                .peekable()
+        )
+        .is_err());
+    }
+    #[test]
+    fn stat() {
+        for (option, stat) in [
+            ("--stat", StatFormat::Stat),
+            ("--numstat", StatFormat::Numstat),
+            ("--shortstat", StatFormat::Shortstat),
+        ] {
             assert_eq!(
                 Ok(Params {
                     executable: os("diff"),
                     from: os("foo"),
                     to: os("bar"),
-                    expand_tabs: true,
+                    stat: Some(stat),
                     ..Default::default()
                 }),
                 parse_params(
-                    [os("diff"), os(option), os("foo"), os("bar")]
+                    ["diff", option, "foo", "bar"]
                         .iter()
-                        .cloned()
+                        .map(|x| os(x))
                         .peekable()
                 )
             );
         }
     }
     #[test]
+    fn paginate() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                paginate: true,
+                ..Default::default()
+            }),
+            parse_params(
+                ["diff", "--paginate", "foo", "bar"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+    }
+    #[test]
     fn tabsize() {
         assert_eq!(
             Ok(Params {
@@ -704,11 +2943,11 @@ mod tests {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                tabsize: 0,
+                tabsize: 42,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("--tabsize=0"), os("foo"), os("bar")]
+                [os("diff"), os("--tabsize=42"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
@@ -719,16 +2958,23 @@ mod tests {
                 executable: os("diff"),
                 from: os("foo"),
                 to: os("bar"),
-                tabsize: 42,
+                tabsize: 4,
                 ..Default::default()
             }),
             parse_params(
-                [os("diff"), os("--tabsize=42"), os("foo"), os("bar")]
+                [os("diff"), os("--tabsize"), os("4"), os("foo"), os("bar")]
                     .iter()
                     .cloned()
                     .peekable()
             )
         );
+        assert!(parse_params(
+            [os("diff"), os("--tabsize=0"), os("foo"), os("bar")]
+                .iter()
+                .cloned()
+                .peekable()
+        )
+        .is_err());
         assert!(parse_params(
             [os("diff"), os("--tabsize"), os("foo"), os("bar")]
                 .iter()
@@ -778,6 +3024,189 @@ mod tests {
         .is_err());
     }
     #[test]
+    fn numeric_fuzz() {
+        for args in [vec!["--numeric-fuzz=1e-3"], vec!["--numeric-fuzz", "1e-3"]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    numeric_fuzz: Tolerance::parse("0.001"),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for params in [
+            vec!["diff", "--numeric-fuzz=", "foo", "bar"],
+            vec!["diff", "--numeric-fuzz=-1", "foo", "bar"],
+            vec!["diff", "--numeric-fuzz=inf", "foo", "bar"],
+            vec!["diff", "--numeric-fuzz=x", "foo", "bar"],
+            vec!["diff", "foo", "bar", "--numeric-fuzz"],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn max_depth() {
+        for args in [vec!["--max-depth=0"], vec!["--max-depth", "0"]] {
+            let mut params = vec!["diff", "-r"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    recursive: true,
+                    max_depth: Some(0),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for params in [
+            vec!["diff", "--max-depth=", "foo", "bar"],
+            vec!["diff", "--max-depth=-1", "foo", "bar"],
+            vec!["diff", "--max-depth=x", "foo", "bar"],
+            vec!["diff", "foo", "bar", "--max-depth"],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn output_limits() {
+        for args in [
+            vec!["--max-hunks=2", "--max-lines=0"],
+            vec!["--max-lines", "0", "--max-hunks", "2"],
+        ] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    max_hunks: Some(2),
+                    max_lines: Some(0),
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        assert_eq!(
+            Err("invalid argument «-1» for '--max-lines'".to_string()),
+            parse_params(
+                ["diff", "--max-lines=-1", "foo", "bar"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Err("option '--max-hunks' requires an argument".to_string()),
+            parse_params(
+                ["diff", "foo", "bar", "--max-hunks"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn archives() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo.tar.gz"),
+                to: os("bar.zip"),
+                recursive: true,
+                archives: true,
+                ..Default::default()
+            }),
+            parse_params(
+                ["diff", "-r", "--archives", "foo.tar.gz", "bar.zip"]
+                    .iter()
+                    .map(|x| os(x))
+                    .peekable()
+            )
+        );
+    }
+    #[test]
+    fn jobs() {
+        for args in [vec!["--jobs=4"], vec!["--jobs", "4"]] {
+            let mut params = vec!["diff"];
+            params.extend(args);
+            params.extend(["foo", "bar"]);
+            assert_eq!(
+                Ok(Params {
+                    executable: os("diff"),
+                    from: os("foo"),
+                    to: os("bar"),
+                    jobs: 4,
+                    ..Default::default()
+                }),
+                parse_params(params.iter().map(|x| os(x)).peekable())
+            );
+        }
+        for params in [
+            vec!["diff", "--jobs=0", "foo", "bar"],
+            vec!["diff", "--jobs=", "foo", "bar"],
+            vec!["diff", "--jobs=-1", "foo", "bar"],
+            vec!["diff", "foo", "bar", "--jobs"],
+        ] {
+            assert!(parse_params(params.iter().map(|x| os(x)).peekable()).is_err());
+        }
+    }
+    #[test]
+    fn verify_against() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("diff"),
+                from: os("foo"),
+                to: os("bar"),
+                verify_against: Some(os("/usr/bin/diff")),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("diff"),
+                    os("--verify-against=/usr/bin/diff"),
+                    os("foo"),
+                    os("bar"),
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert!(parse_params(
+            [os("diff"), os("--verify-against="), os("foo"), os("bar")]
+                .iter()
+                .cloned()
+                .peekable()
+        )
+        .is_err()); // The path of the program is kept as it is, even if it isn't UTF-8.
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let arg = OsStr::from_bytes(b"--verify-against=/usr/bin/\xffdiff").to_os_string();
+            let params = parse_params(
+                [os("diff"), arg, os("foo"), os("bar")]
+                    .into_iter()
+                    .peekable(),
+            );
+            assert_eq!(
+                params.unwrap().verify_against,
+                Some(OsStr::from_bytes(b"/usr/bin/\xffdiff").to_os_string())
+            );
+        }
+    }
+    #[test]
     fn double_dash() {
         assert_eq!(
             Ok(Params {
@@ -871,6 +3300,10 @@ mod tests {
             ("-u", "--normal"),
             ("--normal", "-e"),
             ("--context", "--normal"),
+            ("-n", "-u"),
+            ("-e", "--rcs"),
+            ("-y", "-c"),
+            ("--normal", "--side-by-side"),
         ] {
             assert!(parse_params(
                 [os("diff"), os(arg1), os(arg2), os("foo"), os("bar")]
@@ -0,0 +1,2684 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use crate::diff3;
+use crate::hunks::{self, CompareOptions};
+use crate::params::Format;
+use crate::utils::{
+    error_message, format_failure_to_read_input_file, path_from_bytes, shell_quote,
+    CONTEXT_TIME_FORMAT, UNIFIED_TIME_FORMAT,
+};
+use std::env::{self, ArgsOs};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Params {
+    executable: OsString,
+    /// The file to patch, instead of the one named in the patch.
+    file: Option<OsString>,
+    /// The file to read the patch from, instead of standard input, with -i
+    /// or as the second operand.
+    patch: Option<OsString>,
+    /// Apply the patches as if their old and new files were swapped.
+    reverse: bool,
+    /// Skip the patches that seem reversed or already applied, with -N.
+    forward: bool,
+    /// Ask no questions: assume that the patches that seem reversed are,
+    /// with -t.
+    batch: bool,
+    /// Ask no questions: apply the patches as they are, with -f.
+    force: bool,
+    /// The number of leading components to remove from the names of the
+    /// patch, with -p. Without it, only their base names are kept.
+    strip: Option<usize>,
+    /// The number of lines of context at each end of a hunk that may be
+    /// ignored to find where it goes, with -F. It's 2 by default.
+    fuzz: Option<usize>,
+    /// The directory to change to before doing anything else, with -d.
+    directory: Option<OsString>,
+    /// Tell what would be done, without changing any file.
+    dry_run: bool,
+    /// Back up the files before patching them, with -b.
+    backup: bool,
+    /// Back up the files that the patches don't apply to exactly. It's the
+    /// default unless POSIXLY_CORRECT is set.
+    backup_if_mismatch: Option<bool>,
+    /// What to add to the names of the files for their backups, with -z.
+    /// It's ".orig" by default, or SIMPLE_BACKUP_SUFFIX.
+    suffix: Option<OsString>,
+    /// The file to save the hunks that aren't applied to, with -r, instead
+    /// of the file to patch with ".rej" added to its name.
+    reject_file: Option<OsString>,
+    /// Merge the hunks that aren't found into the file, with conflict
+    /// markers where they clash with it, instead of rejecting them.
+    merge: Option<MergeStyle>,
+}
+
+/// How the conflicts of --merge are written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MergeStyle {
+    /// The lines of the file, then those of the hunk.
+    Merge,
+    /// Also the lines that the hunk replaces, between them, with
+    /// --merge=diff3.
+    Diff3,
+}
+
+// The options of --help, in the words of GNU patch.
+const OPTIONS_HELP: &str = "
+Input options:
+
+  -p NUM  --strip=NUM  Strip NUM leading components from file names.
+  -F LINES  --fuzz LINES  Set the fuzz factor to LINES for inexact matching.
+
+  -N  --forward  Ignore patches that appear to be reversed or already applied.
+  -R  --reverse  Assume patches were created with old and new files swapped.
+
+  -i PATCHFILE  --input=PATCHFILE  Read patch from PATCHFILE instead of stdin.
+
+Output options:
+
+  -r FILE  --reject-file=FILE  Output rejects to FILE.
+
+  --merge  Merge using conflict markers instead of creating reject files.
+
+Backup and version control options:
+
+  -b  --backup  Back up the original contents of each file.
+  --backup-if-mismatch  Back up if the patch does not match exactly.
+  --no-backup-if-mismatch  Back up mismatches only if otherwise requested.
+
+  -z SUFFIX  --suffix=SUFFIX  Append SUFFIX to backup file names.
+
+Miscellaneous options:
+
+  -t  --batch  Ask no questions; skip bad-Prereq patches; assume reversed.
+  -f  --force  Like -t, but ignore bad-Prereq patches, and assume unreversed.
+  --dry-run  Do not actually change any files; just print what would happen.
+
+  -d DIR  --directory=DIR  Change the working directory to DIR first.
+
+  -v  --version  Output version info.
+  --help  Output this help.
+";
+
+// The value of an option that takes one, given as "-xVALUE", "-x VALUE",
+// "--long=VALUE" or "--long VALUE", or None when `param` is another option.
+fn option_value(
+    param: &OsStr,
+    short: &str,
+    long: &str,
+    opts: &mut impl Iterator<Item = OsString>,
+    executable: &str,
+) -> Option<Result<OsString, String>> {
+    if param == short || param == long {
+        return Some(opts.next().ok_or_else(|| {
+            if param == short {
+                format!(
+                    "{executable}: option requires an argument -- '{}'",
+                    &short[1..]
+                )
+            } else {
+                format!("{executable}: option '{long}' requires an argument")
+            }
+        }));
+    }
+    let bytes = param.as_encoded_bytes();
+    let value = bytes
+        .strip_prefix(format!("{long}=").as_bytes())
+        .or(bytes.strip_prefix(short.as_bytes()))?;
+    // SAFETY: the prefix that was removed is ASCII, so the rest is still made
+    // of whole characters.
+    Some(Ok(
+        unsafe { OsStr::from_encoded_bytes_unchecked(value) }.to_os_string()
+    ))
+}
+
+// Parses the value of an option that's a number of things, like GNU patch.
+fn parse_count(value: &OsStr, what: &str, executable: &str) -> Result<usize, String> {
+    let value = value.to_string_lossy();
+    match value.parse::<isize>() {
+        Ok(count) if count < 0 => Err(format!("{executable}: **** {what} {value} is negative")),
+        Ok(count) => Ok(count as usize),
+        Err(_) => Err(format!("{executable}: **** {what} {value} is not a number")),
+    }
+}
+
+pub fn parse_params<I: Iterator<Item = OsString>>(mut opts: Peekable<I>) -> Result<Params, String> {
+    let Some(executable) = opts.next() else {
+        return Err("Usage: <exe> [ORIGFILE [PATCHFILE]]".to_string());
+    };
+    let executable_str = executable.to_string_lossy().to_string();
+    let mut params = Params {
+        executable,
+        ..Default::default()
+    };
+    let mut operands = Vec::new();
+    while let Some(param) = opts.next() {
+        if param == "--" {
+            break;
+        }
+        if param == "-" {
+            operands.push(param);
+            continue;
+        }
+        if param == "-R" || param == "--reverse" {
+            params.reverse = true;
+            continue;
+        }
+        if param == "-N" || param == "--forward" {
+            params.forward = true;
+            continue;
+        }
+        if param == "-t" || param == "--batch" {
+            params.batch = true;
+            continue;
+        }
+        if param == "-f" || param == "--force" {
+            params.force = true;
+            continue;
+        }
+        if param == "--dry-run" {
+            params.dry_run = true;
+            continue;
+        }
+        if param == "--merge" || param == "--merge=merge" {
+            params.merge = Some(MergeStyle::Merge);
+            continue;
+        }
+        if param == "--merge=diff3" {
+            params.merge = Some(MergeStyle::Diff3);
+            continue;
+        }
+        if let Some(style) = param.to_string_lossy().strip_prefix("--merge=") {
+            return Err(format!(
+                "{executable_str}: invalid argument '{style}' for '--merge'"
+            ));
+        }
+        if param == "-b" || param == "--backup" {
+            params.backup = true;
+            continue;
+        }
+        if param == "--backup-if-mismatch" {
+            params.backup_if_mismatch = Some(true);
+            continue;
+        }
+        if param == "--no-backup-if-mismatch" {
+            params.backup_if_mismatch = Some(false);
+            continue;
+        }
+        if let Some(strip) = option_value(&param, "-p", "--strip", &mut opts, &executable_str) {
+            params.strip = Some(parse_count(&strip?, "strip count", &executable_str)?);
+            continue;
+        }
+        if let Some(fuzz) = option_value(&param, "-F", "--fuzz", &mut opts, &executable_str) {
+            params.fuzz = Some(parse_count(&fuzz?, "fuzz factor", &executable_str)?);
+            continue;
+        }
+        if let Some(patch) = option_value(&param, "-i", "--input", &mut opts, &executable_str) {
+            params.patch = Some(patch?);
+            continue;
+        }
+        if let Some(reject_file) =
+            option_value(&param, "-r", "--reject-file", &mut opts, &executable_str)
+        {
+            params.reject_file = Some(reject_file?);
+            continue;
+        }
+        if let Some(suffix) = option_value(&param, "-z", "--suffix", &mut opts, &executable_str) {
+            params.suffix = Some(suffix?);
+            continue;
+        }
+        if let Some(directory) =
+            option_value(&param, "-d", "--directory", &mut opts, &executable_str)
+        {
+            params.directory = Some(directory?);
+            continue;
+        }
+        if param == "--help" {
+            print!("Usage: {executable_str} [OPTION]... [ORIGFILE [PATCHFILE]]\n{OPTIONS_HELP}");
+            std::process::exit(0);
+        }
+        if param == "-v" || param == "--version" {
+            println!("patch (uutils diffutils) {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        if param.to_string_lossy().starts_with('-') {
+            return Err(format!("Unknown option: {:?}", param));
+        }
+        operands.push(param);
+    }
+    operands.extend(opts);
+
+    let mut operands = operands.into_iter();
+    params.file = operands.next();
+    // The operand wins over -i.
+    params.patch = operands.next().or(params.patch);
+    if let Some(operand) = operands.next() {
+        return Err(format!(
+            "{executable_str}: {}: extra operand",
+            operand.to_string_lossy()
+        ));
+    }
+    Ok(params)
+}
+
+/// What a line of a hunk is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineKind {
+    /// A line that both files have.
+    Context,
+    /// A line of the old file that the new one doesn't have.
+    Removed,
+    /// A line of the new file that the old one doesn't have.
+    Added,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    /// The line, with its newline unless it's the incomplete last line of a
+    /// file.
+    pub content: Vec<u8>,
+}
+
+/// A change to a file, with the lines of the old file that it replaces,
+/// and some context around them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Hunk {
+    /// The (1-based) number of the first line of the hunk in the old file,
+    /// or of the line it comes after when it has no lines there.
+    pub old_start: usize,
+    /// Likewise, in the new file.
+    pub new_start: usize,
+    /// What follows the ranges in the header of the hunk, like the function
+    /// that it's in.
+    pub heading: Vec<u8>,
+    pub lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// The hunk that undoes this one. Like in GNU patch, the lines that it
+    /// removes come before those it adds, where they're together.
+    #[must_use]
+    pub fn reversed(&self) -> Self {
+        let mut lines = Vec::with_capacity(self.lines.len());
+        for change in self
+            .lines
+            .chunk_by(|a, b| (a.kind == LineKind::Context) == (b.kind == LineKind::Context))
+        {
+            for (from, to) in [
+                (LineKind::Added, LineKind::Removed),
+                (LineKind::Context, LineKind::Context),
+                (LineKind::Removed, LineKind::Added),
+            ] {
+                lines.extend(
+                    change
+                        .iter()
+                        .filter(|line| line.kind == from)
+                        .map(|line| HunkLine {
+                            kind: to,
+                            content: line.content.clone(),
+                        }),
+                );
+            }
+        }
+        Self {
+            old_start: self.new_start,
+            new_start: self.old_start,
+            heading: self.heading.clone(),
+            lines,
+        }
+    }
+
+    /// The lines of the old file.
+    pub fn old_lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.lines
+            .iter()
+            .filter(|line| line.kind != LineKind::Added)
+            .map(|line| line.content.as_slice())
+    }
+
+    /// The lines of the new file.
+    pub fn new_lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.lines
+            .iter()
+            .filter(|line| line.kind != LineKind::Removed)
+            .map(|line| line.content.as_slice())
+    }
+
+    /// The (1-based) number of the first line of the hunk in the old file,
+    /// or of the line it goes before when it has no lines there.
+    #[must_use]
+    pub fn old_first(&self) -> usize {
+        self.old_start + usize::from(self.old_lines().next().is_none())
+    }
+
+    /// Likewise, in the new file.
+    #[must_use]
+    pub fn new_first(&self) -> usize {
+        self.new_start + usize::from(self.new_lines().next().is_none())
+    }
+
+    /// The numbers of lines of context before the first change of the hunk
+    /// and after the last one.
+    #[must_use]
+    pub fn context(&self) -> (usize, usize) {
+        let is_context = |line: &&HunkLine| line.kind == LineKind::Context;
+        let prefix = self.lines.iter().take_while(is_context).count();
+        let suffix = if prefix == self.lines.len() {
+            0
+        } else {
+            self.lines.iter().rev().take_while(is_context).count()
+        };
+        (prefix, suffix)
+    }
+}
+
+/// The changes to a file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FilePatch {
+    /// The format of the patch: unified, context or ed.
+    pub format: Format,
+    /// The names of the old and new files, from the "---" and "+++" lines
+    /// (or "***" and "---"), without their timestamps.
+    pub old_name: Vec<u8>,
+    pub new_name: Vec<u8>,
+    /// Their timestamps, or whatever follows them, if anything.
+    pub old_time: Vec<u8>,
+    pub new_time: Vec<u8>,
+    /// The text of the patch before the hunks, and the (1-based) number of
+    /// the line of the patch where they start, for messages.
+    pub header: Vec<u8>,
+    pub hunks_line: usize,
+    pub hunks: Vec<Hunk>,
+    /// The commands of an ed script, which has no hunks.
+    pub commands: Vec<EdCommand>,
+    /// What the extended header lines of a git diff tell: whether the old
+    /// file is renamed or copied to the new one, the modes of the files,
+    /// when they change, and whether it's a binary patch.
+    pub renamed: bool,
+    pub copied: bool,
+    pub old_mode: Option<u32>,
+    pub new_mode: Option<u32>,
+    pub binary: bool,
+}
+
+/// A command of an ed script, like "3,4c" and the lines it changes them to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdCommand {
+    /// The (1-based) number of the line of the patch where it is, for
+    /// messages.
+    pub patch_line: usize,
+    /// The first and last lines it's about, or None for the current one.
+    pub range: Option<(usize, usize)>,
+    /// What it does: 'a' to add lines after the range, 'i' before it, 'c'
+    /// to change it, 'd' to delete it, or 's' to remove the first character
+    /// of its lines, for "s/.//".
+    pub letter: u8,
+    /// The lines that it adds, with their newlines.
+    pub text: Vec<Vec<u8>>,
+}
+
+impl FilePatch {
+    /// The patch that undoes this one, for -R.
+    #[must_use]
+    pub fn reversed(&self) -> Self {
+        Self {
+            old_name: self.new_name.clone(),
+            new_name: self.old_name.clone(),
+            old_time: self.new_time.clone(),
+            new_time: self.old_time.clone(),
+            old_mode: self.new_mode,
+            new_mode: self.old_mode,
+            hunks: self.hunks.iter().map(Hunk::reversed).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the patch creates the file, like GNU patch tells: when the
+    /// old file doesn't exist, or when a single hunk adds all the lines.
+    #[must_use]
+    pub fn creates_file(&self) -> bool {
+        let adds_all = |hunk: &Hunk| hunk.old_start == 0 && hunk.old_lines().next().is_none();
+        self.old_missing() || matches!(&self.hunks[..], [hunk] if adds_all(hunk))
+    }
+
+    /// Whether the patch deletes the file, when the new one doesn't exist.
+    #[must_use]
+    pub fn deletes_file(&self) -> bool {
+        missing(&self.new_name, &self.new_time)
+    }
+
+    // Whether the old file doesn't exist.
+    fn old_missing(&self) -> bool {
+        missing(&self.old_name, &self.old_time)
+    }
+}
+
+// Whether a file of the patch doesn't exist: when it's /dev/null, or when
+// it's stamped with the epoch, like diff -N does for the files it compares
+// as empty, as GNU patch tells.
+fn missing(name: &[u8], time: &[u8]) -> bool {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+    if name == b"/dev/null" {
+        return true;
+    }
+    let Ok(time) = std::str::from_utf8(time) else {
+        return false;
+    };
+    // A context diff doesn't tell the time zone: it's the local one.
+    let stamp = DateTime::parse_from_str(time, UNIFIED_TIME_FORMAT)
+        .map(|time| time.timestamp())
+        .ok()
+        .or_else(|| {
+            let time = NaiveDateTime::parse_from_str(time, CONTEXT_TIME_FORMAT).ok()?;
+            Some(Local.from_local_datetime(&time).earliest()?.timestamp())
+        });
+    stamp == Some(0)
+}
+
+// The name and the timestamp of a file in a "---" or "+++" line. The name
+// ends at the tab before the timestamp, or at the first space when there's
+// no tab, like in GNU patch.
+fn header_name(rest: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let rest = rest.strip_suffix(b"\n").unwrap_or(rest);
+    let rest = rest.strip_suffix(b"\r").unwrap_or(rest);
+    let end = match rest.iter().position(|&c| c == b'\t') {
+        Some(tab) => tab,
+        None => rest
+            .iter()
+            .position(|c| c.is_ascii_whitespace())
+            .unwrap_or(rest.len()),
+    };
+    (
+        rest[..end].trim_ascii_end().to_vec(),
+        rest[end..].trim_ascii().to_vec(),
+    )
+}
+
+// The names of the files in a "diff --git" line, like "a/f b/f". As the
+// names can have spaces, they're split at the space that leaves the same
+// name on both sides but for their first component, if there's one, or
+// else before the "b/" of the new name.
+fn git_names(rest: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let rest = chomp(rest);
+    let unprefixed = |name: &[u8]| match name.iter().position(|&c| c == b'/') {
+        Some(slash) => name[slash + 1..].to_vec(),
+        None => name.to_vec(),
+    };
+    let spaces: Vec<usize> = (0..rest.len()).filter(|&j| rest[j] == b' ').collect();
+    let space = spaces
+        .iter()
+        .copied()
+        .find(|&j| unprefixed(&rest[..j]) == unprefixed(&rest[j + 1..]))
+        .or_else(|| {
+            spaces
+                .iter()
+                .copied()
+                .find(|&j| rest[j..].starts_with(b" b/"))
+        })
+        .or(spaces.first().copied());
+    match space {
+        Some(space) => (rest[..space].to_vec(), rest[space + 1..].to_vec()),
+        None => (rest.to_vec(), rest.to_vec()),
+    }
+}
+
+// Takes what an extended header line of a git diff tells, like
+// "new file mode 100644". Returns false if it isn't one, or if it's the
+// start of the data of a binary patch, which ends them.
+fn parse_git_header_line(file_patch: &mut FilePatch, line: &[u8]) -> bool {
+    let line = chomp(line);
+    let mode = |rest: &[u8]| {
+        std::str::from_utf8(rest)
+            .ok()
+            .and_then(|rest| u32::from_str_radix(rest.trim(), 8).ok())
+    };
+    if let Some(rest) = line.strip_prefix(b"old mode ") {
+        file_patch.old_mode = mode(rest);
+    } else if let Some(rest) = line.strip_prefix(b"new mode ") {
+        file_patch.new_mode = mode(rest);
+    } else if let Some(rest) = line.strip_prefix(b"deleted file mode ") {
+        file_patch.old_mode = mode(rest);
+        file_patch.new_name = b"/dev/null".to_vec();
+    } else if let Some(rest) = line.strip_prefix(b"new file mode ") {
+        file_patch.new_mode = mode(rest);
+        file_patch.old_name = b"/dev/null".to_vec();
+    } else if line.starts_with(b"rename from ") || line.starts_with(b"rename to ") {
+        file_patch.renamed = true;
+    } else if line.starts_with(b"copy from ") || line.starts_with(b"copy to ") {
+        file_patch.copied = true;
+    } else if line == b"GIT binary patch" {
+        file_patch.binary = true;
+        return false;
+    } else {
+        return [
+            &b"index "[..],
+            b"similarity index ",
+            b"dissimilarity index ",
+            b"Binary files ",
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix));
+    }
+    true
+}
+
+// Parses the start and the number of lines of a range of a hunk header,
+// like "3,4", or "3" for a single line.
+fn parse_range(range: &[u8]) -> Option<(usize, usize)> {
+    let range = std::str::from_utf8(range).ok()?;
+    let (start, count) = match range.split_once(',') {
+        Some((start, count)) => (start, count.parse().ok()?),
+        None => (range, 1),
+    };
+    Some((start.parse().ok()?, count))
+}
+
+// The ranges of the old and new files in a unified hunk header, and the
+// heading after them.
+type HunkHeader<'a> = ((usize, usize), (usize, usize), &'a [u8]);
+
+// Parses a unified hunk header, like "@@ -1,3 +1,4 @@ fn main() {".
+fn parse_hunk_header(line: &[u8]) -> Option<HunkHeader<'_>> {
+    let rest = line.strip_prefix(b"@@ -")?;
+    let end = rest.windows(3).position(|w| w == b" @@")?;
+    let mut ranges = rest[..end].split(|&c| c == b' ');
+    let old = parse_range(ranges.next()?)?;
+    let new = parse_range(ranges.next()?.strip_prefix(b"+")?)?;
+    if ranges.next().is_some() {
+        return None;
+    }
+    let heading = &rest[end + 3..];
+    let heading = heading.strip_suffix(b"\n").unwrap_or(heading);
+    Some((old, new, heading.strip_suffix(b"\r").unwrap_or(heading)))
+}
+
+fn malformed(line_number: usize, line: &[u8]) -> String {
+    format!(
+        "malformed patch at line {line_number}: {}",
+        String::from_utf8_lossy(line)
+    )
+}
+
+// The text of a line, without its line ending.
+fn chomp(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// Parses the unified hunks at the `i`th line of a patch, leaving `i` after
+// them.
+fn parse_unified_hunks(lines: &[&[u8]], i: &mut usize) -> Result<Vec<Hunk>, String> {
+    let mut hunks = Vec::new();
+    while *i < lines.len() && lines[*i].starts_with(b"@@ ") {
+        let Some(((old_start, old_count), (new_start, new_count), heading)) =
+            parse_hunk_header(lines[*i])
+        else {
+            return Err(malformed(*i + 1, lines[*i]));
+        };
+        *i += 1;
+        let mut hunk = Hunk {
+            old_start,
+            new_start,
+            heading: heading.to_vec(),
+            lines: Vec::new(),
+        };
+        let (mut old_left, mut new_left) = (old_count, new_count);
+        while old_left > 0 || new_left > 0 {
+            let line = lines.get(*i).copied().unwrap_or_default();
+            // Like GNU patch, an empty line is an empty line of context,
+            // whose leading space was lost.
+            let (kind, content) = match line.first() {
+                Some(b' ') if old_left > 0 && new_left > 0 => (LineKind::Context, &line[1..]),
+                Some(b'\n') if old_left > 0 && new_left > 0 => (LineKind::Context, line),
+                Some(b'-') if old_left > 0 => (LineKind::Removed, &line[1..]),
+                Some(b'+') if new_left > 0 => (LineKind::Added, &line[1..]),
+                Some(b'\\') if !hunk.lines.is_empty() => {
+                    *i += 1;
+                    continue;
+                }
+                _ => return Err(malformed(*i + 1, line)),
+            };
+            if kind != LineKind::Added {
+                old_left -= 1;
+            }
+            if kind != LineKind::Removed {
+                new_left -= 1;
+            }
+            hunk.lines.push(HunkLine {
+                kind,
+                content: content.to_vec(),
+            });
+            *i += 1;
+            no_newline(lines, i, &mut hunk.lines);
+        }
+        hunks.push(hunk);
+    }
+    Ok(hunks)
+}
+
+// Skips a "\ No newline at end of file" line at the `i`th line of a patch,
+// removing the newline of the line before it.
+fn no_newline(lines: &[&[u8]], i: &mut usize, hunk_lines: &mut [HunkLine]) {
+    if lines.get(*i).is_some_and(|line| line.starts_with(b"\\")) {
+        let last = hunk_lines.last_mut().unwrap();
+        if last.content.ends_with(b"\n") {
+            last.content.pop();
+        }
+        *i += 1;
+    }
+}
+
+// Parses the range of a context hunk, like "*** 3,5 ****", as its first line
+// and its number of lines. A single number is one line, or no lines after
+// that one when the hunk has none there: that's told by its lines.
+fn parse_context_range(line: &[u8], prefix: &[u8], suffix: &[u8]) -> Option<(usize, usize)> {
+    let range = chomp(line).strip_prefix(prefix)?.strip_suffix(suffix)?;
+    let range = std::str::from_utf8(range).ok()?;
+    Some(match range.split_once(',') {
+        Some((first, last)) => {
+            let (first, last): (usize, usize) = (first.parse().ok()?, last.parse().ok()?);
+            (first, (last + 1).checked_sub(first)?)
+        }
+        None => {
+            let line: usize = range.parse().ok()?;
+            (line, usize::from(line > 0))
+        }
+    })
+}
+
+// Parses the lines of the old or new file of a context hunk, which are
+// marked with `change` when only that file has them, or "!" when they're
+// changed. They may be left out when they're all context.
+fn parse_context_section(
+    lines: &[&[u8]],
+    i: &mut usize,
+    count: usize,
+    change: u8,
+) -> Result<Vec<HunkLine>, String> {
+    let kind = if change == b'-' {
+        LineKind::Removed
+    } else {
+        LineKind::Added
+    };
+    let parse = |line: &[u8]| match line {
+        [b' ', b' ', content @ ..] => Some((LineKind::Context, content.to_vec())),
+        [c, b' ', content @ ..] if *c == change || *c == b'!' => Some((kind, content.to_vec())),
+        b"\n" => Some((LineKind::Context, line.to_vec())),
+        _ => None,
+    };
+    let mut section = Vec::new();
+    if lines.get(*i).and_then(|line| parse(line)).is_none() {
+        return Ok(section);
+    }
+    for _ in 0..count {
+        let line = lines.get(*i).copied().unwrap_or_default();
+        let Some((kind, content)) = parse(line) else {
+            return Err(malformed(*i + 1, line));
+        };
+        section.push(HunkLine { kind, content });
+        *i += 1;
+        no_newline(lines, i, &mut section);
+    }
+    Ok(section)
+}
+
+// Parses the context hunks at the `i`th line of a patch, leaving `i` after
+// them.
+fn parse_context_hunks(lines: &[&[u8]], i: &mut usize) -> Result<Vec<Hunk>, String> {
+    let mut hunks = Vec::new();
+    while *i < lines.len() && lines[*i].starts_with(b"***************") {
+        let heading = chomp(&lines[*i][15..]).to_vec();
+        let header = *i;
+        *i += 1;
+        let line = lines.get(*i).copied().unwrap_or_default();
+        let (old_start, old_count) =
+            parse_context_range(line, b"*** ", b" ****").ok_or_else(|| malformed(*i + 1, line))?;
+        *i += 1;
+        let mut old = parse_context_section(lines, i, old_count, b'-')?;
+        let line = lines.get(*i).copied().unwrap_or_default();
+        let (new_start, new_count) =
+            parse_context_range(line, b"--- ", b" ----").ok_or_else(|| malformed(*i + 1, line))?;
+        *i += 1;
+        let mut new = parse_context_section(lines, i, new_count, b'+')?;
+
+        // The lines left out are the context of the other file.
+        let context = |section: &[HunkLine]| {
+            section
+                .iter()
+                .filter(|line| line.kind == LineKind::Context)
+                .cloned()
+                .collect()
+        };
+        if old.is_empty() {
+            old = context(&new);
+        } else if new.is_empty() {
+            new = context(&old);
+        }
+        // Between the lines of context that they have in common, the lines
+        // of the old file go before those of the new one, like in unified
+        // hunks.
+        let mut hunk = Hunk {
+            old_start,
+            new_start,
+            heading,
+            lines: Vec::with_capacity(old.len() + new.len()),
+        };
+        let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+        loop {
+            let is_change = |line: &HunkLine| line.kind != LineKind::Context;
+            while let Some(line) = old.next_if(is_change) {
+                hunk.lines.push(line);
+            }
+            while let Some(line) = new.next_if(is_change) {
+                hunk.lines.push(line);
+            }
+            match (old.next(), new.next()) {
+                (Some(line), Some(_)) => hunk.lines.push(line),
+                (None, None) => break,
+                _ => return Err(malformed(header + 1, lines[header])),
+            }
+        }
+        hunks.push(hunk);
+    }
+    Ok(hunks)
+}
+
+// The range and the letter of an ed command, of those that GNU patch
+// accepts: "a", "i", "c", "d" and "s/.//", after a line number or two.
+fn parse_ed_command(line: &[u8]) -> Option<(Option<(usize, usize)>, u8)> {
+    let line = std::str::from_utf8(line).ok()?.strip_suffix('\n')?;
+    let line = line.trim_end_matches([' ', '\t']);
+    let (range, command) = line.split_at(line.find(|c: char| !c.is_ascii_digit() && c != ',')?);
+    let range = match range.split_once(',') {
+        _ if range.is_empty() => None,
+        Some((first, last)) => Some((first.parse().ok()?, last.parse().ok()?)),
+        None => range.parse().ok().map(|line| (line, line)),
+    };
+    match command {
+        "a" | "i" if !line.contains(',') => Some((range, command.as_bytes()[0])),
+        "c" | "d" => Some((range, command.as_bytes()[0])),
+        "s/.//" => Some((range, b's')),
+        _ => None,
+    }
+}
+
+// Parses the ed commands at the `i`th line of a patch, leaving `i` after
+// them. The lines that "a", "i" and "c" add end with one that's a dot.
+fn parse_ed_script(lines: &[&[u8]], i: &mut usize) -> Vec<EdCommand> {
+    let mut commands = Vec::new();
+    while let Some((range, letter)) = lines.get(*i).and_then(|line| parse_ed_command(line)) {
+        let mut command = EdCommand {
+            patch_line: *i + 1,
+            range,
+            letter,
+            text: Vec::new(),
+        };
+        *i += 1;
+        if matches!(letter, b'a' | b'i' | b'c') {
+            while let Some(&line) = lines.get(*i) {
+                *i += 1;
+                if line == b".\n" {
+                    break;
+                }
+                command.text.push(line.to_vec());
+            }
+        }
+        commands.push(command);
+    }
+    commands
+}
+
+/// Parses a patch in the unified or context format: for each file, the
+/// "---" and "+++" lines that name it (or "***" and "---"), then the hunks.
+/// The text before them, like mail headers, is kept as the header of the
+/// patch of the file, and the text after the last one is ignored. An ed
+/// script, which names no file, is parsed as the commands it's made of.
+pub fn parse_patch(patch: &[u8]) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&[u8]> = patch.split_inclusive(|&c| c == b'\n').collect();
+    let starts = |j: usize, prefixes: &[&[u8]]| {
+        prefixes
+            .iter()
+            .enumerate()
+            .all(|(k, prefix)| lines.get(j + k).is_some_and(|l| l.starts_with(prefix)))
+    };
+    let mut file_patches = Vec::new();
+    let mut i = 0;
+    let is_diff = |j: usize| {
+        starts(j, &[b"--- ", b"+++ "]) || starts(j, &[b"*** ", b"--- ", b"***************"])
+    };
+    while let Some(mut start) = (i..lines.len()).find(|&j| {
+        is_diff(j) || lines[j].starts_with(b"diff --git ") || parse_ed_command(lines[j]).is_some()
+    }) {
+        // A git diff can have no hunks, like when it only renames a file or
+        // changes its mode, with just its extended header lines.
+        let mut git = FilePatch::default();
+        if let Some(rest) = lines[start].strip_prefix(b"diff --git ") {
+            (git.old_name, git.new_name) = git_names(rest);
+            start += 1;
+            while start < lines.len()
+                && !is_diff(start)
+                && parse_git_header_line(&mut git, lines[start])
+            {
+                start += 1;
+            }
+            if !is_diff(start) {
+                git.header = lines[i..start].concat();
+                // The line of the data of a binary patch, or the last one of
+                // the header.
+                git.hunks_line = start + usize::from(git.binary);
+                i = start;
+                file_patches.push(git);
+                continue;
+            }
+        }
+        if !lines[start].starts_with(b"--- ") && !lines[start].starts_with(b"*** ") {
+            let header = lines[i..start].concat();
+            i = start;
+            file_patches.push(FilePatch {
+                format: Format::Ed,
+                header,
+                hunks_line: start + 1,
+                commands: parse_ed_script(&lines, &mut i),
+                ..Default::default()
+            });
+            continue;
+        }
+        let format = if lines[start].starts_with(b"*** ") {
+            Format::Context
+        } else {
+            Format::Unified
+        };
+        let (old_name, old_time) = header_name(&lines[start][4..]);
+        let (new_name, new_time) = header_name(&lines[start + 1][4..]);
+        let header = lines[i..start + 2].concat();
+        i = start + 2;
+        let hunks = match format {
+            Format::Context => parse_context_hunks(&lines, &mut i)?,
+            _ => parse_unified_hunks(&lines, &mut i)?,
+        };
+        file_patches.push(FilePatch {
+            format,
+            old_name,
+            new_name,
+            old_time,
+            new_time,
+            header,
+            hunks_line: start + 3,
+            hunks,
+            ..git
+        });
+    }
+    Ok(file_patches)
+}
+
+/// Runs the commands of an ed script on the lines of a file, like ed.
+/// Returns the number of the line of the patch of the command that fails
+/// when its lines aren't in the file.
+pub fn run_ed_script(content: &[u8], commands: &[EdCommand]) -> Result<Vec<u8>, usize> {
+    let mut lines: Vec<Vec<u8>> = content
+        .split_inclusive(|&c| c == b'\n')
+        .map(<[u8]>::to_vec)
+        .collect();
+    // The current line, which the commands without a range are about.
+    let mut current = lines.len();
+    for command in commands {
+        let fail = || command.patch_line;
+        let (first, last) = command.range.unwrap_or((current, current));
+        let text = command.text.iter().cloned();
+        match command.letter {
+            b'a' | b'i' => {
+                if last > lines.len() {
+                    return Err(fail());
+                }
+                let at = if command.letter == b'a' {
+                    last
+                } else {
+                    last.saturating_sub(1)
+                };
+                lines.splice(at..at, text);
+                current = if command.text.is_empty() {
+                    last
+                } else {
+                    at + command.text.len()
+                };
+            }
+            b'c' | b'd' => {
+                if first == 0 || first > last || last > lines.len() {
+                    return Err(fail());
+                }
+                if command.letter == b'd' {
+                    lines.drain(first - 1..last);
+                } else {
+                    lines.splice(first - 1..last, text);
+                }
+                let added = command.text.len();
+                current = if added > 0 {
+                    first - 1 + added
+                } else {
+                    // The line after those deleted, or the last one.
+                    first.min(lines.len())
+                };
+            }
+            _ => {
+                if first == 0 || first > last || last > lines.len() {
+                    return Err(fail());
+                }
+                for line in &mut lines[first - 1..last] {
+                    if line.first().is_none_or(|&c| c == b'\n') {
+                        return Err(fail());
+                    }
+                    line.remove(0);
+                }
+                current = last;
+            }
+        }
+    }
+    // A line that doesn't end with a newline gets one when another comes
+    // after it.
+    let last = lines.len().saturating_sub(1);
+    for line in &mut lines[..last] {
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+    }
+    Ok(lines.concat())
+}
+
+/// What became of a hunk that was applied to a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HunkOutcome {
+    /// The hunk was applied at this (1-based) line of the patched file. It
+    /// was found this many lines away from where it says, ignoring this many
+    /// lines of context at its ends.
+    Applied {
+        line: usize,
+        offset: isize,
+        fuzz: usize,
+    },
+    /// The lines of the hunk weren't found. The line is where the hunk says
+    /// it goes in the patched file.
+    Failed { line: usize },
+    /// The lines of the hunk weren't found as they are, so its changes were
+    /// merged into the lines that look the most like them, with --merge.
+    /// What became of each change, with the (1-based) lines of the patched
+    /// file it's at.
+    Merged(Vec<(Merge, Range<usize>)>),
+}
+
+/// What became of a change that was merged into a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Merge {
+    /// It was made.
+    Merged,
+    /// The file already had it.
+    AlreadyApplied,
+    /// The file changed the same lines in another way: both versions were
+    /// written, between conflict markers.
+    NotMerged,
+}
+
+// Whether the old lines of a hunk are at a (1-based) line of a file, except
+// for the first and last ones that are ignored.
+fn hunk_matches(
+    lines: &[&[u8]],
+    old: &[&[u8]],
+    at: isize,
+    prefix_fuzz: isize,
+    suffix_fuzz: isize,
+) -> bool {
+    (prefix_fuzz..old.len() as isize - suffix_fuzz).all(|i| {
+        let line = at - 1 + i;
+        line >= 0 && lines.get(line as usize) == Some(&old[i as usize])
+    })
+}
+
+// Finds the (1-based) line where the old lines of a hunk are, like GNU
+// patch: from where the hunk says, moved by the offset of the hunks before,
+// outward to the `frozen` lines already patched and the end of the file,
+// ignoring up to `fuzz` lines of context at each end. A hunk with less
+// context at an end than at the other, because it's at the start or the end
+// of the file, must still be there unless the fuzz makes up for it.
+fn locate_hunk(
+    lines: &[&[u8]],
+    hunk: &Hunk,
+    offset: isize,
+    frozen: usize,
+    fuzz: usize,
+) -> Option<usize> {
+    let old: Vec<&[u8]> = hunk.old_lines().collect();
+    let first_guess = hunk.old_first() as isize + offset;
+    if old.is_empty() {
+        // Nothing to find.
+        return Some(first_guess.max(frozen as isize + 1) as usize);
+    }
+    let (input_lines, pat_lines) = (lines.len() as isize, old.len() as isize);
+    let (prefix, suffix) = hunk.context();
+    let (prefix, suffix, fuzz) = (prefix as isize, suffix as isize, fuzz as isize);
+    let context = prefix.max(suffix);
+    let mut prefix_fuzz = fuzz + prefix - context;
+    let suffix_fuzz = fuzz + suffix - context;
+    let frozen = frozen as isize;
+    // The lines of context that are ignored can be past the end of the file,
+    // but not among the lines already patched.
+    let max_where = input_lines - (pat_lines - suffix_fuzz) + 1;
+    let min_where = frozen + 1;
+    let max_pos_offset = max_where - first_guess;
+    let max_neg_offset = first_guess - min_where;
+    let matches = |at, prefix_fuzz, suffix_fuzz| {
+        hunk_matches(lines, &old, at, prefix_fuzz, suffix_fuzz).then_some(at as usize)
+    };
+
+    if prefix_fuzz < 0 && hunk.old_first() <= 1 {
+        // Only the start of the file will do, or the whole file.
+        if suffix_fuzz < 0 && (pat_lines != input_lines || prefix < frozen) {
+            return None;
+        }
+        if frozen <= prefix && 1 - first_guess <= max_pos_offset {
+            return matches(1, 0, suffix_fuzz);
+        }
+        return None;
+    }
+    prefix_fuzz = prefix_fuzz.max(0);
+    if suffix_fuzz < 0 {
+        // Only the end of the file will do.
+        let at = input_lines - pat_lines + 1;
+        if first_guess - at <= max_neg_offset {
+            return matches(at, prefix_fuzz, 0);
+        }
+        return None;
+    }
+    // Closest first, and after rather than before.
+    (0..=max_pos_offset.max(max_neg_offset))
+        .flat_map(|offset| [first_guess + offset, first_guess - offset])
+        .filter(|at| (min_where..=max_where).contains(at))
+        .find_map(|at| matches(at, prefix_fuzz, suffix_fuzz))
+}
+
+// The fuzz factor that's used for a hunk: no more than its context.
+fn max_fuzz(hunk: &Hunk, fuzz: usize) -> usize {
+    let (prefix, suffix) = hunk.context();
+    fuzz.min(prefix.max(suffix))
+}
+
+// How many lines of a file from its `start`th (0-based) line on look the
+// most like the old lines of a hunk: those that need the fewest lines
+// removed and added to get them, and the most lines when there's a tie.
+// Returns how many lines they are, if at least `min_lines`, and how many
+// lines are removed and added, if no more than `max_changes`.
+fn best_match(
+    lines: &[&[u8]],
+    old: &[&[u8]],
+    start: usize,
+    min_lines: usize,
+    max_changes: usize,
+) -> Option<(usize, usize)> {
+    let candidates = &lines[start..(start + old.len() + max_changes).min(lines.len())];
+    // The longest common subsequences of the old lines and of the first
+    // lines of the candidates, row by row.
+    let mut common = vec![0; candidates.len() + 1];
+    for old_line in old {
+        let mut diagonal = 0;
+        for (j, line) in candidates.iter().enumerate() {
+            let above = common[j + 1];
+            common[j + 1] = if old_line == line {
+                diagonal + 1
+            } else {
+                above.max(common[j])
+            };
+            diagonal = above;
+        }
+    }
+    (min_lines..=candidates.len())
+        .map(|count| (count, old.len() + count - 2 * common[count]))
+        .filter(|&(_, changes)| changes <= max_changes)
+        .min_by_key(|&(count, changes)| (changes, std::cmp::Reverse(count)))
+}
+
+// The (1-based) line where the lines of a hunk that isn't found as it is
+// look the most like its old lines, and how many lines of the file they
+// are. Like in GNU patch, no more lines than the hunk has of context may
+// differ, counting those removed and added, and there must be at least as
+// many as it has other old lines. Those closest to where the hunk says are
+// taken, unless others farther away are more lines with fewer changes. When
+// there are none, it goes where it says, moved by the offset of the hunks
+// before.
+fn locate_merge(lines: &[&[u8]], hunk: &Hunk, offset: isize, frozen: usize) -> (usize, usize) {
+    let old: Vec<&[u8]> = hunk.old_lines().collect();
+    let context = hunk
+        .lines
+        .iter()
+        .filter(|line| line.kind == LineKind::Context)
+        .count();
+    let min_where = frozen + 1;
+    let max_where = (lines.len() + 1).max(min_where);
+    let first_guess =
+        (hunk.old_first() as isize + offset).clamp(min_where as isize, max_where as isize) as usize;
+    let mut best = (first_guess, old.len().min(lines.len() + 1 - first_guess));
+    let mut min_lines = old.len() - context.min(old.len());
+    let mut max_changes = 2 * context;
+    // Closest first, and after rather than before.
+    for at in (0..=max_where - min_where)
+        .flat_map(|offset| [first_guess + offset, first_guess.wrapping_sub(offset)])
+        .filter(|at| (min_where..=max_where).contains(at))
+    {
+        let Some((count, changes)) = best_match(lines, &old, at - 1, min_lines, max_changes) else {
+            continue;
+        };
+        best = (at, count);
+        if changes == 0 {
+            break;
+        }
+        min_lines = count + 1;
+        max_changes = changes - 1;
+    }
+    best
+}
+
+// The changes of a hunk, with the indices of its old and new lines.
+fn hunk_changes(hunk: &Hunk) -> Vec<hunks::Change> {
+    let mut changes = Vec::new();
+    let (mut old, mut new) = (0, 0);
+    for lines in hunk
+        .lines
+        .chunk_by(|a, b| (a.kind == LineKind::Context) == (b.kind == LineKind::Context))
+    {
+        let count = |kind| lines.iter().filter(|line| line.kind == kind).count();
+        let (removed, added) = (count(LineKind::Removed), count(LineKind::Added));
+        if removed + added > 0 {
+            changes.push(hunks::Change {
+                expected: old..old + removed,
+                actual: new..new + added,
+                ignored: false,
+            });
+        }
+        let context = count(LineKind::Context);
+        old += removed + context;
+        new += added + context;
+    }
+    changes
+}
+
+// Merges the changes of a hunk into the lines of a file that it replaces,
+// like diff3 -m does with the old lines of the hunk as the common file.
+// Writes the merged lines to the output, and returns what became of each
+// change, at the lines of the output from `first_line` on, and the number
+// of lines written.
+fn merge_hunk(
+    output: &mut Vec<u8>,
+    region: &[&[u8]],
+    hunk: &Hunk,
+    first_line: usize,
+    style: MergeStyle,
+) -> (Vec<(Merge, Range<usize>)>, usize) {
+    let files = [
+        region.to_vec(),
+        hunk.old_lines().collect(),
+        hunk.new_lines().collect(),
+    ];
+    let mut results = Vec::new();
+    let mut line = first_line;
+    let mut copied = 0;
+    let write = |output: &mut Vec<u8>, lines: &[&[u8]], line: &mut usize, newline: bool| {
+        for content in lines {
+            output.extend_from_slice(content);
+            // The markers must be on lines of their own.
+            if newline && !content.ends_with(b"\n") {
+                output.push(b'\n');
+            }
+        }
+        *line += lines.len();
+    };
+    // The changes of the hunk are where it says, rather than where diff
+    // would find them when they could be in several places.
+    let changes = [
+        hunks::changes(&files[diff3::OLDER], region, &CompareOptions::default()),
+        hunk_changes(hunk),
+    ];
+    for block in diff3::blocks_of_changes(&files, diff3::OLDER, &changes) {
+        let [mine, older, yours] = block.ranges;
+        write(output, &region[copied..mine.start], &mut line, false);
+        copied = mine.end;
+        let start = line;
+        match block.differs {
+            // Only the file changed these lines: they're kept.
+            Some(diff3::MINE) => write(output, &region[mine], &mut line, false),
+            Some(diff3::YOURS) => {
+                write(output, &files[diff3::YOURS][yours], &mut line, false);
+                results.push((Merge::Merged, start..line));
+            }
+            Some(_) => {
+                write(output, &region[mine], &mut line, false);
+                results.push((Merge::AlreadyApplied, start..line));
+            }
+            None => {
+                output.extend_from_slice(b"<<<<<<<\n");
+                line += 1;
+                write(output, &region[mine], &mut line, true);
+                if style == MergeStyle::Diff3 {
+                    output.extend_from_slice(b"|||||||\n");
+                    line += 1;
+                    write(output, &files[diff3::OLDER][older], &mut line, true);
+                }
+                output.extend_from_slice(b"=======\n");
+                line += 1;
+                write(output, &files[diff3::YOURS][yours], &mut line, true);
+                output.extend_from_slice(b">>>>>>>\n");
+                line += 1;
+                results.push((Merge::NotMerged, start..line));
+            }
+        }
+    }
+    write(output, &region[copied..], &mut line, false);
+    (results, line - first_line)
+}
+
+/// Applies hunks to the content of a file. Each hunk is looked for where it
+/// says, then moved by as many lines as the hunk before, then further away,
+/// and with up to `fuzz` lines of context at its ends ignored when it still
+/// isn't found. Returns the new content, with the hunks that could be
+/// applied, and what became of each hunk.
+#[must_use]
+pub fn apply_hunks(content: &[u8], hunks: &[Hunk], fuzz: usize) -> (Vec<u8>, Vec<HunkOutcome>) {
+    patch_lines(content, hunks, fuzz, None)
+}
+
+/// Applies hunks to the content of a file like `apply_hunks` does without
+/// fuzz, but merges those that aren't found into it, with conflict markers
+/// where their changes clash with those of the file.
+#[must_use]
+pub fn merge_hunks(
+    content: &[u8],
+    hunks: &[Hunk],
+    style: MergeStyle,
+) -> (Vec<u8>, Vec<HunkOutcome>) {
+    patch_lines(content, hunks, 0, Some(style))
+}
+
+fn patch_lines(
+    content: &[u8],
+    hunks: &[Hunk],
+    fuzz: usize,
+    merge: Option<MergeStyle>,
+) -> (Vec<u8>, Vec<HunkOutcome>) {
+    let lines: Vec<&[u8]> = content.split_inclusive(|&c| c == b'\n').collect();
+    let mut output = Vec::with_capacity(content.len());
+    // The lines before this one are already in the output.
+    let mut copied = 0;
+    let mut offset = 0;
+    // The number of lines that the hunks applied so far added, less those
+    // they removed.
+    let mut growth = 0;
+    let mut outcomes = Vec::with_capacity(hunks.len());
+    for hunk in hunks {
+        let found = (0..=max_fuzz(hunk, fuzz)).find_map(|fuzz| {
+            locate_hunk(&lines, hunk, offset, copied, fuzz).map(|line| (line, fuzz))
+        });
+        let Some((line, fuzz)) = found else {
+            let Some(style) = merge else {
+                outcomes.push(HunkOutcome::Failed {
+                    line: (hunk.old_first() as isize + growth) as usize,
+                });
+                continue;
+            };
+            let (line, count) = locate_merge(&lines, hunk, offset, copied);
+            offset = line as isize - hunk.old_first() as isize;
+            output.extend(lines[copied..line - 1].concat());
+            let first_line = (line as isize + growth) as usize;
+            let (merged, written) = merge_hunk(
+                &mut output,
+                &lines[line - 1..line - 1 + count],
+                hunk,
+                first_line,
+                style,
+            );
+            copied = line - 1 + count;
+            growth += written as isize - count as isize;
+            outcomes.push(HunkOutcome::Merged(merged));
+            continue;
+        };
+        offset = line as isize - hunk.old_first() as isize;
+        // The lines of context are left in the file, which can differ from
+        // the hunk's with fuzz. Those at the end may be the start of the next
+        // hunk too, like in GNU patch.
+        let mut position = line - 1;
+        for hunk_line in &hunk.lines {
+            if hunk_line.kind != LineKind::Context {
+                let end = position.min(lines.len());
+                if end > copied {
+                    output.extend(lines[copied..end].concat());
+                    copied = end;
+                }
+            }
+            match hunk_line.kind {
+                LineKind::Context => position += 1,
+                LineKind::Removed => {
+                    position += 1;
+                    copied = position.min(lines.len());
+                }
+                LineKind::Added => output.extend_from_slice(&hunk_line.content),
+            }
+        }
+        let line_in_output = (line as isize + growth) as usize;
+        growth += hunk.new_lines().count() as isize - hunk.old_lines().count() as isize;
+        outcomes.push(HunkOutcome::Applied {
+            line: line_in_output,
+            offset,
+            fuzz,
+        });
+    }
+    output.extend(lines[copied..].concat());
+    (output, outcomes)
+}
+
+// The file that a name of the patch refers to: its base name, or with -p,
+// what's left after removing that many leading components, if that many.
+fn target_path(name: &[u8], strip: Option<usize>) -> Option<PathBuf> {
+    let Some(strip) = strip else {
+        let path = PathBuf::from(path_from_bytes(name));
+        return Some(match path.file_name() {
+            Some(file_name) => PathBuf::from(file_name),
+            None => path,
+        });
+    };
+    let mut rest = name;
+    for _ in 0..strip {
+        let slash = rest.iter().position(|&c| c == b'/')?;
+        // Like in GNU patch, repeated slashes separate just two components.
+        let next = rest[slash..].iter().position(|&c| c != b'/')?;
+        rest = &rest[slash + next..];
+    }
+    Some(PathBuf::from(path_from_bytes(rest)))
+}
+
+// The file to patch, out of the names of the patch: the new one for a file
+// that's created, the old one for a file that's deleted, otherwise the one
+// that exists.
+fn find_target(file_patch: &FilePatch, strip: Option<usize>) -> Option<PathBuf> {
+    let old = target_path(&file_patch.old_name, strip);
+    let new = target_path(&file_patch.new_name, strip);
+    if file_patch.deletes_file() {
+        old
+    } else if file_patch.old_missing() {
+        new
+    } else if let Some(old) = old.filter(|old| old.exists()) {
+        Some(old)
+    } else {
+        new.filter(|new| new.exists() || file_patch.creates_file())
+    }
+}
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{count} {word}{}", if count == 1 { "" } else { "s" })
+}
+
+// Asks a question, like GNU patch: the answer is read from the terminal,
+// or the default one, no, is taken when there's none to ask.
+fn ask(output: &mut impl Write, question: &str) -> bool {
+    write!(output, "{question}").unwrap();
+    output.flush().unwrap();
+    let mut answer = String::new();
+    if io::stdin().is_terminal() {
+        let _ = io::stdin().read_line(&mut answer);
+    } else {
+        writeln!(output).unwrap();
+    }
+    answer.starts_with('y')
+}
+
+// What to do with a patch that seems reversed, or already applied.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Reversal {
+    Reverse,
+    Apply,
+    Skip,
+}
+
+// Tells what seems wrong with a patch, then decides what to do with it like
+// GNU patch: -N skips it, -f applies it anyway, -t reverses it, and
+// otherwise the user is asked.
+fn ask_reversal(output: &mut impl Write, problem: &str, params: &Params) -> Reversal {
+    write!(output, "{problem}").unwrap();
+    if params.forward {
+        writeln!(output, "  Skipping patch.").unwrap();
+        Reversal::Skip
+    } else if params.force {
+        writeln!(output, "  Applying it anyway.").unwrap();
+        Reversal::Apply
+    } else if params.batch {
+        let assumption = if params.reverse {
+            "  Ignoring -R."
+        } else {
+            "  Assuming -R."
+        };
+        writeln!(output, "{assumption}").unwrap();
+        Reversal::Reverse
+    } else if ask(
+        output,
+        if params.reverse {
+            "  Ignore -R? [n] "
+        } else {
+            "  Assume -R? [n] "
+        },
+    ) {
+        Reversal::Reverse
+    } else if ask(output, "Apply anyway? [n] ") {
+        Reversal::Apply
+    } else {
+        writeln!(output, "Skipping patch.").unwrap();
+        Reversal::Skip
+    }
+}
+
+// The hunks that weren't applied, as a patch in the format of the one they
+// come from, with the names of the files as they were looked for. The lines
+// of each hunk are moved by as many lines as the hunks before it added to
+// the file.
+fn format_rejects(
+    file_patch: &FilePatch,
+    rejected: &[(&Hunk, isize)],
+    strip: Option<usize>,
+) -> Vec<u8> {
+    let context = file_patch.format == Format::Context;
+    let markers: [&[u8]; 2] = if context {
+        [b"*** ", b"--- "]
+    } else {
+        [b"--- ", b"+++ "]
+    };
+    let mut rejects = Vec::new();
+    for (marker, name, time) in [
+        (markers[0], &file_patch.old_name, &file_patch.old_time),
+        (markers[1], &file_patch.new_name, &file_patch.new_time),
+    ] {
+        rejects.extend_from_slice(marker);
+        if name == b"/dev/null" {
+            rejects.extend_from_slice(name);
+        } else {
+            match target_path(name, strip) {
+                Some(path) => rejects.extend_from_slice(path.as_os_str().as_encoded_bytes()),
+                None => rejects.extend_from_slice(name),
+            }
+            if !time.is_empty() {
+                rejects.push(b'\t');
+                rejects.extend_from_slice(time);
+            }
+        }
+        rejects.push(b'\n');
+    }
+    let write_line = |rejects: &mut Vec<u8>, prefix: &[u8], content: &[u8]| {
+        rejects.extend_from_slice(prefix);
+        rejects.extend_from_slice(content);
+        if !content.ends_with(b"\n") {
+            rejects.extend_from_slice(b"\n\\ No newline at end of file\n");
+        }
+    };
+    for &(hunk, growth) in rejected {
+        let old = (hunk.old_first(), hunk.old_lines().count());
+        let new = (hunk.new_first(), hunk.new_lines().count());
+        if !context {
+            // Like diff, an empty range is the line before it.
+            let range = |(first, count): (usize, usize)| {
+                let first = first as isize + growth;
+                match count {
+                    0 => format!("{},0", first - 1),
+                    1 => first.to_string(),
+                    _ => format!("{first},{count}"),
+                }
+            };
+            rejects.extend_from_slice(format!("@@ -{} +{} @@", range(old), range(new)).as_bytes());
+            rejects.extend_from_slice(&hunk.heading);
+            rejects.push(b'\n');
+            for line in &hunk.lines {
+                let prefix: &[u8] = match line.kind {
+                    LineKind::Context => b" ",
+                    LineKind::Removed => b"-",
+                    LineKind::Added => b"+",
+                };
+                write_line(&mut rejects, prefix, &line.content);
+            }
+            continue;
+        }
+
+        // The ranges of context hunks are from their first line to their
+        // last one.
+        let range = |(first, count): (usize, usize)| {
+            let first = first as isize + growth;
+            match count {
+                0 => (first - 1).to_string(),
+                1 => first.to_string(),
+                _ => format!("{first},{}", first + count as isize - 1),
+            }
+        };
+        rejects.extend_from_slice(b"***************");
+        rejects.extend_from_slice(&hunk.heading);
+        rejects.push(b'\n');
+        // The lines that are removed where others are added are changed.
+        let changes: Vec<_> = hunk
+            .lines
+            .chunk_by(|a, b| (a.kind == LineKind::Context) == (b.kind == LineKind::Context))
+            .collect();
+        for (kind, prefix, marker, end, range) in [
+            (LineKind::Removed, b"- ", "***", "****", range(old)),
+            (LineKind::Added, b"+ ", "---", "----", range(new)),
+        ] {
+            rejects.extend_from_slice(format!("{marker} {range} {end}\n").as_bytes());
+            for change in &changes {
+                let changed = change.iter().any(|line| line.kind == LineKind::Removed)
+                    && change.iter().any(|line| line.kind == LineKind::Added);
+                for line in change.iter() {
+                    if line.kind == LineKind::Context {
+                        write_line(&mut rejects, b"  ", &line.content);
+                    } else if line.kind == kind {
+                        write_line(
+                            &mut rejects,
+                            if changed { b"! " } else { prefix },
+                            &line.content,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    rejects
+}
+
+// Tells how many hunks of a patch weren't applied, because they "FAILED" or
+// were "ignored", and saves them to the reject file, if there's one, to be
+// applied by hand. Failing to save them is collected in errors.
+fn report_rejects(
+    output: &mut impl Write,
+    file_patch: &FilePatch,
+    rejected: &[(&Hunk, isize)],
+    what: &str,
+    reject_file: Option<&PathBuf>,
+    params: &Params,
+    errors: &mut Vec<String>,
+) {
+    write!(
+        output,
+        "{} out of {} {what}",
+        rejected.len(),
+        plural(file_patch.hunks.len(), "hunk")
+    )
+    .unwrap();
+    let Some(reject_file) = reject_file else {
+        writeln!(output).unwrap();
+        return;
+    };
+    writeln!(
+        output,
+        " -- saving rejects to file {}",
+        shell_quote(&reject_file.to_string_lossy())
+    )
+    .unwrap();
+    let rejects = format_rejects(file_patch, rejected, params.strip);
+    if let Err(e) = fs::write(reject_file, rejects) {
+        errors.push(format_failure_to_read_input_file(
+            &params.executable,
+            &reject_file.clone().into_os_string(),
+            &e,
+        ));
+    }
+}
+
+// Whether the first hunk of a patch is found reversed before it's found as
+// it is, with more and more fuzz.
+fn seems_reversed(content: &[u8], file_patch: &FilePatch, fuzz: usize) -> bool {
+    let Some(hunk) = file_patch.hunks.first() else {
+        return false;
+    };
+    let lines: Vec<&[u8]> = content.split_inclusive(|&c| c == b'\n').collect();
+    let reversed = hunk.reversed();
+    (0..=max_fuzz(hunk, fuzz))
+        .find_map(|fuzz| {
+            if locate_hunk(&lines, hunk, 0, 0, fuzz).is_some() {
+                Some(false)
+            } else {
+                locate_hunk(&lines, &reversed, 0, 0, fuzz).map(|_| true)
+            }
+        })
+        .unwrap_or(false)
+}
+
+// Applies the changes of a patch to its file, writing the messages of GNU
+// patch. Returns whether all the hunks were applied. The errors that don't
+// stop the patching, like failing to save the rejects, are collected.
+fn patch_file(
+    output: &mut impl Write,
+    file_patch: &FilePatch,
+    params: &Params,
+    errors: &mut Vec<String>,
+) -> Result<bool, String> {
+    if params.reverse && file_patch.format == Format::Ed {
+        return Err(format!(
+            "{}: **** ed scripts can't be reversed",
+            params.executable.to_string_lossy()
+        ));
+    }
+    let mut file_patch = if params.reverse {
+        file_patch.reversed()
+    } else {
+        file_patch.clone()
+    };
+    // Skipping a patch rejects all its hunks.
+    let skip = |output: &mut _, file_patch: &FilePatch, reject_file, errors: &mut _| {
+        if file_patch.format != Format::Ed && !file_patch.hunks.is_empty() {
+            let rejected: Vec<_> = file_patch.hunks.iter().map(|hunk| (hunk, 0)).collect();
+            report_rejects(
+                output,
+                file_patch,
+                &rejected,
+                "ignored",
+                reject_file,
+                params,
+                errors,
+            );
+        }
+        false
+    };
+    let target = match (&params.file, find_target(&file_patch, params.strip)) {
+        (Some(file), _) => PathBuf::from(file),
+        (None, Some(target)) => target,
+        (None, None) => {
+            writeln!(
+                output,
+                "can't find file to patch at input line {}\n\
+                 Perhaps you {} -p or --strip option?\n\
+                 The text leading up to this was:\n\
+                 --------------------------",
+                file_patch.hunks_line,
+                if params.strip.is_some() {
+                    "used the wrong"
+                } else {
+                    "should have used the"
+                }
+            )
+            .unwrap();
+            for line in file_patch.header.split_inclusive(|&c| c == b'\n') {
+                output.write_all(b"|").unwrap();
+                output.write_all(line).unwrap();
+            }
+            writeln!(
+                output,
+                "--------------------------\n\
+                 No file to patch.  Skipping patch."
+            )
+            .unwrap();
+            return Ok(skip(output, &file_patch, None, errors));
+        }
+    };
+    let target_name = shell_quote(&target.to_string_lossy());
+    if file_patch.binary {
+        writeln!(
+            output,
+            "File {target_name}: git binary diffs are not supported."
+        )
+        .unwrap();
+        return Ok(false);
+    }
+    // A git diff can rename or copy the file to patch to its new name.
+    let destination = match target_path(&file_patch.new_name, params.strip) {
+        Some(new) if params.file.is_none() && (file_patch.renamed || file_patch.copied) => new,
+        _ => target.clone(),
+    };
+    let reject_file = match &params.reject_file {
+        _ if params.dry_run => None,
+        Some(reject_file) => Some(PathBuf::from(reject_file)),
+        None => {
+            let mut reject_file = target.clone().into_os_string();
+            reject_file.push(".rej");
+            Some(PathBuf::from(reject_file))
+        }
+    };
+    let fail = |e: &io::Error| {
+        format_failure_to_read_input_file(&params.executable, &target.clone().into_os_string(), e)
+    };
+
+    let content = match fs::read(&target) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(e),
+        Err(e) => return Err(fail(&e)),
+    };
+    // A file that's created shouldn't be there yet, and one that's deleted
+    // should. When such a patch is applied anyway, all its hunks fail, like
+    // with GNU patch.
+    let problem = match &content {
+        Ok(content) if file_patch.old_missing() && !content.is_empty() => Some(format!(
+            "create the file {target_name},\nwhich already exists!"
+        )),
+        Err(_) if file_patch.deletes_file() => Some(format!(
+            "delete the file {target_name},\nwhich does not exist!"
+        )),
+        _ => None,
+    };
+    // Such a patch is skipped without saving its hunks, which have nowhere to
+    // go.
+    let mut misplaced = false;
+    if let Some(problem) = problem {
+        let next_patch = if params.reverse {
+            "The next patch, when reversed,"
+        } else {
+            "The next patch"
+        };
+        match ask_reversal(output, &format!("{next_patch} would {problem}"), params) {
+            Reversal::Reverse => file_patch = file_patch.reversed(),
+            Reversal::Apply => misplaced = true,
+            Reversal::Skip => return Ok(skip(output, &file_patch, None, errors)),
+        }
+    }
+
+    let action = if params.dry_run {
+        "checking"
+    } else {
+        "patching"
+    };
+    if destination == target {
+        writeln!(output, "{action} file {target_name}").unwrap();
+    } else {
+        writeln!(
+            output,
+            "{action} file {} ({} from {target_name})",
+            shell_quote(&destination.to_string_lossy()),
+            if file_patch.renamed {
+                "renamed"
+            } else {
+                "copied"
+            }
+        )
+        .unwrap();
+    }
+    let content = match content {
+        Ok(content) => content,
+        Err(_) if file_patch.creates_file() || misplaced => Vec::new(),
+        Err(e) => return Err(fail(&e)),
+    };
+    if file_patch.format == Format::Ed {
+        let patched = run_ed_script(&content, &file_patch.commands).map_err(|line| {
+            format!(
+                "{}: **** ed command at line {line} FAILED",
+                params.executable.to_string_lossy()
+            )
+        })?;
+        if !params.dry_run {
+            write_patched(
+                &file_patch,
+                &target,
+                &destination,
+                &content,
+                &patched,
+                false,
+                params,
+            )?;
+        }
+        return Ok(true);
+    }
+    let fuzz = params.fuzz.unwrap_or(2);
+
+    // When the first hunk isn't found but its reverse is, the patch was
+    // probably reversed, or already applied. With -f, it isn't checked, and
+    // with --merge, the hunks that are already applied are told.
+    // The file is backed up when the patch doesn't apply exactly as it is.
+    let mut mismatch = false;
+    let reversed = file_patch.reversed();
+    if !params.force && params.merge.is_none() && seems_reversed(&content, &file_patch, fuzz) {
+        mismatch = true;
+        let kind = if params.reverse {
+            "Unreversed"
+        } else {
+            "Reversed (or previously applied)"
+        };
+        match ask_reversal(output, &format!("{kind} patch detected!"), params) {
+            Reversal::Reverse => file_patch = reversed,
+            Reversal::Apply => {}
+            Reversal::Skip => return Ok(skip(output, &file_patch, reject_file.as_ref(), errors)),
+        }
+    }
+    let (patched, outcomes) = match params.merge {
+        _ if misplaced => (
+            content.clone(),
+            file_patch
+                .hunks
+                .iter()
+                .map(|hunk| HunkOutcome::Failed {
+                    line: hunk.old_first(),
+                })
+                .collect(),
+        ),
+        Some(style) => merge_hunks(&content, &file_patch.hunks, style),
+        None => apply_hunks(&content, &file_patch.hunks, fuzz),
+    };
+    mismatch |= outcomes.iter().any(|outcome| {
+        !matches!(
+            outcome,
+            HunkOutcome::Applied {
+                offset: 0,
+                fuzz: 0,
+                ..
+            }
+        )
+    });
+
+    let mut conflicts = false;
+    for (number, outcome) in outcomes.iter().enumerate() {
+        let number = number + 1;
+        match *outcome {
+            HunkOutcome::Failed { line } => {
+                writeln!(output, "Hunk #{number} FAILED at {line}.").unwrap();
+            }
+            // Like GNU patch, the lines that the changes are at are told
+            // together, like "merged at 4, NOT MERGED at 7-11,20-24".
+            HunkOutcome::Merged(ref merged) => {
+                let mut last_what = None;
+                for (merge, lines) in merged {
+                    let what = match merge {
+                        Merge::Merged => "merged",
+                        Merge::AlreadyApplied => "already applied",
+                        Merge::NotMerged => "NOT MERGED",
+                    };
+                    conflicts |= *merge == Merge::NotMerged;
+                    match last_what {
+                        None => write!(output, "Hunk #{number} {what} at ").unwrap(),
+                        Some(last) if last == what => write!(output, ",").unwrap(),
+                        Some(_) => write!(output, ", {what} at ").unwrap(),
+                    }
+                    last_what = Some(what);
+                    if lines.len() > 1 {
+                        write!(output, "{}-{}", lines.start, lines.end - 1).unwrap();
+                    } else {
+                        write!(output, "{}", lines.start).unwrap();
+                    }
+                }
+                if last_what.is_some() {
+                    writeln!(output, ".").unwrap();
+                }
+            }
+            // With --merge, the hunks that are found as they are aren't told.
+            HunkOutcome::Applied { .. } if params.merge.is_some() => {}
+            HunkOutcome::Applied { line, offset, fuzz } if offset != 0 || fuzz > 0 => {
+                write!(output, "Hunk #{number} succeeded at {line}").unwrap();
+                if fuzz > 0 {
+                    write!(output, " with fuzz {fuzz}").unwrap();
+                }
+                if offset != 0 {
+                    let lines = if offset == 1 { "line" } else { "lines" };
+                    write!(output, " (offset {offset} {lines})").unwrap();
+                }
+                writeln!(output, ".").unwrap();
+            }
+            HunkOutcome::Applied { .. } => {}
+        }
+    }
+    let rejected: Vec<_> = file_patch
+        .hunks
+        .iter()
+        .zip(&outcomes)
+        .filter_map(|(hunk, outcome)| match *outcome {
+            HunkOutcome::Failed { line } => Some((hunk, line as isize - hunk.old_first() as isize)),
+            _ => None,
+        })
+        .collect();
+    if !rejected.is_empty() {
+        report_rejects(
+            output,
+            &file_patch,
+            &rejected,
+            "FAILED",
+            reject_file.as_ref(),
+            params,
+            errors,
+        );
+    }
+
+    if !params.dry_run {
+        write_patched(
+            &file_patch,
+            &target,
+            &destination,
+            &content,
+            &patched,
+            mismatch,
+            params,
+        )?;
+    }
+    Ok(rejected.is_empty() && !conflicts)
+}
+
+// Writes a patched file, or removes it when the patch deletes it, after
+// backing it up if needed. When it's renamed or copied, it's written to its
+// destination, which is backed up too, and a renamed one is removed.
+fn write_patched(
+    file_patch: &FilePatch,
+    target: &PathBuf,
+    destination: &PathBuf,
+    content: &[u8],
+    patched: &[u8],
+    mismatch: bool,
+    params: &Params,
+) -> Result<(), String> {
+    let fail = |path: &PathBuf, e: &io::Error| {
+        format_failure_to_read_input_file(&params.executable, &path.clone().into_os_string(), e)
+    };
+    // Unless POSIXLY_CORRECT is set, like GNU patch.
+    let backup_if_mismatch = params
+        .backup_if_mismatch
+        .unwrap_or_else(|| env::var_os("POSIXLY_CORRECT").is_none());
+    if params.backup || (mismatch && backup_if_mismatch) {
+        let suffix = params
+            .suffix
+            .clone()
+            .or_else(|| env::var_os("SIMPLE_BACKUP_SUFFIX"))
+            .unwrap_or_else(|| OsString::from(".orig"));
+        let backup = |path: &PathBuf, content: &[u8]| {
+            let mut backup = path.clone().into_os_string();
+            backup.push(&suffix);
+            // The backup of a file that's created is empty.
+            fs::write(&backup, content)
+                .map_err(|e| format_failure_to_read_input_file(&params.executable, &backup, &e))
+        };
+        backup(target, content)?;
+        if destination != target {
+            backup(destination, &fs::read(destination).unwrap_or_default())?;
+        }
+    }
+    if file_patch.deletes_file() && patched.is_empty() {
+        // A file that's deleted when it doesn't exist stays missing.
+        return match fs::remove_file(target) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(fail(target, &e)),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = destination.parent().filter(|parent| !parent.exists()) {
+        fs::create_dir_all(parent).map_err(|e| fail(destination, &e))?;
+    }
+    fs::write(destination, patched).map_err(|e| fail(destination, &e))?;
+    if file_patch.renamed && destination != target {
+        fs::remove_file(target).map_err(|e| fail(target, &e))?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = file_patch.new_mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(destination, fs::Permissions::from_mode(mode & 0o7777))
+            .map_err(|e| fail(destination, &e))?;
+    }
+    Ok(())
+}
+
+// Exit codes are documented at
+// https://www.gnu.org/software/diffutils/manual/html_node/patch-Options.html
+//     An exit status of 0 means all the hunks were applied,
+//     1 means some hunks could not be applied,
+//     and 2 means trouble.
+pub fn main(opts: Peekable<ArgsOs>) -> ExitCode {
+    let params = match parse_params(opts) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+    let executable = params.executable.to_string_lossy().to_string();
+    if let Some(directory) = &params.directory {
+        if let Err(e) = std::env::set_current_dir(directory) {
+            eprintln!(
+                "{executable}: **** Can't change to directory {} : {}",
+                directory.to_string_lossy(),
+                error_message(&e)
+            );
+            return ExitCode::from(2);
+        }
+    }
+
+    let mut patch = Vec::new();
+    let read = match &params.patch {
+        Some(path) if path != "-" => fs::read(path).map(|content| patch = content),
+        _ => io::stdin().read_to_end(&mut patch).map(|_| ()),
+    };
+    if let Err(e) = read {
+        let path = params.patch.clone().unwrap_or_else(|| OsString::from("-"));
+        eprintln!(
+            "{}",
+            format_failure_to_read_input_file(&params.executable, &path, &e)
+        );
+        return ExitCode::from(2);
+    }
+
+    let file_patches = match parse_patch(&patch) {
+        Ok(file_patches) => file_patches,
+        Err(e) => {
+            eprintln!("{executable}: **** {e}");
+            return ExitCode::from(2);
+        }
+    };
+    if file_patches.is_empty() {
+        if patch.is_empty() {
+            return ExitCode::SUCCESS;
+        }
+        eprintln!("{executable}: **** Only garbage was found in the patch input.");
+        return ExitCode::from(2);
+    }
+
+    let mut all_applied = true;
+    let mut failed = false;
+    let mut stdout = io::stdout().lock();
+    for file_patch in &file_patches {
+        let mut errors = Vec::new();
+        let result = patch_file(&mut stdout, file_patch, &params, &mut errors);
+        for error in &errors {
+            eprintln!("{error}");
+        }
+        failed |= !errors.is_empty();
+        match result {
+            Ok(applied) => all_applied &= applied,
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    if failed {
+        ExitCode::from(2)
+    } else if all_applied {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn os(s: &str) -> OsString {
+        OsString::from(s)
+    }
+
+    fn applied(line: usize, offset: isize, fuzz: usize) -> HunkOutcome {
+        HunkOutcome::Applied { line, offset, fuzz }
+    }
+
+    fn line(kind: LineKind, content: &str) -> HunkLine {
+        HunkLine {
+            kind,
+            content: content.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(
+            Ok(Params {
+                executable: os("patch"),
+                file: Some(os("f")),
+                patch: Some(os("-")),
+                ..Default::default()
+            }),
+            parse_params([os("patch"), os("f"), os("-")].iter().cloned().peekable())
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("patch"),
+                reverse: true,
+                forward: true,
+                batch: true,
+                force: true,
+                dry_run: true,
+                backup: true,
+                backup_if_mismatch: Some(false),
+                ..Default::default()
+            }),
+            parse_params(
+                [
+                    os("patch"),
+                    os("-R"),
+                    os("--forward"),
+                    os("-t"),
+                    os("--force"),
+                    os("--dry-run"),
+                    os("-b"),
+                    os("--no-backup-if-mismatch")
+                ]
+                .iter()
+                .cloned()
+                .peekable()
+            )
+        );
+        assert_eq!(
+            Ok(Params {
+                executable: os("patch"),
+                file: Some(os("-f")),
+                ..Default::default()
+            }),
+            parse_params([os("patch"), os("--"), os("-f")].iter().cloned().peekable())
+        );
+        for (arg, merge) in [
+            ("--merge", MergeStyle::Merge),
+            ("--merge=merge", MergeStyle::Merge),
+            ("--merge=diff3", MergeStyle::Diff3),
+        ] {
+            assert_eq!(
+                Ok(Params {
+                    executable: os("patch"),
+                    merge: Some(merge),
+                    ..Default::default()
+                }),
+                parse_params([os("patch"), os(arg)].iter().cloned().peekable())
+            );
+        }
+        assert_eq!(
+            Err("patch: invalid argument 'foo' for '--merge'".to_string()),
+            parse_params([os("patch"), os("--merge=foo")].iter().cloned().peekable())
+        );
+        assert_eq!(
+            Err("patch: c: extra operand".to_string()),
+            parse_params(
+                [os("patch"), os("a"), os("b"), os("c")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+    }
+
+    #[test]
+    fn options_with_values() {
+        for args in [
+            ["-p1", "-dsub", "-F3", "-z~"],
+            ["--strip=1", "--directory=sub", "--fuzz=3", "--suffix=~"],
+        ] {
+            let mut opts = vec![os("patch")];
+            opts.extend(args.map(os));
+            assert_eq!(
+                Ok(Params {
+                    executable: os("patch"),
+                    strip: Some(1),
+                    fuzz: Some(3),
+                    directory: Some(os("sub")),
+                    suffix: Some(os("~")),
+                    ..Default::default()
+                }),
+                parse_params(opts.into_iter().peekable())
+            );
+        }
+        assert_eq!(
+            Ok(Params {
+                executable: os("patch"),
+                strip: Some(0),
+                file: Some(os("f")),
+                ..Default::default()
+            }),
+            parse_params(
+                [os("patch"), os("-p"), os("0"), os("f")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        // The patch operand wins over -i.
+        for (args, patch) in [
+            (vec!["-i", "p.diff", "f"], "p.diff"),
+            (vec!["--input=p.diff", "f", "q.diff"], "q.diff"),
+        ] {
+            let mut opts = vec![os("patch")];
+            opts.extend(args.into_iter().map(os));
+            assert_eq!(
+                Ok(Params {
+                    executable: os("patch"),
+                    file: Some(os("f")),
+                    patch: Some(os(patch)),
+                    ..Default::default()
+                }),
+                parse_params(opts.into_iter().peekable())
+            );
+        }
+        assert_eq!(
+            Err("patch: **** strip count 1x is not a number".to_string()),
+            parse_params([os("patch"), os("-p1x")].iter().cloned().peekable())
+        );
+        assert_eq!(
+            Err("patch: **** fuzz factor -1 is negative".to_string()),
+            parse_params(
+                [os("patch"), os("--fuzz"), os("-1")]
+                    .iter()
+                    .cloned()
+                    .peekable()
+            )
+        );
+        assert_eq!(
+            Err("patch: option requires an argument -- 'p'".to_string()),
+            parse_params([os("patch"), os("-p")].iter().cloned().peekable())
+        );
+
+        assert_eq!(target_path(b"a/sub/f", None), Some(PathBuf::from("f")));
+        assert_eq!(
+            target_path(b"a/sub/f", Some(0)),
+            Some(PathBuf::from("a/sub/f"))
+        );
+        assert_eq!(
+            target_path(b"/a//sub/f", Some(2)),
+            Some(PathBuf::from("sub/f"))
+        );
+        assert_eq!(target_path(b"a/sub/f", Some(3)), None);
+    }
+
+    #[test]
+    fn parse_unified() {
+        // The hunks end at the first line that isn't part of one.
+        let patch = b"diff -u a/f b/f\n--- a/f\t2024-01-01 00:00:00\n+++ b/f\n\
+                      @@ -1,3 +1,3 @@ fn f()\n a\n-b\n+B\n c\n@@ -5 +5,0 @@\n-e\ntrailing\n";
+        assert_eq!(
+            parse_patch(patch),
+            Ok(vec![FilePatch {
+                format: Format::Unified,
+                old_name: b"a/f".to_vec(),
+                new_name: b"b/f".to_vec(),
+                old_time: b"2024-01-01 00:00:00".to_vec(),
+                new_time: Vec::new(),
+                header: b"diff -u a/f b/f\n--- a/f\t2024-01-01 00:00:00\n+++ b/f\n".to_vec(),
+                hunks_line: 4,
+                hunks: vec![
+                    Hunk {
+                        old_start: 1,
+                        new_start: 1,
+                        lines: vec![
+                            line(LineKind::Context, "a\n"),
+                            line(LineKind::Removed, "b\n"),
+                            line(LineKind::Added, "B\n"),
+                            line(LineKind::Context, "c\n"),
+                        ],
+                        heading: b" fn f()".to_vec(),
+                    },
+                    Hunk {
+                        old_start: 5,
+                        new_start: 5,
+                        lines: vec![line(LineKind::Removed, "e\n")],
+                        heading: Vec::new(),
+                    },
+                ],
+                ..Default::default()
+            }])
+        );
+
+        let patch = b"--- f\n+++ f\n@@ -1 +1,2 @@\n\n+x\n\\ No newline at end of file\n";
+        assert_eq!(
+            parse_patch(patch).unwrap()[0].hunks[0].lines,
+            vec![line(LineKind::Context, "\n"), line(LineKind::Added, "x")]
+        );
+
+        // Each file has the text after the hunks of the one before as its
+        // header.
+        let patch = b"From: someone\n\n--- a\n+++ a\n@@ -1 +1 @@\n-a\n+A\n\
+                      garbage\n--- b\n+++ b\n@@ -2 +2 @@\n-b\n+B\n-- \n";
+        let file_patches = parse_patch(patch).unwrap();
+        assert_eq!(file_patches.len(), 2);
+        assert_eq!(file_patches[0].header, b"From: someone\n\n--- a\n+++ a\n");
+        assert_eq!(file_patches[1].header, b"garbage\n--- b\n+++ b\n");
+        assert_eq!(file_patches[1].hunks_line, 11);
+        assert_eq!(file_patches[1].hunks[0].old_start, 2);
+
+        assert_eq!(parse_patch(b"nothing\nto see\n"), Ok(Vec::new()));
+        assert_eq!(
+            parse_patch(b"--- f\n+++ f\n@@ -1,2 +1,2 @@\n 1\nz2\n"),
+            Err("malformed patch at line 5: z2\n".to_string())
+        );
+        assert_eq!(
+            parse_patch(b"--- f\n+++ f\n@@ -1,2 +1,2 @@\n 1\n-2\n"),
+            Err("malformed patch at line 6: ".to_string())
+        );
+        assert_eq!(
+            parse_patch(b"--- f\n+++ f\n@@ -1,x +1 @@\n 1\n"),
+            Err("malformed patch at line 3: @@ -1,x +1 @@\n".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_context() {
+        // The lines of a file that are all context are left out, and the
+        // changed ones are matched with the same lines of context.
+        let patch = b"*** a/f\t2024-01-01\n--- b/f\t2024-01-02\n\
+                      *************** fn f()\n*** 1,3 ****\n  a\n! b\n  c\n--- 1,4 ----\n  a\n! B\n+ x\n  c\n\
+                      ***************\n*** 7,8 ****\n--- 8,9 ----\n  g\n+ y\n\
+                      ***************\n*** 9 ****\n- h\n--- 10 ----\n";
+        let file_patches = parse_patch(patch).unwrap();
+        assert_eq!(file_patches[0].format, Format::Context);
+        assert_eq!(file_patches[0].old_name, b"a/f");
+        assert_eq!(file_patches[0].new_time, b"2024-01-02");
+        assert_eq!(
+            file_patches[0].hunks,
+            vec![
+                Hunk {
+                    old_start: 1,
+                    new_start: 1,
+                    heading: b" fn f()".to_vec(),
+                    lines: vec![
+                        line(LineKind::Context, "a\n"),
+                        line(LineKind::Removed, "b\n"),
+                        line(LineKind::Added, "B\n"),
+                        line(LineKind::Added, "x\n"),
+                        line(LineKind::Context, "c\n"),
+                    ],
+                },
+                Hunk {
+                    old_start: 7,
+                    new_start: 8,
+                    heading: Vec::new(),
+                    lines: vec![line(LineKind::Context, "g\n"), line(LineKind::Added, "y\n")],
+                },
+                Hunk {
+                    old_start: 9,
+                    new_start: 10,
+                    heading: Vec::new(),
+                    lines: vec![line(LineKind::Removed, "h\n")],
+                },
+            ]
+        );
+
+        // Without context, a single line number may be the line that an
+        // insertion goes after.
+        let patch = b"*** f\n--- f\n***************\n*** 4 ****\n--- 5 ----\n+ x\n";
+        let hunk = &parse_patch(patch).unwrap()[0].hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_first()), (4, 5));
+
+        assert_eq!(
+            parse_patch(b"*** f\n--- f\n***************\n*** 1,2 ****\n  a\n--- 1 ----\n"),
+            Err("malformed patch at line 6: --- 1 ----\n".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_git() {
+        // The extended header lines tell what happens to the files, even
+        // without hunks.
+        let patch = b"diff --git a/old name b/new name\nsimilarity index 90%\n\
+                      rename from old name\nrename to new name\n\
+                      diff --git a/m b/m\nold mode 100644\nnew mode 100755\n\
+                      diff --git a/e b/e\nnew file mode 100644\nindex 0000000..e69de29\n\
+                      diff --git a/g b/g\ndeleted file mode 100644\nindex 587be6b..0000000\n\
+                      --- a/g\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n\
+                      diff --git a/b b/b\nindex 1..2 100644\nGIT binary patch\nliteral 0\n";
+        let file_patches = parse_patch(patch).unwrap();
+        assert_eq!(file_patches.len(), 5);
+        assert_eq!(file_patches[0].old_name, b"a/old name");
+        assert_eq!(file_patches[0].new_name, b"b/new name");
+        assert!(file_patches[0].renamed && file_patches[0].hunks.is_empty());
+        assert_eq!(
+            (file_patches[1].old_mode, file_patches[1].new_mode),
+            (Some(0o100644), Some(0o100755))
+        );
+        assert!(file_patches[2].creates_file());
+        assert_eq!(file_patches[2].new_mode, Some(0o100644));
+        assert!(file_patches[3].deletes_file());
+        assert_eq!(file_patches[3].hunks.len(), 1);
+        assert_eq!(
+            file_patches[3].header,
+            b"diff --git a/g b/g\ndeleted file mode 100644\nindex 587be6b..0000000\n\
+              --- a/g\n+++ /dev/null\n"
+        );
+        assert!(file_patches[4].binary);
+
+        let reversed = file_patches[1].reversed();
+        assert_eq!(
+            (reversed.old_mode, reversed.new_mode),
+            (Some(0o100755), Some(0o100644))
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let patch = parse_patch(
+            b"--- f\n+++ f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -4,0 +5,2 @@\n+x\n+y\n@@ -6 +7,0 @@\n-f\n",
+        )
+        .unwrap();
+        let hunks = &patch[0].hunks;
+        assert_eq!(
+            apply_hunks(b"a\nb\nc\nd\ne\nf\n", hunks, 0),
+            (
+                b"a\nB\nc\nd\nx\ny\ne\n".to_vec(),
+                vec![applied(1, 0, 0), applied(5, 0, 0), applied(8, 0, 0)]
+            )
+        );
+        // Hunks whose lines aren't there are left out.
+        assert_eq!(
+            apply_hunks(b"a\nX\nc\nd\ne\nf\n", hunks, 0),
+            (
+                b"a\nX\nc\nd\nx\ny\ne\n".to_vec(),
+                vec![
+                    HunkOutcome::Failed { line: 1 },
+                    applied(5, 0, 0),
+                    applied(8, 0, 0),
+                ]
+            )
+        );
+
+        // Creating a file, and adding its missing newline.
+        let patch = parse_patch(b"--- /dev/null\n+++ f\n@@ -0,0 +1 @@\n+a\n").unwrap();
+        assert!(patch[0].creates_file());
+        assert_eq!(apply_hunks(b"", &patch[0].hunks, 0).0, b"a\n".to_vec());
+        let patch =
+            parse_patch(b"--- f\n+++ f\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+a\n")
+                .unwrap();
+        assert!(!patch[0].creates_file());
+        assert_eq!(apply_hunks(b"a", &patch[0].hunks, 0).0, b"a\n".to_vec());
+    }
+
+    #[test]
+    fn epoch_timestamps() {
+        // Files that diff -N compares as empty are stamped with the epoch,
+        // in whatever time zone.
+        let patch = parse_patch(
+            b"--- old/f\t2024-05-06 07:08:09.000000000 +0200\n\
+              +++ new/f\t1970-01-01 02:00:00.000000000 +0200\n@@ -1 +0,0 @@\n-a\n\
+              --- old/g\t1969-12-31 19:00:00.000000000 -0500\n\
+              +++ new/g\t2024-05-06 07:08:09.000000000 -0500\n@@ -0,0 +1 @@\n+b\n",
+        )
+        .unwrap();
+        assert!(patch[0].deletes_file() && !patch[0].creates_file());
+        assert!(patch[1].creates_file() && !patch[1].deletes_file());
+        assert!(patch[0].reversed().creates_file());
+        assert!(patch[1].reversed().deletes_file());
+
+        // Context diffs are stamped in the local time zone.
+        use chrono::{DateTime, Local};
+        let epoch: DateTime<Local> = std::time::SystemTime::UNIX_EPOCH.into();
+        let patch = parse_patch(
+            format!(
+                "*** f\t{}\n--- f\tMon May  6 07:08:09 2024\n***************\n\
+                 *** 0 ****\n--- 1 ----\n+ b\n",
+                epoch.format(CONTEXT_TIME_FORMAT)
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert!(patch[0].old_missing());
+        assert!(!patch[0].deletes_file());
+    }
+
+    #[test]
+    fn offset_and_fuzz() {
+        let patch = parse_patch(
+            b"--- f\n+++ f\n@@ -3,5 +3,5 @@\n 3\n 4\n-5\n+five\n 6\n 7\n\
+              @@ -10,3 +10,4 @@\n 10\n+ten\n 11\n 12\n",
+        )
+        .unwrap();
+        let hunks = &patch[0].hunks;
+        let numbers = |lines: &[&str]| {
+            lines
+                .iter()
+                .map(|line| format!("{line}\n"))
+                .collect::<String>()
+        };
+
+        // The hunks are found after lines that were added, and the second
+        // one is first looked for as far from where it says as the first.
+        let content = numbers(&[
+            "0", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+        ]);
+        assert_eq!(
+            apply_hunks(content.as_bytes(), hunks, 0),
+            (
+                numbers(&[
+                    "0", "0", "1", "2", "3", "4", "five", "6", "7", "8", "9", "10", "ten", "11",
+                    "12"
+                ])
+                .into_bytes(),
+                vec![applied(5, 2, 0), applied(12, 2, 0)]
+            )
+        );
+
+        // Or before lines that were removed, the closest first.
+        let content = numbers(&["3", "4", "5", "6", "7", "10", "11", "12", "10", "11", "12"]);
+        assert_eq!(
+            apply_hunks(content.as_bytes(), hunks, 0).1,
+            vec![applied(1, -2, 0), applied(9, -1, 0)]
+        );
+
+        // Lines of context that differ are ignored at the ends of the hunk,
+        // up to the fuzz factor, and kept.
+        let content = numbers(&["1", "2", "3", "x", "5", "6", "y", "8", "9", "10", "11", "z"]);
+        assert_eq!(
+            apply_hunks(content.as_bytes(), hunks, 1).1,
+            vec![HunkOutcome::Failed { line: 3 }, applied(10, 0, 1)]
+        );
+        assert_eq!(
+            apply_hunks(content.as_bytes(), hunks, 2),
+            (
+                numbers(&["1", "2", "3", "x", "five", "6", "y", "8", "9", "10", "ten", "11", "z"])
+                    .into_bytes(),
+                vec![applied(3, 0, 2), applied(10, 0, 1)]
+            )
+        );
+
+        // A hunk with less context at its start must be at the start of the
+        // file, unless the fuzz makes up for the difference.
+        let patch = parse_patch(b"--- f\n+++ f\n@@ -1,3 +1,3 @@\n-1\n+one\n 2\n 3\n").unwrap();
+        assert_eq!(
+            apply_hunks(b"0\n1\n2\n3\n", &patch[0].hunks, 1).1,
+            vec![HunkOutcome::Failed { line: 1 }]
+        );
+        assert_eq!(
+            apply_hunks(b"0\n1\n2\n3\n", &patch[0].hunks, 2),
+            (b"0\none\n2\n3\n".to_vec(), vec![applied(2, 1, 2)])
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        let patch = parse_patch(b"--- a\n+++ b\n@@ -1,2 +1,3 @@\n a\n-b\n+B\n+c\n").unwrap();
+        let reversed = patch[0].reversed();
+        assert_eq!(reversed.old_name, b"b");
+        assert_eq!(reversed.new_name, b"a");
+        assert_eq!(
+            apply_hunks(b"a\nB\nc\n", &reversed.hunks, 0),
+            (b"a\nb\n".to_vec(), vec![applied(1, 0, 0)])
+        );
+        assert_eq!(reversed.reversed(), patch[0]);
+    }
+
+    #[test]
+    fn rejects() {
+        let patch = parse_patch(
+            b"--- a/f\t2024-01-01\n+++ b/f\n@@ -2,2 +2,3 @@ g\n b\n+x\n c\n@@ -7 +8,0 @@\n-e\n",
+        )
+        .unwrap();
+        let hunks = &patch[0].hunks;
+        // The ranges move by the lines the hunks before them added.
+        assert_eq!(
+            String::from_utf8(format_rejects(
+                &patch[0],
+                &[(&hunks[0], 0), (&hunks[1], 1)],
+                Some(1)
+            ))
+            .unwrap(),
+            "--- f\t2024-01-01\n+++ f\n@@ -2,2 +2,3 @@ g\n b\n+x\n c\n@@ -8 +9,0 @@\n-e\n"
+        );
+        let patch = parse_patch(b"--- /dev/null\n+++ f\n@@ -0,0 +1 @@\n+x\n").unwrap();
+        assert_eq!(
+            format_rejects(&patch[0], &[(&patch[0].hunks[0], 0)], None),
+            b"--- /dev/null\n+++ f\n@@ -0,0 +1 @@\n+x\n"
+        );
+
+        // Context hunks have all their lines in each file.
+        let patch = parse_patch(
+            b"*** a/f\n--- b/f\n***************\n*** 1,2 ****\n  a\n! b\n--- 1,3 ----\n  a\n! B\n+ c\n",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(format_rejects(&patch[0], &[(&patch[0].hunks[0], 2)], None)).unwrap(),
+            "*** f\n--- f\n***************\n*** 3,4 ****\n  a\n! b\n--- 3,5 ----\n  a\n! B\n! c\n"
+        );
+    }
+
+    #[test]
+    fn ed_script() {
+        assert_eq!(parse_ed_command(b"3,4c\n"), Some((Some((3, 4)), b'c')));
+        assert_eq!(parse_ed_command(b"0a \n"), Some((Some((0, 0)), b'a')));
+        assert_eq!(parse_ed_command(b"s/.//\n"), Some((None, b's')));
+        assert_eq!(parse_ed_command(b"3,4a\n"), None);
+        assert_eq!(parse_ed_command(b"3w\n"), None);
+        assert_eq!(parse_ed_command(b"3d"), None);
+
+        // Like diff -e writes them, with a line that's a dot.
+        let patch =
+            parse_patch(b"diff -e a b\n4a\n..\n.\ns/.//\na\nx\n.\n2,3c\nB\n.\n1d\nend\n").unwrap();
+        assert_eq!(patch.len(), 1);
+        assert_eq!(patch[0].format, Format::Ed);
+        assert_eq!(patch[0].header, b"diff -e a b\n");
+        assert_eq!(patch[0].hunks_line, 2);
+        assert_eq!(patch[0].commands.len(), 5);
+        assert_eq!(
+            run_ed_script(b"a\nb\nc\nd", &patch[0].commands),
+            Ok(b"B\nd\n.\nx\n".to_vec())
+        );
+        assert_eq!(run_ed_script(b"a\n", &patch[0].commands), Err(2));
+    }
+
+    #[test]
+    fn merge() {
+        let patch = parse_patch(b"--- a\n+++ b\n@@ -1,7 +1,7 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n")
+            .unwrap();
+        let hunks = &patch[0].hunks;
+        // Found as it is.
+        assert_eq!(
+            merge_hunks(b"x\na\nb\nc\nd\ne\nf\ng\n", hunks, MergeStyle::Merge),
+            (b"x\na\nb\nc\nD\ne\nf\ng\n".to_vec(), vec![applied(2, 1, 0)])
+        );
+        // Without fuzz, but its change is still made.
+        assert_eq!(
+            merge_hunks(b"Q\nb\nc\nd\ne\nf\ng\n", hunks, MergeStyle::Merge),
+            (
+                b"Q\nb\nc\nD\ne\nf\ng\n".to_vec(),
+                vec![HunkOutcome::Merged(vec![(Merge::Merged, 4..5)])]
+            )
+        );
+        assert_eq!(
+            merge_hunks(b"a\nb\nc\nD\ne\nf\ng\n", hunks, MergeStyle::Merge),
+            (
+                b"a\nb\nc\nD\ne\nf\ng\n".to_vec(),
+                vec![HunkOutcome::Merged(vec![(Merge::AlreadyApplied, 4..5)])]
+            )
+        );
+        assert_eq!(
+            merge_hunks(b"a\nb\nc\nX\ne\nf\ng\n", hunks, MergeStyle::Diff3),
+            (
+                b"a\nb\nc\n<<<<<<<\nX\n|||||||\nd\n=======\nD\n>>>>>>>\ne\nf\ng\n".to_vec(),
+                vec![HunkOutcome::Merged(vec![(Merge::NotMerged, 4..11)])]
+            )
+        );
+        // The changes that touch the lines that the file changed clash.
+        assert_eq!(
+            merge_hunks(b"a\nb\nQ\nd\ne\nf\ng\n", hunks, MergeStyle::Merge).0,
+            b"a\nb\n<<<<<<<\nQ\nd\n=======\nc\nD\n>>>>>>>\ne\nf\ng\n"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::io::{self, Write};
+
+use crate::hunks::Diff;
+use crate::params::Params;
+use crate::utils::do_write_line;
+
+/// Writes the differences in the RCS format, whose commands, unlike those of
+/// an ed script, refer to the line numbers of the expected file.
+pub fn diff(output: &mut impl Write, diff: &Diff, params: &Params) -> io::Result<()> {
+    // See https://www.gnu.org/software/diffutils/manual/html_node/RCS.html
+    // for details on the syntax of the RCS format.
+    if params.brief && !diff.hunks.is_empty() {
+        return write!(output, "\0");
+    }
+    for change in diff.changes() {
+        let line_number_expected = change.expected.start + 1;
+        let expected_count = change.expected.len();
+        let actual_count = change.actual.len();
+        // Unlike ed scripts, the commands are applied in order, and the line
+        // numbers always refer to the original file.
+        if expected_count != 0 {
+            writeln!(output, "d{line_number_expected} {expected_count}")?;
+        }
+        if actual_count != 0 {
+            writeln!(
+                output,
+                "a{} {}",
+                line_number_expected + expected_count - 1,
+                actual_count
+            )?;
+            for actual in diff.added(change) {
+                // A last line that is missing its newline is written as is.
+                match actual.strip_suffix(b"\n") {
+                    Some(line) => {
+                        do_write_line(output, line, params.expand_tabs, params.tabsize)?;
+                        writeln!(output)?;
+                    }
+                    None => {
+                        do_write_line(output, actual, params.expand_tabs, params.tabsize)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hunks::{self, CompareOptions};
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+        let mut output = Vec::new();
+        let diff = hunks::compare(expected, actual, &CompareOptions::from(params));
+        super::diff(&mut output, &diff, params).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_basic() {
+        let diff = diff(b"a\nb\nc\nd\n", b"x\nb\nd\ne\n", &Params::default());
+        let expected = ["d1 1", "a1 1", "x", "d3 1", "a4 1", "e", ""].join("\n");
+        assert_eq!(diff, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_empty_files() {
+        assert_eq!(
+            diff(b"a\nb\n", b"", &Params::default()),
+            "d1 2\n".as_bytes()
+        );
+        assert_eq!(
+            diff(b"", b"a\nb\n", &Params::default()),
+            "a0 2\na\nb\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_missing_newline() {
+        assert_eq!(
+            diff(b"a\nb", b"a\nc", &Params::default()),
+            "d2 1\na2 1\nc".as_bytes()
+        );
+        assert_eq!(
+            diff(b"a\nb", b"a\nb\n", &Params::default()),
+            "d2 1\na2 1\nb\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_permutations() {
+        let target = "target/rcs-diff/";
+        // test all possible six-line files, against GNU diff's output.
+        let _ = std::fs::create_dir(target);
+        for &a in &[0, 1, 2] {
+            for &b in &[0, 1, 2] {
+                for &c in &[0, 1, 2] {
+                    use std::fs::File;
+                    use std::process::Command;
+                    let mut alef = Vec::new();
+                    let mut bet = Vec::new();
+                    alef.write_all(if a == 0 { b"a\n" } else { b"b\n" })
+                        .unwrap();
+                    if a != 2 {
+                        bet.write_all(b"b\n").unwrap();
+                    }
+                    alef.write_all(if b == 0 { b"c\n" } else { b"d\n" })
+                        .unwrap();
+                    if b != 2 {
+                        bet.write_all(b"d\n").unwrap();
+                    }
+                    alef.write_all(if c == 0 { b"e\n" } else { b"f\n" })
+                        .unwrap();
+                    if c != 2 {
+                        bet.write_all(b"f\n").unwrap();
+                    }
+                    let diff = diff(&alef, &bet, &Params::default());
+                    File::create(format!("{target}/alef"))
+                        .unwrap()
+                        .write_all(&alef)
+                        .unwrap();
+                    File::create(format!("{target}/bet"))
+                        .unwrap()
+                        .write_all(&bet)
+                        .unwrap();
+                    let Ok(output) = Command::new("diff")
+                        .arg("-n")
+                        .arg(format!("{target}/alef"))
+                        .arg(format!("{target}/bet"))
+                        .output()
+                    else {
+                        // no reference implementation available
+                        return;
+                    };
+                    assert_eq!(
+                        String::from_utf8_lossy(&diff),
+                        String::from_utf8_lossy(&output.stdout)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stop_early() {
+        let from = ["a", "b", "c", ""].join("\n");
+        let to = ["a", "d", "c", ""].join("\n");
+
+        let diff_full = diff(from.as_bytes(), to.as_bytes(), &Params::default());
+        let expected_full = ["d2 1", "a2 1", "d", ""].join("\n");
+        assert_eq!(diff_full, expected_full.as_bytes());
+
+        let diff_brief = diff(
+            from.as_bytes(),
+            to.as_bytes(),
+            &Params {
+                brief: true,
+                ..Default::default()
+            },
+        );
+        let expected_brief = "\0".as_bytes();
+        assert_eq!(diff_brief, expected_brief);
+
+        let nodiff_full = diff(from.as_bytes(), from.as_bytes(), &Params::default());
+        assert!(nodiff_full.is_empty());
+    }
+}
@@ -0,0 +1,910 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::cmp;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::hunks::{self, CompareOptions};
+use crate::palette::{ColorClass, Palette};
+use crate::params::Params;
+
+// Minimum number of columns between the two halves of the output.
+const GUTTER_WIDTH_MINIMUM: usize = 3;
+
+/// How the two sides of a side-by-side row relate to each other.
+/// The variants map to the gutter markers used by GNU diff -y and sdiff.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RowKind {
+    /// Both sides hold the same line (' ').
+    Unchanged,
+    /// The left line was replaced by the right line ('|').
+    Changed,
+    /// The line only exists on the left side ('<').
+    Removed,
+    /// The line only exists on the right side ('>').
+    Added,
+    /// The line only exists on the left side, but the change is ignored with
+    /// -I so that it is shown among the common lines ('(').
+    IgnoredRemoved,
+    /// The line only exists on the right side, but the change is ignored with
+    /// -I so that it is shown among the common lines (')').
+    IgnoredAdded,
+}
+
+/// A single row of a side-by-side comparison.
+/// Line numbers are 1-based, and the text doesn't include the line terminator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Row<'a> {
+    pub kind: RowKind,
+    pub left: Option<&'a [u8]>,
+    pub right: Option<&'a [u8]>,
+    pub left_line_number: Option<usize>,
+    pub right_line_number: Option<usize>,
+    pub left_missing_nl: bool,
+    pub right_missing_nl: bool,
+}
+
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    // Keep the line terminator so that a final line that is missing its
+    // newline doesn't compare equal to the same line with a newline.
+    content.split_inclusive(|&c| c == b'\n').collect()
+}
+
+fn strip_nl(line: &[u8]) -> (&[u8], bool) {
+    match line.strip_suffix(b"\n") {
+        Some(line) => (line, false),
+        None => (line, true),
+    }
+}
+
+/// Aligns the lines of expected and actual, the way diff -y displays them.
+///
+/// Within a block of changes, removed and added lines are paired up in order
+/// as [`RowKind::Changed`] rows, and the remaining lines of the longer side
+/// become [`RowKind::Removed`] or [`RowKind::Added`] rows.
+///
+/// Like GNU diff, changes ignored with -I are shown as if their lines were
+/// common lines: the lines in common between two changes are paired up in
+/// order, and the remaining ones become [`RowKind::IgnoredAdded`] and
+/// [`RowKind::IgnoredRemoved`] rows.
+#[must_use]
+pub fn rows<'a>(expected: &'a [u8], actual: &'a [u8], options: &SideBySideOptions) -> Vec<Row<'a>> {
+    let expected_lines = split_lines(expected);
+    let actual_lines = split_lines(actual);
+
+    let mut rows = Vec::new();
+    // The lines of a range, with their (1-based) numbers.
+    let numbered = |lines: &[&'a [u8]], range: Range<usize>| -> Vec<(usize, &'a [u8])> {
+        range
+            .clone()
+            .map(|i| i + 1)
+            .zip(lines[range].iter().copied())
+            .collect()
+    };
+    // The common lines since the previous change.
+    let mut common_left: Vec<(usize, &[u8])> = Vec::new();
+    let mut common_right: Vec<(usize, &[u8])> = Vec::new();
+
+    fn flush<'a>(
+        rows: &mut Vec<Row<'a>>,
+        left: &mut Vec<(usize, &'a [u8])>,
+        right: &mut Vec<(usize, &'a [u8])>,
+        common: bool,
+    ) {
+        let (paired, right_only, left_only) = if common {
+            (
+                RowKind::Unchanged,
+                RowKind::IgnoredAdded,
+                RowKind::IgnoredRemoved,
+            )
+        } else {
+            (RowKind::Changed, RowKind::Added, RowKind::Removed)
+        };
+        let pairs = cmp::min(left.len(), right.len());
+        let mut entries = Vec::new();
+        entries.extend(
+            left.drain(..pairs)
+                .zip(right.drain(..pairs))
+                .map(|(left, right)| (paired, Some(left), Some(right))),
+        );
+        entries.extend(right.drain(..).map(|right| (right_only, None, Some(right))));
+        entries.extend(left.drain(..).map(|left| (left_only, Some(left), None)));
+        for (kind, left, right) in entries {
+            let (left_line_number, left) = left.unzip();
+            let (right_line_number, right) = right.unzip();
+            let (left, left_missing_nl) = left.map(strip_nl).unzip();
+            let (right, right_missing_nl) = right.map(strip_nl).unzip();
+            rows.push(Row {
+                kind,
+                left,
+                right,
+                left_line_number,
+                right_line_number,
+                left_missing_nl: left_missing_nl.unwrap_or(false),
+                right_missing_nl: right_missing_nl.unwrap_or(false),
+            });
+        }
+    }
+
+    let changes = hunks::changes(&expected_lines, &actual_lines, &options.compare);
+    let (mut line_expected, mut line_actual) = (0, 0);
+    for change in changes {
+        common_left.extend(numbered(
+            &expected_lines,
+            line_expected..change.expected.start,
+        ));
+        common_right.extend(numbered(&actual_lines, line_actual..change.actual.start));
+        let mut removed = numbered(&expected_lines, change.expected.clone());
+        let mut added = numbered(&actual_lines, change.actual.clone());
+        if change.ignored {
+            common_left.append(&mut removed);
+            common_right.append(&mut added);
+        } else {
+            flush(&mut rows, &mut common_left, &mut common_right, true);
+            flush(&mut rows, &mut removed, &mut added, false);
+        }
+        line_expected = change.expected.end;
+        line_actual = change.actual.end;
+    }
+    common_left.extend(numbered(
+        &expected_lines,
+        line_expected..expected_lines.len(),
+    ));
+    common_right.extend(numbered(&actual_lines, line_actual..actual_lines.len()));
+    flush(&mut rows, &mut common_left, &mut common_right, true);
+
+    rows
+}
+
+/// How the lines of side-by-side output are fitted in their columns.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnOptions {
+    /// The distance between tab stops.
+    pub tabsize: usize,
+    /// Output tabs as the spaces they stand for.
+    pub expand_tabs: bool,
+    /// End a line that is cut to fit its column with '…'.
+    pub mark_truncated: bool,
+    /// Go on over as many output lines as it takes instead of cutting a
+    /// line that doesn't fit its column.
+    pub wrap_lines: bool,
+}
+
+impl Default for ColumnOptions {
+    fn default() -> Self {
+        Self {
+            tabsize: 8,
+            expand_tabs: false,
+            mark_truncated: false,
+            wrap_lines: false,
+        }
+    }
+}
+
+impl From<&Params> for ColumnOptions {
+    fn from(params: &Params) -> Self {
+        Self {
+            tabsize: params.tabsize,
+            expand_tabs: params.expand_tabs,
+            mark_truncated: params.mark_truncated,
+            wrap_lines: params.wrap_lines,
+        }
+    }
+}
+
+/// Everything diff -y needs besides the files: how wide its output is, which
+/// rows it leaves out, how lines fit in their columns, which lines compare
+/// equal, and how changes are colored. The command-line options of diff make
+/// them with `SideBySideOptions::from`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SideBySideOptions {
+    /// The number of columns of the output, like with -W.
+    pub width: usize,
+    /// Only write the left side of common lines, like with --left-column.
+    pub left_column: bool,
+    /// Leave out the common lines, like with --suppress-common-lines.
+    pub suppress_common_lines: bool,
+    pub columns: ColumnOptions,
+    pub compare: CompareOptions,
+    /// The colors of the changes, if they are colored, like with --color.
+    pub palette: Option<Palette>,
+}
+
+impl Default for SideBySideOptions {
+    fn default() -> Self {
+        Self {
+            width: 130,
+            left_column: false,
+            suppress_common_lines: false,
+            columns: ColumnOptions::default(),
+            compare: CompareOptions::default(),
+            palette: None,
+        }
+    }
+}
+
+impl From<&Params> for SideBySideOptions {
+    fn from(params: &Params) -> Self {
+        Self {
+            width: params.width,
+            left_column: params.left_column,
+            suppress_common_lines: params.suppress_common_lines,
+            columns: ColumnOptions::from(params),
+            compare: CompareOptions::from(params),
+            palette: params.color.then(|| params.palette.clone()),
+        }
+    }
+}
+
+// Pads the output from column `from` to column `to`, with tabs when possible
+// unless tabs are to be expanded. Returns the new column.
+fn tab_from_to(
+    output: &mut impl Write,
+    mut from: usize,
+    to: usize,
+    options: &ColumnOptions,
+) -> io::Result<usize> {
+    if !options.expand_tabs && options.tabsize > 0 {
+        let mut tab = from + options.tabsize - from % options.tabsize;
+        while tab <= to {
+            output.write_all(b"\t")?;
+            from = tab;
+            tab += options.tabsize;
+        }
+    }
+    while from < to {
+        output.write_all(b" ")?;
+        from += 1;
+    }
+    Ok(to)
+}
+
+// Writes as much of the line as fits in `out_bound` columns.
+// Returns the column the output ends at, and the offset of the first
+// character that doesn't fit, if one doesn't.
+fn print_half_line(
+    output: &mut impl Write,
+    line: &[u8],
+    out_bound: usize,
+    options: &ColumnOptions,
+) -> io::Result<(usize, Option<usize>)> {
+    let tabsize = cmp::max(options.tabsize, 1);
+    let mut in_position = 0;
+    let mut out_position = 0;
+    let mut cut = None;
+    let mut rest = line;
+    while let Some(&c) = rest.first() {
+        match c {
+            b'\t' => {
+                let spaces = tabsize - in_position % tabsize;
+                // A tab that starts in the column and expands past its end
+                // cuts the line, like a character that doesn't fit.
+                if in_position < out_bound && in_position + spaces > out_bound {
+                    cut.get_or_insert(line.len() - rest.len());
+                }
+                if in_position == out_position {
+                    let tabstop = out_position + spaces;
+                    if options.expand_tabs {
+                        while out_position < cmp::min(tabstop, out_bound) {
+                            output.write_all(b" ")?;
+                            out_position += 1;
+                        }
+                    } else if tabstop < out_bound {
+                        out_position = tabstop;
+                        output.write_all(&[c])?;
+                    }
+                }
+                in_position += spaces;
+                rest = &rest[1..];
+            }
+            b'\r' => {
+                output.write_all(&[c])?;
+                in_position = 0;
+                out_position = 0;
+                rest = &rest[1..];
+            }
+            b'\x08' => {
+                // backspace
+                if in_position != 0 {
+                    in_position -= 1;
+                    if in_position < out_bound {
+                        if out_position <= in_position {
+                            // Add spaces to make up for a suppressed tab past out_bound.
+                            while out_position < in_position {
+                                output.write_all(b" ")?;
+                                out_position += 1;
+                            }
+                        } else {
+                            out_position = in_position;
+                            output.write_all(&[c])?;
+                        }
+                    }
+                }
+                rest = &rest[1..];
+            }
+            _ => {
+                // Multi-byte characters are as wide as they are printed, and
+                // bytes that aren't valid UTF-8 are one column wide each.
+                let len = match c {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => 1,
+                };
+                let (bytes, width) = match rest
+                    .get(..len)
+                    .and_then(|bytes| std::str::from_utf8(bytes).ok())
+                {
+                    Some(s) => {
+                        let ch = s.chars().next().unwrap();
+                        (&rest[..len], ch.width().unwrap_or(0))
+                    }
+                    None => (&rest[..1], 1),
+                };
+                if in_position + width <= out_bound {
+                    output.write_all(bytes)?;
+                    out_position = in_position + width;
+                } else {
+                    cut.get_or_insert(line.len() - rest.len());
+                }
+                in_position += width;
+                rest = &rest[bytes.len()..];
+            }
+        }
+    }
+    Ok((out_position, cut))
+}
+
+// The offset of the first character of the line that doesn't fit in
+// `out_bound` columns, if one doesn't.
+fn find_cut(line: &[u8], out_bound: usize, options: &ColumnOptions) -> Option<usize> {
+    // Nothing written to a sink can fail.
+    print_half_line(&mut io::sink(), line, out_bound, options)
+        .ok()
+        .and_then(|(_, cut)| cut)
+}
+
+/// Splits a line into the parts that go on successive output lines of a
+/// column `half_width` columns wide: the whole line, or with `wrap_lines`,
+/// as many parts as it takes for each to fit.
+pub fn wrap<'a>(line: &'a [u8], half_width: usize, options: &ColumnOptions) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+    if options.wrap_lines {
+        while let Some(mut cut) = find_cut(rest, half_width, options) {
+            // A tab that expands past the end of the column ends the part.
+            if rest[cut] == b'\t' {
+                cut += 1;
+            }
+            // A character wider than the column is cut anyway.
+            if cut == 0 {
+                break;
+            }
+            parts.push(&rest[..cut]);
+            rest = &rest[cut..];
+            if rest.is_empty() {
+                return parts;
+            }
+        }
+    }
+    parts.push(rest);
+    parts
+}
+
+/// Writes as much of a line as fits in a column `half_width` columns wide,
+/// and returns the column the output ends at. With `mark_truncated`, a line
+/// that doesn't fit ends with '…' in the last column.
+pub fn write_half_line(
+    output: &mut impl Write,
+    line: &[u8],
+    half_width: usize,
+    options: &ColumnOptions,
+) -> io::Result<usize> {
+    if options.mark_truncated && half_width > 0 && find_cut(line, half_width, options).is_some() {
+        let (column, _) = print_half_line(output, line, half_width - 1, options)?;
+        tab_from_to(output, column, half_width - 1, options)?;
+        output.write_all("…".as_bytes())?;
+        return Ok(half_width);
+    }
+    Ok(print_half_line(output, line, half_width, options)?.0)
+}
+
+// Computes the width of each half of the output, and the column at which
+// the right half starts, the same way GNU diff does.
+fn layout(width: usize, columns: &ColumnOptions) -> (usize, usize) {
+    let tabsize = if columns.expand_tabs {
+        1
+    } else {
+        cmp::max(columns.tabsize, 1)
+    };
+    let offset = (width + tabsize + GUTTER_WIDTH_MINIMUM) / (2 * tabsize) * tabsize;
+    let half_width = cmp::min(
+        offset.saturating_sub(GUTTER_WIDTH_MINIMUM),
+        width.saturating_sub(offset),
+    );
+    let column2_offset = if half_width > 0 { offset } else { width };
+    (half_width, column2_offset)
+}
+
+// Writes with `write`, in the color of the class if there's one and the
+// output is colored.
+fn write_colored<W: Write, T>(
+    output: &mut W,
+    class: Option<ColorClass>,
+    palette: Option<&Palette>,
+    write: impl FnOnce(&mut W) -> io::Result<T>,
+) -> io::Result<T> {
+    let Some((class, palette)) = class.zip(palette) else {
+        return write(output);
+    };
+    output.write_all(palette.start(class).as_bytes())?;
+    let result = write(output)?;
+    output.write_all(palette.end().as_bytes())?;
+    Ok(result)
+}
+
+/// Writes rows in the format of diff -y.
+pub fn format_rows(
+    output: &mut impl Write,
+    rows: &[Row],
+    options: &SideBySideOptions,
+) -> io::Result<()> {
+    let columns = &options.columns;
+    let palette = options.palette.as_ref();
+    let (half_width, column2_offset) = layout(options.width, columns);
+    for row in rows {
+        let (mut separator, right) = match row.kind {
+            RowKind::Unchanged | RowKind::IgnoredRemoved | RowKind::IgnoredAdded
+                if options.suppress_common_lines =>
+            {
+                continue
+            }
+            RowKind::Unchanged if options.left_column => (b'(', None),
+            RowKind::Unchanged => (b' ', row.right),
+            RowKind::Changed => (b'|', row.right),
+            RowKind::Removed => (b'<', None),
+            RowKind::Added => (b'>', row.right),
+            RowKind::IgnoredRemoved => (b'(', None),
+            // Only the left side of common lines is output with --left-column.
+            RowKind::IgnoredAdded if options.left_column => continue,
+            RowKind::IgnoredAdded => (b')', row.right),
+        };
+        // The newline is left out when the lines are missing theirs.
+        let left_newline = row.left.is_some() && !row.left_missing_nl;
+        let right_newline = right.is_some() && !row.right_missing_nl;
+        if separator == b'|' && left_newline == row.right_missing_nl {
+            // Only one of the two lines is missing its newline.
+            separator = if left_newline { b'/' } else { b'\\' };
+        }
+        let lefts = match row.left {
+            Some(left) => wrap(left, half_width, columns),
+            None => Vec::new(),
+        };
+        let rights = match right {
+            Some(right) => wrap(right, half_width, columns),
+            None => Vec::new(),
+        };
+        // With --color, the lines of the first file are colored as deleted
+        // and those of the second one as added, along with the separators
+        // of the rows that only have one of them.
+        let left_class =
+            matches!(row.kind, RowKind::Changed | RowKind::Removed).then_some(ColorClass::Deleted);
+        let right_class =
+            matches!(row.kind, RowKind::Changed | RowKind::Added).then_some(ColorClass::Added);
+        let separator_class = match row.kind {
+            RowKind::Removed => left_class,
+            RowKind::Added => right_class,
+            _ => None,
+        };
+        // Wrapped lines go on as many output lines as they take, with the
+        // separator on the first one only.
+        let count = cmp::max(cmp::max(lefts.len(), rights.len()), 1);
+        for i in 0..count {
+            let mut column = 0;
+            if let Some(left) = lefts.get(i) {
+                column = write_colored(output, left_class, palette, |output| {
+                    write_half_line(output, left, half_width, columns)
+                })?;
+            }
+            if i == 0 && separator != b' ' {
+                column = tab_from_to(
+                    output,
+                    column,
+                    (half_width + column2_offset - 1) / 2,
+                    columns,
+                )? + 1;
+                write_colored(output, separator_class, palette, |output| {
+                    output.write_all(&[separator])
+                })?;
+            }
+            if let Some(right) = rights.get(i).filter(|right| !right.is_empty()) {
+                tab_from_to(output, column, column2_offset, columns)?;
+                write_colored(output, right_class, palette, |output| {
+                    write_half_line(output, right, half_width, columns)
+                })?;
+            }
+            if i + 1 < count || left_newline || right_newline {
+                writeln!(output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the output of diff -y to `output`, row by row, so that it doesn't
+/// need to be built whole first. Unlike the other formats, the output isn't
+/// empty when there are no differences.
+pub fn diff(
+    output: &mut impl Write,
+    expected: &[u8],
+    actual: &[u8],
+    options: &SideBySideOptions,
+) -> io::Result<()> {
+    format_rows(output, &rows(expected, actual, options), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::LinePattern;
+    use pretty_assertions::assert_eq;
+
+    fn kinds(rows: &[Row]) -> Vec<RowKind> {
+        rows.iter().map(|row| row.kind).collect()
+    }
+
+    #[test]
+    fn identical() {
+        let rows = rows(b"a\nb\n", b"a\nb\n", &SideBySideOptions::default());
+        assert_eq!(kinds(&rows), vec![RowKind::Unchanged, RowKind::Unchanged]);
+        assert_eq!(rows[1].left, Some(&b"b"[..]));
+        assert_eq!(rows[1].right_line_number, Some(2));
+    }
+
+    #[test]
+    fn changes_are_paired() {
+        let rows = rows(
+            b"a\nb\nc\nd\n",
+            b"a\nx\nd\ne\n",
+            &SideBySideOptions::default(),
+        );
+        assert_eq!(
+            kinds(&rows),
+            vec![
+                RowKind::Unchanged,
+                RowKind::Changed,
+                RowKind::Removed,
+                RowKind::Unchanged,
+                RowKind::Added,
+            ]
+        );
+        assert_eq!(
+            rows[1],
+            Row {
+                kind: RowKind::Changed,
+                left: Some(b"b"),
+                right: Some(b"x"),
+                left_line_number: Some(2),
+                right_line_number: Some(2),
+                left_missing_nl: false,
+                right_missing_nl: false,
+            }
+        );
+        assert_eq!(rows[2].left, Some(&b"c"[..]));
+        assert_eq!(rows[2].right, None);
+        assert_eq!(rows[4].left_line_number, None);
+        assert_eq!(rows[4].right_line_number, Some(4));
+    }
+
+    #[test]
+    fn missing_newline() {
+        let rows = rows(b"a\nb", b"a\nb\n", &SideBySideOptions::default());
+        assert_eq!(kinds(&rows), vec![RowKind::Unchanged, RowKind::Changed]);
+        assert!(rows[1].left_missing_nl);
+        assert!(!rows[1].right_missing_nl);
+        assert_eq!(rows[1].left, rows[1].right);
+    }
+
+    fn side_by_side(expected: &str, actual: &str, options: &SideBySideOptions) -> String {
+        let mut output = Vec::new();
+        diff(&mut output, expected.as_bytes(), actual.as_bytes(), options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn layouts() {
+        for (width, expand_tabs, expected) in [
+            (130, false, (61, 64)),
+            (40, false, (16, 24)),
+            (40, true, (18, 22)),
+            (5, false, (0, 5)),
+        ] {
+            let columns = ColumnOptions {
+                expand_tabs,
+                ..Default::default()
+            };
+            assert_eq!(layout(width, &columns), expected);
+        }
+    }
+
+    #[test]
+    fn format() {
+        let options = SideBySideOptions {
+            width: 40,
+            ..Default::default()
+        };
+        let from = "hello\tworld\n\nsame\nlonglonglonglonglonglong\nonly left\n";
+        let to = "hello\tthere\n\nsame\nzz\n";
+        assert_eq!(
+            side_by_side(from, to, &options),
+            [
+                "hello\tworld\t   |\thello\tthere",
+                "",
+                "same\t\t\tsame",
+                "longlonglonglong   |\tzz",
+                "only left\t   <",
+                "",
+            ]
+            .join("\n")
+        );
+        let options = SideBySideOptions {
+            columns: ColumnOptions {
+                expand_tabs: true,
+                ..Default::default()
+            },
+            ..options
+        };
+        assert_eq!(
+            side_by_side(from, to, &options),
+            [
+                "hello   world      |  hello   there",
+                "",
+                "same                  same",
+                "longlonglonglonglo |  zz",
+                "only left          <",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn format_options() {
+        let options = SideBySideOptions {
+            width: 40,
+            left_column: true,
+            ..Default::default()
+        };
+        let from = "a\nb\n\n";
+        let to = "a\nc\n\nd\n";
+        assert_eq!(
+            side_by_side(from, to, &options),
+            "a\t\t   (\nb\t\t   |\tc\n\t\t   (\n\t\t   >\td\n"
+        );
+        let options = SideBySideOptions {
+            width: 40,
+            suppress_common_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            side_by_side(from, to, &options),
+            "b\t\t   |\tc\n\t\t   >\td\n"
+        );
+    }
+
+    #[test]
+    fn format_long_lines() {
+        let options = SideBySideOptions {
+            width: 20,
+            columns: ColumnOptions {
+                expand_tabs: true,
+                mark_truncated: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let from = "abcdefghijkl\nab\n";
+        let to = "ABCDEFGHIJKL\nab\n";
+        assert_eq!(
+            side_by_side(from, to, &options),
+            "abcdefg… |  ABCDEFG…\nab          ab\n"
+        );
+        let options = SideBySideOptions {
+            columns: ColumnOptions {
+                mark_truncated: false,
+                wrap_lines: true,
+                ..options.columns
+            },
+            ..options
+        };
+        assert_eq!(
+            side_by_side(from, to, &options),
+            "abcdefgh |  ABCDEFGH\nijkl        IJKL\nab          ab\n"
+        );
+        assert_eq!(
+            side_by_side("abcdefghijkl\n", "", &options),
+            "abcdefgh <\nijkl\n"
+        );
+        // Tabs count as the columns they expand to.
+        assert_eq!(
+            side_by_side("abcdef\tgh\n", "", &options),
+            "abcdef   <\ngh\n"
+        );
+        assert_eq!(side_by_side("abcdefg\t\n", "", &options), "abcdefg  <\n");
+        let options = SideBySideOptions {
+            width: 22,
+            ..options
+        };
+        assert_eq!(
+            side_by_side("abcdefgh\tij\n", "", &options),
+            "abcdefgh  <\nij\n"
+        );
+        let options = SideBySideOptions {
+            columns: ColumnOptions {
+                mark_truncated: true,
+                wrap_lines: false,
+                ..options.columns
+            },
+            ..options
+        };
+        assert_eq!(side_by_side("abcdefgh\t\n", "", &options), "abcdefgh… <\n");
+    }
+
+    #[test]
+    fn format_colors() {
+        let options = SideBySideOptions {
+            width: 40,
+            palette: Some(Palette::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            side_by_side("a\nb\nc\n", "a\nB\nd\n", &options),
+            "a\t\t\ta\n\
+             \x1b[31mb\x1b[0m\t\t   |\t\x1b[32mB\x1b[0m\n\
+             \x1b[31mc\x1b[0m\t\t   |\t\x1b[32md\x1b[0m\n"
+        );
+        assert_eq!(
+            side_by_side("a\nb\n", "b\nc\n", &options),
+            "\x1b[31ma\x1b[0m\t\t   \x1b[31m<\x1b[0m\n\
+             b\t\t\tb\n\
+             \t\t   \x1b[32m>\x1b[0m\t\x1b[32mc\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn format_missing_newline() {
+        let options = SideBySideOptions {
+            width: 40,
+            ..Default::default()
+        };
+        assert_eq!(
+            side_by_side("a\nb", "a\nc", &options),
+            "a\t\t\ta\nb\t\t   |\tc"
+        );
+        assert_eq!(
+            side_by_side("a\nb", "a\nb\n", &options),
+            "a\t\t\ta\nb\t\t   \\\tb\n"
+        );
+        assert_eq!(
+            side_by_side("a\nb\n", "a\nb", &options),
+            "a\t\t\ta\nb\t\t   /\tb\n"
+        );
+    }
+
+    #[test]
+    fn ignored_changes() {
+        // The common lines are paired up regardless of the ignored changes.
+        let options = SideBySideOptions {
+            width: 40,
+            compare: CompareOptions {
+                ignore_matching_lines: vec![LinePattern::new("^#").unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rows = rows(b"a\n# 1\n# 2\nb\n", b"a\n# 3\nb\n", &options);
+        assert_eq!(
+            kinds(&rows),
+            vec![
+                RowKind::Unchanged,
+                RowKind::Unchanged,
+                RowKind::Unchanged,
+                RowKind::IgnoredRemoved
+            ]
+        );
+        assert_eq!(rows[2].right, Some(&b"b"[..]));
+        assert_eq!(
+            side_by_side("a\n# 3\nb\n", "a\n# 1\n# 2\nb\n", &options),
+            "a\t\t\ta\n# 3\t\t\t# 1\nb\t\t\t# 2\n\t\t   )\tb\n"
+        );
+        let options = SideBySideOptions {
+            suppress_common_lines: true,
+            ..options
+        };
+        assert_eq!(
+            side_by_side("a\n# 3\nb\n", "a\n# 1\n# 2\nb\n", &options),
+            ""
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert!(rows(b"", b"", &SideBySideOptions::default()).is_empty());
+        assert_eq!(
+            kinds(&rows(b"", b"a\n", &SideBySideOptions::default())),
+            vec![RowKind::Added]
+        );
+    }
+
+    fn half_line(line: &str, half_width: usize, options: &ColumnOptions) -> (String, usize) {
+        let mut output = Vec::new();
+        let column = write_half_line(&mut output, line.as_bytes(), half_width, options).unwrap();
+        (String::from_utf8(output).unwrap(), column)
+    }
+
+    fn parts<'a>(line: &'a str, half_width: usize, options: &ColumnOptions) -> Vec<&'a [u8]> {
+        wrap(line.as_bytes(), half_width, options)
+    }
+
+    #[test]
+    fn cut_lines() {
+        let options = ColumnOptions {
+            expand_tabs: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            half_line("abcdefghijkl", 8, &options),
+            ("abcdefgh".into(), 8)
+        );
+        assert_eq!(half_line("ab\tc", 8, &options), ("ab      ".into(), 8));
+        assert_eq!(half_line("ab", 8, &options), ("ab".into(), 2));
+        let options = ColumnOptions {
+            mark_truncated: true,
+            ..options
+        };
+        assert_eq!(
+            half_line("abcdefghijkl", 8, &options),
+            ("abcdefg…".into(), 8)
+        );
+        assert_eq!(half_line("abcdefgh", 8, &options), ("abcdefgh".into(), 8));
+        // Tabs count as the columns they expand to.
+        assert_eq!(
+            half_line("abcdefgh\t", 9, &options),
+            ("abcdefgh…".into(), 9)
+        );
+        assert_eq!(
+            half_line("abc\tdefgh", 9, &options),
+            ("abc     …".into(), 9)
+        );
+    }
+
+    #[test]
+    fn wrapped_lines() {
+        let options = ColumnOptions::default();
+        assert_eq!(parts("abcdefghijkl", 8, &options), [b"abcdefghijkl"]);
+        let options = ColumnOptions {
+            wrap_lines: true,
+            ..options
+        };
+        assert_eq!(
+            parts("abcdefghijkl", 8, &options),
+            [&b"abcdefgh"[..], b"ijkl"]
+        );
+        assert_eq!(parts("abcdefgh", 8, &options), [b"abcdefgh"]);
+        assert_eq!(parts("", 8, &options), [b""]);
+        // A tab that expands past the end of the column ends its part.
+        assert_eq!(parts("abcdef\tgh", 8, &options), [&b"abcdef\t"[..], b"gh"]);
+        assert_eq!(parts("abcdefg\t", 8, &options), [b"abcdefg\t"]);
+        assert_eq!(
+            parts("abcdefgh\tij", 9, &options),
+            [&b"abcdefgh\t"[..], b"ij"]
+        );
+        // A character wider than the column can't be wrapped.
+        assert_eq!(parts("日本", 1, &options), ["日本".as_bytes()]);
+    }
+}
@@ -0,0 +1,297 @@
+// This file is part of the uutils diffutils package.
+//
+// For the full copyright and license information, please view the LICENSE-*
+// files that was distributed with this source code.
+
+use std::io::{self, Write};
+
+use crate::hunks::{self, CompareOptions};
+use crate::params::{Params, StatFormat};
+
+/// How much a file changed, for --stat, --numstat and --shortstat.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileStat {
+    pub name: String,
+    pub changes: StatChanges,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StatChanges {
+    Lines {
+        added: usize,
+        removed: usize,
+    },
+    /// The sizes of binary files, whose lines aren't counted.
+    Binary {
+        from_size: usize,
+        to_size: usize,
+    },
+}
+
+impl FileStat {
+    /// Counts the lines that are added and removed between two files, in
+    /// the changes that aren't ignored.
+    #[must_use]
+    pub fn new(name: String, expected: &[u8], actual: &[u8], params: &Params) -> Self {
+        let expected_lines: Vec<&[u8]> = expected.split_inclusive(|&c| c == b'\n').collect();
+        let actual_lines: Vec<&[u8]> = actual.split_inclusive(|&c| c == b'\n').collect();
+        let (mut added, mut removed) = (0, 0);
+        for change in hunks::changes(
+            &expected_lines,
+            &actual_lines,
+            &CompareOptions::from(params),
+        ) {
+            if !change.ignored {
+                added += change.actual.len();
+                removed += change.expected.len();
+            }
+        }
+        Self {
+            name,
+            changes: StatChanges::Lines { added, removed },
+        }
+    }
+
+    #[must_use]
+    pub fn binary(name: String, expected: &[u8], actual: &[u8]) -> Self {
+        Self {
+            name,
+            changes: StatChanges::Binary {
+                from_size: expected.len(),
+                to_size: actual.len(),
+            },
+        }
+    }
+
+    /// Whether the files differ.
+    #[must_use]
+    pub fn changed(&self) -> bool {
+        match self.changes {
+            StatChanges::Lines { added, removed } => added + removed > 0,
+            StatChanges::Binary { .. } => true,
+        }
+    }
+}
+
+// The width of the summary, like git's when it doesn't write to a terminal.
+const STAT_WIDTH: usize = 80;
+
+/// Writes the summary of the changes to the files that changed, like git
+/// diff: with --stat, a line for each file with the number of lines changed
+/// and a graph of the proportions of added and removed lines, then the
+/// totals; with --numstat, the numbers of added and removed lines of each
+/// file; with --shortstat, only the totals.
+pub fn write_stat(
+    output: &mut impl Write,
+    stats: &[FileStat],
+    format: StatFormat,
+) -> io::Result<()> {
+    let stats: Vec<&FileStat> = stats.iter().filter(|stat| stat.changed()).collect();
+    if stats.is_empty() {
+        return Ok(());
+    }
+    match format {
+        StatFormat::Stat => write_graphs(output, &stats)?,
+        StatFormat::Numstat => {
+            for stat in &stats {
+                match stat.changes {
+                    StatChanges::Lines { added, removed } => {
+                        writeln!(output, "{added}\t{removed}\t{}", stat.name)?;
+                    }
+                    // Like git, binary files have no numbers of lines.
+                    StatChanges::Binary { .. } => {
+                        writeln!(output, "-\t-\t{}", stat.name)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+        StatFormat::Shortstat => {}
+    }
+
+    let (insertions, deletions) = stats
+        .iter()
+        .filter_map(|stat| match stat.changes {
+            StatChanges::Lines { added, removed } => Some((added, removed)),
+            StatChanges::Binary { .. } => None,
+        })
+        .fold((0, 0), |(a, r), (added, removed)| (a + added, r + removed));
+    let plural =
+        |count: usize, word: &str| format!("{count} {word}{}", if count == 1 { "" } else { "s" });
+    write!(output, " {} changed", plural(stats.len(), "file"))?;
+    if insertions > 0 {
+        write!(output, ", {}(+)", plural(insertions, "insertion"))?;
+    }
+    if deletions > 0 {
+        write!(output, ", {}(-)", plural(deletions, "deletion"))?;
+    }
+    writeln!(output)
+}
+
+// Writes the line of each file of --stat.
+fn write_graphs(output: &mut impl Write, stats: &[&FileStat]) -> io::Result<()> {
+    let name_width = stats
+        .iter()
+        .map(|stat| stat.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_change = stats
+        .iter()
+        .map(|stat| match stat.changes {
+            StatChanges::Lines { added, removed } => added + removed,
+            StatChanges::Binary { .. } => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    // "Bin" is aligned with the numbers of lines.
+    let has_binary = stats
+        .iter()
+        .any(|stat| matches!(stat.changes, StatChanges::Binary { .. }));
+    let count_width = max_change
+        .to_string()
+        .len()
+        .max(if has_binary { 3 } else { 0 });
+    let graph_width = STAT_WIDTH
+        .saturating_sub(name_width + count_width + 5)
+        .max(10);
+
+    for stat in stats {
+        write!(output, " {:<name_width$} | ", stat.name)?;
+        match stat.changes {
+            StatChanges::Lines { added, removed } => {
+                let (added_width, removed_width) =
+                    graph_widths(added, removed, graph_width, max_change);
+                writeln!(
+                    output,
+                    "{:>count_width$} {}{}",
+                    added + removed,
+                    "+".repeat(added_width),
+                    "-".repeat(removed_width)
+                )?;
+            }
+            StatChanges::Binary { from_size, to_size } => {
+                writeln!(
+                    output,
+                    "{:>count_width$} {from_size} -> {to_size} bytes",
+                    "Bin"
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The widths of the bars of added and removed lines, scaled down like git
+// does when the largest change doesn't fit in the graph.
+fn graph_widths(added: usize, removed: usize, width: usize, max_change: usize) -> (usize, usize) {
+    if max_change <= width {
+        return (added, removed);
+    }
+    let scale = |count: usize| {
+        if count == 0 {
+            0
+        } else {
+            1 + count * (width - 1) / max_change
+        }
+    };
+    let mut total = scale(added + removed);
+    if total < 2 && added > 0 && removed > 0 {
+        total = 2;
+    }
+    if added < removed {
+        let added = scale(added);
+        (added, total - added)
+    } else {
+        let removed = scale(removed);
+        (total - removed, removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn lines(name: &str, added: usize, removed: usize) -> FileStat {
+        FileStat {
+            name: name.to_string(),
+            changes: StatChanges::Lines { added, removed },
+        }
+    }
+
+    #[test]
+    fn counts() {
+        let stat = FileStat::new(
+            "f".to_string(),
+            b"a\nb\nc\nd\n",
+            b"a\nB\nc\nd\ne\nf",
+            &Params::default(),
+        );
+        assert_eq!(stat, lines("f", 3, 1));
+        let stat = FileStat::new("f".to_string(), b"a\n", b"a\n", &Params::default());
+        assert!(!stat.changed());
+    }
+
+    #[test]
+    fn summary() {
+        let mut output = Vec::new();
+        write_stat(
+            &mut output,
+            &[
+                lines("a/f", 2, 1),
+                lines("a/same", 0, 0),
+                lines("a/longer", 0, 1),
+                FileStat::binary("a/bin".to_string(), b"\0", b"\0\0"),
+            ],
+            StatFormat::Stat,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            " a/f      |   3 ++-\n a/longer |   1 -\n a/bin    | Bin 1 -> 2 bytes\n \
+             3 files changed, 2 insertions(+), 2 deletions(-)\n"
+        );
+
+        let mut output = Vec::new();
+        write_stat(&mut output, &[lines("f", 1, 0)], StatFormat::Stat).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            " f | 1 +\n 1 file changed, 1 insertion(+)\n"
+        );
+
+        for format in [StatFormat::Stat, StatFormat::Numstat, StatFormat::Shortstat] {
+            let mut output = Vec::new();
+            write_stat(&mut output, &[lines("f", 0, 0)], format).unwrap();
+            assert!(output.is_empty());
+        }
+    }
+
+    #[test]
+    fn machine_readable_summaries() {
+        let stats = [
+            lines("a/f", 2, 1),
+            lines("a/same", 0, 0),
+            FileStat::binary("a/bin".to_string(), b"\0", b"\0\0"),
+        ];
+        let mut output = Vec::new();
+        write_stat(&mut output, &stats, StatFormat::Numstat).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2\t1\ta/f\n-\t-\ta/bin\n"
+        );
+        let mut output = Vec::new();
+        write_stat(&mut output, &stats, StatFormat::Shortstat).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            " 2 files changed, 2 insertions(+), 1 deletion(-)\n"
+        );
+    }
+
+    #[test]
+    fn scaled_graph() {
+        assert_eq!(graph_widths(3, 1, 70, 4), (3, 1));
+        assert_eq!(graph_widths(700, 700, 70, 1400), (35, 35));
+        assert_eq!(graph_widths(1, 1000, 70, 1001), (1, 69));
+        assert_eq!(graph_widths(1, 1, 70, 1000), (1, 1));
+    }
+}
@@ -3,12 +3,12 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use std::collections::VecDeque;
-use std::io::Write;
+use std::io::{self, Write};
 
+use crate::hunks::Diff;
+use crate::palette::ColorClass;
 use crate::params::Params;
-use crate::utils::do_write_line;
-use crate::utils::get_modification_time;
+use crate::utils::{file_header, write_colored_line, write_marked_line, UNIFIED_TIME_FORMAT};
 
 #[derive(Debug, PartialEq)]
 pub enum DiffLine {
@@ -35,228 +35,79 @@ impl Mismatch {
     }
 }
 
-// Produces a diff between the expected output and actual output.
-fn make_diff(
-    expected: &[u8],
-    actual: &[u8],
-    context_size: usize,
-    stop_early: bool,
-) -> Vec<Mismatch> {
-    let mut line_number_expected = 1;
-    let mut line_number_actual = 1;
-    let mut context_queue: VecDeque<&[u8]> = VecDeque::with_capacity(context_size);
-    let mut lines_since_mismatch = context_size + 1;
-    let mut results = Vec::new();
-    let mut mismatch = Mismatch::new(0, 0);
-
-    let mut expected_lines: Vec<&[u8]> = expected.split(|&c| c == b'\n').collect();
-    let mut actual_lines: Vec<&[u8]> = actual.split(|&c| c == b'\n').collect();
-
-    debug_assert_eq!(b"".split(|&c| c == b'\n').count(), 1);
-    // ^ means that underflow here is impossible
-    let expected_lines_count = expected_lines.len() as u32 - 1;
-    let actual_lines_count = actual_lines.len() as u32 - 1;
-
-    if expected_lines.last() == Some(&&b""[..]) {
-        expected_lines.pop();
-    }
-
-    if actual_lines.last() == Some(&&b""[..]) {
-        actual_lines.pop();
+// Pushes a line to the mismatch, followed by a missing newline marker
+// if it is the last line of a file that doesn't end with a newline.
+fn push_line(mismatch: &mut Mismatch, line: &[u8], make_line: fn(Vec<u8>) -> DiffLine) {
+    match line.strip_suffix(b"\n") {
+        Some(line) => mismatch.lines.push(make_line(line.to_vec())),
+        None => {
+            mismatch.lines.push(make_line(line.to_vec()));
+            mismatch.lines.push(DiffLine::MissingNL);
+        }
     }
+}
 
-    for result in diff::slice(&expected_lines, &actual_lines) {
-        match result {
-            diff::Result::Left(str) => {
-                if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(
-                        line_number_expected - context_queue.len() as u32,
-                        line_number_actual - context_queue.len() as u32,
-                    );
-                }
-
-                while let Some(line) = context_queue.pop_front() {
-                    mismatch.lines.push(DiffLine::Context(line.to_vec()));
-                }
-
-                if mismatch.lines.last() == Some(&DiffLine::MissingNL) {
-                    mismatch.lines.pop();
-                    match mismatch.lines.pop() {
-                        Some(DiffLine::Actual(res)) => {
-                            // We have to make sure that Actual (the + lines)
-                            // always come after Expected (the - lines)
-                            mismatch.lines.push(DiffLine::Expected(str.to_vec()));
-                            if line_number_expected > expected_lines_count {
-                                mismatch.lines.push(DiffLine::MissingNL);
-                            }
-                            mismatch.lines.push(DiffLine::Actual(res));
-                            mismatch.lines.push(DiffLine::MissingNL);
-                        }
-                        _ => unreachable!("unterminated Left and Common lines shouldn't be followed by more Left lines"),
-                    }
-                } else {
-                    mismatch.lines.push(DiffLine::Expected(str.to_vec()));
-                    if line_number_expected > expected_lines_count {
-                        mismatch.lines.push(DiffLine::MissingNL);
-                    }
-                }
-                line_number_expected += 1;
-                lines_since_mismatch = 0;
+// Collects the lines of the hunks of a diff, with their context.
+fn make_diff(diff: &Diff) -> Vec<Mismatch> {
+    let (expected_lines, actual_lines) = (&diff.expected_lines, &diff.actual_lines);
+    let mut results = Vec::new();
+    for hunk in &diff.hunks {
+        let mut mismatch = Mismatch::new(
+            (hunk.expected.start + 1) as u32,
+            (hunk.actual.start + 1) as u32,
+        );
+        let mut line_expected = hunk.expected.start;
+        for change in &hunk.changes {
+            for line in &expected_lines[line_expected..change.expected.start] {
+                push_line(&mut mismatch, line, DiffLine::Context);
             }
-            diff::Result::Right(str) => {
-                if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                    results.push(mismatch);
-                    mismatch = Mismatch::new(
-                        line_number_expected - context_queue.len() as u32,
-                        line_number_actual - context_queue.len() as u32,
-                    );
-                }
-
-                while let Some(line) = context_queue.pop_front() {
-                    debug_assert!(mismatch.lines.last() != Some(&DiffLine::MissingNL));
-                    mismatch.lines.push(DiffLine::Context(line.to_vec()));
-                }
-
-                mismatch.lines.push(DiffLine::Actual(str.to_vec()));
-                if line_number_actual > actual_lines_count {
-                    mismatch.lines.push(DiffLine::MissingNL);
-                }
-                line_number_actual += 1;
-                lines_since_mismatch = 0;
+            // GNU diff lists all the removed lines of a group before the added ones.
+            for line in &expected_lines[change.expected.clone()] {
+                push_line(&mut mismatch, line, DiffLine::Expected);
             }
-            diff::Result::Both(str, _) => {
-                // if one of them is missing a newline and the other isn't, then they don't actually match
-                if (line_number_actual > actual_lines_count)
-                    && (line_number_expected > expected_lines_count)
-                {
-                    if context_queue.len() < context_size {
-                        while let Some(line) = context_queue.pop_front() {
-                            debug_assert!(mismatch.lines.last() != Some(&DiffLine::MissingNL));
-                            mismatch.lines.push(DiffLine::Context(line.to_vec()));
-                        }
-                        if lines_since_mismatch < context_size {
-                            mismatch.lines.push(DiffLine::Context(str.to_vec()));
-                            mismatch.lines.push(DiffLine::MissingNL);
-                        }
-                    }
-                    lines_since_mismatch = 0;
-                } else if line_number_actual > actual_lines_count {
-                    if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                        results.push(mismatch);
-                        mismatch = Mismatch::new(
-                            line_number_expected - context_queue.len() as u32,
-                            line_number_actual - context_queue.len() as u32,
-                        );
-                    }
-                    while let Some(line) = context_queue.pop_front() {
-                        debug_assert!(mismatch.lines.last() != Some(&DiffLine::MissingNL));
-                        mismatch.lines.push(DiffLine::Context(line.to_vec()));
-                    }
-                    mismatch.lines.push(DiffLine::Expected(str.to_vec()));
-                    mismatch.lines.push(DiffLine::Actual(str.to_vec()));
-                    mismatch.lines.push(DiffLine::MissingNL);
-                    lines_since_mismatch = 0;
-                } else if line_number_expected > expected_lines_count {
-                    if lines_since_mismatch >= context_size && lines_since_mismatch > 0 {
-                        results.push(mismatch);
-                        mismatch = Mismatch::new(
-                            line_number_expected - context_queue.len() as u32,
-                            line_number_actual - context_queue.len() as u32,
-                        );
-                    }
-                    while let Some(line) = context_queue.pop_front() {
-                        debug_assert!(mismatch.lines.last() != Some(&DiffLine::MissingNL));
-                        mismatch.lines.push(DiffLine::Context(line.to_vec()));
-                    }
-                    mismatch.lines.push(DiffLine::Expected(str.to_vec()));
-                    mismatch.lines.push(DiffLine::MissingNL);
-                    mismatch.lines.push(DiffLine::Actual(str.to_vec()));
-                    lines_since_mismatch = 0;
-                } else {
-                    debug_assert!(context_queue.len() <= context_size);
-                    if context_queue.len() >= context_size {
-                        let _ = context_queue.pop_front();
-                    }
-                    if lines_since_mismatch < context_size {
-                        mismatch.lines.push(DiffLine::Context(str.to_vec()));
-                    } else if context_size > 0 {
-                        context_queue.push_back(str);
-                    }
-                    lines_since_mismatch += 1;
-                }
-                line_number_expected += 1;
-                line_number_actual += 1;
+            for line in &actual_lines[change.actual.clone()] {
+                push_line(&mut mismatch, line, DiffLine::Actual);
             }
+            line_expected = change.expected.end;
         }
-        if stop_early && !results.is_empty() {
-            // Optimization: stop analyzing the files as soon as there are any differences
-            return results;
-        }
-    }
-
-    results.push(mismatch);
-    results.remove(0);
-
-    if results.is_empty() && expected_lines_count != actual_lines_count {
-        let mut mismatch = Mismatch::new(expected_lines.len() as u32, actual_lines.len() as u32);
-        // empty diff and only expected lines has a missing line at end
-        if expected_lines_count != expected_lines.len() as u32 {
-            mismatch.lines.push(DiffLine::Expected(
-                expected_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.lines.push(DiffLine::MissingNL);
-            mismatch.lines.push(DiffLine::Actual(
-                actual_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            results.push(mismatch);
-        } else if actual_lines_count != actual_lines.len() as u32 {
-            mismatch.lines.push(DiffLine::Expected(
-                expected_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.lines.push(DiffLine::Actual(
-                actual_lines
-                    .pop()
-                    .expect("can't be empty; produced by split()")
-                    .to_vec(),
-            ));
-            mismatch.lines.push(DiffLine::MissingNL);
-            results.push(mismatch);
+        for line in &expected_lines[line_expected..hunk.expected.end] {
+            push_line(&mut mismatch, line, DiffLine::Context);
         }
+        results.push(mismatch);
     }
 
     results
 }
 
-#[must_use]
-pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
-    let from_modified_time = get_modification_time(&params.from.to_string_lossy());
-    let to_modified_time = get_modification_time(&params.to.to_string_lossy());
-    let mut output = format!(
-        "--- {0}\t{1}\n+++ {2}\t{3}\n",
-        params.from.to_string_lossy(),
-        from_modified_time,
-        params.to.to_string_lossy(),
-        to_modified_time
-    )
-    .into_bytes();
-    let diff_results = make_diff(expected, actual, params.context_count, params.brief);
-    if diff_results.is_empty() {
-        return Vec::new();
+/// Writes the differences in the unified format: the headers of both files,
+/// then each hunk under a `@@` line, with its removed and added lines marked
+/// among the context.
+pub fn diff(output: &mut impl Write, diff: &Diff, params: &Params) -> io::Result<()> {
+    if diff.hunks.is_empty() {
+        return Ok(());
     }
+    write_colored_line(
+        output,
+        ColorClass::Header,
+        format_args!("--- {}", file_header(params, true, UNIFIED_TIME_FORMAT)),
+        params,
+    )?;
+    write_colored_line(
+        output,
+        ColorClass::Header,
+        format_args!("+++ {}", file_header(params, false, UNIFIED_TIME_FORMAT)),
+        params,
+    )?;
     if params.brief {
-        return output;
+        return Ok(());
     }
-    for result in diff_results {
+    // With -T, a tab follows the markers, and replaces the one of context lines.
+    let [context_marker, removed_marker, added_marker] = if params.initial_tab {
+        ["\t", "-\t", "+\t"]
+    } else {
+        [" ", "-", "+"]
+    };
+    for result in make_diff(diff) {
         let mut line_number_expected = result.line_number_expected;
         let mut line_number_actual = result.line_number_actual;
         let mut expected_count = 0;
@@ -368,46 +219,51 @@ pub fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
         } else {
             format!(",{actual_count}")
         };
-        writeln!(
+        write_colored_line(
             output,
-            "@@ -{line_number_expected}{exp_ct} +{line_number_actual}{act_ct} @@"
-        )
-        .expect("write to Vec is infallible");
+            ColorClass::LineNumbers,
+            format_args!("@@ -{line_number_expected}{exp_ct} +{line_number_actual}{act_ct} @@"),
+            params,
+        )?;
         for line in result.lines {
             match line {
                 DiffLine::Expected(e) => {
-                    write!(output, "-").expect("write to Vec is infallible");
-                    do_write_line(&mut output, &e, params.expand_tabs, params.tabsize)
-                        .expect("write to Vec is infallible");
-                    writeln!(output).unwrap();
+                    write_marked_line(
+                        output,
+                        removed_marker,
+                        &e,
+                        Some(ColorClass::Deleted),
+                        params,
+                    )?;
                 }
                 DiffLine::Context(c) => {
-                    write!(output, " ").expect("write to Vec is infallible");
-                    do_write_line(&mut output, &c, params.expand_tabs, params.tabsize)
-                        .expect("write to Vec is infallible");
-                    writeln!(output).unwrap();
+                    write_marked_line(output, context_marker, &c, None, params)?;
                 }
                 DiffLine::Actual(r) => {
-                    write!(output, "+",).expect("write to Vec is infallible");
-                    do_write_line(&mut output, &r, params.expand_tabs, params.tabsize)
-                        .expect("write to Vec is infallible");
-                    writeln!(output).unwrap();
+                    write_marked_line(output, added_marker, &r, Some(ColorClass::Added), params)?;
                 }
                 DiffLine::MissingNL => {
-                    writeln!(output, r"\ No newline at end of file")
-                        .expect("write to Vec is infallible");
+                    writeln!(output, r"\ No newline at end of file")?;
                 }
             }
         }
     }
-    output
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hunks::{self, CompareOptions};
     use pretty_assertions::assert_eq;
 
+    fn diff(expected: &[u8], actual: &[u8], params: &Params) -> Vec<u8> {
+        let mut output = Vec::new();
+        let diff = hunks::compare(expected, actual, &CompareOptions::from(params));
+        super::diff(&mut output, &diff, params).unwrap();
+        output
+    }
+
     #[test]
     fn test_permutations() {
         let target = "target/unified-diff/";
@@ -873,6 +729,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_hunk_merging() {
+        use crate::assert_diff_eq;
+
+        let from = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let to = "a\nB\nc\nd\ne\nf\ng\nH\ni\nj\n";
+        let params = Params {
+            from: "foo".into(),
+            to: "bar".into(),
+            context_count: 3,
+            ..Default::default()
+        };
+        // The changes are 5 lines apart, so their contexts overlap
+        let diff_merged = diff(from.as_bytes(), to.as_bytes(), &params);
+        let expected_merged = [
+            "--- foo\tTIMESTAMP",
+            "+++ bar\tTIMESTAMP",
+            "@@ -1,10 +1,10 @@",
+            " a",
+            "-b",
+            "+B",
+            " c",
+            " d",
+            " e",
+            " f",
+            " g",
+            "-h",
+            "+H",
+            " i",
+            " j",
+            "",
+        ]
+        .join("\n");
+        assert_diff_eq!(diff_merged, expected_merged);
+
+        let params = Params {
+            context_count: 2,
+            ..params
+        };
+        let diff_split = diff(from.as_bytes(), to.as_bytes(), &params);
+        let expected_split = [
+            "--- foo\tTIMESTAMP",
+            "+++ bar\tTIMESTAMP",
+            "@@ -1,4 +1,4 @@",
+            " a",
+            "-b",
+            "+B",
+            " c",
+            " d",
+            "@@ -6,5 +6,5 @@",
+            " f",
+            " g",
+            "-h",
+            "+H",
+            " i",
+            " j",
+            "",
+        ]
+        .join("\n");
+        assert_diff_eq!(diff_split, expected_split);
+    }
+
+    #[test]
+    fn test_zero_context() {
+        use crate::assert_diff_eq;
+
+        // Changes one line apart aren't merged, and the line counts of one
+        // line are left out of the hunk headers
+        let diff = diff(
+            b"a\nb\nc\nd\ne\nf\n",
+            b"a\nB\nc\nx\nd\nf",
+            &Params {
+                from: "foo".into(),
+                to: "bar".into(),
+                context_count: 0,
+                ..Default::default()
+            },
+        );
+        let expected = [
+            "--- foo\tTIMESTAMP",
+            "+++ bar\tTIMESTAMP",
+            "@@ -2 +2 @@",
+            "-b",
+            "+B",
+            "@@ -3,0 +4 @@",
+            "+x",
+            "@@ -5,2 +6 @@",
+            "-e",
+            "-f",
+            "+f",
+            "\\ No newline at end of file",
+            "",
+        ]
+        .join("\n");
+        assert_diff_eq!(diff, expected);
+    }
+
+    #[test]
+    fn test_removed_lines_come_first() {
+        use crate::assert_diff_eq;
+
+        let diff = diff(
+            b"a\nb\nc\n",
+            b"x\ny\nc",
+            &Params {
+                from: "foo".into(),
+                to: "bar".into(),
+                ..Default::default()
+            },
+        );
+        let expected = [
+            "--- foo\tTIMESTAMP",
+            "+++ bar\tTIMESTAMP",
+            "@@ -1,3 +1,3 @@",
+            "-a",
+            "-b",
+            "-c",
+            "+x",
+            "+y",
+            "+c",
+            "\\ No newline at end of file",
+            "",
+        ]
+        .join("\n");
+        assert_diff_eq!(diff, expected);
+    }
+
     #[test]
     fn test_stop_early() {
         use crate::assert_diff_eq;
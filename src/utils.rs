@@ -3,9 +3,15 @@
 // For the full copyright and license information, please view the LICENSE-*
 // files that was distributed with this source code.
 
-use std::{ffi::OsString, io::Write};
+use std::{borrow::Cow, ffi::OsString, io::Write};
 
 use regex::Regex;
+
+use crate::chunked;
+use crate::hunks::CompareOptions;
+use crate::palette::ColorClass;
+use crate::params::{Normalization, Params};
+use unicode_normalization::UnicodeNormalization;
 use unicode_width::UnicodeWidthStr;
 
 /// Replace tabs by spaces in the input line.
@@ -40,8 +46,8 @@ pub fn do_expand_tabs(line: &[u8], tabsize: usize) -> Vec<u8> {
 /// Write a single line to an output stream, expanding tabs to space if necessary.
 /// This assumes that line does not contain any line breaks
 /// (if it does and tabs are to be expanded to spaces, the result is undefined).
-pub fn do_write_line(
-    output: &mut Vec<u8>,
+pub fn do_write_line<W: Write + ?Sized>(
+    output: &mut W,
     line: &[u8],
     expand_tabs: bool,
     tabsize: usize,
@@ -53,23 +59,738 @@ pub fn do_write_line(
     }
 }
 
-/// Retrieves the modification time of the input file specified by file path
-/// If an error occurs, it returns the current system time
-pub fn get_modification_time(file_path: &str) -> String {
+/// What a line is compared by, once the differences that don't matter
+/// (--ignore-ansi, --normalize, -E, -w) have been removed.
+fn comparison_key<'a>(line: &'a [u8], options: &CompareOptions) -> Cow<'a, [u8]> {
+    let mut key = Cow::Borrowed(line);
+    if options.ignore_ansi {
+        key = strip_ansi_escapes(line);
+    }
+    if let Some(form) = options.normalize {
+        if let Some(normalized) = normalize(&key, form) {
+            key = Cow::Owned(normalized);
+        }
+    }
+    if options.ignore_tab_expansion && key.contains(&b'\t') {
+        key = Cow::Owned(do_expand_tabs(&key, options.tabsize));
+    }
+    if options.ignore_all_space {
+        if key.iter().any(|&c| is_blank(c)) {
+            key = Cow::Owned(key.iter().copied().filter(|&c| !is_blank(c)).collect());
+        }
+        // A last line that is missing its newline is the same as with one.
+        if key.ends_with(b"\n") {
+            key = match key {
+                Cow::Borrowed(key) => Cow::Borrowed(&key[..key.len() - 1]),
+                Cow::Owned(mut key) => {
+                    key.pop();
+                    Cow::Owned(key)
+                }
+            };
+        }
+    }
+    key
+}
+
+/// Puts a line in a Unicode normalization form, for --normalize. Lines that
+/// aren't valid UTF-8, or that are already normalized, give None.
+fn normalize(line: &[u8], form: Normalization) -> Option<Vec<u8>> {
+    if line.is_ascii() {
+        return None;
+    }
+    let text = std::str::from_utf8(line).ok()?;
+    let normalized: String = match form {
+        Normalization::Nfc => text.nfc().collect(),
+        Normalization::Nfd => text.nfd().collect(),
+    };
+    (normalized != text).then(|| normalized.into_bytes())
+}
+
+/// A line compared with --numeric-fuzz: the text around its numbers must be
+/// the same, and its numbers must be within the tolerance of each other.
+struct FuzzyLine<'a> {
+    texts: Vec<Cow<'a, [u8]>>,
+    numbers: Vec<f64>,
+    tolerance: f64,
+}
+
+impl<'a> FuzzyLine<'a> {
+    fn new(
+        line: &'a [u8],
+        options: &CompareOptions,
+        numbers: &regex::bytes::Regex,
+        tolerance: f64,
+    ) -> Self {
+        let mut texts = Vec::new();
+        let mut values = Vec::new();
+        let mut start = 0;
+        for number in numbers.find_iter(line) {
+            // The numbers are made of ASCII characters.
+            let value = std::str::from_utf8(number.as_bytes()).map(str::parse::<f64>);
+            if let Ok(Ok(value)) = value {
+                texts.push(comparison_key(&line[start..number.start()], options));
+                values.push(value);
+                start = number.end();
+            }
+        }
+        texts.push(comparison_key(&line[start..], options));
+        Self {
+            texts,
+            numbers: values,
+            tolerance,
+        }
+    }
+}
+
+impl PartialEq for FuzzyLine<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        // The tolerance is relative to the numbers larger than one.
+        let close =
+            |a: f64, b: f64| (a - b).abs() <= self.tolerance * a.abs().max(b.abs()).max(1.0);
+        self.texts == other.texts
+            && self.numbers.len() == other.numbers.len()
+            && self
+                .numbers
+                .iter()
+                .zip(&other.numbers)
+                .all(|(&a, &b)| close(a, b))
+    }
+}
+
+// The equality isn't transitive: two lines can each be close to a third one
+// without being close to each other. That's enough for the comparisons, which
+// only ever compare a line of one file with a line of the other, and for the
+// hash, which covers the texts that are compared exactly.
+impl Eq for FuzzyLine<'_> {}
+
+impl std::hash::Hash for FuzzyLine<'_> {
+    // Lines that compare equal have the same text, whatever their numbers.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.texts.hash(state);
+    }
+}
+
+// Diffs two lists of lines by their keys, with results that refer to the lines.
+fn diff_by_keys<'a, K: Eq + std::hash::Hash>(
+    expected: &[&'a [u8]],
+    actual: &[&'a [u8]],
+    expected_keys: &[K],
+    actual_keys: &[K],
+    minimal: bool,
+) -> Vec<diff::Result<&'a [u8]>> {
+    let (mut e, mut a) = (0, 0);
+    chunked::diff(expected_keys, actual_keys, minimal)
+        .into_iter()
+        .map(|result| match result {
+            diff::Result::Left(_) => {
+                e += 1;
+                diff::Result::Left(expected[e - 1])
+            }
+            diff::Result::Right(_) => {
+                a += 1;
+                diff::Result::Right(actual[a - 1])
+            }
+            diff::Result::Both(_, _) => {
+                e += 1;
+                a += 1;
+                diff::Result::Both(expected[e - 1], actual[a - 1])
+            }
+        })
+        .collect()
+}
+
+// White space other than the newline that ends a line.
+fn is_blank(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\r' | b'\x0b' | b'\x0c')
+}
+
+/// Diffs two lists of lines, comparing them according to the options that
+/// make some differences insignificant. The results refer to the original
+/// lines, so that they are output unchanged.
+#[must_use]
+pub fn diff_lines<'a>(
+    expected: &[&'a [u8]],
+    actual: &[&'a [u8]],
+    options: &CompareOptions,
+) -> Vec<diff::Result<&'a [u8]>> {
+    if let Some(tolerance) = options.numeric_fuzz {
+        let tolerance = tolerance.value();
+        let numbers = regex::bytes::Regex::new(NUMBER).unwrap();
+        let key = |line| FuzzyLine::new(line, options, &numbers, tolerance);
+        let expected_keys: Vec<_> = expected.iter().copied().map(key).collect();
+        let actual_keys: Vec<_> = actual.iter().copied().map(key).collect();
+        return diff_by_keys(
+            expected,
+            actual,
+            &expected_keys,
+            &actual_keys,
+            options.minimal,
+        );
+    }
+    let expected_keys: Vec<_> = expected
+        .iter()
+        .map(|l| comparison_key(l, options))
+        .collect();
+    let actual_keys: Vec<_> = actual.iter().map(|l| comparison_key(l, options)).collect();
+    diff_by_keys(
+        expected,
+        actual,
+        &expected_keys,
+        &actual_keys,
+        options.minimal,
+    )
+}
+
+// The decimal numbers that --numeric-fuzz compares with a tolerance.
+const NUMBER: &str = r"[-+]?(?:[0-9]+\.?[0-9]*|\.[0-9]+)(?:[eE][-+]?[0-9]+)?";
+
+/// Starts coloring the output of a class with --color, if there's a class.
+pub fn start_color(
+    output: &mut impl Write,
+    class: Option<ColorClass>,
+    params: &Params,
+) -> std::io::Result<()> {
+    match (params.color, class) {
+        (true, Some(class)) => output.write_all(params.palette.start(class).as_bytes()),
+        _ => Ok(()),
+    }
+}
+
+/// Stops coloring the output started with `start_color`.
+pub fn end_color(
+    output: &mut impl Write,
+    class: Option<ColorClass>,
+    params: &Params,
+) -> std::io::Result<()> {
+    if params.color && class.is_some() {
+        output.write_all(params.palette.end().as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Write a line of output, colored with --color.
+pub fn write_colored_line(
+    output: &mut impl Write,
+    class: ColorClass,
+    text: std::fmt::Arguments,
+    params: &Params,
+) -> std::io::Result<()> {
+    start_color(output, Some(class), params)?;
+    output.write_fmt(text)?;
+    end_color(output, Some(class), params)?;
+    writeln!(output)
+}
+
+/// Write a line of output preceded by its marker, expanding tabs if necessary.
+/// With --suppress-blank-empty, empty lines don't get the blanks that
+/// separate the marker from the line.
+pub fn write_marked_line(
+    output: &mut impl Write,
+    marker: &str,
+    line: &[u8],
+    class: Option<ColorClass>,
+    params: &Params,
+) -> std::io::Result<()> {
+    let marker = if line.is_empty() && params.suppress_blank_empty {
+        marker.trim_end()
+    } else {
+        marker
+    };
+    start_color(output, class, params)?;
+    output.write_all(marker.as_bytes())?;
+    do_write_line(output, line, params.expand_tabs, params.tabsize)?;
+    end_color(output, class, params)?;
+    writeln!(output)
+}
+
+/// The format of the timestamps in the headers of unified diffs.
+pub const UNIFIED_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.9f %z";
+
+/// The format of the timestamps in the headers of context diffs, as ctime(3).
+pub const CONTEXT_TIME_FORMAT: &str = "%a %b %e %H:%M:%S %Y";
+
+/// Retrieves the modification time of the input file specified by file path,
+/// in the given format.
+/// Like GNU diff, standard input is stamped with the current time, and a file
+/// that doesn't exist (compared as empty with -N) with the epoch.
+#[must_use]
+pub fn get_modification_time(file_path: &str, format: &str) -> String {
     use chrono::{DateTime, Local};
     use std::fs;
     use std::time::SystemTime;
 
-    let modification_time: SystemTime = fs::metadata(file_path)
-        .and_then(|m| m.modified())
-        .unwrap_or(SystemTime::now());
+    let modification_time: SystemTime = if file_path == "-" {
+        SystemTime::now()
+    } else {
+        fs::metadata(file_path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    };
 
     let modification_time: DateTime<Local> = modification_time.into();
-    let modification_time: String = modification_time
-        .format("%Y-%m-%d %H:%M:%S%.9f %z")
-        .to_string();
+    modification_time.format(format).to_string()
+}
+
+/// Translates a shell wildcard pattern, as understood by fnmatch(3), into an
+/// equivalent regular expression (without anchors).
+#[must_use]
+pub fn wildcard_to_regex(pattern: &str) -> String {
+    translate_wildcard(pattern, false)
+}
+
+/// Translates a wildcard pattern of a path into a regular expression
+/// (without anchors), where wildcards don't match slashes, but `**` matches
+/// any number of directories when it is a whole component of the path, like
+/// in .gitignore files.
+#[must_use]
+pub fn path_wildcard_to_regex(pattern: &str) -> String {
+    translate_wildcard(pattern, true)
+}
+
+fn translate_wildcard(pattern: &str, pathname: bool) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if pathname
+                && chars.get(i + 1) == Some(&'*')
+                && (i == 0 || chars[i - 1] == '/') =>
+            {
+                match chars.get(i + 2) {
+                    None => {
+                        regex.push_str(".*");
+                        i += 1;
+                    }
+                    Some('/') => {
+                        regex.push_str("(?:.*/)?");
+                        i += 2;
+                    }
+                    _ => regex.push_str("[^/]*"),
+                }
+            }
+            '*' if pathname => regex.push_str("[^/]*"),
+            '?' if pathname => regex.push_str("[^/]"),
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '[' => match bracket_to_regex(&chars[i + 1..], true) {
+                Some((class, len)) => {
+                    regex.push_str(&class);
+                    i += len;
+                }
+                None => regex.push_str(r"\["),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+// Translates the bracket expression that chars starts with (right after the
+// opening bracket), returning the character class and the number of
+// characters it spans, or None if the bracket isn't closed.
+// Unlike in regular expressions, in wildcards (fnmatch) '!' negates the
+// expression too, and backslashes escape the next character.
+fn bracket_to_regex(chars: &[char], fnmatch: bool) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut i = 0;
+    if chars.first() == Some(&'^') || fnmatch && chars.first() == Some(&'!') {
+        class.push('^');
+        i = 1;
+    }
+    let start = i;
+    loop {
+        match *chars.get(i)? {
+            ']' if i > start => {
+                class.push(']');
+                return Some((class, i + 1));
+            }
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == ':' && chars[j + 1] == ']')?;
+                class.extend(&chars[i..end + 2]);
+                i = end + 1;
+            }
+            '-' if i > start && chars.get(i + 1) != Some(&']') => class.push('-'),
+            '\\' if fnmatch && i + 1 < chars.len() => {
+                i += 1;
+                class.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            c => class.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+}
+
+/// Translates a POSIX basic regular expression, with the GNU extensions
+/// that grep and diff understand, into the syntax of the regex crate.
+pub fn basic_regex_to_regex(pattern: &str) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut depth = 0;
+    // Whether an expression starts here, where '^' is an anchor and '*' is
+    // an ordinary character.
+    let mut at_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let start = std::mem::replace(&mut at_start, false);
+        match chars[i] {
+            '^' if start => {
+                regex.push('^');
+                at_start = true;
+            }
+            '$' if matches!(chars.get(i + 1..), Some([] | ['\\', ')' | '|', ..])) => {
+                regex.push('$');
+            }
+            '*' if !start => regex.push('*'),
+            '.' => regex.push('.'),
+            '[' => match bracket_to_regex(&chars[i + 1..], false) {
+                Some((class, len)) => {
+                    regex.push_str(&class);
+                    i += len;
+                }
+                None => return Err("Unmatched [, [^, [:, [., or [=".to_string()),
+            },
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    None => return Err("Trailing backslash".to_string()),
+                    Some('(') => {
+                        depth += 1;
+                        regex.push('(');
+                        at_start = true;
+                    }
+                    Some(')') if depth > 0 => {
+                        depth -= 1;
+                        regex.push(')');
+                    }
+                    Some(')') => return Err("Unmatched ) or \\)".to_string()),
+                    Some('|') => {
+                        regex.push('|');
+                        at_start = true;
+                    }
+                    Some('{') if !start => {
+                        let end = (i + 1..chars.len().saturating_sub(1))
+                            .find(|&j| chars[j] == '\\' && chars[j + 1] == '}')
+                            .ok_or("Unmatched \\{")?;
+                        let bounds: String = chars[i + 1..end].iter().collect();
+                        let valid = bounds.split_once(',').map_or(
+                            !bounds.is_empty() && bounds.bytes().all(|b| b.is_ascii_digit()),
+                            |(min, max)| {
+                                !min.is_empty()
+                                    && min.bytes().all(|b| b.is_ascii_digit())
+                                    && max.bytes().all(|b| b.is_ascii_digit())
+                            },
+                        );
+                        if !valid {
+                            return Err("Invalid content of \\{\\}".to_string());
+                        }
+                        regex.push_str(&format!("{{{bounds}}}"));
+                        i = end + 1;
+                    }
+                    Some(&c @ ('+' | '?')) if !start => regex.push(c),
+                    Some(&c @ ('w' | 'W' | 's' | 'S' | 'b' | 'B')) => {
+                        regex.push('\\');
+                        regex.push(c);
+                    }
+                    Some('<') => regex.push_str(r"\b{start}"),
+                    Some('>') => regex.push_str(r"\b{end}"),
+                    Some('`') => regex.push_str(r"\A"),
+                    Some('\'') => regex.push_str(r"\z"),
+                    Some('1'..='9') => return Err("Back references are not supported".to_string()),
+                    Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    if depth > 0 {
+        return Err("Unmatched ( or \\(".to_string());
+    }
+    Ok(regex)
+}
+
+/// A basic regular expression given to -I/--ignore-matching-lines, compiled
+/// once so that an invalid one is rejected when it is given rather than when
+/// files are compared.
+#[derive(Clone, Debug)]
+pub struct LinePattern {
+    pattern: String,
+    regex: regex::bytes::Regex,
+}
+
+impl LinePattern {
+    /// Compiles `pattern`, returning the message GNU diff gives for it if it
+    /// isn't a valid basic regular expression.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = regex::bytes::Regex::new(&basic_regex_to_regex(pattern)?).map_err(|error| {
+            match error {
+                regex::Error::CompiledTooBig(_) => "Regular expression too big",
+                _ => "Invalid regular expression",
+            }
+            .to_string()
+        })?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// Whether the line, without its newline, matches.
+    #[must_use]
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.regex.is_match(line)
+    }
+}
+
+impl PartialEq for LinePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for LinePattern {}
+
+/// The lines that -I/--ignore-matching-lines makes ignorable: a change is
+/// not output when all the lines it deletes and inserts match one of the
+/// patterns.
+pub struct IgnoredLines<'a> {
+    patterns: &'a [LinePattern],
+}
+
+impl<'a> IgnoredLines<'a> {
+    #[must_use]
+    pub fn new(options: &'a CompareOptions) -> Self {
+        Self {
+            patterns: &options.ignore_matching_lines,
+        }
+    }
+
+    /// Whether a change made of the given lines (with or without their
+    /// newline) is ignored.
+    pub fn ignores<'b>(&self, mut lines: impl Iterator<Item = &'b [u8]>) -> bool {
+        !self.patterns.is_empty()
+            && lines.all(|line| {
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                self.patterns.iter().any(|pattern| pattern.is_match(line))
+            })
+    }
+}
+
+/// Describes the first or second file in the headers of unified and context
+/// diffs: its label if one was given, otherwise its name followed by its
+/// modification time in the given format.
+/// With --git, the name comes alone, and is /dev/null for an absent file.
+#[must_use]
+pub fn file_header(params: &Params, from_side: bool, time_format: &str) -> String {
+    let (path, label) = if from_side {
+        (&params.from, &params.from_label)
+    } else {
+        (&params.to, &params.to_label)
+    };
+    match label {
+        Some(label) => label.clone(),
+        None if params.git => {
+            if path != "-" && std::fs::metadata(path).is_err() {
+                "/dev/null".to_string()
+            } else {
+                params.reported_name(from_side)
+            }
+        }
+        None => format!(
+            "{}\t{}",
+            params.reported_name(from_side),
+            get_modification_time(&path.to_string_lossy(), time_format)
+        ),
+    }
+}
+
+/// Tells how many hunks --max-hunks left out, after the ones that are output.
+pub fn write_suppressed_hunks(output: &mut impl Write, suppressed: usize) -> std::io::Result<()> {
+    match suppressed {
+        0 => Ok(()),
+        1 => writeln!(output, "... 1 more hunk suppressed"),
+        _ => writeln!(output, "... {suppressed} more hunks suppressed"),
+    }
+}
 
-    modification_time
+/// Like GNU diff, consider a file binary if a null byte appears in its first
+/// block, unless -a/--text is given.
+#[must_use]
+pub fn is_binary(content: &[u8]) -> bool {
+    const BLOCK_SIZE: usize = 4096;
+    content.iter().take(BLOCK_SIZE).any(|&b| b == 0)
+}
+
+/// The command that decompresses content compressed with gzip, bzip2, xz or
+/// zstd, recognized by its magic bytes, for --decompress.
+#[must_use]
+pub fn decompressor(content: &[u8]) -> Option<&'static str> {
+    const FORMATS: [(&[u8], &str); 4] = [
+        (b"\x1f\x8b", "gzip -dc"),
+        (b"BZh", "bzip2 -dc"),
+        (b"\xfd7zXZ\x00", "xz -dc"),
+        (b"\x28\xb5\x2f\xfd", "zstd -dc"),
+    ];
+    FORMATS
+        .iter()
+        .find(|(magic, _)| content.starts_with(magic))
+        .map(|&(_, command)| command)
+}
+
+/// Removes the escape sequences that control terminals (CSI sequences, which
+/// include the SGR sequences that set colors), for --ignore-ansi and
+/// --strip-ansi. Sequences that aren't terminated are kept.
+#[must_use]
+pub fn strip_ansi_escapes(text: &[u8]) -> Cow<'_, [u8]> {
+    // ESC '[', parameter bytes, intermediate bytes, and a final byte.
+    let sequence_len = |rest: &[u8]| {
+        let rest = rest.strip_prefix(b"\x1b[")?;
+        let parameters = rest
+            .iter()
+            .take_while(|c| (0x30..=0x3f).contains(*c))
+            .count();
+        let intermediates = rest[parameters..]
+            .iter()
+            .take_while(|c| (0x20..=0x2f).contains(*c))
+            .count();
+        let end = parameters + intermediates;
+        rest.get(end)
+            .filter(|c| (0x40..=0x7e).contains(*c))
+            .map(|_| end + 3)
+    };
+    if !text.contains(&0x1b) {
+        return Cow::Borrowed(text);
+    }
+    let mut stripped = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        match sequence_len(&text[i..]) {
+            Some(len) => i += len,
+            None => {
+                stripped.push(text[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+/// Removes the carriage return at the end of each line, for --strip-trailing-cr.
+/// A carriage return at the end of an incomplete last line is kept.
+#[must_use]
+pub fn strip_trailing_cr(content: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(content.len());
+    for line in content.split_inclusive(|&c| c == b'\n') {
+        match line.strip_suffix(b"\r\n") {
+            Some(line) => {
+                stripped.extend_from_slice(line);
+                stripped.push(b'\n');
+            }
+            None => stripped.extend_from_slice(line),
+        }
+    }
+    stripped
+}
+
+/// Parses a number of bytes like GNU cmp does: in decimal, in hexadecimal
+/// after 0x, or in octal after 0, optionally followed by a multiplicative
+/// suffix: kB is 1000, K, k and KiB are 1024, and so on for M, G, T, P, E, Z
+/// and Y. Numbers that don't fit saturate to usize::MAX.
+#[must_use]
+pub fn parse_byte_quantity(value: &str) -> Option<usize> {
+    let (digits, radix) = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(digits) => (digits, 16),
+        None if value.starts_with('0') => (value, 8),
+        None => (value, 10),
+    };
+    let suffix_start = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let num = match usize::from_str_radix(&digits[..suffix_start], radix) {
+        Ok(num) => num,
+        Err(e) if *e.kind() == std::num::IntErrorKind::PosOverflow => usize::MAX,
+        Err(_) => return None,
+    };
+
+    let mut suffix = digits[suffix_start..].chars();
+    let power = match suffix.next() {
+        None => return Some(num),
+        Some('k') => 1,
+        Some(c) => "KMGTPEZY".find(c)? as u32 + 1,
+    };
+    let base: usize = match suffix.as_str() {
+        "" | "iB" => 1024,
+        "B" => 1000,
+        _ => return None,
+    };
+    Some(
+        base.checked_pow(power)
+            .and_then(|multiplier| num.checked_mul(multiplier))
+            .unwrap_or(usize::MAX),
+    )
+}
+
+/// Quotes an argument the way a shell would need it, if it needs it.
+#[must_use]
+pub fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "#%+,-./:@_~".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Makes a path out of bytes read from a file, which only Unix allows in
+/// any encoding.
+#[must_use]
+pub fn path_from_bytes(bytes: &[u8]) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::ffi::OsStr::from_bytes(bytes).to_os_string()
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Whether two paths name the same file. On Unix this only looks at their
+/// metadata: opening them would take what writers send to FIFOs.
+#[must_use]
+pub fn is_same_file(a: &OsString, b: &OsString) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        same_file::is_same_file(a, b).unwrap_or(false)
+    }
+}
+
+/// The description of an error, without the code that std::io::Error's
+/// display trait puts after it, in "{detail} (os error {code})".
+pub fn error_message(error: &std::io::Error) -> String {
+    let error_code_re = Regex::new(r"\ \(os\ error\ \d+\)$").unwrap();
+    error_code_re
+        .replace(error.to_string().as_str(), "")
+        .into_owned()
 }
 
 pub fn format_failure_to_read_input_file(
@@ -77,14 +798,11 @@ pub fn format_failure_to_read_input_file(
     filepath: &OsString,
     error: &std::io::Error,
 ) -> String {
-    // std::io::Error's display trait outputs "{detail} (os error {code})"
-    // but we want only the {detail} (error string) part
-    let error_code_re = Regex::new(r"\ \(os\ error\ \d+\)$").unwrap();
     format!(
         "{}: {}: {}",
         executable.to_string_lossy(),
         filepath.to_string_lossy(),
-        error_code_re.replace(error.to_string().as_str(), ""),
+        error_message(error),
     )
 }
 
@@ -99,9 +817,48 @@ pub fn report_failure_to_read_input_file(
     );
 }
 
+/// Replaces the `@FILE` arguments that come before `--` with the arguments
+/// listed in FILE, one per line, so that long lists of operands don't run
+/// into the limits of command lines. The first argument, the executable, is
+/// left as it is, and so are the arguments read from files.
+pub fn expand_response_files(args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let mut args = args.into_iter();
+    let Some(executable) = args.next() else {
+        return Ok(Vec::new());
+    };
+    let mut expanded = vec![executable.clone()];
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            expanded.push(arg);
+            expanded.extend(args);
+            break;
+        }
+        let file = match arg.as_encoded_bytes().strip_prefix(b"@") {
+            Some(file) if !file.is_empty() => path_from_bytes(file),
+            _ => {
+                expanded.push(arg);
+                continue;
+            }
+        };
+        let contents = std::fs::read(&file)
+            .map_err(|e| format_failure_to_read_input_file(&executable, &file, &e))?;
+        // The arguments are kept as bytes, like the paths of --batch, and
+        // the lines can end with CR LF.
+        expanded.extend(
+            contents
+                .split(|&c| c == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                .filter(|line| !line.is_empty())
+                .map(path_from_bytes),
+        );
+    }
+    Ok(expanded)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::params::Tolerance;
 
     mod expand_tabs {
         use super::*;
@@ -164,6 +921,284 @@ mod tests {
         }
     }
 
+    mod wildcards {
+        use super::*;
+
+        fn matches(pattern: &str, name: &str) -> bool {
+            Regex::new(&format!("^(?:{})$", wildcard_to_regex(pattern)))
+                .unwrap()
+                .is_match(name)
+        }
+
+        #[test]
+        fn basics() {
+            assert!(matches("*.o", "foo.o"));
+            assert!(matches("*.o", ".o"));
+            assert!(!matches("*.o", "foo.c"));
+            assert!(matches("target", "target"));
+            assert!(!matches("target", "targets"));
+            assert!(matches("a?c", "abc"));
+            assert!(!matches("a?c", "ac"));
+            assert!(matches("a.c", "a.c"));
+            assert!(!matches("a.c", "abc"));
+            assert!(matches(r"\*", "*"));
+            assert!(!matches(r"\*", "a"));
+        }
+
+        #[test]
+        fn brackets() {
+            assert!(matches("[ab]c", "bc"));
+            assert!(!matches("[ab]c", "cc"));
+            assert!(matches("[!ab]c", "cc"));
+            assert!(matches("[^ab]c", "cc"));
+            assert!(matches("[a-c]", "b"));
+            assert!(matches("[]a]", "]"));
+            assert!(matches("[a-]", "-"));
+            assert!(matches("[[:digit:]]x", "7x"));
+            assert!(matches("[&~]", "~"));
+            assert!(matches("[ab", "[ab"));
+        }
+
+        #[test]
+        fn paths() {
+            let matches = |pattern: &str, path: &str| {
+                Regex::new(&format!("^(?:{})$", path_wildcard_to_regex(pattern)))
+                    .unwrap()
+                    .is_match(path)
+            };
+            assert!(matches("*.o", "foo.o"));
+            assert!(!matches("*.o", "dir/foo.o"));
+            assert!(!matches("a?b", "a/b"));
+            assert!(matches("**/foo", "foo"));
+            assert!(matches("**/foo", "a/b/foo"));
+            assert!(matches("a/**/b", "a/b"));
+            assert!(matches("a/**/b", "a/x/y/b"));
+            assert!(!matches("a/**/b", "ab"));
+            assert!(matches("a/**", "a/x/y"));
+            assert!(!matches("a/**", "a"));
+            assert!(matches("a**b", "axxb"));
+            assert!(!matches("a**b", "a/b"));
+        }
+    }
+
+    #[test]
+    fn lines_compared() {
+        let expected: Vec<&[u8]> = vec![b"a\tb\n", b"c\n"];
+        let actual: Vec<&[u8]> = vec![b"a       b\n", b"c\n"];
+        let options = CompareOptions::default();
+        assert_eq!(
+            diff_lines(&expected, &actual, &options),
+            vec![
+                diff::Result::Left(&b"a\tb\n"[..]),
+                diff::Result::Right(&b"a       b\n"[..]),
+                diff::Result::Both(&b"c\n"[..], &b"c\n"[..]),
+            ]
+        );
+        let options = CompareOptions {
+            ignore_tab_expansion: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff_lines(&expected, &actual, &options),
+            vec![
+                diff::Result::Both(&b"a\tb\n"[..], &b"a       b\n"[..]),
+                diff::Result::Both(&b"c\n"[..], &b"c\n"[..]),
+            ]
+        );
+        let actual: Vec<&[u8]> = vec![b" ab\r\n", b"c \n"];
+        let options = CompareOptions {
+            ignore_all_space: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff_lines(&expected, &actual, &options),
+            vec![
+                diff::Result::Both(&b"a\tb\n"[..], &b" ab\r\n"[..]),
+                diff::Result::Both(&b"c\n"[..], &b"c \n"[..]),
+            ]
+        );
+        // Nor does the newline missing at the end of a file.
+        let actual: Vec<&[u8]> = vec![b"a b\n", b"c"];
+        assert_eq!(
+            diff_lines(&expected, &actual, &options),
+            vec![
+                diff::Result::Both(&b"a\tb\n"[..], &b"a b\n"[..]),
+                diff::Result::Both(&b"c\n"[..], &b"c"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_fuzz() {
+        let expected: Vec<&[u8]> = vec![b"t=1.0001 x=-2e3\n", b"n 10\n", b"v 1\n"];
+        let actual: Vec<&[u8]> = vec![b"t=1.0002 x=-2000.5\n", b"n 12\n", b"v  1.00\n"];
+        let options = CompareOptions {
+            numeric_fuzz: Tolerance::parse("0.001"),
+            ..Default::default()
+        };
+        assert_eq!(
+            diff_lines(&expected, &actual, &options),
+            vec![
+                diff::Result::Both(expected[0], actual[0]),
+                diff::Result::Left(expected[1]),
+                diff::Result::Left(expected[2]),
+                diff::Result::Right(actual[1]),
+                diff::Result::Right(actual[2]),
+            ]
+        );
+        // The text around the numbers is compared like without the option.
+        let options = CompareOptions {
+            ignore_all_space: true,
+            ..options
+        };
+        assert_eq!(
+            diff_lines(&expected[2..], &actual[2..], &options),
+            vec![diff::Result::Both(expected[2], actual[2])]
+        );
+    }
+
+    #[test]
+    fn unicode_normalization() {
+        // "é" composed, and decomposed as "e" and a combining acute accent.
+        let expected: Vec<&[u8]> = vec![b"caf\xc3\xa9\n", b"\xff\n"];
+        let actual: Vec<&[u8]> = vec![b"cafe\xcc\x81\n", b"\xff\n"];
+        for form in [Normalization::Nfc, Normalization::Nfd] {
+            let options = CompareOptions {
+                normalize: Some(form),
+                ..Default::default()
+            };
+            assert_eq!(
+                diff_lines(&expected, &actual, &options),
+                vec![
+                    diff::Result::Both(expected[0], actual[0]),
+                    diff::Result::Both(expected[1], actual[1]),
+                ]
+            );
+        }
+        assert_eq!(
+            diff_lines(&expected[..1], &actual[..1], &CompareOptions::default()),
+            vec![
+                diff::Result::Left(expected[0]),
+                diff::Result::Right(actual[0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary("a\nb\u{e9}\n".as_bytes()));
+        assert!(is_binary(b"a\0b\n"));
+        let mut content = vec![b'x'; 4096];
+        content.push(0);
+        assert!(!is_binary(&content));
+    }
+
+    #[test]
+    fn compressed() {
+        assert_eq!(decompressor(b"\x1f\x8b\x08\x00"), Some("gzip -dc"));
+        assert_eq!(decompressor(b"BZh91AY&SY"), Some("bzip2 -dc"));
+        assert_eq!(decompressor(b"\xfd7zXZ\x00\x00"), Some("xz -dc"));
+        assert_eq!(decompressor(b"\x28\xb5\x2f\xfd\x24"), Some("zstd -dc"));
+        assert_eq!(decompressor(b"BZ"), None);
+        assert_eq!(decompressor(b"plain text\n"), None);
+    }
+
+    #[test]
+    fn ansi_escapes() {
+        assert_eq!(strip_ansi_escapes(b"plain\n"), &b"plain\n"[..]);
+        assert_eq!(
+            strip_ansi_escapes(b"\x1b[1;31merror\x1b[0m: \x1b[Kx\x1b[?25h\n"),
+            &b"error: x\n"[..]
+        );
+        // Sequences that aren't terminated, and other escapes, are kept.
+        assert_eq!(strip_ansi_escapes(b"\x1b[31\n"), &b"\x1b[31\n"[..]);
+        assert_eq!(strip_ansi_escapes(b"\x1b]0;t\x07"), &b"\x1b]0;t\x07"[..]);
+    }
+
+    #[test]
+    fn byte_quantities() {
+        assert_eq!(parse_byte_quantity("0"), Some(0));
+        assert_eq!(parse_byte_quantity("12"), Some(12));
+        assert_eq!(parse_byte_quantity("2kB"), Some(2_000));
+        assert_eq!(parse_byte_quantity("2K"), Some(2_048));
+        assert_eq!(parse_byte_quantity("2k"), Some(2_048));
+        assert_eq!(parse_byte_quantity("2KiB"), Some(2_048));
+        assert_eq!(parse_byte_quantity("1MB"), Some(1_000_000));
+        assert_eq!(parse_byte_quantity("1G"), Some(1_073_741_824));
+        assert_eq!(parse_byte_quantity("0x10"), Some(16));
+        assert_eq!(parse_byte_quantity("0XfFK"), Some(255 * 1024));
+        assert_eq!(parse_byte_quantity("010"), Some(8));
+        assert_eq!(parse_byte_quantity("0k"), Some(0));
+        assert_eq!(parse_byte_quantity("1Y"), Some(usize::MAX));
+        assert_eq!(
+            parse_byte_quantity("99999999999999999999999999999"),
+            Some(usize::MAX)
+        );
+        for value in ["", "K", "0x", "-1", "1.5", "1m", "1KB2", "1Q", "0xg", "08"] {
+            assert_eq!(parse_byte_quantity(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn trailing_cr() {
+        assert_eq!(strip_trailing_cr(b""), b"");
+        assert_eq!(strip_trailing_cr(b"a\r\nb\n\r\n"), b"a\nb\n\n");
+        assert_eq!(strip_trailing_cr(b"a\r\rb\r"), b"a\r\rb\r");
+    }
+
+    mod basic_regexes {
+        use super::*;
+
+        fn matches(pattern: &str, line: &str) -> bool {
+            Regex::new(&basic_regex_to_regex(pattern).unwrap())
+                .unwrap()
+                .is_match(line)
+        }
+
+        #[test]
+        fn basics() {
+            assert!(matches("^#", "# comment"));
+            assert!(!matches("^#", "a # comment"));
+            assert!(matches("a^b$c", "a^b$c"));
+            assert!(matches("x$", "ax"));
+            assert!(matches("*a", "*a"));
+            assert!(matches("ab*c", "ac"));
+            assert!(matches("a.c", "abc"));
+            assert!(matches("a+?(|){}", "a+?(|){}"));
+            assert!(matches(r"ab\+c", "abbc"));
+            assert!(!matches(r"ab\+c", "ac"));
+            assert!(matches(r"\(ab\)\{2\}", "abab"));
+            assert!(!matches(r"^\(ab\)\{2,\}$", "ab"));
+            assert!(matches(r"foo\|bar", "bar"));
+            assert!(matches(r"\<id\>", "an id"));
+            assert!(!matches(r"\<id\>", "idle"));
+            assert!(matches(r"[[:digit:]]\{4\}-", "2024-"));
+            assert!(matches(r"[\]", r"\"));
+            assert!(matches("[!a]", "!"));
+        }
+
+        #[test]
+        fn errors() {
+            for pattern in [r"a\(", r"a\)", "[a", "a\\", r"a\{1", r"a\{x\}", r"\(a\)\1"] {
+                assert!(basic_regex_to_regex(pattern).is_err(), "{pattern}");
+            }
+        }
+
+        #[test]
+        fn line_patterns() {
+            let pattern = LinePattern::new(r"^\(ab\)*$").unwrap();
+            assert!(pattern.is_match(b"abab"));
+            assert!(!pattern.is_match(b"aba"));
+            assert_eq!(LinePattern::new(r"a\(").unwrap_err(), "Unmatched ( or \\(");
+            // Valid, but too big for the regex engine.
+            assert_eq!(
+                LinePattern::new(r"a\{1000\}\{1000\}").unwrap_err(),
+                "Regular expression too big"
+            );
+        }
+    }
+
     mod modification_time {
         use super::*;
 
@@ -180,12 +1215,12 @@ mod tests {
 
             // format current time
             let current: DateTime<Local> = current.into();
-            let current: String = current.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string();
+            let current: String = current.format(UNIFIED_TIME_FORMAT).to_string();
 
             // verify
             assert_eq!(
                 current,
-                get_modification_time(&temp.path().to_string_lossy())
+                get_modification_time(&temp.path().to_string_lossy(), UNIFIED_TIME_FORMAT)
             );
         }
 
@@ -196,13 +1231,88 @@ mod tests {
 
             let invalid_file = "target/utils/invalid-file";
 
+            // A file that doesn't exist is stamped with the epoch.
+            let epoch: DateTime<Local> = SystemTime::UNIX_EPOCH.into();
+            let m_time: DateTime<Local> = get_modification_time(invalid_file, UNIFIED_TIME_FORMAT)
+                .parse()
+                .unwrap();
+
+            assert_eq!(m_time, epoch);
+        }
+
+        #[test]
+        fn stdin() {
+            use chrono::{DateTime, Local};
+            use std::time::SystemTime;
+
             // store current time before calling `get_modification_time`
-            // Because the file is invalid, it will return SystemTime::now()
-            // which will be greater than previously saved time
+            // Standard input is stamped with SystemTime::now(), which will be
+            // greater than previously saved time
             let current_time: DateTime<Local> = SystemTime::now().into();
-            let m_time: DateTime<Local> = get_modification_time(invalid_file).parse().unwrap();
+            let m_time: DateTime<Local> = get_modification_time("-", UNIFIED_TIME_FORMAT)
+                .parse()
+                .unwrap();
 
             assert!(m_time > current_time);
         }
+
+        #[test]
+        fn context_format() {
+            use chrono::{DateTime, Local};
+            use std::ffi::OsString;
+            use std::time::SystemTime;
+
+            let epoch: DateTime<Local> = SystemTime::UNIX_EPOCH.into();
+            let params = Params {
+                from: OsString::from("target/utils/invalid-file"),
+                to: OsString::from("a"),
+                to_label: Some("label".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                file_header(&params, true, CONTEXT_TIME_FORMAT),
+                format!(
+                    "target/utils/invalid-file\t{}",
+                    epoch.format("%a %b %e %H:%M:%S %Y")
+                )
+            );
+            assert_eq!(file_header(&params, false, CONTEXT_TIME_FORMAT), "label");
+        }
+    }
+
+    #[test]
+    fn response_files() {
+        let os = |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"-u\r\n\nfoo bar\n@baz\n").unwrap();
+        let response = format!("@{}", file.path().to_string_lossy());
+        assert_eq!(
+            expand_response_files(os(&["diff", &response, "qux", "@", "--", &response])),
+            Ok(os(&[
+                "diff", "-u", "foo bar", "@baz", "qux", "@", "--", &response
+            ]))
+        );
+        assert_eq!(
+            expand_response_files(os(&["diff", "-u"])),
+            Ok(os(&["diff", "-u"]))
+        );
+        assert!(expand_response_files(os(&["diff", "@target/nonexistent"]))
+            .is_err_and(|error| error.starts_with("diff: target/nonexistent: ")));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(b"caf\xe9\n").unwrap();
+            // Arguments that aren't valid UTF-8 are kept as they are.
+            let response = format!("@{}", file.path().to_string_lossy());
+            assert_eq!(
+                expand_response_files(os(&["diff", &response])),
+                Ok(vec![
+                    OsString::from("diff"),
+                    std::ffi::OsStr::from_bytes(b"caf\xe9").to_os_string()
+                ])
+            );
+        }
     }
 }
@@ -18,11 +18,11 @@ mod common {
     #[test]
     fn unknown_param() -> Result<(), Box<dyn std::error::Error>> {
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("patch");
+        cmd.arg("sdiff");
         cmd.assert()
             .code(predicate::eq(2))
             .failure()
-            .stderr(predicate::eq("patch: utility not supported\n"));
+            .stderr(predicate::eq("sdiff: utility not supported\n"));
 
         let mut cmd = Command::cargo_bin("diffutils")?;
         cmd.assert()
@@ -32,7 +32,7 @@ mod common {
                 "Expected utility name as second argument, got nothing.\n",
             ));
 
-        for subcmd in ["diff", "cmp"] {
+        for subcmd in ["diff", "cmp", "diff3", "patch"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg(subcmd);
             cmd.arg("--foobar");
@@ -101,10 +101,40 @@ mod diff {
 
     use super::*;
 
+    #[test]
+    fn help_and_version() -> Result<(), Box<dyn std::error::Error>> {
+        // Both take precedence over the missing operands
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "--help"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Usage: diff [OPTION]... FILES\nCompare FILES line by line.\n",
+            ))
+            .stdout(predicate::str::contains(
+                "  -u, -U NUM, --unified[=NUM]   output NUM (default 3) lines of unified context\n",
+            ))
+            .stdout(predicate::str::ends_with(
+                "Exit status is 0 if inputs are the same, 1 if different, 2 if trouble.\n",
+            ));
+
+        for option in ["-v", "--version"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.args(["diff", option]);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::starts_with("diff (uutils diffutils) "));
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn no_differences() -> Result<(), Box<dyn std::error::Error>> {
         let file = NamedTempFile::new()?;
-        for option in ["", "-u", "-c", "-e"] {
+        for option in ["", "-u", "-c", "-e", "-n"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg("diff");
             if !option.is_empty() {
@@ -124,7 +154,7 @@ mod diff {
         // same file
         let mut file1 = NamedTempFile::new()?;
         file1.write_all("foo\n".as_bytes())?;
-        for option in ["", "-u", "-c", "-e"] {
+        for option in ["", "-u", "-c", "-e", "-n"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg("diff");
             if !option.is_empty() {
@@ -143,7 +173,7 @@ mod diff {
         // two files with the same content
         let mut file2 = NamedTempFile::new()?;
         file2.write_all("foo\n".as_bytes())?;
-        for option in ["", "-u", "-c", "-e"] {
+        for option in ["", "-u", "-c", "-e", "-n"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg("diff");
             if !option.is_empty() {
@@ -168,7 +198,7 @@ mod diff {
         file1.write_all("foo\n".as_bytes())?;
         let mut file2 = NamedTempFile::new()?;
         file2.write_all("bar\n".as_bytes())?;
-        for option in ["", "-u", "-c", "-e"] {
+        for option in ["", "-u", "-c", "-e", "-n"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg("diff");
             if !option.is_empty() {
@@ -183,13 +213,58 @@ mod diff {
         Ok(())
     }
 
+    #[test]
+    fn large_files() -> Result<(), Box<dyn std::error::Error>> {
+        // Too many lines between the first and last differences to compare
+        // them all at once.
+        let lines: Vec<String> = (0..10000).map(|i| format!("{i}\n")).collect();
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all(lines.concat().as_bytes())?;
+        let mut changed = lines.clone();
+        changed[0] = "first\n".to_string();
+        changed.remove(5000);
+        changed[9998] = "last\n".to_string();
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all(changed.concat().as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff").arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "1c1\n< 0\n---\n> first\n5001d5000\n< 5000\n10000c9999\n< 9999\n---\n> last\n",
+            ));
+        Ok(())
+    }
+
+    #[test]
+    fn mapped_files() -> Result<(), Box<dyn std::error::Error>> {
+        // Files this large are mapped instead of read.
+        let line = "a".repeat(64 << 20);
+        let mut file1 = NamedTempFile::new()?;
+        write!(file1, "x\n{line}\nz\n")?;
+        let mut file2 = NamedTempFile::new()?;
+        write!(file2, "y\n{line}\nz")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff").arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "1c1\n< x\n---\n> y\n3c3\n< z\n---\n> z\n\\ No newline at end of file\n",
+            ));
+        Ok(())
+    }
+
     #[test]
     fn differences_brief() -> Result<(), Box<dyn std::error::Error>> {
         let mut file1 = NamedTempFile::new()?;
         file1.write_all("foo\n".as_bytes())?;
         let mut file2 = NamedTempFile::new()?;
         file2.write_all("bar\n".as_bytes())?;
-        for option in ["", "-u", "-c", "-e"] {
+        for option in ["", "-u", "-c", "-e", "-n"] {
             let mut cmd = Command::cargo_bin("diffutils")?;
             cmd.arg("diff");
             if !option.is_empty() {
@@ -208,19 +283,152 @@ mod diff {
         Ok(())
     }
 
+    #[test]
+    fn brief_skips_formatting() -> Result<(), Box<dyn std::error::Error>> {
+        // With -q, the ed format doesn't get to complain about missing newlines
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("foo".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("bar".as_bytes())?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.arg("-q").arg("-e").arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(format!(
+                "Files {} and {} differ\n",
+                file1.path().to_string_lossy(),
+                file2.path().to_string_lossy()
+            )));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.arg("-q").arg(file1.path()).arg(file1.path());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        // Reading stops at the first difference, even if there's no end.
+        #[cfg(target_os = "linux")]
+        {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff");
+            cmd.arg("-q").arg(file1.path()).arg("/dev/zero");
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(format!(
+                    "Files {} and /dev/zero differ\n",
+                    file1.path().to_string_lossy()
+                )));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn side_by_side() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\nb\nc\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\nx\nc\nd\n".as_bytes())?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.arg("-y")
+            .arg("-W40")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "a\t\t\ta\nb\t\t   |\tx\nc\t\t\tc\n\t\t   >\td\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-y", "--width=40", "--suppress-common-lines"])
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("b\t\t   |\tx\n\t\t   >\td\n"));
+
+        // Identical files are still listed in full
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-y", "-W", "40"])
+            .arg(file1.path())
+            .arg(file1.path());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("a\t\t\ta\nb\t\t\tb\nc\t\t\tc\n"));
+        Ok(())
+    }
+
     #[test]
     fn missing_newline() -> Result<(), Box<dyn std::error::Error>> {
         let mut file1 = NamedTempFile::new()?;
         file1.write_all("foo".as_bytes())?;
         let mut file2 = NamedTempFile::new()?;
         file2.write_all("bar".as_bytes())?;
+        let mut file3 = NamedTempFile::new()?;
+        file3.write_all("foo\n".as_bytes())?;
+
+        for (option, expected) in [
+            (
+                "--normal",
+                "1c1\n< foo\n\\ No newline at end of file\n---\n> foo\n",
+            ),
+            (
+                "-u",
+                "--- a\n+++ b\n@@ -1 +1 @@\n-foo\n\\ No newline at end of file\n+foo\n",
+            ),
+            (
+                "-c",
+                "*** a\n--- b\n***************\n*** 1 ****\n! foo\n\\ No newline at end of file\n--- 1 ----\n! foo\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.args(["diff", option, "-L", "a", "-L", "b"]);
+            cmd.arg(file1.path()).arg(file3.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        // ed scripts compare the last lines as if they were complete, and
+        // warn about the files that lack a newline.
         let mut cmd = Command::cargo_bin("diffutils")?;
         cmd.arg("diff");
         cmd.arg("-e").arg(file1.path()).arg(file2.path());
         cmd.assert()
             .code(predicate::eq(2))
             .failure()
-            .stderr(predicate::str::starts_with("No newline at end of file"));
+            .stdout(predicate::eq("1c\nbar\n.\n"))
+            .stderr(predicate::eq(format!(
+                "diff: {}: No newline at end of file\n\ndiff: {}: No newline at end of file\n\n",
+                file1.path().display(),
+                file2.path().display()
+            )));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-e", "-s"]).arg(file3.path()).arg(file1.path());
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::eq(format!(
+                "diff: {}: No newline at end of file\n\n",
+                file1.path().display()
+            )));
+
         Ok(())
     }
 
@@ -267,11 +475,30 @@ mod diff {
 
         let mut cmd = Command::cargo_bin("diffutils")?;
         cmd.arg("diff");
-        cmd.arg("-u").arg("-").arg("-");
+        cmd.arg("-u").arg("-").arg("-").write_stdin("foo\n");
         cmd.assert()
-            .code(predicate::eq(0))
-            .success()
-            .stdout(predicate::str::is_empty());
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::ends_with(
+                "cannot compare standard input to itself\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-u", "-L", "stdin", "-"])
+            .arg(file2.path())
+            .write_stdin("foo\n");
+        cmd.assert().code(predicate::eq(1)).failure();
+
+        let output = cmd.output().unwrap().stdout;
+        assert_diff_eq!(
+            output,
+            format!(
+                "--- stdin\n+++ {}\tTIMESTAMP\n@@ -1 +1 @@\n-foo\n+bar\n",
+                file2.path().to_string_lossy()
+            )
+        );
 
         #[cfg(unix)]
         {
@@ -296,6 +523,44 @@ mod diff {
         Ok(())
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn verify_against() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("foo\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("bar\n".as_bytes())?;
+
+        // A reference that agrees with us
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.arg("--verify-against=diff")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("1c1\n< foo\n---\n> bar\n"))
+            .stderr(predicate::str::is_empty());
+
+        // A reference that reports no differences and prints nothing
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.arg("--verify-against=true")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::eq("1c1\n< foo\n---\n> bar\n"))
+            .stderr(predicate::str::contains(
+                "exit status 1 differs from true's (0)",
+            ))
+            .stderr(predicate::str::contains("output differs from true's"));
+
+        Ok(())
+    }
+
     #[test]
     fn compare_file_to_directory() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
@@ -341,533 +606,3961 @@ mod diff {
             )
         );
 
-        Ok(())
-    }
-}
+        // The file named after the other operand must exist in the directory.
+        let b_path = tmp_dir.path().join("b");
+        std::fs::write(&b_path, "b\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff").arg(&b_path).arg(&directory);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::contains(format!(
+                "{}: No such file or directory",
+                directory.join("b").display()
+            )));
 
-mod cmp {
-    use super::*;
+        #[cfg(unix)]
+        {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff").arg(&directory).arg("/dev/null");
+            cmd.assert()
+                .code(predicate::eq(2))
+                .failure()
+                .stderr(predicate::str::contains(format!(
+                    "{}: No such file or directory",
+                    directory.join("null").display()
+                )));
+        }
 
-    #[test]
-    fn cmp_incompatible_params() -> Result<(), Box<dyn std::error::Error>> {
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-s");
-        cmd.arg("/etc/passwd").arg("/etc/group");
+        cmd.arg("diff").arg("-").arg(&directory);
         cmd.assert()
             .code(predicate::eq(2))
             .failure()
             .stderr(predicate::str::ends_with(
-                ": options -l and -s are incompatible\n",
+                "cannot compare '-' to a directory\n",
             ));
 
         Ok(())
     }
 
     #[test]
-    fn cmp_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    fn exit_codes() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir(tmp_dir.path().join("b"))?;
+        std::fs::write(tmp_dir.path().join("a/x"), "1\n")?;
+        std::fs::write(tmp_dir.path().join("b/x"), "1\n")?;
+        std::fs::write(tmp_dir.path().join("a/y"), "1\n")?;
+        std::fs::write(tmp_dir.path().join("b/y"), "2\n")?;
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"a\n").unwrap();
+        for (operands, code) in [
+            (["a/x", "b/x"], 0),
+            (["a/y", "b/y"], 1),
+            (["a/x", "b/z"], 2),
+            (["a", "a"], 0),
+            (["a", "b"], 1),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").args(operands);
+            cmd.assert().code(predicate::eq(code));
+        }
 
-        let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg(&a_path);
-        cmd.write_stdin("a\n");
-        cmd.assert()
-            .code(predicate::eq(0))
-            .success()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::is_empty());
+        // Trouble with one file wins over the differences found in others.
+        #[cfg(unix)]
+        {
+            std::fs::write(tmp_dir.path().join("b/z"), "z\n")?;
+            std::os::unix::fs::symlink("nowhere", tmp_dir.path().join("a/z"))?;
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").arg("-r").arg("a").arg("b");
+            cmd.assert()
+                .code(predicate::eq(2))
+                .stdout(predicate::str::contains("diff -r a/y b/y\n"));
+        }
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg(&a_path);
-        cmd.write_stdin("b\n");
-        cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(" - differ: char 1, line 1\n"));
+        cmd.arg("diff").arg("--no-such-option").arg("a").arg("b");
+        cmd.assert().code(predicate::eq(2));
+
+        #[cfg(target_os = "linux")]
+        {
+            let output = std::process::Command::new(assert_cmd::cargo::cargo_bin("diffutils"))
+                .current_dir(tmp_dir.path())
+                .args(["diff", "a/y", "b/y"])
+                .stdout(File::create("/dev/full")?)
+                .output()?;
+            assert_eq!(output.status.code(), Some(2));
+            assert!(String::from_utf8(output.stderr)?.contains("standard output: "));
+        }
 
         Ok(())
     }
 
     #[test]
-    fn cmp_equal_files() -> Result<(), Box<dyn std::error::Error>> {
+    fn compare_directories() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/f", "1\n"),
+            ("b/f", "2\n"),
+            ("a/same", "same\n"),
+            ("b/same", "same\n"),
+            ("a/only", "x\n"),
+            ("b/sub/s", "t\n"),
+            ("a/sub/s", "s\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"a\n").unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("a").arg("b");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "diff a/f b/f\n1c1\n< 1\n---\n> 2\nOnly in a: only\n\
+             Common subdirectories: a/sub and b/sub\n",
+            ));
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"a\n").unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-r").arg("-q").arg("a").arg("b");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "Files a/f and b/f differ\nOnly in a: only\nFiles a/sub/s and b/sub/s differ\n",
+            ));
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-r").arg("a/sub").arg("a/sub");
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stderr(predicate::str::is_empty())
             .stdout(predicate::str::is_empty());
 
-        Ok(())
+        for args in [vec!["a", "a"], vec!["-s", "a", "./a"]] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").args(args);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::is_empty());
+        }
+
+        Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    fn cmp_one_file_empty() -> Result<(), Box<dyn std::error::Error>> {
+    fn permission_errors() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
         let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/f", "1\n"),
+            ("b/f", "2\n"),
+            ("a/locked/f", "1\n"),
+            ("b/locked/f", "2\n"),
+            ("a/secret", "1\n"),
+            ("b/secret", "2\n"),
+            ("a/z", "1\n"),
+            ("b/z", "2\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+        let set_mode = |path: &str, mode| {
+            std::fs::set_permissions(
+                tmp_dir.path().join(path),
+                std::fs::Permissions::from_mode(mode),
+            )
+        };
+        set_mode("a/locked", 0o000)?;
+        set_mode("a/secret", 0o000)?;
+        // Permissions aren't enforced for the superuser.
+        let enforced = std::fs::read_dir(tmp_dir.path().join("a/locked")).is_err();
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"a\n").unwrap();
+        if enforced {
+            // The walk goes on after the errors, which make the status 2.
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "-r", "-q", "a", "b"]);
+            cmd.assert()
+                .code(predicate::eq(2))
+                .failure()
+                .stdout(predicate::eq(
+                    "Files a/f and b/f differ\nFiles a/z and b/z differ\n",
+                ))
+                .stderr(predicate::eq(
+                    "diff: a/locked: Permission denied\ndiff: a/secret: Permission denied\n",
+                ));
+        }
 
-        let b_path = tmp_dir.path().join("b");
-        let _ = File::create(&b_path).unwrap();
+        set_mode("a/locked", 0o755)?;
+        set_mode("a/secret", 0o644)?;
+        Ok(())
+    }
 
-        let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stderr(predicate::str::contains(" EOF on "))
-            .stderr(predicate::str::ends_with(" which is empty\n"));
+    #[test]
+    fn max_depth() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/f", "1\n"),
+            ("b/f", "2\n"),
+            ("a/sub/f", "1\n"),
+            ("b/sub/f", "2\n"),
+            ("a/sub/deep/f", "1\n"),
+            ("b/sub/deep/f", "2\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+
+        for (depth, expected) in [
+            (
+                "0",
+                "Files a/f and b/f differ\nCommon subdirectories: a/sub and b/sub\n",
+            ),
+            (
+                "1",
+                "Files a/f and b/f differ\nCommon subdirectories: a/sub/deep and b/sub/deep\n\
+                 Files a/sub/f and b/sub/f differ\n",
+            ),
+            (
+                "2",
+                "Files a/f and b/f differ\nFiles a/sub/deep/f and b/sub/deep/f differ\n\
+                 Files a/sub/f and b/sub/f differ\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "-r", "-q", "--max-depth", depth, "a", "b"]);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
 
         Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    fn cmp_immediate_difference() -> Result<(), Box<dyn std::error::Error>> {
+    fn archives() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/pkg/f", "1\n"),
+            ("b/pkg/f", "2\n"),
+            ("a/pkg/only", "x\n"),
+            ("a/pkg/same", "x\n"),
+            ("b/pkg/same", "x\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+        for (archive, dir) in [("a.tar.gz", "a"), ("b.tar", "b")] {
+            std::process::Command::new("tar")
+                .current_dir(tmp_dir.path())
+                .args(["-caf", archive, "-C", dir, "pkg"])
+                .status()?;
+        }
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"abc\n").unwrap();
-
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"bcd\n").unwrap();
+        // Archives are compared to archives or directories, under their names.
+        for to in ["b.tar", "b"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "-r", "--archives", "a.tar.gz", to]);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(format!(
+                    "diff -r --archives a.tar.gz/pkg/f {to}/pkg/f\n1c1\n< 1\n---\n> 2\n\
+                     Only in a.tar.gz/pkg: only\n"
+                )));
+        }
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-q", "--archives", "a.tar.gz", "b.tar"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stdout(predicate::str::ends_with(" differ: char 1, line 1\n"));
+            .stdout(predicate::eq(
+                "Files a.tar.gz/pkg/f and b.tar/pkg/f differ
+Only in a.tar.gz/pkg: only
+",
+            ));
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-b");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--archives", "a.tar.gz", "b.tar"]);
         cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(
-                " differ: byte 1, line 1 is 141 a 142 b\n",
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "Common subdirectories: a.tar.gz/pkg and b.tar/pkg\n",
             ));
 
+        std::fs::write(tmp_dir.path().join("c.tar"), "not an archive\n")?;
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "--archives", "c.tar", "b.tar"]);
         cmd.assert()
-            .code(predicate::eq(1))
+            .code(predicate::eq(2))
             .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::eq("1 141 142\n2 142 143\n3 143 144\n"));
+            .stderr(predicate::str::starts_with("diff: cannot extract c.tar: "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_in() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/B", "x\n"),
+            ("b/a", "x\n"),
+            ("a/f", "1\n"),
+            ("b/f", "2\n"),
+            ("a/dir/x", "x\n"),
+            ("a/sub/x", "x\n"),
+            ("b/sub/y", "y\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
+        // Directories that are only on one side aren't walked, and the paths
+        // of the entries collapse the trailing slashes of the operands.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "a//", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stderr(predicate::str::is_empty())
             .stdout(predicate::eq(
-                "1 141 a    142 b\n2 142 b    143 c\n3 143 c    144 d\n",
+                "Only in a//: B\nOnly in b: a\nOnly in a//: dir\n\
+                 diff -r a/f b/f\n1c1\n< 1\n---\n> 2\n\
+                 Only in a/sub: x\nOnly in b/sub: y\n",
             ));
 
+        // Errors come in order with the output.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("nowhere", tmp_dir.path().join("b/e"))?;
+            let log = tmp_dir.path().join("log");
+            let log_file = File::create(&log)?;
+            let output = std::process::Command::new(assert_cmd::cargo::cargo_bin("diffutils"))
+                .current_dir(tmp_dir.path())
+                .args(["diff", "-r", "-q", "-N", "a", "b"])
+                .stdout(log_file.try_clone()?)
+                .stderr(log_file)
+                .output()?;
+            assert_eq!(output.status.code(), Some(2));
+            assert_eq!(
+                std::fs::read_to_string(&log)?,
+                "Files a/B and b/B differ\nFiles a/a and b/a differ\n\
+                 Files a/dir/x and b/dir/x differ\n\
+                 diff: b/e: No such file or directory\n\
+                 Files a/f and b/f differ\nFiles a/sub/x and b/sub/x differ\n\
+                 Files a/sub/y and b/sub/y differ\n"
+            );
+        }
+
         Ok(())
     }
 
     #[test]
-    fn cmp_newline_difference() -> Result<(), Box<dyn std::error::Error>> {
+    fn assume_unchanged_by_mtime() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
-
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"abc\ndefg").unwrap();
-
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"abc\ndef\ng").unwrap();
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir(tmp_dir.path().join("b"))?;
+        std::fs::write(tmp_dir.path().join("a/f"), "1\n")?;
+        std::fs::write(tmp_dir.path().join("b/f"), "2\n")?;
+        let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        for path in ["a/f", "b/f"] {
+            File::options()
+                .write(true)
+                .open(tmp_dir.path().join(path))?
+                .set_modified(mtime)?;
+        }
+        // Hard links are the same file, whatever their contents.
+        std::fs::write(tmp_dir.path().join("a/g"), "g\n")?;
+        std::fs::hard_link(tmp_dir.path().join("a/g"), tmp_dir.path().join("b/g"))?;
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-s", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(" differ: char 8, line 2\n"));
+            .stdout(predicate::eq(
+                "diff -r -s a/f b/f\n1c1\n< 1\n---\n> 2\nFiles a/g and b/g are identical\n",
+            ));
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-b");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-s", "--assume-unchanged-by-mtime", "a", "b"]);
         cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(
-                " differ: byte 8, line 2 is 147 g  12 ^J\n",
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "Files a/f and b/f are identical\nFiles a/g and b/g are identical\n",
             ));
 
+        // Files of different sizes are compared.
+        std::fs::write(tmp_dir.path().join("b/f"), "22\n")?;
+        File::options()
+            .write(true)
+            .open(tmp_dir.path().join("b/f"))?
+            .set_modified(mtime)?;
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-q", "--assume-unchanged-by-mtime", "a/f", "b/f"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stdout(predicate::str::starts_with("8 147  12\n"))
-            .stderr(predicate::str::contains(" EOF on"))
-            .stderr(predicate::str::ends_with(" after byte 8\n"));
+            .stdout(predicate::eq("Files a/f and b/f differ\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn jobs() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for i in 0..20 {
+            for (dir, content) in [("a", format!("{i}\nx\n")), ("b", format!("x\n{i}\n"))] {
+                let path = tmp_dir.path().join(dir).join(i.to_string()).join("f");
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(tmp_dir.path().join(dir).join(format!("f{i}")), &content)?;
+                std::fs::write(path, &content)?;
+            }
+        }
+        std::fs::write(tmp_dir.path().join("a/only"), "")?;
+        std::fs::write(tmp_dir.path().join("a/5/f"), "x\n")?;
 
+        // The results come in the same order as when comparing one file
+        // at a time, and the option isn't repeated.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-b");
-        cmd.arg("-l");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stdout(predicate::str::starts_with("8 147 g     12 ^J\n"))
-            .stderr(predicate::str::contains(" EOF on"))
-            .stderr(predicate::str::ends_with(" after byte 8\n"));
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-u", "a", "b"]);
+        let expected = cmd.assert().code(predicate::eq(1)).get_output().clone();
+        assert!(String::from_utf8(expected.stdout.clone())?
+            .starts_with("diff -r -u a/0/f b/0/f\n--- a/0/f\t"));
+        for jobs in ["--jobs=4", "--jobs=64"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", jobs, "-r", "-u", "a", "b"]);
+            let output = cmd.assert().code(predicate::eq(1)).get_output().clone();
+            assert_eq!(
+                String::from_utf8(output.stdout)?,
+                String::from_utf8(expected.stdout.clone())?
+            );
+        }
 
         Ok(())
     }
 
     #[test]
-    fn cmp_max_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    fn stat() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/f", "1\n2\n3\n"),
+            ("b/f", "1\n3\n4\n5\n"),
+            ("a/same", "x\n"),
+            ("b/same", "x\n"),
+            ("a/sub/bin", "\0a"),
+            ("b/sub/bin", "\0bc"),
+            ("a/only", "x\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"abc efg ijkl\n").unwrap();
-
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"abcdefghijkl\n").unwrap();
+        for (options, stdout) in [
+            (
+                vec!["-r"],
+                "Only in a: only\n f       |   3 ++-\n sub/bin | Bin 2 -> 3 bytes\n \
+                 2 files changed, 2 insertions(+), 1 deletion(-)\n",
+            ),
+            // The entries are named the same way whichever side they're on,
+            // without the prefixes of the headers.
+            (
+                vec!["-r", "-N", "--git"],
+                " f       |   3 ++-\n only    |   1 -\n sub/bin | Bin 2 -> 3 bytes\n \
+                 3 files changed, 2 insertions(+), 2 deletions(-)\n",
+            ),
+            (
+                vec!["-r", "-N", "--relative"],
+                " f       |   3 ++-\n only    |   1 -\n sub/bin | Bin 2 -> 3 bytes\n \
+                 3 files changed, 2 insertions(+), 2 deletions(-)\n",
+            ),
+            (
+                vec!["-r", "--numstat"],
+                "Only in a: only\n2\t1\tf\n-\t-\tsub/bin\n",
+            ),
+            (
+                vec!["-r", "-N", "--shortstat"],
+                " 3 files changed, 2 insertions(+), 2 deletions(-)\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "--stat"]).args(options).args(["a", "b"]);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(stdout));
+        }
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg("-n");
-        cmd.arg("3");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--stat", "-s", "a/same", "b/same"]);
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::is_empty());
+            .stdout(predicate::eq("Files a/same and b/same are identical\n"));
 
-        let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg("-n");
-        cmd.arg("4");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.assert()
-            .code(predicate::eq(1))
-            .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::eq("4  40      144 d\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn paginate() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [("a/f", "1\n"), ("b/f", "2\n")] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
+        // The output only goes through the pager on a terminal, and the option
+        // isn't repeated.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg("-n");
-        cmd.arg("13");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.env("PAGER", "false");
+        cmd.args(["diff", "--paginate", "-r", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::eq(" 4  40      144 d\n 8  40      150 h\n"));
+            .stdout(predicate::eq("diff -r a/f b/f\n1c1\n< 1\n---\n> 2\n"));
+
         Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    fn cmp_skip_args_parsing() -> Result<(), Box<dyn std::error::Error>> {
-        let tmp_dir = tempdir()?;
-
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"---abc\n").unwrap();
+    fn special_files() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::net::UnixListener;
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"###abc\n").unwrap();
+        let tmp_dir = tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir(tmp_dir.path().join("b"))?;
+        std::fs::write(tmp_dir.path().join("a/f"), "x\n")?;
+        let _from = UnixListener::bind(tmp_dir.path().join("a/s"))?;
+        let _to = UnixListener::bind(tmp_dir.path().join("b/f"))?;
 
+        // Special files aren't read when comparing directories.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-i");
-        cmd.arg("3");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("a").arg("b");
         cmd.assert()
-            .code(predicate::eq(0))
-            .success()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::is_empty());
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "File a/f is a regular file while file b/f is a socket\nOnly in a: s\n",
+            ));
 
-        // Positional skips should be ignored
+        // Sockets can't be read.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg("-i");
-        cmd.arg("3");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.arg("1").arg("1");
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("a/f").arg("b/f");
         cmd.assert()
-            .code(predicate::eq(0))
-            .success()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::is_empty());
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::contains("b/f: "));
 
-        // Single positional argument should only affect first file.
+        // Devices are read like files.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.arg("3");
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("/dev/null").arg("a/f");
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(" differ: char 1, line 1\n"));
+            .stdout(predicate::eq("0a1\n> x\n"));
 
-        let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.env("LC_ALL", "C");
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
-        cmd.arg("3");
-        cmd.arg("3");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fifo() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let fifo = tmp_dir.path().join("fifo");
+        let file = tmp_dir.path().join("file");
+        std::fs::write(&file, "a\n")?;
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()?
+            .success());
+
+        // Like with a process substitution, the FIFO is only written once, so
+        // it's opened only to be read.
+        let writer = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || std::fs::write(fifo, "a\n"))
+        };
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff").arg(&fifo).arg(&file);
+        cmd.timeout(std::time::Duration::from_secs(10));
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stderr(predicate::str::is_empty())
             .stdout(predicate::str::is_empty());
+        writer.join().unwrap()?;
 
         Ok(())
     }
 
     #[test]
-    fn cmp_skip_suffix_parsing() -> Result<(), Box<dyn std::error::Error>> {
+    fn file_type_mismatch() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for dir in ["a/d/x", "b/e"] {
+            std::fs::create_dir_all(tmp_dir.path().join(dir))?;
+        }
+        std::fs::write(tmp_dir.path().join("b/d"), "x\n")?;
+        std::fs::write(tmp_dir.path().join("a/e"), "")?;
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        writeln!(a, "{}c", "a".repeat(1024)).unwrap();
-        a.flush().unwrap();
+        for options in [vec!["-r"], vec!["-r", "-q"], vec![]] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").args(options).args(["a", "b"]);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(
+                    "File a/d is a directory while file b/d is a regular file\n\
+                     File a/e is a regular empty file while file b/e is a directory\n",
+                ));
+        }
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        writeln!(b, "{}c", "b".repeat(1024)).unwrap();
-        b.flush().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn exclude() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/main.c", "1\n"),
+            ("b/main.c", "2\n"),
+            ("a/main.o", "x\n"),
+            ("b/target/out", "y\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("--ignore-initial=1K");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-x", "*.o", "--exclude=target", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "diff -r -x '*.o' '--exclude=target' a/main.c b/main.c\n1c1\n< 1\n---\n> 2\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-x", "main.*", "-x", "target", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stderr(predicate::str::is_empty())
             .stdout(predicate::str::is_empty());
 
         Ok(())
     }
 
     #[test]
-    fn cmp_skip() -> Result<(), Box<dyn std::error::Error>> {
+    fn exclude_vcs_ignores() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/.gitignore", "*.o\n/build/\n"),
+            ("b/.gitignore", "*.o\n/build/\n"),
+            ("a/main.o", "1\n"),
+            ("b/main.o", "2\n"),
+            ("b/build/out", "x\n"),
+            ("a/src/.gitignore", "generated.c\n!keep.o\n"),
+            ("a/src/generated.c", "1\n"),
+            ("b/src/lib.o", "x\n"),
+            ("b/src/keep.o", "x\n"),
+            ("a/src/build/main.c", "1\n"),
+            ("b/src/build/main.c", "2\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(b"abc efg ijkl\n").unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "--exclude-vcs-ignores", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "Only in a/src: .gitignore\n\
+             diff -r --exclude-vcs-ignores a/src/build/main.c b/src/build/main.c\n\
+             1c1\n< 1\n---\n> 2\n\
+             Only in b/src: keep.o\n",
+            ));
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(b"abcdefghijkl\n").unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn starting_file() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/1", "1\n"),
+            ("b/1", "2\n"),
+            ("a/3", "3\n"),
+            ("a/sub/1", "1\n"),
+            ("b/sub/1", "2\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
+        // Subdirectories are compared in full
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg("-i");
-        cmd.arg("8");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-q", "-S", "2", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "Only in a: 3\nFiles a/sub/1 and b/sub/1 differ\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_file_name_case() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/Foo", "1\n"),
+            ("b/foo", "2\n"),
+            ("a/BAR", "1\n"),
+            ("a/Dir/X", "1\n"),
+            ("b/dir/x", "1\n"),
+            ("a/zed", "1\n"),
+            ("b/zed", "1\n"),
+            ("b/ZED", "2\n"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+
+        // An exact match is preferred
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-q", "--ignore-file-name-case", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "Only in a: BAR\nFiles a/Foo and b/foo differ\nOnly in b: ZED\n",
+            ));
+
+        // Exclusions ignore case too
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-r", "-q", "--ignore-file-name-case", "-x", "f*"]);
+        cmd.args(["-x", "bar", "-x", "Zed", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stderr(predicate::str::is_empty())
             .stdout(predicate::str::is_empty());
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-b");
-        cmd.arg("-i");
-        cmd.arg("4");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-q", "--ignore-file-name-case"]);
+        cmd.args(["--no-ignore-file-name-case", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stderr(predicate::str::is_empty())
-            .stdout(predicate::str::ends_with(
-                " differ: byte 4, line 1 is  40   150 h\n",
+            .stdout(predicate::eq(
+                "Only in a: BAR\nOnly in a: Dir\nOnly in a: Foo\nOnly in b: ZED\n\
+             Only in b: dir\nOnly in b: foo\n",
             ));
 
         Ok(())
     }
 
     #[test]
-    fn cmp_binary() -> Result<(), Box<dyn std::error::Error>> {
+    fn from_file() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        for (path, content) in [("a", "a\n"), ("b", "b\n"), ("c", "a\n")] {
+            std::fs::write(tmp_dir.path().join(path), content)?;
+        }
 
-        let mut bytes = vec![0, 15, 31, 32, 33, 40, 64, 126, 127, 128, 129, 200, 254, 255];
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--from-file=a", "b", "c"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("1c1\n< a\n---\n> b\n"));
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(&bytes).unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--to-file=a", "c", "nonexistent", "b"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::eq("1c1\n< b\n---\n> a\n"));
 
-        bytes.reverse();
+        // A missing file is looked up in the directory, whose entry is
+        // reported missing.
+        std::fs::create_dir(tmp_dir.path().join("d"))?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--from-file=d", "f"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::contains(
+                "diff: d/f: No such file or directory\n",
+            ))
+            .stderr(predicate::str::contains("Is a directory").not());
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(&bytes).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn prefixes() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [("x/old/src/f", "a\n"), ("y/new/src/f", "b\n")] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
 
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-l");
-        cmd.arg("-b");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.args(["diff", "-r", "-u", "--relative"])
+            .args(["--src-prefix=a/", "--dst-prefix=b/"])
+            .arg(tmp_dir.path().join("x/old"))
+            .arg(tmp_dir.path().join("y/new"));
+        let output = cmd.output()?;
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8(output.stdout)?;
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(
+            lines[0],
+            "diff -r -u --relative '--src-prefix=a/' '--dst-prefix=b/' a/src/f b/src/f"
+        );
+        assert!(lines[1].starts_with("--- a/src/f\t"));
+        assert!(lines[2].starts_with("+++ b/src/f\t"));
+        assert_eq!(lines[3..], ["@@ -1 +1 @@", "-a", "+b"]);
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-c", "--src-prefix=a/", "--relative"])
+            .args(["x/old/src/f", "y/new/src/f"]);
+        let output = cmd.output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        assert!(stdout.starts_with("*** a/f\t"));
+        assert!(stdout.lines().nth(1).unwrap().starts_with("--- f\t"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn git() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [
+            ("a/f", "1\n2\n"),
+            ("b/f", "1\n3\n"),
+            ("a/gone", "x\n"),
+            ("b/sub/new", "y\n"),
+            ("a/bin", "\0a"),
+            ("b/bin", "\0b"),
+        ] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--git", "-r", "-N", "a", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stdout(predicate::eq(concat!(
-                " 1   0 ^@   377 M-^?\n",
-                " 2  17 ^O   376 M-~\n",
-                " 3  37 ^_   310 M-H\n",
-                " 4  40      201 M-^A\n",
-                " 5  41 !    200 M-^@\n",
-                " 6  50 (    177 ^?\n",
-                " 7 100 @    176 ~\n",
-                " 8 176 ~    100 @\n",
-                " 9 177 ^?    50 (\n",
-                "10 200 M-^@  41 !\n",
-                "11 201 M-^A  40  \n",
-                "12 310 M-H   37 ^_\n",
-                "13 376 M-~   17 ^O\n",
-                "14 377 M-^?   0 ^@\n"
-            )));
+            .stdout(predicate::eq(
+                "diff --git a/bin b/bin\n\
+             Binary files a/bin and b/bin differ\n\
+             diff --git a/f b/f\n\
+             --- a/f\n\
+             +++ b/f\n\
+             @@ -1,2 +1,2 @@\n 1\n-2\n+3\n\
+             diff --git a/gone b/gone\n\
+             deleted file mode 100644\n\
+             --- a/gone\n\
+             +++ /dev/null\n\
+             @@ -1 +0,0 @@\n-x\n\
+             diff --git a/sub/new b/sub/new\n\
+             new file mode 100644\n\
+             --- /dev/null\n\
+             +++ b/sub/new\n\
+             @@ -0,0 +1 @@\n+y\n",
+            ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = tmp_dir.path().join("b/f");
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "--git", "--src-prefix=old/", "a/f", "b/f"]);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(
+                    "diff --git old/f b/f\n\
+                 old mode 100644\n\
+                 new mode 100755\n\
+                 --- old/f\n\
+                 +++ b/f\n\
+                 @@ -1,2 +1,2 @@\n 1\n-2\n+3\n",
+                ));
+        }
 
         Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    #[cfg(not(windows))]
-    fn cmp_fast_paths() -> Result<(), Box<dyn std::error::Error>> {
+    fn textconv() -> Result<(), Box<dyn std::error::Error>> {
         let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("a"), "b\0\na\n")?;
+        std::fs::write(tmp_dir.path().join("b"), "a\nc\0\n")?;
 
-        // This test mimics one found in the GNU cmp test suite. It is used for
-        // validating the /dev/null optimization.
-        let a_path = tmp_dir.path().join("a");
-        let a = File::create(&a_path).unwrap();
-        a.set_len(14 * 1024 * 1024 * 1024 * 1024).unwrap();
+        // The output of the command is text, and the headers keep the paths.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args([
+            "diff",
+            "-u",
+            "-L",
+            "a",
+            "-L",
+            "b",
+            "--textconv=sort",
+            "a",
+            "b",
+        ]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\0\n+c\0\n",
+            ));
 
-        let b_path = tmp_dir.path().join("b");
-        let b = File::create(&b_path).unwrap();
-        b.set_len(15 * 1024 * 1024 * 1024 * 1024).unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--textconv", "sort", "-", "a"])
+            .write_stdin("a\nb\0\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
 
-        let dev_null = OpenOptions::new().write(true).open("/dev/null").unwrap();
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-q", "--textconv=false", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::contains(
+                "diff: 'false' failed on a with exit status: 1\n",
+            ));
 
-        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("diffutils"))
-            .arg("cmp")
-            .arg(&a_path)
-            .arg(&b_path)
-            .stdout(dev_null)
-            .spawn()
-            .unwrap();
+        Ok(())
+    }
 
-        std::thread::sleep(std::time::Duration::from_millis(100));
+    #[cfg(unix)]
+    #[test]
+    fn preprocess() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("a"), "b\na\n")?;
+        std::fs::write(tmp_dir.path().join("b"), "a\nb\n")?;
+        std::fs::write(tmp_dir.path().join("c"), "c\nb\n")?;
 
-        assert_eq!(child.try_wait().unwrap().unwrap().code(), Some(1));
+        for (to, code, stdout) in [
+            ("b", 0, ""),
+            (
+                "c",
+                1,
+                "diff '--preprocess=sort' -r a c\n1d0\n< a\n2a2\n> c\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "--batch", "--preprocess=sort", "-r"])
+                .write_stdin(format!("a\t{to}\n"));
+            cmd.assert()
+                .code(predicate::eq(code))
+                .stdout(predicate::eq(stdout));
+        }
 
-        // Two stdins should be equal
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg("-");
-        cmd.arg("-");
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--preprocess", "sort", "-", "b"])
+            .write_stdin("b\na\n");
         cmd.assert()
             .code(predicate::eq(0))
             .success()
-            .stdout(predicate::str::is_empty())
-            .stderr(predicate::str::is_empty());
-
-        // Files with longer than block size equal segments should still report
-        // the correct line number for the difference. Assumes 8KB block size (see
-        // https://github.com/rust-lang/rust/blob/master/library/std/src/sys_common/io.rs),
-        // create a 24KB equality.
-        let mut bytes = " ".repeat(4095);
-        bytes.push('\n');
-        bytes.push_str(&" ".repeat(4096));
+            .stdout(predicate::str::is_empty());
 
-        let bytes = bytes.repeat(3);
-        let bytes = bytes.as_bytes();
+        // Failures of the command are trouble.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--preprocess=exit 3", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::contains(
+                "diff: 'exit 3' failed on a with exit status: 3\n",
+            ));
 
-        let a_path = tmp_dir.path().join("a");
-        let mut a = File::create(&a_path).unwrap();
-        a.write_all(bytes).unwrap();
-        a.write_all(b"A").unwrap();
+        Ok(())
+    }
 
-        let b_path = tmp_dir.path().join("b");
-        let mut b = File::create(&b_path).unwrap();
-        b.write_all(bytes).unwrap();
-        b.write_all(b"B").unwrap();
+    #[cfg(unix)]
+    #[test]
+    fn decompress() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("a"), "a\nb\n")?;
+        std::fs::write(tmp_dir.path().join("b"), "a\nc\n")?;
+        std::process::Command::new("gzip")
+            .current_dir(tmp_dir.path())
+            .arg("a")
+            .status()?;
 
+        // The compressed file keeps its name in the headers.
         let mut cmd = Command::cargo_bin("diffutils")?;
-        cmd.arg("cmp");
-        cmd.arg(&a_path).arg(&b_path);
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-z", "-u", "--label=a.gz", "--label=b", "a.gz", "b"]);
         cmd.assert()
             .code(predicate::eq(1))
             .failure()
-            .stdout(predicate::str::ends_with(" differ: byte 24577, line 4\n"));
+            .stdout(predicate::eq(
+                "--- a.gz\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--decompress", "-q", "-", "a.gz"])
+            .write_stdin("a\nb\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        // Without the option, the compressed file is binary.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "a.gz", "b"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .stdout(predicate::eq("Binary files a.gz and b differ\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [("a", "a\n"), ("b", "b\n"), ("c", "a\n")] {
+            std::fs::write(tmp_dir.path().join(path), content)?;
+        }
+
+        for input in ["a\tb\na\tc\nc\tb\n", "a\0b\0a\0c\0c\0b\0"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.args(["diff", "--batch", "-s"]).write_stdin(input);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(
+                    "diff -s a b\n1c1\n< a\n---\n> b\n\
+                     Files a and c are identical\n\
+                     diff -s c b\n1c1\n< a\n---\n> b\n",
+                ));
+        }
+
+        // Trouble with one pair wins over the differences of the others.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--batch", "-q"])
+            .write_stdin("a\tnonexistent\na\tb\n");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::eq("Files a and b differ\n"))
+            .stderr(predicate::str::starts_with("diff: nonexistent: "));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--batch"]).write_stdin("a b\n");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::eq(
+                "diff: standard input:1: missing tab between paths\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn response_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [("a", "a\n"), ("b", "b\n"), ("c", "a\n"), ("list", "b\nc\n")] {
+            std::fs::write(tmp_dir.path().join(path), content)?;
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-s", "--from-file=a", "@list"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "1c1\n< a\n---\n> b\nFiles a and c are identical\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--from-file=a", "@nonexistent"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::starts_with("diff: nonexistent: "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn labels() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all("foo\n".as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-u", "-L", "a/foo", "--label=b/foo", "-"])
+            .arg(file.path());
+        cmd.write_stdin("bar\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "--- a/foo\n+++ b/foo\n@@ -1 +1 @@\n-bar\n+foo\n",
+            ));
+
+        // Only the first file gets a label
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff");
+        cmd.args(["-c", "-L", "old", "-"]).arg(file.path());
+        cmd.write_stdin("bar\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::starts_with(format!(
+                "*** old\n--- {}\t",
+                file.path().to_string_lossy()
+            )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn expand_tabs() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\tb\nab\tc\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\tB\nab\tc\n".as_bytes())?;
+
+        // Tabs are expanded relative to the start of the line, not the
+        // start of the output, so that the columns line up as in the files.
+        for (options, expected) in [
+            (vec!["-t"], "1c1\n< a       b\n---\n> a       B\n"),
+            (
+                vec!["--expand-tabs", "-U1", "-L", "x", "-L", "y"],
+                "--- x\n+++ y\n@@ -1,2 +1,2 @@\n-a       b\n+a       B\n ab      c\n",
+            ),
+            (
+                vec!["-t", "-C0", "-L", "x", "-L", "y"],
+                "*** x\n--- y\n***************\n*** 1 ****\n! a       b\n--- 1 ----\n! a       B\n",
+            ),
+            (vec!["-t", "-e"], "1c\na       B\n.\n"),
+            (vec!["-t", "--tabsize=4"], "1c1\n< a   b\n---\n> a   B\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff");
+            cmd.args(options).arg(file1.path()).arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn initial_tab() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\tb\nc\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\tB\nc\n".as_bytes())?;
+
+        for (options, expected) in [
+            (vec!["-T"], "1c1\n<\ta\tb\n---\n>\ta\tB\n"),
+            (
+                vec!["--initial-tab", "-u", "-L", "x", "-L", "y"],
+                "--- x\n+++ y\n@@ -1,2 +1,2 @@\n-\ta\tb\n+\ta\tB\n\tc\n",
+            ),
+            (
+                vec!["-T", "-c", "-L", "x", "-L", "y"],
+                "*** x\n--- y\n***************\n*** 1,2 ****\n!\ta\tb\n \tc\n\
+                 --- 1,2 ----\n!\ta\tB\n \tc\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff");
+            cmd.args(options).arg(file1.path()).arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn suppress_blank_empty() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\n\nb\n\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("x\n\nb\n".as_bytes())?;
+
+        for (options, expected) in [
+            (vec![], "1c1\n< a\n---\n> x\n4d3\n<\n"),
+            (
+                vec!["-u", "-L", "x", "-L", "y"],
+                "--- x\n+++ y\n@@ -1,4 +1,3 @@\n-a\n+x\n\n b\n-\n",
+            ),
+            (
+                vec!["-c", "-T", "-L", "x", "-L", "y"],
+                "*** x\n--- y\n***************\n*** 1,4 ****\n!\ta\n\n \tb\n-\n\
+                 --- 1,3 ----\n!\tx\n\n \tb\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff");
+            cmd.arg("--suppress-blank-empty")
+                .args(options)
+                .arg(file1.path())
+                .arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn text() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir(tmp_dir.path().join("b"))?;
+        std::fs::write(tmp_dir.path().join("a/f"), "a\0b\n")?;
+        std::fs::write(tmp_dir.path().join("b/f"), "a\0c\n")?;
+
+        for (options, expected) in [
+            (vec![], "Binary files a/f and b/f differ\n"),
+            (vec!["-u"], "Binary files a/f and b/f differ\n"),
+            (vec!["-q"], "Files a/f and b/f differ\n"),
+            (vec!["-a"], "1c1\n< a\0b\n---\n> a\0c\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").args(options).arg("a/f").arg("b/f");
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        // No header for binary files in directories
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("a").arg("b");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("Binary files a/f and b/f differ\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("a/f").arg("a/f");
+        cmd.assert().code(predicate::eq(0)).success();
+
+        Ok(())
+    }
+
+    #[test]
+    fn binary_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("bin"), "a\0b\n")?;
+        std::fs::write(tmp_dir.path().join("bin2"), "a\0b\n")?;
+        std::fs::write(tmp_dir.path().join("text"), "text\n")?;
+
+        // Whatever the output format, only the fact that they differ is reported.
+        for options in [
+            vec![],
+            vec!["-u"],
+            vec!["-c"],
+            vec!["-e"],
+            vec!["-n"],
+            vec!["-y"],
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").args(options).arg("text").arg("bin");
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq("Binary files text and bin differ\n"));
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-").arg("text");
+        cmd.write_stdin("a\0b\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("Binary files - and text differ\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-s").arg("bin").arg("bin2");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("Files bin and bin2 are identical\n"));
+
+        // Only the start of the files is probed for null bytes.
+        let mut late = "x\n".repeat(4096);
+        late.push('\0');
+        std::fs::write(tmp_dir.path().join("late"), &late)?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("late").arg("text");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::starts_with("1,4097c1\n< x\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn binary() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\r\nb\r\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\nb\r\n".as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff")
+            .arg("--binary")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("1c1\n< a\r\n---\n> a\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn strip_trailing_cr() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\r\nb\r\nc\r\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\nb\nd\n".as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff").arg("--strip-trailing-cr");
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("3c3\n< c\n---\n> d\n"));
+
+        let mut file3 = NamedTempFile::new()?;
+        file3.write_all("a\nb\nc\n".as_bytes())?;
+        for options in [vec![], vec!["-q"]] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff").arg("--strip-trailing-cr").args(options);
+            cmd.arg(file1.path()).arg(file3.path());
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_tab_expansion() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\tb\nc\td\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a       b\nc   d\n".as_bytes())?;
+
+        for (options, expected) in [
+            (vec!["-E"], "2c2\n< c\td\n---\n> c   d\n"),
+            (vec!["-E", "--tabsize=4"], "1c1\n< a\tb\n---\n> a       b\n"),
+            (
+                vec!["-E", "-q"],
+                &format!(
+                    "Files {} and {} differ\n",
+                    file1.path().display(),
+                    file2.path().display()
+                ),
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff")
+                .args(options)
+                .arg(file1.path())
+                .arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        let mut file3 = NamedTempFile::new()?;
+        file3.write_all("a       b\nc\td\n".as_bytes())?;
+        for options in [vec!["-E"], vec!["-E", "-q"], vec!["-E", "-u"]] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff")
+                .args(options)
+                .arg(file1.path())
+                .arg(file3.path());
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::is_empty());
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "-E", "-y", "-W", "30"]);
+        cmd.arg(file1.path()).arg(file3.path());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("a\tb\ta       b\nc\td\tc\td\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_all_space() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("fn main() {\n    run();\n}\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("fn main(){\n\trun( );\n\texit();\n}\n".as_bytes())?;
+
+        // The original lines are output
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff")
+            .arg("-w")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("2a3\n> \texit();\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("diff")
+            .arg("-w")
+            .arg("-u")
+            .arg(file1.path())
+            .arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(
+                "@@ -1,3 +1,4 @@\n fn main() {\n     run();\n+\texit();\n }\n",
+            ));
+
+        let mut file3 = NamedTempFile::new()?;
+        file3.write_all("fn  main ( ) {\r\n  run();\n}\n".as_bytes())?;
+        for options in [vec!["-w"], vec!["-w", "-q"]] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff")
+                .args(options)
+                .arg(file1.path())
+                .arg(file3.path());
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_fuzz() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(b"step 1: energy=-1.23456e2 t=0.5\nstep 2: energy=-1.3e2 t=1.0\n")?;
+
+        for (fuzz, code, stdout) in [
+            ("1e-3", 0, ""),
+            (
+                "1e-7",
+                1,
+                "1c1\n< step 1: energy=-123.4561 t=0.5\n---\n> step 1: energy=-1.23456e2 t=0.5\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff")
+                .arg(format!("--numeric-fuzz={fuzz}"))
+                .arg("-")
+                .arg(file.path());
+            cmd.write_stdin("step 1: energy=-123.4561 t=0.5\nstep 2: energy=-130.00001 t=1\n");
+            cmd.assert()
+                .code(predicate::eq(code))
+                .stdout(predicate::eq(stdout));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ansi() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(b"\x1b[32mok\x1b[0m\n\x1b[31merror\x1b[0m: x\n")?;
+
+        for (option, stdout) in [
+            (
+                "--ignore-ansi",
+                "2c2\n< error: y\n---\n> \x1b[31merror\x1b[0m: x\n",
+            ),
+            ("--strip-ansi", "2c2\n< error: y\n---\n> error: x\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff").arg(option).arg("-").arg(file.path());
+            cmd.write_stdin("ok\nerror: y\n");
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(stdout));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalize() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all("re\u{301}sume\u{301}\nnai\u{308}ve\n".as_bytes())?;
+
+        for (options, code, stdout) in [
+            (vec!["--normalize=nfc"], 0, ""),
+            (vec!["--normalize", "nfd", "-q"], 0, ""),
+            (
+                vec![],
+                1,
+                "1c1\n< r\u{e9}sum\u{e9}\n---\n> re\u{301}sume\u{301}\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff").args(options).arg("-").arg(file.path());
+            cmd.write_stdin("r\u{e9}sum\u{e9}\nnai\u{308}ve\n");
+            cmd.assert()
+                .code(predicate::eq(code))
+                .stdout(predicate::eq(stdout));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("a"), "1\n2\n3\n4\n5\n6\n7\n")?;
+        std::fs::write(tmp_dir.path().join("b"), "x\n2\n3\nx\n5\n6\nx\n")?;
+        std::fs::write(tmp_dir.path().join("c"), "1\n")?;
+
+        for (options, stdout) in [
+            (
+                vec!["--max-hunks=1", "a", "b"],
+                "1c1\n< 1\n---\n> x\n... 2 more hunks suppressed\n",
+            ),
+            (
+                vec!["-U0", "--max-hunks", "2", "a", "b"],
+                "--- a\n+++ b\n@@ -1 +1 @@\n-1\n+x\n@@ -4 +4 @@\n-4\n+x\n\
+                 ... 1 more hunk suppressed\n",
+            ),
+            (
+                vec!["--max-lines=6", "a", "b"],
+                "1c1\n< 1\n---\n> x\n4c4\n< 4\n... 6 more lines suppressed\n",
+            ),
+            // The limit on lines applies to the output for all the files.
+            (
+                vec!["--max-lines=3", "--to-file=b", "a", "c"],
+                "1c1\n< 1\n---\n... 19 more lines suppressed\n",
+            ),
+            // And so does the limit on hunks.
+            (
+                vec!["--max-hunks=2", "--to-file=b", "a", "c"],
+                "1c1\n< 1\n---\n> x\n4c4\n< 4\n---\n> x\n... 2 more hunks suppressed\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff")
+                .args(["--label=a", "--label=b"])
+                .args(options);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(stdout));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_matching_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("# v1\na\nb\n# date: 1\nc\nd\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("# v2\na\nb\n# date: 2\nc\ne\n".as_bytes())?;
+
+        for (options, expected) in [
+            (
+                vec!["-I", "^# v"],
+                "4c4\n< # date: 1\n---\n> # date: 2\n6c6\n< d\n---\n> e\n",
+            ),
+            (vec!["-I", "^# v", "-I", "date"], "6c6\n< d\n---\n> e\n"),
+            // An ignored change is output when it is part of a hunk.
+            (
+                vec!["--ignore-matching-lines=^#", "-U1"],
+                "@@ -3,4 +3,4 @@\n b\n-# date: 1\n+# date: 2\n c\n-d\n+e\n",
+            ),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff")
+                .args(options)
+                .arg(file1.path())
+                .arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::str::ends_with(expected));
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "-I", "^#", "-I", "^[de]$"]);
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "-I", "a\\("]);
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::contains("a\\(: Unmatched ( or \\("));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ifdef() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (path, content) in [("a/x", "a\nb\nc\nd\n"), ("b/x", "a\nB\nc\nd\ne")] {
+            let path = tmp_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-D", "FOO", "a/x", "b/x"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "a\n#ifndef FOO\nb\n#else /* FOO */\nB\n#endif /* FOO */\nc\nd\n\
+             #ifdef FOO\ne\n#endif /* FOO */\n",
+            ));
+
+        // The file is output even when there are no differences
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "--ifdef=FOO", "a/x", "a/x"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("a\nb\nc\nd\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.args(["diff", "-D", "FOO", "a", "b"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::ends_with(
+                "-D option not supported with directories\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn line_formats() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\nb\nc\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("a\nB\nc\nd".as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args([
+            "diff",
+            "--unchanged-line-format=%dn %L",
+            "--old-line-format=-%l\n",
+            "--new-line-format=+%3dn %l\n",
+        ]);
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("1 a\n-b\n+  2 B\n3 c\n+  4 d\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "--unchanged-line-format=", "--new-line-format="]);
+        cmd.arg(file1.path()).arg(file1.path());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        // Files that differ still do when nothing is output for them.
+        for options in [
+            &["--line-format="][..],
+            &[
+                "--old-line-format=",
+                "--new-line-format=",
+                "--unchanged-line-format=",
+            ],
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff").arg("-s").args(options);
+            cmd.arg(file1.path()).arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::str::is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_formats() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\nb\nc\nd\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("x\na\nB\nc\n".as_bytes())?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args([
+            "diff",
+            "--old-group-format=%df,%dld\n%<",
+            "--new-group-format=%dea%dF,%dL\n%>",
+            "--changed-group-format=%(n=1?%df:%df,%dl)c%dF\n%<---\n%>",
+            "--unchanged-group-format=",
+            "--old-line-format=< %L",
+            "--new-line-format=> %L",
+        ]);
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("0a1,1\n> x\n2c3\n< b\n---\n> B\n4,4d\n< d\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff", "-D", "FOO", "--old-group-format=%<"]);
+        cmd.arg(file1.path()).arg(file2.path());
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::contains(
+                "conflicting --old-group-format option value '%<'",
+            ));
+
+        let mut file3 = NamedTempFile::new()?;
+        file3.write_all("a\nB\nc\nd\n".as_bytes())?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args([
+            "diff",
+            "-s",
+            "--changed-group-format=",
+            "--unchanged-group-format=",
+        ]);
+        cmd.arg(file1.path()).arg(file3.path());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn color() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file1 = NamedTempFile::new()?;
+        file1.write_all("a\nb\n".as_bytes())?;
+        let mut file2 = NamedTempFile::new()?;
+        file2.write_all("x\nb\n".as_bytes())?;
+
+        for (options, expected) in [
+            (
+                vec!["--color=always"],
+                "\x1b[36m1c1\x1b[0m\n\x1b[31m< a\x1b[0m\n---\n\x1b[32m> x\x1b[0m\n",
+            ),
+            (
+                vec!["--color=always", "-u", "-L", "x", "-L", "y"],
+                "\x1b[1m--- x\x1b[0m\n\x1b[1m+++ y\x1b[0m\n\x1b[36m@@ -1,2 +1,2 @@\x1b[0m\n\
+                 \x1b[31m-a\x1b[0m\n\x1b[32m+x\x1b[0m\n b\n",
+            ),
+            (
+                vec!["--color=always", "--palette=ad=1;32:de=9;31:rs=:lc=<:rc=>"],
+                "<36>1c1<>\n<9;31>< a<>\n---\n<1;32>> x<>\n",
+            ),
+            // Without --color, the palette has no effect
+            (vec!["--palette=ad=1;32"], "1c1\n< a\n---\n> x\n"),
+            (vec!["--color=never"], "1c1\n< a\n---\n> x\n"),
+            // Not a terminal
+            (vec!["--color"], "1c1\n< a\n---\n> x\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("diff");
+            cmd.args(options).arg(file1.path()).arg(file2.path());
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stdout(predicate::eq(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_file() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir_all(tmp_dir.path().join("b/sub"))?;
+        std::fs::write(tmp_dir.path().join("a/gone"), "x\n")?;
+        std::fs::write(tmp_dir.path().join("b/sub/new"), "y\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-r").arg("-N").arg("a").arg("b");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "diff -r -N a/gone b/gone\n1d0\n< x\n\
+             diff -r -N a/sub/new b/sub/new\n0a1\n> y\n",
+            ));
+
+        // A missing operand is also compared as empty
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff")
+            .arg("--new-file")
+            .arg("nonexistent")
+            .arg("a/gone");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq("0a1\n> x\n"));
+
+        // but not if both are missing
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff").arg("-N").arg("nonexistent").arg("b/gone");
+        cmd.assert().code(predicate::eq(2)).failure();
+
+        // An entry that is listed but can't be read isn't a new file
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("nowhere", tmp_dir.path().join("a/link"))?;
+            std::fs::write(tmp_dir.path().join("b/link"), "z\n")?;
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(tmp_dir.path());
+            cmd.arg("diff").arg("-r").arg("-N").arg("a").arg("b");
+            cmd.assert()
+                .code(predicate::eq(2))
+                .failure()
+                .stderr(predicate::str::contains(
+                    "diff: a/link: No such file or directory",
+                ))
+                .stdout(predicate::str::contains("link").not());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unidirectional_new_file() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("a"))?;
+        std::fs::create_dir(tmp_dir.path().join("b"))?;
+        std::fs::write(tmp_dir.path().join("a/gone"), "x\n")?;
+        std::fs::write(tmp_dir.path().join("b/new"), "y\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff")
+            .arg("--unidirectional-new-file")
+            .arg("a")
+            .arg("b");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "Only in a: gone\ndiff --unidirectional-new-file a/new b/new\n0a1\n> y\n",
+            ));
+
+        // Only the first operand may be missing
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(tmp_dir.path());
+        cmd.arg("diff")
+            .arg("--unidirectional-new-file")
+            .arg("a/gone")
+            .arg("nonexistent");
+        cmd.assert().code(predicate::eq(2)).failure();
+
+        Ok(())
+    }
+}
+
+mod cmp {
+    use super::*;
+
+    #[test]
+    fn cmp_help_and_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["cmp", "--help"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Usage: cmp [OPTION]... FILE1 [FILE2 [SKIP1 [SKIP2]]]\n\
+                 Compare two files byte by byte.\n",
+            ))
+            .stdout(predicate::str::contains(
+                "  -n, --bytes=LIMIT          compare at most LIMIT bytes\n",
+            ))
+            .stdout(predicate::str::ends_with(
+                "Exit status is 0 if inputs are the same, 1 if different, 2 if trouble.\n",
+            ));
+
+        for option in ["-v", "--version"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.args(["cmp", option]);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::starts_with("cmp (uutils diffutils) "));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_incompatible_params() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-s");
+        cmd.arg("/etc/passwd").arg("/etc/group");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::ends_with(
+                ": options -l and -s are incompatible\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_stdin() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"a\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path);
+        cmd.write_stdin("a\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path);
+        cmd.write_stdin("b\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(" - differ: char 1, line 1\n"));
+
+        // Standard input may be the first file, and is a prefix of a.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-").arg(&a_path);
+        cmd.write_stdin("a");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::eq("cmp: EOF on '-' after byte 1, in line 1\n"))
+            .stdout(predicate::str::is_empty());
+
+        // A file named "-" is not standard input.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("cmp");
+        cmd.args(["-s", "a", "-"]);
+        cmd.write_stdin("a\n");
+        File::create(tmp_dir.path().join("-"))?;
+        cmd.assert().code(predicate::eq(0)).success();
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_same_file() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"aab\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&a_path).arg("1").arg("1");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        // Different bytes of the same file are compared.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&a_path).arg("0").arg("1");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(" differ: char 2, line 1\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_missing_file() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let missing = tmp_dir.path().join("missing");
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg(&missing).arg(&missing);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::starts_with("cmp: "))
+            .stderr(predicate::str::ends_with(
+                "missing: No such file or directory\n",
+            ))
+            .stdout(predicate::str::is_empty());
+
+        // With -s, errors are only told by the exit status, like with GNU cmp.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-s");
+        cmd.arg(&missing).arg(&missing);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cmp_fifo() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let fifo = tmp_dir.path().join("fifo");
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()?
+            .success());
+
+        // The FIFO is only opened once, and its skipped bytes are read.
+        let writer = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || std::fs::write(fifo, "xxabc"))
+        };
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.args(["-l", "-i", "2:1"]);
+        cmd.arg(&fifo).arg("-");
+        cmd.write_stdin("xabd");
+        cmd.timeout(std::time::Duration::from_secs(10));
+        // Neither input has a size, so offsets are as wide as GNU cmp's.
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(format!("{:>19} 143 144\n", 3)));
+        writer.join().unwrap()?;
+
+        let writer = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || std::fs::write(fifo, "abc"))
+        };
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-s");
+        cmd.arg(&fifo).arg(&fifo);
+        cmd.timeout(std::time::Duration::from_secs(10));
+        cmd.assert().code(predicate::eq(0)).success();
+        // Nothing read the FIFO, so let the writer go.
+        assert_eq!(std::fs::read(&fifo)?, b"abc");
+        writer.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_equal_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"a\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"a\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_one_file_empty() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"a\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let _ = File::create(&b_path).unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::contains(" EOF on "))
+            .stderr(predicate::str::ends_with(" which is empty\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_immediate_difference() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"abc\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"bcd\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(" differ: char 1, line 1\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(
+                " differ: byte 1, line 1 is 141 a 142 b\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq("1 141 142\n2 142 143\n3 143 144\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(
+                "1 141 a    142 b\n2 142 b    143 c\n3 143 c    144 d\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_quiet() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"xabc\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"abc\nd\n").unwrap();
+
+        // The sizes differ, and so do the files.
+        for option in ["-s", "--quiet", "--silent"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("cmp");
+            cmd.arg(option);
+            cmd.arg(&a_path).arg(&b_path);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stderr(predicate::str::is_empty())
+                .stdout(predicate::str::is_empty());
+        }
+
+        // The sizes of the compared bytes are the same.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.args(["-s", "-n", "3", "-i", "1:0"]);
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.args(["-s", "-n", "5"]);
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_hex_context() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"hello world, this is file one\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"hello world, this is file two\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["cmp", "--hex-context=8", "a", "-"]);
+        cmd.write_stdin("hello world, this is file two\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(
+                "a - differ: char 27, line 1\n\
+                 a:\n\
+                 00000012  69 73 20 66 69 6c 65 20  6f 6e 65 0a              |is file one.|\n\
+                 -:\n\
+                 00000012  69 73 20 66 69 6c 65 20  74 77 6f 0a              |is file two.|\n",
+            ));
+
+        // The offsets are those of the files, past the skipped bytes.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["cmp", "--hex-context=2", "a", "b", "20", "20"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "a b differ: char 7, line 1\n\
+                 a:\n\
+                 00000018  65 20 6f 6e                                       |e on|\n\
+                 b:\n\
+                 00000018  65 20 74 77                                       |e tw|\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.args(["-s", "--hex-context=8"]);
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_newline_difference() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"abc\ndefg").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"abc\ndef\ng").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(" differ: char 8, line 2\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(
+                " differ: byte 8, line 2 is 147 g  12 ^J\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::starts_with("8 147  12\n"))
+            .stderr(predicate::str::contains(" EOF on"))
+            .stderr(predicate::str::ends_with(" after byte 8\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg("-l");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::starts_with("8 147 g     12 ^J\n"))
+            .stderr(predicate::str::contains(" EOF on"))
+            .stderr(predicate::str::ends_with(" after byte 8\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_max_bytes() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"abc efg ijkl\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"abcdefghijkl\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg("-n");
+        cmd.arg("3");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg("-n");
+        cmd.arg("4");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq("4  40      144 d\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg("-n");
+        cmd.arg("13");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(" 4  40      144 d\n 8  40      150 h\n"));
+
+        // Nothing is compared, not even the end of an empty file.
+        let empty_path = tmp_dir.path().join("empty");
+        File::create(&empty_path).unwrap();
+        for (from, to) in [(&a_path, &b_path), (&empty_path, &b_path)] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("cmp");
+            cmd.arg("-n");
+            cmd.arg("0");
+            cmd.arg(from).arg(to);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stderr(predicate::str::is_empty())
+                .stdout(predicate::str::is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_skip_args_parsing() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"---abc\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"###abc\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-i");
+        cmd.arg("3");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        // Positional skips should be ignored
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg("-i");
+        cmd.arg("3");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.arg("1").arg("1");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        // Single positional argument should only affect first file.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.arg("3");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(" differ: char 1, line 1\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.env("LC_ALL", "C");
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.arg("3");
+        cmd.arg("3");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_skip_suffix_parsing() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        writeln!(a, "{}c", "a".repeat(1024)).unwrap();
+        a.flush().unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        writeln!(b, "{}c", "b".repeat(1024)).unwrap();
+        b.flush().unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("--ignore-initial=1K");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_skip() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"abc efg ijkl\n").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"abcdefghijkl\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg("-i");
+        cmd.arg("8");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg("-i");
+        cmd.arg("4");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(
+                " differ: byte 4, line 1 is  40   150 h\n",
+            ));
+
+        // The skipped bytes of standard input are read.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-b");
+        cmd.arg(&a_path).arg("-").arg("4").arg("5");
+        cmd.write_stdin("abcdefghijkl\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::ends_with(
+                " differ: byte 1, line 1 is 145 e 146 f\n",
+            ));
+
+        // Skipping past the end of both files leaves nothing to compare.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path).arg("1Y").arg("20");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_value_format() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(b"a\n\xff").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(b"b\n\x0a").unwrap();
+
+        for (format, output) in [
+            ("octal", "1 141 142\n3 377  12\n"),
+            ("hex", "1 61 62\n3 ff 0a\n"),
+            ("decimal", "1  97  98\n3 255  10\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.arg("cmp");
+            cmd.arg("-l");
+            cmd.arg(format!("--format={format}"));
+            cmd.arg(&a_path).arg(&b_path);
+            cmd.assert()
+                .code(predicate::eq(1))
+                .failure()
+                .stderr(predicate::str::is_empty())
+                .stdout(predicate::eq(output));
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.args(["-b", "--format=hex"]);
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(
+                " differ: byte 1, line 1 is 61 a 62 b\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cmp_binary() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        let mut bytes = vec![0, 15, 31, 32, 33, 40, 64, 126, 127, 128, 129, 200, 254, 255];
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(&bytes).unwrap();
+
+        bytes.reverse();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(&bytes).unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-l");
+        cmd.arg("-b");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(concat!(
+                " 1   0 ^@   377 M-^?\n",
+                " 2  17 ^O   376 M-~\n",
+                " 3  37 ^_   310 M-H\n",
+                " 4  40      201 M-^A\n",
+                " 5  41 !    200 M-^@\n",
+                " 6  50 (    177 ^?\n",
+                " 7 100 @    176 ~\n",
+                " 8 176 ~    100 @\n",
+                " 9 177 ^?    50 (\n",
+                "10 200 M-^@  41 !\n",
+                "11 201 M-^A  40  \n",
+                "12 310 M-H   37 ^_\n",
+                "13 376 M-~   17 ^O\n",
+                "14 377 M-^?   0 ^@\n"
+            )));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn cmp_fast_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+
+        // This test mimics one found in the GNU cmp test suite. It is used for
+        // validating the /dev/null optimization.
+        let a_path = tmp_dir.path().join("a");
+        let a = File::create(&a_path).unwrap();
+        a.set_len(14 * 1024 * 1024 * 1024 * 1024).unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let b = File::create(&b_path).unwrap();
+        b.set_len(15 * 1024 * 1024 * 1024 * 1024).unwrap();
+
+        let dev_null = OpenOptions::new().write(true).open("/dev/null").unwrap();
+
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("diffutils"))
+            .arg("cmp")
+            .arg(&a_path)
+            .arg(&b_path)
+            .stdout(dev_null)
+            .spawn()
+            .unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        assert_eq!(child.try_wait().unwrap().unwrap().code(), Some(1));
+
+        // Two stdins should be equal
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg("-");
+        cmd.arg("-");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::is_empty());
+
+        // Files with longer than block size equal segments should still report
+        // the correct line number for the difference. Assumes 8KB block size (see
+        // https://github.com/rust-lang/rust/blob/master/library/std/src/sys_common/io.rs),
+        // create a 24KB equality.
+        let mut bytes = " ".repeat(4095);
+        bytes.push('\n');
+        bytes.push_str(&" ".repeat(4096));
+
+        let bytes = bytes.repeat(3);
+        let bytes = bytes.as_bytes();
+
+        let a_path = tmp_dir.path().join("a");
+        let mut a = File::create(&a_path).unwrap();
+        a.write_all(bytes).unwrap();
+        a.write_all(b"A").unwrap();
+
+        let b_path = tmp_dir.path().join("b");
+        let mut b = File::create(&b_path).unwrap();
+        b.write_all(bytes).unwrap();
+        b.write_all(b"B").unwrap();
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("cmp");
+        cmd.arg(&a_path).arg(&b_path);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(" differ: byte 24577, line 4\n"));
+
+        Ok(())
+    }
+}
+
+mod diff3 {
+    use super::*;
+
+    // Writes MYFILE, OLDFILE and YOURFILE in a new temporary directory.
+    fn three_files(
+        mine: &str,
+        older: &str,
+        yours: &str,
+    ) -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        for (name, content) in [("mine", mine), ("older", older), ("yours", yours)] {
+            File::create(tmp_dir.path().join(name))?.write_all(content.as_bytes())?;
+        }
+        Ok(tmp_dir)
+    }
+
+    #[test]
+    fn diff3_help_and_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["diff3", "--help"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Usage: diff3 [OPTION]... MYFILE OLDFILE YOURFILE\n\
+                 Compare three files line by line.\n",
+            ));
+
+        for option in ["-v", "--version"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.args(["diff3", option]);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::starts_with("diff3 (uutils diffutils) "));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff3_normal() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = three_files(
+            "a\nB\nc\nd\ne\nf\n",
+            "a\nb\nc\nd\ne\n",
+            "a\nb\nc\nD\ne\ng\n",
+        )?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(
+                "====1\n1:2c\n  B\n2:2c\n3:2c\n  b\n\
+                 ====3\n1:4c\n2:4c\n  d\n3:4c\n  D\n\
+                 ====\n1:6c\n  f\n2:5a\n3:6c\n  g\n",
+            ));
+
+        // Standard input can be any of the files.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "-", "yours"]);
+        cmd.write_stdin("a\nb\nc\nd\ne\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with("====1\n1:2c\n  B\n"));
+
+        // No output for the same files.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "mine", "mine"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff3_ed_script() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = three_files(
+            "a\nB\nc\nd\ne\nf\n",
+            "a\nb\nc\nd\ne\n",
+            "a\nb\nc\nD\ne\ng\n",
+        )?;
+        for (option, script) in [
+            ("-e", "6c\ng\n.\n4c\nD\n.\n"),
+            ("--ed", "6c\ng\n.\n4c\nD\n.\n"),
+            ("-3", "4c\nD\n.\n"),
+            ("--easy-only", "4c\nD\n.\n"),
+            ("-x", "6c\ng\n.\n"),
+            ("--overlap-only", "6c\ng\n.\n"),
+        ] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(&tmp_dir);
+            cmd.args(["diff3", option, "mine", "older", "yours"]);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stderr(predicate::str::is_empty())
+                .stdout(predicate::eq(script));
+        }
+
+        // Bracketed conflicts make the exit status 1.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-A", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq(
+                "6a\n||||||| older\n=======\ng\n>>>>>>> yours\n.\n5a\n<<<<<<< mine\n.\n4c\nD\n.\n",
+            ));
+
+        // -i ends the scripts with w and q, and is ignored by the default
+        // format.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-A", "-i", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with("4c\nD\n.\nw\nq\n"));
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-i", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with("====1\n"));
+
+        // Changed incomplete lines get a newline, with a warning.
+        let tmp_dir = three_files("a\n", "a\n", "a\nb")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-e", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::eq("diff3: No newline at end of file\n"))
+            .stdout(predicate::eq("1a\nb\n.\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-e", "-x", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq("diff3: incompatible options\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff3_labels_and_initial_tab() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = three_files("a\nb\nc\n", "a\nb\n", "a\nB\nd\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args([
+            "diff3",
+            "-A",
+            "-L",
+            "x",
+            "--label=y",
+            "mine",
+            "older",
+            "yours",
+        ]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "3a\n||||||| y\nb\n=======\nB\nd\n>>>>>>> yours\n.\n1a\n<<<<<<< x\n.\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-L", "x", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq("diff3: incompatible options\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-T", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "====\n1:2,3c\n\tb\n\tc\n2:2c\n\tb\n3:2,3c\n\tB\n\td\n",
+            ));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diff3_non_utf8() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp_dir = tempdir()?;
+        for (name, content) in [
+            ("mine", &b"caf\xe9\nna\xefve\n"[..]),
+            ("older", b"caf\xe9\nnaive\n"),
+            ("yours", b"cafe\nnaive\n"),
+        ] {
+            File::create(tmp_dir.path().join(name))?.write_all(content)?;
+        }
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-e", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(&b"1,2c\ncafe\nnaive\n.\n"[..]));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-A", "-L"]);
+        cmd.arg(std::ffi::OsStr::from_bytes(b"m\xe9"));
+        cmd.args(["mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                &b"2a\n||||||| older\ncaf\xe9\nnaive\n=======\ncafe\nnaive\n>>>>>>> yours\n.\n\
+                   0a\n<<<<<<< m\xe9\n.\n"[..],
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff3_trouble() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = three_files("a\n", "a\0\n", "b\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "older"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq("diff3: missing operand after 'older'\n"));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "missing", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::starts_with("diff3: missing: "))
+            .stdout(predicate::str::is_empty());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq(
+                "diff3: diff failed: Binary files older and yours differ\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["diff3", "-a", "mine", "older", "yours"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("====\n1:1c\n  a\n2:1c\n  a\0\n3:1c\n  b\n"));
+
+        Ok(())
+    }
+}
+
+mod patch {
+    use super::*;
+
+    const PATCH: &str = "--- a/f\t2024-01-01 00:00:00\n+++ b/f\t2024-01-01 00:00:00\n\
+                         @@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -5 +5,2 @@\n e\n+f\n";
+
+    #[test]
+    fn patch_help_and_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["patch", "--help"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Usage: patch [OPTION]... [ORIGFILE [PATCHFILE]]\n",
+            ));
+
+        for option in ["-v", "--version"] {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.args(["patch", option]);
+            cmd.assert()
+                .code(predicate::eq(0))
+                .success()
+                .stdout(predicate::str::starts_with("patch (uutils diffutils) "));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_unified() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        std::fs::write(&file, "a\nb\nc\nd\ne\n")?;
+
+        // The file is found by the base name of the names of the patch.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stderr(predicate::str::is_empty())
+            .stdout(predicate::eq("patching file f\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nB\nc\nd\ne\nf\n");
+
+        // Or given, with the patch in a file.
+        let other = tmp_dir.path().join("other");
+        std::fs::write(&other, "a\nb\nc\nd\ne\n")?;
+        std::fs::write(tmp_dir.path().join("p.diff"), PATCH)?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "other", "p.diff"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file other\n"));
+        assert_eq!(std::fs::read_to_string(&other)?, "a\nB\nc\nd\ne\nf\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_offset_and_fuzz() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+
+        // The hunks are found where the lines moved to.
+        std::fs::write(&file, "0\na\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "patching file f\n\
+                 Hunk #1 succeeded at 2 (offset 1 line).\n\
+                 Hunk #2 succeeded at 6 (offset 1 line).\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "0\na\nB\nc\nd\ne\nf\n");
+
+        // Lines of context that differ are ignored, within the fuzz factor.
+        std::fs::write(&file, "x\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 succeeded at 1 with fuzz 1.\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "x\nB\nc\nd\ne\nf\n");
+
+        std::fs::write(&file, "x\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-F0"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 FAILED at 1.\n\
+                 1 out of 2 hunks FAILED -- saving rejects to file f.rej\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_backup() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        let backup = tmp_dir.path().join("f.orig");
+
+        // Files are backed up with -b.
+        std::fs::write(&file, "a\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-b"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert().code(predicate::eq(0)).success();
+        assert_eq!(std::fs::read_to_string(&backup)?, "a\nb\nc\nd\ne\n");
+        std::fs::remove_file(&backup)?;
+
+        // Or when the patch doesn't match exactly.
+        std::fs::write(&file, "0\na\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--suffix=~"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert().code(predicate::eq(0)).success();
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("f~"))?,
+            "0\na\nb\nc\nd\ne\n"
+        );
+        assert!(!backup.exists());
+
+        // Unless told not to.
+        std::fs::write(&file, "0\na\nb\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--no-backup-if-mismatch"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert().code(predicate::eq(0)).success();
+        assert!(!backup.exists());
+
+        // A file that's created is backed up empty.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-b"]);
+        cmd.write_stdin("--- /dev/null\n+++ new\n@@ -0,0 +1 @@\n+a\n");
+        cmd.assert().code(predicate::eq(0)).success();
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("new.orig"))?,
+            ""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        std::fs::write(&file, "a\nb\nc\nd\ne\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--dry-run"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("checking file f\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nb\nc\nd\ne\n");
+
+        std::fs::write(&file, "a\nX\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--dry-run"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "checking file f\nHunk #1 FAILED at 1.\n1 out of 2 hunks FAILED\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nX\nc\nd\ne\n");
+        assert!(!tmp_dir.path().join("f.rej").exists());
+
+        // Nor are files created.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--dry-run"]);
+        cmd.write_stdin("--- /dev/null\n+++ new\n@@ -0,0 +1 @@\n+a\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("checking file new\n"));
+        assert!(!tmp_dir.path().join("new").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_strip_and_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let dir = tmp_dir.path().join("d");
+        std::fs::create_dir_all(dir.join("sub"))?;
+        let file = dir.join("sub/f");
+        std::fs::write(&file, "a\nb\nc\nd\ne\n")?;
+        let patch = PATCH.replace("a/f", "a/sub/f").replace("b/f", "b/sub/f");
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-d", "d", "-p1"]);
+        cmd.write_stdin(patch.clone());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file sub/f\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nB\nc\nd\ne\nf\n");
+
+        // Names without enough components to strip aren't found.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&dir);
+        cmd.args(["patch", "-t", "--strip=3"]);
+        cmd.write_stdin(patch);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::contains(
+                "Perhaps you used the wrong -p or --strip option?\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-d", "nowhere"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::starts_with(
+                "patch: **** Can't change to directory nowhere : ",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_multiple_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("x"), "a\nb\nc\n")?;
+        std::fs::write(tmp_dir.path().join("y"), "1\n2\n3\n")?;
+        // Like a patch sent by mail.
+        let patch = "From: someone@example.com\n\
+                     Subject: [PATCH] two files\n\
+                     \n\
+                     ---\n \
+                     x | 2 +-\n\
+                     diff --git a/x b/x\n\
+                     --- a/x\n\
+                     +++ b/x\n\
+                     @@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+                     diff --git a/y b/y\n\
+                     --- a/y\n\
+                     +++ b/y\n\
+                     @@ -2 +2 @@\n-2\n+TWO\n\
+                     -- \n\
+                     2.40\n";
+        std::fs::write(tmp_dir.path().join("m.diff"), patch)?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-p1", "-i", "m.diff"]);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file x\npatching file y\n"));
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("x"))?,
+            "a\nB\nc\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("y"))?,
+            "1\nTWO\n3\n"
+        );
+
+        // The same, from standard input.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-p1", "-R"]);
+        cmd.write_stdin(patch);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file x\npatching file y\n"));
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("y"))?,
+            "1\n2\n3\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_context() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        std::fs::write(&file, "a\nb\nc\nd\ne\n")?;
+        let patch = "*** a/f\t2024-01-01 00:00:00\n\
+                     --- b/f\t2024-01-01 00:00:00\n\
+                     ***************\n\
+                     *** 1,3 ****\n  a\n! b\n  c\n\
+                     --- 1,3 ----\n  a\n! B\n  c\n\
+                     ***************\n\
+                     *** 5 ****\n- e\n\
+                     --- 4 ----\n";
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(patch);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file f\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nB\nc\nd\n");
+
+        // The rejects are in the same format.
+        std::fs::write(&file, "a\nX\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--no-backup-if-mismatch"]);
+        cmd.write_stdin(patch);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 FAILED at 1.\n\
+                 1 out of 2 hunks FAILED -- saving rejects to file f.rej\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nX\nc\nd\n");
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("f.rej"))?,
+            "*** f\t2024-01-01 00:00:00\n--- f\t2024-01-01 00:00:00\n\
+             ***************\n*** 1,3 ****\n  a\n! b\n  c\n--- 1,3 ----\n  a\n! B\n  c\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_ed_script() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let old = tmp_dir.path().join("old");
+        let new = tmp_dir.path().join("new");
+        std::fs::write(&old, "a\nb\n.\nc\nd\n")?;
+        std::fs::write(&new, "x\na\n.\n..\nc\nD\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        let script = cmd
+            .args(["diff", "-e"])
+            .arg(&old)
+            .arg(&new)
+            .output()?
+            .stdout;
+
+        // The file to patch is given, as an ed script doesn't name it.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "old"]);
+        cmd.write_stdin(script.clone());
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file old\n"));
+        assert_eq!(std::fs::read(&old)?, std::fs::read(&new)?);
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(script.clone());
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(
+                "No file to patch.  Skipping patch.\n",
+            ));
+
+        // The lines that the commands are about must be there.
+        std::fs::write(&old, "a\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "old"]);
+        cmd.write_stdin(script);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq("patch: **** ed command at line 1 FAILED\n"));
+        assert_eq!(std::fs::read_to_string(&old)?, "a\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_merge() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        std::fs::write(&file, "a\nX\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "--merge"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 NOT MERGED at 2-6.\n",
+            ));
+        assert_eq!(
+            std::fs::read_to_string(&file)?,
+            "a\n<<<<<<<\nX\n=======\nB\n>>>>>>>\nc\nd\ne\nf\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("f.orig"))?,
+            "a\nX\nc\nd\ne\n"
+        );
+        assert!(!tmp_dir.path().join("f.rej").exists());
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["patch", "--merge=none"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::str::starts_with(
+                "patch: invalid argument 'none' for '--merge'",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_git() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("f"), "a\nb\nc\n")?;
+        std::fs::write(tmp_dir.path().join("r"), "1\n2\n")?;
+        std::fs::write(tmp_dir.path().join("m"), "m\n")?;
+        let patch = "From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n\
+                     Subject: [PATCH] change\n\
+                     \n\
+                     ---\n f | 2 +-\n\
+                     \n\
+                     diff --git a/f b/f\nindex de98044..7be73ce 100644\n\
+                     --- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+                     diff --git a/r b/r2\nsimilarity index 100%\nrename from r\nrename to r2\n\
+                     diff --git a/m b/m\nold mode 100644\nnew mode 100755\n\
+                     diff --git a/e b/e\nnew file mode 100644\nindex 0000000..e69de29\n\
+                     -- \n2.39.5\n";
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-p1"]);
+        cmd.write_stdin(patch);
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "patching file f\npatching file r2 (renamed from r)\n\
+                 patching file m\npatching file e\n",
+            ));
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("f"))?,
+            "a\nB\nc\n"
+        );
+        assert!(!tmp_dir.path().join("r").exists());
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("r2"))?,
+            "1\n2\n"
+        );
+        assert_eq!(std::fs::read_to_string(tmp_dir.path().join("e"))?, "");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(tmp_dir.path().join("m"))?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        // Binary patches can't be applied.
+        std::fs::write(tmp_dir.path().join("b"), "b\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-p1"]);
+        cmd.write_stdin("diff --git a/b b/b\nindex 1..2 100644\nGIT binary patch\nliteral 0\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "File b: git binary diffs are not supported.\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_create_and_delete() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("new");
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin("--- /dev/null\n+++ new\n@@ -0,0 +1,2 @@\n+a\n+b\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file new\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nb\n");
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin("--- new\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-a\n-b\n");
+        cmd.assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file new\n"));
+        assert!(!file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_create_and_delete_anyway() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let added = tmp_dir.path().join("add");
+        let kept = tmp_dir.path().join("keep");
+        std::fs::write(&added, "old\n")?;
+        std::fs::write(&kept, "keep\n")?;
+
+        // With -f, a file that already exists isn't created over, and one
+        // that doesn't isn't deleted: their hunks fail, and the files after
+        // them are still patched.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-f"]);
+        cmd.write_stdin(
+            "--- /dev/null\n+++ add\n@@ -0,0 +1 @@\n+new\n\
+             --- gone\n+++ /dev/null\n@@ -1 +0,0 @@\n-gone\n\
+             --- keep\n+++ keep\n@@ -1 +1 @@\n-keep\n+kept\n",
+        );
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "The next patch would create the file add,\n\
+                 which already exists!  Applying it anyway.\n\
+                 patching file add\n\
+                 Hunk #1 FAILED at 1.\n\
+                 1 out of 1 hunk FAILED -- saving rejects to file add.rej\n\
+                 The next patch would delete the file gone,\n\
+                 which does not exist!  Applying it anyway.\n\
+                 patching file gone\n\
+                 Hunk #1 FAILED at 1.\n\
+                 1 out of 1 hunk FAILED -- saving rejects to file gone.rej\n\
+                 patching file keep\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&added)?, "old\n");
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("add.rej"))?,
+            "--- /dev/null\n+++ add\n@@ -0,0 +1 @@\n+new\n"
+        );
+        assert!(!tmp_dir.path().join("gone").exists());
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("gone.rej"))?,
+            "--- gone\n+++ /dev/null\n@@ -1 +0,0 @@\n-gone\n"
+        );
+        assert_eq!(std::fs::read_to_string(&kept)?, "kept\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_reject_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        std::fs::write(tmp_dir.path().join("f"), "x\n")?;
+        std::fs::write(tmp_dir.path().join("g"), "a\n")?;
+        std::fs::create_dir(tmp_dir.path().join("f.rej"))?;
+
+        // The rejects of a file that can't be saved don't stop the patching
+        // of the next files.
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin("--- f\n+++ f\n@@ -1 +1 @@\n-y\n+z\n--- g\n+++ g\n@@ -1 +1 @@\n-a\n+b\n");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 FAILED at 1.\n\
+                 1 out of 1 hunk FAILED -- saving rejects to file f.rej\n\
+                 patching file g\n",
+            ))
+            .stderr(predicate::str::starts_with("patch: f.rej: "));
+        assert_eq!(std::fs::read_to_string(tmp_dir.path().join("g"))?, "b\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_failed_hunk() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        std::fs::write(&file, "a\nX\nc\nd\ne\n")?;
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 FAILED at 1.\n\
+                 1 out of 2 hunks FAILED -- saving rejects to file f.rej\n",
+            ));
+        // The other hunks are applied, and those that can't be are saved.
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nX\nc\nd\ne\nf\n");
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("f.rej"))?,
+            "--- f\t2024-01-01 00:00:00\n+++ f\t2024-01-01 00:00:00\n\
+             @@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+
+        // Or to the given file.
+        std::fs::write(&file, "a\nX\nc\nd\ne\n")?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-r", "rejects"]);
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::ends_with(
+                "1 out of 2 hunks FAILED -- saving rejects to file rejects\n",
+            ));
+        assert!(tmp_dir.path().join("rejects").exists());
+
+        std::fs::remove_file(&file)?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.arg("patch");
+        cmd.write_stdin(PATCH);
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::str::starts_with(
+                "can't find file to patch at input line 3\n",
+            ))
+            .stdout(predicate::str::ends_with(
+                "No file to patch.  Skipping patch.\n2 out of 2 hunks ignored\n",
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_reverse() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let file = tmp_dir.path().join("f");
+        let patch = |args: &[&str]| -> Result<Command, Box<dyn std::error::Error>> {
+            let mut cmd = Command::cargo_bin("diffutils")?;
+            cmd.current_dir(&tmp_dir);
+            cmd.arg("patch").args(args);
+            cmd.write_stdin(PATCH);
+            Ok(cmd)
+        };
+
+        std::fs::write(&file, "a\nB\nc\nd\ne\nf\n")?;
+        patch(&["-R"])?
+            .assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq("patching file f\n"));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nb\nc\nd\ne\n");
+
+        // A patch that was already applied is skipped, unless the answer to
+        // the questions, which can't be asked without a terminal, is yes.
+        std::fs::write(&file, "a\nB\nc\nd\ne\nf\n")?;
+        patch(&[])?
+            .assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\n\
+                 Reversed (or previously applied) patch detected!  Assume -R? [n] \n\
+                 Apply anyway? [n] \n\
+                 Skipping patch.\n\
+                 2 out of 2 hunks ignored -- saving rejects to file f.rej\n",
+            ));
+        patch(&["-N"])?
+            .assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\n\
+                 Reversed (or previously applied) patch detected!  Skipping patch.\n\
+                 2 out of 2 hunks ignored -- saving rejects to file f.rej\n",
+            ));
+        patch(&["-t"])?
+            .assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "patching file f\n\
+                 Reversed (or previously applied) patch detected!  Assuming -R.\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nb\nc\nd\ne\n");
+        patch(&["-R", "-t"])?
+            .assert()
+            .code(predicate::eq(0))
+            .success()
+            .stdout(predicate::eq(
+                "patching file f\nUnreversed patch detected!  Ignoring -R.\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nB\nc\nd\ne\nf\n");
+
+        // -f applies what it can, and the hunk that adds at the end of the
+        // file takes fuzz now that it isn't the end.
+        patch(&["-f"])?
+            .assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "patching file f\nHunk #1 FAILED at 1.\n\
+                 Hunk #2 succeeded at 5 with fuzz 1.\n\
+                 1 out of 2 hunks FAILED -- saving rejects to file f.rej\n",
+            ));
+        assert_eq!(std::fs::read_to_string(&file)?, "a\nB\nc\nd\ne\nf\nf\n");
+
+        // Files that would be created are already there, and those that would
+        // be deleted aren't: the hunks of such patches aren't saved.
+        std::fs::remove_file(tmp_dir.path().join("f.rej"))?;
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-N"]);
+        cmd.write_stdin("--- /dev/null\n+++ f\n@@ -0,0 +1 @@\n+a\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "The next patch would create the file f,\n\
+                 which already exists!  Skipping patch.\n\
+                 1 out of 1 hunk ignored\n",
+            ));
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.current_dir(&tmp_dir);
+        cmd.args(["patch", "-R", "-N"]);
+        cmd.write_stdin("--- /dev/null\n+++ g\n@@ -0,0 +1 @@\n+a\n");
+        cmd.assert()
+            .code(predicate::eq(1))
+            .failure()
+            .stdout(predicate::eq(
+                "The next patch, when reversed, would delete the file g,\n\
+                 which does not exist!  Skipping patch.\n\
+                 1 out of 1 hunk ignored\n",
+            ));
+        assert!(!tmp_dir.path().join("f.rej").exists());
+        assert!(!tmp_dir.path().join("g.rej").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn patch_trouble() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("patch");
+        cmd.write_stdin("--- f\n+++ f\n@@ -1,2 +1,2 @@\n 1\nz2\n");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq(
+                "patch: **** malformed patch at line 5: z2\n\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.arg("patch");
+        cmd.write_stdin("garbage\n");
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq(
+                "patch: **** Only garbage was found in the patch input.\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("diffutils")?;
+        cmd.args(["patch", "a", "b", "c"]);
+        cmd.assert()
+            .code(predicate::eq(2))
+            .failure()
+            .stderr(predicate::eq("patch: c: extra operand\n"));
 
         Ok(())
     }